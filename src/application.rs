@@ -4,45 +4,71 @@
 
 //! Main application
 
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
 use std::future::Future;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::http::StatusCode;
-use log::{error, info};
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use log::{error, info, warn};
 use smol_str::SmolStr;
 use thiserror::Error;
 use tokio::io;
 use tokio::sync::mpsc::{Receiver, Sender, channel};
 
-use crate::model::auth::{Authentication, RegistryUserToken, RegistryUserTokenWithSecret};
+use crate::model::advisories::{
+    AdvisoryDbStatus, AdvisorySuppression, AdvisorySuppressionCreation, InternalAdvisory, InternalAdvisoryData,
+};
+use crate::model::auth::{
+    Authentication, CrateReader, DEVICE_CODE_GRANT_TYPE, DeviceAuthorizationApproval, DeviceAuthorizationResponse,
+    DeviceTokenPoll, DeviceTokenRequest, EndpointScope, ExpiringToken, InvalidTokenScope, MutationKind, OAuthLoginRequest,
+    RegistryMutation, RegistryUserKey, RegistryUserToken, RegistryUserTokenCreation, RegistryUserTokenWithSecret,
+    TokenExpiryOptions, TokenKind, TokenScopes,
+};
 use crate::model::cargo::{
     CrateUploadData, CrateUploadResult, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult, YesNoResult,
 };
 use crate::model::config::Configuration;
-use crate::model::deps::DepsAnalysis;
+use crate::model::deps::{AdvisoryImpact, CRATES_IO_REGISTRY_URI, DepsAnalysis};
 use crate::model::docs::{DocGenEvent, DocGenJob, DocGenJobSpec, DocGenTrigger};
-use crate::model::packages::{CrateInfo, CrateInfoTarget};
+use crate::model::osv::Advisory;
+use crate::model::packages::{CrateDependents, CrateInfo, CrateInfoTarget};
+use crate::model::policy::{LicensePolicy, LicenseRule, LicenseRuleCreation, PublishRule, PublishRuleCreation};
+use crate::model::reservations::{NameReservation, NameReservationCreation};
+use crate::model::sbom::SbomFormat;
 use crate::model::stats::{DownloadStats, GlobalStats};
+use crate::model::teams::{Team, TeamMembership};
+use crate::model::typosquat::{CrateReview, TyposquatPolicy, builtin_popular_names};
 use crate::model::worker::{WorkerEvent, WorkerPublicData, WorkersManager};
 use crate::model::{AppEvent, CrateVersion, RegistryInformation};
 use crate::services::ServiceProvider;
 use crate::services::database::admin::TokensError;
+use crate::services::database::advisories::AdvisoryError;
 use crate::services::database::packages::{CratesError, DepsError};
+use crate::services::database::policy::PolicyError;
+use crate::services::database::reservations::ReservationError;
 use crate::services::database::stats::CratesStatsError;
-use crate::services::database::users::{UpdateUserError, UserError};
+use crate::services::database::teams::TeamError;
+use crate::services::database::users::{AddKeyError, OAuthLoginError, UpdateUserError, UserError};
 use crate::services::database::{
     Database, DbReadError, DbWriteError, IsCrateManagerError, db_transaction_read, db_transaction_write,
 };
 use crate::services::deps::DepsChecker;
 use crate::services::docs::DocsGenerator;
 use crate::services::emails::EmailSender;
-use crate::services::index::{GitIndexError, Index, IndexError};
+use crate::services::index::{Index, IndexError};
+use crate::services::oauth::OAuthProvider;
+use crate::services::proxy::CratesIoProxy;
 use crate::services::rustsec::RustSecChecker;
-use crate::services::storage::Storage;
-use crate::utils::apierror::{ApiError, AsStatusCode, error_forbidden};
+use crate::services::storage::{Storage, extract_crate_files};
+use crate::utils::apierror::{ApiError, AsStatusCode, error_forbidden, error_not_found, specialize};
 use crate::utils::axum::auth::{AuthData, Token};
 use crate::utils::db::{PoolCreateError, RwSqlitePool};
+use crate::utils::paseto::PasetoError;
+use crate::utils::token::{generate_device_code, generate_user_code, normalize_user_code};
 
 #[derive(Debug, Error)]
 pub enum LaunchError {
@@ -63,13 +89,19 @@ pub enum LaunchError {
     DbRead(#[source] DbReadError),
 
     #[error("failed to get `index service`")]
-    GetIndex(#[source] GitIndexError),
+    GetIndex(#[source] IndexError),
 
     #[error("failed to get JobSpecs for undocumented packages")]
     JobSpecs(#[source] DbWriteError),
 
     #[error("failed to launch doc generator for undocumented packages")]
     DocGenerator(#[source] DbWriteError),
+
+    #[error("failed to index the dependencies of crate versions")]
+    IndexDependencies(#[source] DbWriteError),
+
+    #[error("failed to create the HTTP client")]
+    HttpClient(#[source] reqwest::Error),
 }
 
 /// The state of this application for axum
@@ -83,15 +115,17 @@ pub struct Application {
     /// Service to index the metadata of crates
     service_index: Arc<dyn Index + Send + Sync>,
     /// The `RustSec` checker service
-    #[expect(dead_code)]
     service_rustsec: Arc<dyn RustSecChecker + Send + Sync>,
     /// Service to check the dependencies of a crate
     service_deps_checker: Arc<dyn DepsChecker + Send + Sync>,
     /// The service to send emails
-    #[expect(dead_code)]
     service_email_sender: Arc<dyn EmailSender + Send + Sync>,
     /// The service to generator documentation
     service_docs_generator: Arc<dyn DocsGenerator + Send + Sync>,
+    /// The pull-through caching proxy to crates.io
+    service_crates_io_proxy: Arc<dyn CratesIoProxy + Send + Sync>,
+    /// The OAuth identity provider
+    service_oauth_provider: Arc<dyn OAuthProvider + Send + Sync>,
     /// Sender to use to notify about events that will be asynchronously handled
     app_events_sender: Sender<AppEvent>,
     /// The connected worker nodes
@@ -101,8 +135,24 @@ pub struct Application {
 /// The empty database
 const DB_EMPTY: &[u8] = include_bytes!("empty.db");
 
+/// Number of seconds between each check for expiring tokens
+const TOKEN_EXPIRY_CHECK_PERIOD: u64 = 60 * 60;
+
+/// Number of seconds between each refresh of the names of the popular crates on crates.io
+const POPULAR_CRATES_REFRESH_PERIOD: u64 = 24 * 60 * 60;
+
+/// Number of seconds during which a device authorization request can be approved
+const DEVICE_CODE_VALIDITY: i64 = 15 * 60;
+/// Minimum number of seconds between two polling requests of a device
+const DEVICE_POLL_INTERVAL: i64 = 5;
+/// Maximum number of pending device authorization requests, new requests being refused beyond
+const DEVICE_MAX_PENDING: i64 = 100;
+/// The default name for tokens created through the device authorization flow
+const DEVICE_TOKEN_DEFAULT_NAME: &str = "device";
+
 impl Application {
     /// Creates a new application
+    #[expect(clippy::too_many_lines)]
     pub async fn launch<P: ServiceProvider>(configuration: Configuration) -> Result<Arc<Self>, LaunchError> {
         // load configuration
         let configuration = Arc::new(configuration);
@@ -130,11 +180,22 @@ impl Application {
             .await
             .map_err(LaunchError::DbRead)?;
         let service_storage = P::get_storage(&configuration.deref().clone());
-        let service_index = P::get_index(&configuration, db_is_empty)
+        let service_index = P::get_index(&configuration, service_storage.clone(), db_is_empty)
             .await
             .map_err(LaunchError::GetIndex)?;
-        let service_rustsec = P::get_rustsec(&configuration);
-        let service_deps_checker = P::get_deps_checker(configuration.clone(), service_index.clone(), service_rustsec.clone());
+        Self::index_crate_versions_dependencies(&service_db_pool, service_index.as_ref()).await?;
+        let service_rustsec = P::get_rustsec(&configuration, service_db_pool.clone());
+        let http_client = crate::services::create_http_client().map_err(LaunchError::HttpClient)?;
+        let service_crates_io_proxy =
+            P::get_crates_io_proxy(configuration.clone(), service_storage.clone(), http_client.clone());
+        let service_deps_checker = P::get_deps_checker(
+            configuration.clone(),
+            service_index.clone(),
+            service_rustsec.clone(),
+            service_storage.clone(),
+            service_crates_io_proxy.clone(),
+            http_client,
+        );
         let service_email_sender = P::get_email_sender(configuration.clone());
         let service_docs_generator = P::get_docs_generator(
             configuration.clone(),
@@ -142,6 +203,7 @@ impl Application {
             service_storage.clone(),
             worker_nodes.clone(),
         );
+        let service_oauth_provider = P::get_oauth_provider(configuration.clone());
 
         // check undocumented packages
         let default_target = &configuration.self_toolchain_host;
@@ -187,6 +249,8 @@ impl Application {
             service_deps_checker,
             service_email_sender,
             service_docs_generator,
+            service_crates_io_proxy,
+            service_oauth_provider,
             app_events_sender,
             worker_nodes,
         });
@@ -197,10 +261,72 @@ impl Application {
                 app.events_handler(app_events_receiver).await;
             })
         };
+        let _handle = {
+            let app = this.clone();
+            tokio::spawn(async move {
+                app.token_expiry_worker().await;
+            })
+        };
+        if this.configuration.typosquat_policy != TyposquatPolicy::Off && this.configuration.typosquat_popular_count > 0 {
+            let app = this.clone();
+            let _handle = tokio::spawn(async move {
+                app.popular_crates_worker().await;
+            });
+        }
 
         Ok(this)
     }
 
+    /// Indexes the dependencies of the crate versions that are missing from the index of reverse dependencies,
+    /// i.e. the versions published before the index was introduced
+    async fn index_crate_versions_dependencies(
+        service_db_pool: &RwSqlitePool,
+        service_index: &(dyn Index + Send + Sync),
+    ) -> Result<(), LaunchError> {
+        let unindexed = db_transaction_read(service_db_pool, |database| async move {
+            database.get_unindexed_crate_versions().await
+        })
+        .await
+        .map_err(LaunchError::DbRead)?;
+        if unindexed.is_empty() {
+            return Ok(());
+        }
+        info!("indexing the dependencies of {} crate version(s)", unindexed.len());
+        let mut crates_data = HashMap::new();
+        for crate_version in &unindexed {
+            if !crates_data.contains_key(&crate_version.package) {
+                let data = service_index
+                    .get_crate_data(&crate_version.package)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!("failed to read the index for {}: {e}", crate_version.package);
+                        Vec::new()
+                    });
+                crates_data.insert(crate_version.package.clone(), data);
+            }
+        }
+        let crates_data = &crates_data;
+        db_transaction_write(
+            service_db_pool,
+            "Application::launch::index_crate_versions_dependencies",
+            |database| async move {
+                for metadata in crates_data.values().flatten() {
+                    if unindexed
+                        .iter()
+                        .any(|v| v.package == metadata.name && v.version == metadata.vers)
+                    {
+                        database
+                            .set_crate_version_dependencies(&metadata.name, &metadata.vers, &metadata.deps)
+                            .await?;
+                    }
+                }
+                Ok::<_, sqlx::Error>(())
+            },
+        )
+        .await
+        .map_err(LaunchError::IndexDependencies)
+    }
+
     /// Gets the storage service
     #[must_use]
     pub fn get_service_storage(&self) -> Arc<dyn Storage + Send + Sync> {
@@ -269,6 +395,122 @@ impl Application {
         .await
     }
 
+    /// The worker for the housekeeping of tokens and device authorization requests, notifying the owners of tokens that are about to expire, when activated
+    async fn token_expiry_worker(&self) -> ! {
+        let mut interval = tokio::time::interval(Duration::from_secs(TOKEN_EXPIRY_CHECK_PERIOD));
+        loop {
+            let _instant = interval.tick().await;
+            if let Err(e) = self
+                .db_transaction_write("purge_rotated_secrets", |app| async move {
+                    app.database.purge_rotated_secrets().await
+                })
+                .await
+            {
+                error!("failed to purge the rotated secrets of tokens: {e}");
+            }
+            if let Err(e) = self
+                .db_transaction_write("purge_device_authorizations", |app| async move {
+                    app.database.purge_device_authorizations().await
+                })
+                .await
+            {
+                error!("failed to purge the expired device authorization requests: {e}");
+            }
+            if self.configuration.token_expiry_notify
+                && let Err(e) = self.token_expiry_notify().await
+            {
+                error!("{e}");
+                if let Some(backtrace) = &e.backtrace {
+                    error!("{backtrace}");
+                }
+            }
+        }
+    }
+
+    /// Refreshes the names of the popular crates on crates.io that the names of new crates are compared to
+    async fn popular_crates_worker(&self) -> ! {
+        let mut interval = tokio::time::interval(Duration::from_secs(POPULAR_CRATES_REFRESH_PERIOD));
+        loop {
+            let _instant = interval.tick().await;
+            match self
+                .service_crates_io_proxy
+                .refresh_popular_crates(self.configuration.typosquat_popular_count)
+                .await
+            {
+                Ok(names) => info!("fetched the names of {} popular crates from crates.io", names.len()),
+                Err(e) => error!("failed to fetch the names of the popular crates from crates.io: {e}"),
+            }
+        }
+    }
+
+    /// Gets the names of the popular crates that the names of new crates are compared to
+    /// Until they are fetched from crates.io, and when no names are configured, the built-in list is used.
+    async fn get_typosquat_popular_names(&self) -> Result<Vec<String>, ApiError> {
+        if self.configuration.typosquat_policy == TyposquatPolicy::Off {
+            return Ok(Vec::new());
+        }
+        let mut names = self.service_crates_io_proxy.get_popular_crates().await?;
+        names.extend_from_slice(&self.configuration.typosquat_popular_names);
+        if names.is_empty() {
+            names = builtin_popular_names();
+        }
+        Ok(names)
+    }
+
+    /// Sends the notifications for the tokens that are about to expire
+    /// A failure to notify the owners of a token does not prevent the notifications for the other tokens,
+    /// the notification being attempted again on the next check.
+    pub(crate) async fn token_expiry_notify(&self) -> Result<(), ApiError> {
+        let until = Local::now().naive_local() + TimeDelta::days(self.configuration.token_expiry_notice);
+        let tokens = self
+            .db_transaction_read(|app| async move { app.database.get_expiring_tokens(until).await })
+            .await?;
+        for token in &tokens {
+            if !token.emails.is_empty()
+                && let Err(e) = self
+                    .service_email_sender
+                    .send_email(
+                        &token.emails,
+                        &format!("Cratery - token {} is about to expire", token.name),
+                        self.token_expiry_email_body(token),
+                    )
+                    .await
+            {
+                error!("failed to notify the expiration of token {}: {e}", token.name);
+                continue;
+            }
+            self.db_transaction_write("set_token_expiry_notified", |app| async move {
+                app.database.set_token_expiry_notified(token.kind, token.id).await
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Builds the body of the email for an expiring token
+    fn token_expiry_email_body(&self, token: &ExpiringToken) -> String {
+        let (kind, page) = if token.kind == TokenKind::Registry {
+            ("global token", "admin-tokens.html")
+        } else {
+            ("token", "account.html")
+        };
+        let mut body = String::new();
+        writeln!(
+            body,
+            "The {kind} {} expires on {}.",
+            token.name,
+            token.expires_at.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "It can be rotated at {}/webapp/{page}",
+            self.configuration.web_public_uri
+        )
+        .unwrap();
+        body
+    }
+
     /// Executes a piece of work in the context of a transaction
     /// The transaction is committed if the operation succeed,
     /// or rolled back if it fails
@@ -374,12 +616,28 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Gets the URI of the login page on the identity provider for a new login request
+    pub async fn get_oauth_login_uri(&self, request: &OAuthLoginRequest) -> Result<String, ApiError> {
+        self.service_oauth_provider.get_login_uri(request).await
+    }
+
     /// Attempts to login using an OAuth code
-    pub async fn login_with_oauth_code(&self, code: &str) -> Result<RegistryUser, DbWriteError> {
+    /// The state returned by the identity provider must match the one of the login request.
+    pub async fn login_with_oauth_code(
+        &self,
+        code: &str,
+        state: &str,
+        request: &OAuthLoginRequest,
+    ) -> Result<RegistryUser, ApiError> {
+        if state != request.state {
+            return Err(ApiError::from(OAuthLoginError::InvalidState));
+        }
+        let claims = self.service_oauth_provider.get_user_claims(code, request).await?;
         self.db_transaction_write("login_with_oauth_code", |app| async move {
-            app.database.login_with_oauth_code(&self.configuration, code).await
+            app.database.login_with_oauth_claims(&self.configuration, &claims).await
         })
         .await
+        .map_err(ApiError::from)
     }
 
     /// Gets the known users
@@ -473,6 +731,123 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Gets the known teams
+    pub async fn get_teams(&self, auth_data: &AuthData) -> Result<Vec<Team>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            let mut teams = app
+                .database
+                .get_teams()
+                .await
+                .map_err(|source| ApplicationError::GetTeams { source })?;
+            app.redact_team_members(&authentication, &mut teams).await?;
+            Ok::<_, ApplicationError>(teams)
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Gets a team
+    pub async fn get_team(&self, auth_data: &AuthData, team: &str) -> Result<Team, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            let mut team = app.database.get_team(team).await.map_err(|source| ApplicationError::Team {
+                source,
+                team: team.into(),
+            })?;
+            app.redact_team_members(&authentication, std::slice::from_mut(&mut team))
+                .await?;
+            Ok::<_, ApplicationError>(team)
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Creates a new team
+    pub async fn create_team(&self, auth_data: &AuthData, team: &str) -> Result<Team, ApiError> {
+        self.db_transaction_write("create_team", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.create_team(team).await.map_err(|source| ApplicationError::Team {
+                source,
+                team: team.into(),
+            })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Deletes a team
+    pub async fn delete_team(&self, auth_data: &AuthData, team: &str) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_team", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database.delete_team(team).await.map_err(|source| ApplicationError::Team {
+                source,
+                team: team.into(),
+            })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Adds a member to a team, or updates its membership
+    pub async fn set_team_member(
+        &self,
+        auth_data: &AuthData,
+        team: &str,
+        membership: &TeamMembership,
+    ) -> Result<Team, ApiError> {
+        self.db_transaction_write("set_team_member", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            authentication.check_unscoped()?;
+            let principal_uid = authentication.uid()?;
+            app.database
+                .check_is_team_maintainer(principal_uid, team)
+                .await
+                .map_err(|source| ApplicationError::Team {
+                    source,
+                    team: team.into(),
+                })?;
+            app.database
+                .set_team_member(team, &membership.email, membership.is_maintainer)
+                .await
+                .map_err(|source| ApplicationError::Team {
+                    source,
+                    team: team.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Removes a member from a team
+    pub async fn remove_team_member(&self, auth_data: &AuthData, team: &str, email: &str) -> Result<Team, ApiError> {
+        self.db_transaction_write("remove_team_member", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            authentication.check_unscoped()?;
+            let principal_uid = authentication.uid()?;
+            app.database
+                .check_is_team_maintainer(principal_uid, team)
+                .await
+                .map_err(|source| ApplicationError::Team {
+                    source,
+                    team: team.into(),
+                })?;
+            app.database
+                .remove_team_member(team, email)
+                .await
+                .map_err(|source| ApplicationError::Team {
+                    source,
+                    team: team.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
     /// Gets the tokens for a user
     pub async fn get_tokens(&self, auth_data: &AuthData) -> Result<Vec<RegistryUserToken>, ApiError> {
         self.db_transaction_read(|app| async move {
@@ -492,14 +867,23 @@ impl Application {
         &self,
         auth_data: &AuthData,
         name: &str,
-        can_write: bool,
-        can_admin: bool,
+        options: &RegistryUserTokenCreation,
     ) -> Result<RegistryUserTokenWithSecret, ApiError> {
         self.db_transaction_write("create_token", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             authentication.check_can_admin()?;
+            authentication.check_unscoped()?;
+            let scopes = TokenScopes::parse(&options.crate_scopes, &options.endpoint_scopes)?;
+            let validity = self.get_token_validity(options.expires_in_days)?;
             app.database
-                .create_token(authentication.uid()?, name, can_write, can_admin)
+                .create_token(
+                    authentication.uid()?,
+                    name,
+                    options.can_write,
+                    options.can_admin,
+                    scopes,
+                    validity,
+                )
                 .await
                 .map_err(|source| ApplicationError::CreateToken {
                     source,
@@ -510,6 +894,35 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Rotates the secret of a token for the current user
+    /// The previous secret remains valid during the configured grace period
+    pub async fn rotate_token(
+        &self,
+        auth_data: &AuthData,
+        token_id: i64,
+        options: TokenExpiryOptions,
+    ) -> Result<RegistryUserTokenWithSecret, ApiError> {
+        self.db_transaction_write("rotate_token", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            authentication.check_unscoped()?;
+            let validity = self.check_token_validity(options.expires_in_days)?;
+            app.database
+                .rotate_token(
+                    authentication.uid()?,
+                    token_id,
+                    validity,
+                    self.configuration.token_max_validity,
+                    self.get_token_grace_end(),
+                )
+                .await
+                .map_err(|source| ApplicationError::RotateToken { source, token_id })?
+                .ok_or(ApplicationError::TokenNotFound { token_id })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
     /// Revoke a previous token
     pub async fn revoke_token(&self, auth_data: &AuthData, token_id: i64) -> Result<(), ApiError> {
         self.db_transaction_write("revoke_token", |app| async move {
@@ -524,6 +937,58 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Gets the public keys registered by a user for asymmetric tokens
+    pub async fn get_keys(&self, auth_data: &AuthData) -> Result<Vec<RegistryUserKey>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            app.database
+                .get_keys(authentication.uid()?)
+                .await
+                .map_err(|source| ApplicationError::GetKeys { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Registers a public key for the current user
+    pub async fn add_key(
+        &self,
+        auth_data: &AuthData,
+        name: &str,
+        public_key: &str,
+        can_write: bool,
+    ) -> Result<RegistryUserKey, ApiError> {
+        self.db_transaction_write("add_key", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            authentication.check_unscoped()?;
+            app.database
+                .add_key(authentication.uid()?, name, public_key, can_write)
+                .await
+                .map_err(|source| ApplicationError::AddKey {
+                    source,
+                    name: name.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Removes a previously registered public key
+    pub async fn remove_key(&self, auth_data: &AuthData, key_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("remove_key", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            app.database
+                .remove_key(authentication.uid()?, key_id)
+                .await
+                .map_err(|source| ApplicationError::RemoveKey { source, key_id })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
     /// Gets the global tokens for the registry, usually for CI purposes
     pub async fn get_global_tokens(&self, auth_data: &AuthData) -> Result<Vec<RegistryUserToken>, ApiError> {
         self.db_transaction_read(|app| async move {
@@ -538,13 +1003,105 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Starts a device authorization request (RFC 8628)
+    /// The returned user code is to be approved by a user in the web application.
+    pub async fn request_device_authorization(&self) -> Result<DeviceAuthorizationResponse, ApiError> {
+        self.db_transaction_write("request_device_authorization", |app| async move {
+            let device_code = generate_device_code();
+            let user_code = generate_user_code();
+            let expires_at = Local::now().naive_local() + TimeDelta::seconds(DEVICE_CODE_VALIDITY);
+            let created = app
+                .database
+                .create_device_authorization(&device_code, &user_code, expires_at, DEVICE_POLL_INTERVAL, DEVICE_MAX_PENDING)
+                .await
+                .map_err(ApplicationError::DeviceAuthorization)?;
+            if !created {
+                return Err(ApplicationError::TooManyDeviceAuthorizations);
+            }
+            let verification_uri = format!("{}/webapp/device.html", self.configuration.web_public_uri);
+            Ok::<_, ApplicationError>(DeviceAuthorizationResponse {
+                device_code,
+                verification_uri_complete: format!("{verification_uri}?user_code={user_code}"),
+                user_code,
+                verification_uri,
+                expires_in: DEVICE_CODE_VALIDITY,
+                interval: DEVICE_POLL_INTERVAL,
+            })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Approves or denies a device authorization request for the current user
+    pub async fn decide_device_authorization(
+        &self,
+        auth_data: &AuthData,
+        approval: &DeviceAuthorizationApproval,
+    ) -> Result<(), ApiError> {
+        self.db_transaction_write("decide_device_authorization", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            authentication.check_can_admin()?;
+            authentication.check_unscoped()?;
+            let user_code = normalize_user_code(&approval.user_code);
+            let found = if approval.approve {
+                let options = &approval.options;
+                let scopes = TokenScopes::parse(&options.crate_scopes, &options.endpoint_scopes)?;
+                let validity = self.get_token_validity(options.expires_in_days)?;
+                let name = approval.name.trim();
+                let name = if name.is_empty() { DEVICE_TOKEN_DEFAULT_NAME } else { name };
+                app.database
+                    .approve_device_authorization(
+                        &user_code,
+                        authentication.uid()?,
+                        name,
+                        options.can_write,
+                        options.can_admin,
+                        &scopes,
+                        validity,
+                    )
+                    .await
+            } else {
+                app.database.deny_device_authorization(&user_code).await
+            }
+            .map_err(ApplicationError::DeviceAuthorization)?;
+            if found {
+                Ok(())
+            } else {
+                Err(ApplicationError::DeviceAuthorizationNotFound { user_code })
+            }
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Polls for the token of a device authorization request
+    pub async fn poll_device_authorization(&self, request: &DeviceTokenRequest) -> Result<DeviceTokenPoll, ApiError> {
+        if request.grant_type != DEVICE_CODE_GRANT_TYPE {
+            return Ok(DeviceTokenPoll::UnsupportedGrantType);
+        }
+        self.db_transaction_write("poll_device_authorization", |app| async move {
+            app.database
+                .poll_device_authorization(&request.device_code)
+                .await
+                .map_err(ApplicationError::DeviceAuthorization)
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
     /// Creates a global token for the registry
-    pub async fn create_global_token(&self, auth_data: &AuthData, name: &str) -> Result<RegistryUserTokenWithSecret, ApiError> {
+    pub async fn create_global_token(
+        &self,
+        auth_data: &AuthData,
+        name: &str,
+        options: TokenExpiryOptions,
+    ) -> Result<RegistryUserTokenWithSecret, ApiError> {
         self.db_transaction_write("create_global_token", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
             app.check_can_admin_registry(&authentication).await?;
+            let validity = self.get_token_validity(options.expires_in_days)?;
             app.database
-                .create_global_token(name)
+                .create_global_token(name, validity)
                 .await
                 .map_err(|source| ApplicationError::CreateGlobalToken {
                     source,
@@ -555,6 +1112,54 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Rotates the secret of a global token for the registry
+    /// The previous secret remains valid during the configured grace period
+    pub async fn rotate_global_token(
+        &self,
+        auth_data: &AuthData,
+        token_id: i64,
+        options: TokenExpiryOptions,
+    ) -> Result<RegistryUserTokenWithSecret, ApiError> {
+        self.db_transaction_write("rotate_global_token", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            let validity = self.check_token_validity(options.expires_in_days)?;
+            app.database
+                .rotate_global_token(
+                    token_id,
+                    validity,
+                    self.configuration.token_max_validity,
+                    self.get_token_grace_end(),
+                )
+                .await
+                .map_err(|source| ApplicationError::RotateToken { source, token_id })?
+                .ok_or(ApplicationError::TokenNotFound { token_id })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Checks the requested validity in days for a new or rotated token against the maximum validity, if any
+    fn check_token_validity(&self, expires_in_days: Option<i64>) -> Result<Option<i64>, InvalidTokenExpiry> {
+        match (expires_in_days, self.configuration.token_max_validity) {
+            (Some(days), _) if days <= 0 => Err(InvalidTokenExpiry { days }),
+            (Some(days), Some(max)) if days > max => Err(InvalidTokenExpiry { days }),
+            _ => Ok(expires_in_days),
+        }
+    }
+
+    /// Gets the validity in days for a new token, defaulting to the maximum validity, if any
+    fn get_token_validity(&self, expires_in_days: Option<i64>) -> Result<Option<i64>, InvalidTokenExpiry> {
+        Ok(self
+            .check_token_validity(expires_in_days)?
+            .or(self.configuration.token_max_validity))
+    }
+
+    /// Gets the end of the grace period for the previous secret of a token that is rotated now
+    fn get_token_grace_end(&self) -> NaiveDateTime {
+        Local::now().naive_local() + TimeDelta::minutes(self.configuration.token_rotation_grace)
+    }
+
     /// Revokes a global token for the registry
     pub async fn revoke_global_token(&self, auth_data: &AuthData, token_id: i64) -> Result<(), ApiError> {
         self.db_transaction_write("revoke_global_token", |app| async move {
@@ -569,17 +1174,212 @@ impl Application {
         .map_err(ApiError::from)
     }
 
-    /// Publish a crate
-    pub async fn publish_crate_version(&self, auth_data: &AuthData, content: &[u8]) -> Result<CrateUploadResult, ApiError> {
-        // deserialize payload
+    /// Gets the reservations of crate names
+    pub async fn get_name_reservations(&self, auth_data: &AuthData) -> Result<Vec<NameReservation>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .get_name_reservations()
+                .await
+                .map_err(|source| ApplicationError::NameReservations { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Reserves crate names for a user or a team
+    pub async fn create_name_reservation(
+        &self,
+        auth_data: &AuthData,
+        creation: &NameReservationCreation,
+    ) -> Result<NameReservation, ApiError> {
+        self.db_transaction_write("create_name_reservation", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .create_name_reservation(creation)
+                .await
+                .map_err(|source| ApplicationError::NameReservations { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Removes a reservation of crate names
+    pub async fn delete_name_reservation(&self, auth_data: &AuthData, reservation_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_name_reservation", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .delete_name_reservation(reservation_id)
+                .await
+                .map_err(|source| ApplicationError::NameReservations { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Gets the new crates held for review because their names could be confused with other crates
+    pub async fn get_crate_reviews(&self, auth_data: &AuthData) -> Result<Vec<CrateReview>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .get_crate_reviews()
+                .await
+                .map_err(|source| ApplicationError::GetCrateReviews { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Approves a crate held for review, making it visible to its readers
+    pub async fn approve_crate_review(&self, auth_data: &AuthData, package: &str) -> Result<(), ApiError> {
+        self.db_transaction_write("approve_crate_review", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .approve_crate_review(package)
+                .await
+                .map_err(|source| ApplicationError::ApproveCrateReview {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Gets the rules of the publish policy
+    pub async fn get_publish_rules(&self, auth_data: &AuthData) -> Result<Vec<PublishRule>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .get_publish_rules()
+                .await
+                .map_err(|source| ApplicationError::PublishPolicy { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Adds a rule to the publish policy
+    pub async fn create_publish_rule(
+        &self,
+        auth_data: &AuthData,
+        creation: &PublishRuleCreation,
+    ) -> Result<PublishRule, ApiError> {
+        self.db_transaction_write("create_publish_rule", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .create_publish_rule(creation)
+                .await
+                .map_err(|source| ApplicationError::PublishPolicy { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Removes a rule from the publish policy
+    pub async fn delete_publish_rule(&self, auth_data: &AuthData, rule_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_publish_rule", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .delete_publish_rule(rule_id)
+                .await
+                .map_err(|source| ApplicationError::PublishPolicy { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Gets the rules of the license policy for dependencies
+    pub async fn get_license_rules(&self, auth_data: &AuthData) -> Result<Vec<LicenseRule>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .get_license_rules()
+                .await
+                .map_err(|source| ApplicationError::LicensePolicy { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Adds a rule to the license policy for dependencies
+    pub async fn create_license_rule(
+        &self,
+        auth_data: &AuthData,
+        creation: &LicenseRuleCreation,
+    ) -> Result<LicenseRule, ApiError> {
+        self.db_transaction_write("create_license_rule", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .create_license_rule(creation)
+                .await
+                .map_err(|source| ApplicationError::LicensePolicy { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Removes a rule from the license policy for dependencies
+    pub async fn delete_license_rule(&self, auth_data: &AuthData, rule_id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_license_rule", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            app.database
+                .delete_license_rule(rule_id)
+                .await
+                .map_err(|source| ApplicationError::LicensePolicy { source })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Publish a crate
+    #[expect(clippy::too_many_lines)]
+    pub async fn publish_crate_version(&self, auth_data: &AuthData, content: &[u8]) -> Result<CrateUploadResult, ApiError> {
+        // deserialize payload
         let package = CrateUploadData::new(content)?;
+        let files = extract_crate_files(&package.content)?;
         let index_data = package.build_index_data();
+        let popular_names = self.get_typosquat_popular_names().await?;
 
         let (user, result, targets, capabilities) = {
             let package = &package;
+            let vcs_info = files.vcs_info.as_ref();
+            let index_data = &index_data;
+            let popular_names = &popular_names;
             self.db_transaction_write("publish_crate_version", |app| async move {
-                let authentication = app.authenticate(auth_data).await?;
+                let mutation = RegistryMutation {
+                    kind: MutationKind::Publish,
+                    name: &package.metadata.name,
+                    vers: Some(&package.metadata.vers),
+                    cksum: Some(&index_data.cksum),
+                };
+                let authentication = app.authenticate_for(auth_data, Some(&mutation)).await?;
                 authentication.check_can_write()?;
+                let exists = app
+                    .database
+                    .get_crate_exists(&package.metadata.name)
+                    .await
+                    .map_err(|source| ApplicationError::GetCrateExists {
+                        source,
+                        package: package.metadata.name.as_str().into(),
+                    })?;
+                let endpoint = if exists {
+                    EndpointScope::PublishUpdate
+                } else {
+                    EndpointScope::PublishNew
+                };
+                authentication.scopes.check(Some(endpoint), &package.metadata.name)?;
                 let uid = authentication.uid()?;
                 let user = app
                     .database
@@ -587,13 +1387,23 @@ impl Application {
                     .await
                     .map_err(|source| ApplicationError::GetUserProfile { source, uid })?;
                 // publish
-                let result = app.database.publish_crate_version(user.id, package).await.map_err(|source| {
-                    ApplicationError::PublishVersion {
+                let result = app
+                    .database
+                    .publish_crate_version(user.id, package, vcs_info, self.configuration.typosquat_policy, popular_names)
+                    .await
+                    .map_err(|source| ApplicationError::PublishVersion {
                         source,
                         package: package.metadata.name.as_str().into(),
                         user_login: user.login.as_str().into(),
-                    }
-                })?;
+                    })?;
+                app.database
+                    .set_crate_version_dependencies(&index_data.name, &index_data.vers, &index_data.deps)
+                    .await
+                    .map_err(|source| ApplicationError::SetCrateDependencies {
+                        source,
+                        package: index_data.name.as_str().into(),
+                        version: index_data.vers.as_str().into(),
+                    })?;
                 let mut targets = app
                     .database
                     .get_crate_targets(&package.metadata.name)
@@ -630,9 +1440,18 @@ impl Application {
                 Ok::<_, ApplicationError>((user, result, targets, capabilities))
             })
             .await
-        }?;
+        }
+        .map_err(|error| match &error {
+            // report the cause of the rejection to cargo
+            DbWriteError::Workload { source, status_code, .. } => {
+                ApiError::new(*status_code, "The operation failed in the backend.", Some(source.to_string()))
+            }
+            _ => ApiError::from(error),
+        })?;
 
-        self.service_storage.store_crate(&package.metadata, package.content).await?;
+        self.service_storage
+            .store_crate(&package.metadata, package.content, files.readme)
+            .await?;
         self.service_index.publish_crate_version(&index_data).await?;
         for info in targets {
             self.service_docs_generator
@@ -655,7 +1474,8 @@ impl Application {
     pub async fn get_crate_info(&self, auth_data: &AuthData, package: &str) -> Result<CrateInfo, ApiError> {
         let info = self
             .db_transaction_read(|app| async move {
-                let _authentication = app.authenticate(auth_data).await?;
+                let reader = app.authenticate_reader(auth_data).await?;
+                app.check_can_read_crate(reader, package).await?;
                 let versions =
                     self.service_index
                         .get_crate_data(package)
@@ -684,7 +1504,8 @@ impl Application {
     pub async fn get_crate_last_readme(&self, auth_data: &AuthData, package: &str) -> Result<Vec<u8>, ApiError> {
         let version = self
             .db_transaction_read(|app| async move {
-                let _authentication = app.authenticate(auth_data).await?;
+                let reader = app.authenticate_reader(auth_data).await?;
+                app.check_can_read_crate(reader, package).await?;
                 app.database
                     .get_crate_last_version(package)
                     .await
@@ -700,18 +1521,20 @@ impl Application {
 
     /// Downloads the README for a crate
     pub async fn get_crate_readme(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<Vec<u8>, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
+        self.db_transaction_read(|app| async move {
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await
+        })
+        .await?;
         let readme = self.service_storage.download_crate_readme(package, version).await?;
         Ok(readme)
     }
 
     /// Downloads the content for a crate
     pub async fn get_crate_content(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<Vec<u8>, ApiError> {
-        let public_read = self.configuration.self_public_read;
         self.db_transaction_read(|app| async move {
-            if !public_read {
-                let _authentication = app.authenticate(auth_data).await?;
-            }
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await?;
             app.database
                 .check_crate_exists(package, version)
                 .await
@@ -732,11 +1555,38 @@ impl Application {
         Ok(content)
     }
 
+    /// Gets the content of a file in the mirrored crates.io index
+    pub async fn get_crates_io_index_file(&self, auth_data: &AuthData, path: &str) -> Result<Vec<u8>, ApiError> {
+        if !self.configuration.proxy_crates_io {
+            return Err(error_not_found());
+        }
+        if !self.configuration.self_public_read {
+            let _authentication = self.authenticate(auth_data).await?;
+        }
+        self.service_crates_io_proxy.get_index_file(path).await
+    }
+
+    /// Downloads the content for a crate on crates.io, going through the local cache
+    pub async fn get_crates_io_crate_content(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+    ) -> Result<Vec<u8>, ApiError> {
+        if !self.configuration.proxy_crates_io {
+            return Err(error_not_found());
+        }
+        if !self.configuration.self_public_read {
+            let _authentication = self.authenticate(auth_data).await?;
+        }
+        self.service_crates_io_proxy.download_crate(package, version).await
+    }
+
     /// Completely removes a version from the registry
     pub async fn remove_crate_version(&self, auth_data: &AuthData, package: &str, version: &str) -> Result<(), ApiError> {
         self.db_transaction_write("remove_crate_version", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            app.check_can_manage_crate(&authentication, package, None)
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
@@ -770,8 +1620,14 @@ impl Application {
         version: &str,
     ) -> Result<YesNoResult, ApiError> {
         self.db_transaction_write("yank_crate_version", |app| async move {
-            let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            let mutation = RegistryMutation {
+                kind: MutationKind::Yank,
+                name: package,
+                vers: Some(version),
+                cksum: None,
+            };
+            let authentication = app.authenticate_for(auth_data, Some(&mutation)).await?;
+            app.check_can_manage_crate(&authentication, package, Some(EndpointScope::Yank))
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
@@ -798,21 +1654,46 @@ impl Application {
         version: &str,
     ) -> Result<YesNoResult, ApiError> {
         self.db_transaction_write("unyank_crate_version", |app| async move {
-            let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            let mutation = RegistryMutation {
+                kind: MutationKind::Unyank,
+                name: package,
+                vers: Some(version),
+                cksum: None,
+            };
+            let authentication = app.authenticate_for(auth_data, Some(&mutation)).await?;
+            app.check_can_manage_crate(&authentication, package, Some(EndpointScope::Yank))
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
                     package: package.into(),
                 })?;
-            app.database
-                .unyank_crate_version(package, version)
-                .await
-                .map_err(|source| ApplicationError::UnyankVersion {
+            let result = app.database.unyank_crate_version(package, version).await.map_err(|source| {
+                ApplicationError::UnyankVersion {
                     source,
                     package: package.into(),
                     version: version.into(),
-                })
+                }
+            })?;
+            // restore the version in the index of reverse dependencies
+            let versions =
+                self.service_index
+                    .get_crate_data(package)
+                    .await
+                    .map_err(|source| ApplicationError::GetCrateData {
+                        source,
+                        package: package.into(),
+                    })?;
+            if let Some(metadata) = versions.iter().find(|metadata| metadata.vers == version) {
+                app.database
+                    .set_crate_version_dependencies(package, version, &metadata.deps)
+                    .await
+                    .map_err(|source| ApplicationError::SetCrateDependencies {
+                        source,
+                        package: package.into(),
+                        version: version.into(),
+                    })?;
+            }
+            Ok::<_, ApplicationError>(result)
         })
         .await
         .map_err(ApiError::from)
@@ -821,11 +1702,15 @@ impl Application {
     /// Gets the packages that need documentation generation
     pub async fn get_undocumented_crates(&self, auth_data: &AuthData) -> Result<Vec<DocGenJobSpec>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
-            app.database
+            let authentication = app.authenticate(auth_data).await?;
+            let hidden = app.get_hidden_crates(authentication.reader()).await?;
+            let mut jobs = app
+                .database
                 .get_undocumented_crates(&self.configuration.self_toolchain_host)
                 .await
-                .map_err(|source| ApplicationError::GetUndocumentedCrates { source })
+                .map_err(|source| ApplicationError::GetUndocumentedCrates { source })?;
+            jobs.retain(|job| !hidden.contains(&job.package));
+            Ok::<_, ApplicationError>(jobs)
         })
         .await
         .map_err(ApiError::from)
@@ -833,8 +1718,15 @@ impl Application {
 
     /// Gets the documentation jobs
     pub async fn get_doc_gen_jobs(&self, auth_data: &AuthData) -> Result<Vec<DocGenJob>, ApiError> {
-        let _authentication = self.authenticate(auth_data).await?;
-        self.service_docs_generator.get_jobs().await.map_err(ApiError::from)
+        let hidden = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.get_hidden_crates(authentication.reader()).await
+            })
+            .await?;
+        let mut jobs = self.service_docs_generator.get_jobs().await?;
+        jobs.retain(|job| !hidden.contains(&job.package));
+        Ok(jobs)
     }
 
     /// Gets the log for a documentation generation job
@@ -861,12 +1753,13 @@ impl Application {
         let (user, targets, capabilities) = self
             .db_transaction_write("regen_crate_version_doc", |app| async move {
                 let authentication = app.authenticate(auth_data).await?;
-                let principal_uid = app.check_can_manage_crate(&authentication, package).await.map_err(|source| {
-                    ApplicationError::CanManageCrate {
+                let principal_uid = app
+                    .check_can_manage_crate(&authentication, package, None)
+                    .await
+                    .map_err(|source| ApplicationError::CanManageCrate {
                         source,
                         package: package.into(),
-                    }
-                })?;
+                    })?;
                 let user =
                     app.database
                         .get_user_profile(principal_uid)
@@ -919,11 +1812,15 @@ impl Application {
     /// Gets all the packages that are outdated while also being the latest version
     pub async fn get_crates_outdated_heads(&self, auth_data: &AuthData) -> Result<Vec<CrateVersion>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
-            app.database
+            let authentication = app.authenticate(auth_data).await?;
+            let hidden = app.get_hidden_crates(authentication.reader()).await?;
+            let mut heads = app
+                .database
                 .get_crates_outdated_heads()
                 .await
-                .map_err(ApplicationError::GetOutdatedHeads)
+                .map_err(ApplicationError::GetOutdatedHeads)?;
+            heads.retain(|head| !hidden.contains(&head.package));
+            Ok::<_, ApplicationError>(heads)
         })
         .await
         .map_err(ApiError::from)
@@ -932,7 +1829,8 @@ impl Application {
     /// Gets the download statistics for a crate
     pub async fn get_crate_dl_stats(&self, auth_data: &AuthData, package: &str) -> Result<DownloadStats, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(authentication.reader(), package).await?;
             app.database
                 .get_crate_dl_stats(package)
                 .await
@@ -945,13 +1843,32 @@ impl Application {
         .map_err(ApiError::from)
     }
 
+    /// Gets the versions of other crates that depend on a crate
+    pub async fn get_crate_dependents(&self, auth_data: &AuthData, package: &str) -> Result<CrateDependents, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await?;
+            let hidden = app.get_hidden_crates(reader).await?;
+            let mut dependents =
+                app.database
+                    .get_crate_dependents(package)
+                    .await
+                    .map_err(|source| ApplicationError::GetDependents {
+                        source,
+                        package: package.into(),
+                    })?;
+            dependents.dependents.retain(|dependent| !hidden.contains(&dependent.package));
+            Ok::<_, ApplicationError>(dependents)
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
     /// Gets the list of owners for a package
     pub async fn get_crate_owners(&self, auth_data: &AuthData, package: &str) -> Result<OwnersQueryResult, ApiError> {
-        let public_read = self.configuration.self_public_read;
         self.db_transaction_read(|app| async move {
-            if !public_read {
-                let _authentication = app.authenticate(auth_data).await?;
-            }
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await?;
             app.database
                 .get_crate_owners(package)
                 .await
@@ -972,8 +1889,14 @@ impl Application {
         new_users: &[String],
     ) -> Result<YesNoMsgResult, ApiError> {
         self.db_transaction_write("add_crate_owners", |app| async move {
-            let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            let mutation = RegistryMutation {
+                kind: MutationKind::Owners,
+                name: package,
+                vers: None,
+                cksum: None,
+            };
+            let authentication = app.authenticate_for(auth_data, Some(&mutation)).await?;
+            app.check_can_manage_crate(&authentication, package, Some(EndpointScope::ChangeOwners))
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
@@ -999,8 +1922,14 @@ impl Application {
         old_users: &[String],
     ) -> Result<YesNoResult, ApiError> {
         self.db_transaction_write("remove_crate_owners", |app| async move {
-            let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            let mutation = RegistryMutation {
+                kind: MutationKind::Owners,
+                name: package,
+                vers: None,
+                cksum: None,
+            };
+            let authentication = app.authenticate_for(auth_data, Some(&mutation)).await?;
+            app.check_can_manage_crate(&authentication, package, Some(EndpointScope::ChangeOwners))
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
@@ -1021,7 +1950,8 @@ impl Application {
     /// Gets the targets for a crate
     pub async fn get_crate_targets(&self, auth_data: &AuthData, package: &str) -> Result<Vec<CrateInfoTarget>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(authentication.reader(), package).await?;
             app.database
                 .get_crate_targets(package)
                 .await
@@ -1044,12 +1974,13 @@ impl Application {
         let (user, jobs) = self
             .db_transaction_write("set_crate_targets", |app| async move {
                 let authentication = app.authenticate(auth_data).await?;
-                let principal_uid = app.check_can_manage_crate(&authentication, package).await.map_err(|source| {
-                    ApplicationError::CanManageCrate {
+                let principal_uid = app
+                    .check_can_manage_crate(&authentication, package, None)
+                    .await
+                    .map_err(|source| ApplicationError::CanManageCrate {
                         source,
                         package: package.into(),
-                    }
-                })?;
+                    })?;
                 let user =
                     app.database
                         .get_user_profile(principal_uid)
@@ -1096,7 +2027,8 @@ impl Application {
     /// Gets the required capabilities for a crate
     pub async fn get_crate_required_capabilities(&self, auth_data: &AuthData, package: &str) -> Result<Vec<String>, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(authentication.reader(), package).await?;
             app.database.get_crate_required_capabilities(package).await.map_err(|source| {
                 ApplicationError::GetRequireCapabilities {
                     source,
@@ -1117,12 +2049,13 @@ impl Application {
     ) -> Result<(), ApiError> {
         self.db_transaction_write("set_crate_required_capabilities", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            let _ = app.check_can_manage_crate(&authentication, package).await.map_err(|source| {
-                ApplicationError::CanManageCrate {
+            let _ = app
+                .check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
                     source,
                     package: package.into(),
-                }
-            })?;
+                })?;
             app.database
                 .set_crate_required_capabilities(package, capabilities)
                 .await
@@ -1136,11 +2069,76 @@ impl Application {
         Ok(())
     }
 
+    /// Gets the readers for a crate
+    pub async fn get_crate_readers(&self, auth_data: &AuthData, package: &str) -> Result<Vec<String>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_read_crate(authentication.reader(), package).await?;
+            app.database
+                .get_crate_readers(package)
+                .await
+                .map_err(|source| ApplicationError::GetReaders {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Sets the readers for a crate
+    pub async fn set_crate_readers(&self, auth_data: &AuthData, package: &str, readers: &[String]) -> Result<(), ApiError> {
+        self.db_transaction_write("set_crate_readers", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
+                    source,
+                    package: package.into(),
+                })?;
+            app.database
+                .set_crate_readers(package, readers)
+                .await
+                .map_err(|source| ApplicationError::SetReaders {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Checks that a crate can be read, before serving its index file or its documentation
+    pub async fn check_crate_readable(&self, auth_data: &AuthData, package: &str) -> Result<(), ApiError> {
+        self.db_transaction_read(|app| async move {
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Checks that the whole index can be read, before serving it with the git protocol
+    /// The git index cannot be filtered, so it is refused to readers that cannot see all the crates
+    pub async fn check_git_index_readable(&self, auth_data: &AuthData) -> Result<(), ApiError> {
+        self.db_transaction_read(|app| async move {
+            let reader = app.authenticate_reader(auth_data).await?;
+            let hidden = app.get_hidden_crates(reader).await?;
+            if hidden.is_empty() {
+                Ok(())
+            } else {
+                Err(ApplicationError::GitIndexRestricted)
+            }
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
     /// Sets the deprecation status on a crate
     pub async fn set_crate_deprecation(&self, auth_data: &AuthData, package: &str, deprecated: bool) -> Result<(), ApiError> {
         self.db_transaction_write("set_crate_deprecation", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            app.check_can_manage_crate(&authentication, package, None)
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
@@ -1163,7 +2161,7 @@ impl Application {
     pub async fn set_crate_can_remove(&self, auth_data: &AuthData, package: &str, can_remove: bool) -> Result<(), ApiError> {
         self.db_transaction_write("set_crate_can_remove", |app| async move {
             let authentication = app.authenticate(auth_data).await?;
-            app.check_can_manage_crate(&authentication, package)
+            app.check_can_manage_crate(&authentication, package, None)
                 .await
                 .map_err(|source| ApplicationError::CanManageCrate {
                     source,
@@ -1185,9 +2183,10 @@ impl Application {
     /// Gets the global statistics for the registry
     pub async fn get_crates_stats(&self, auth_data: &AuthData) -> Result<GlobalStats, ApiError> {
         self.db_transaction_read(|app| async move {
-            let _authentication = app.authenticate(auth_data).await?;
+            let authentication = app.authenticate(auth_data).await?;
+            let hidden = app.get_hidden_crates(authentication.reader()).await?;
             app.database
-                .get_crates_stats()
+                .get_crates_stats(&hidden)
                 .await
                 .map_err(|source| ApplicationError::GetCratesStats { source })
         })
@@ -1203,13 +2202,11 @@ impl Application {
         per_page: Option<usize>,
         deprecated: Option<bool>,
     ) -> Result<SearchResults, ApiError> {
-        let public_read = self.configuration.self_public_read;
         self.db_transaction_read(|app| async move {
-            if !public_read {
-                let _authentication = app.authenticate(auth_data).await?;
-            }
+            let reader = app.authenticate_reader(auth_data).await?;
+            let hidden = app.get_hidden_crates(reader).await?;
             app.database
-                .search_crates(query, per_page, deprecated)
+                .search_crates(query, per_page, deprecated, &hidden)
                 .await
                 .map_err(|source| ApplicationError::SearchCrates {
                     source,
@@ -1227,9 +2224,352 @@ impl Application {
         package: &str,
         version: &str,
     ) -> Result<DepsAnalysis, ApiError> {
+        let targets = self.get_crate_version_targets(auth_data, package, version).await?;
+        let mut analysis = self.service_deps_checker.check_crate(package, version, &targets).await?;
+        let (rules, suppressions) = self
+            .db_transaction_read(|app| async move {
+                let rules = app
+                    .database
+                    .get_license_rules()
+                    .await
+                    .map_err(|source| ApplicationError::LicensePolicy { source })?;
+                let suppressions =
+                    app.database
+                        .get_advisory_suppressions(package)
+                        .await
+                        .map_err(|source| ApplicationError::Advisories {
+                            source,
+                            package: package.into(),
+                        })?;
+                Ok::<_, ApplicationError>((rules, suppressions))
+            })
+            .await?;
+        analysis.check_licenses(&LicensePolicy::new(&rules));
+        analysis.apply_suppressions(version, &suppressions);
+        analysis.flag_advisories(
+            self.configuration.deps_cves_min_severity,
+            self.configuration.deps_cves_informational,
+        );
+        Ok(analysis)
+    }
+
+    /// Gets the crate versions of the registry impacted by an advisory, directly or transitively
+    /// The advisory is either an internal advisory against a local crate, or a `RustSec` advisory against a crate on crates.io.
+    pub async fn get_advisory_impact(&self, auth_data: &AuthData, id: &str) -> Result<AdvisoryImpact, ApiError> {
+        let internal = self
+            .db_transaction_read(|app| async move {
+                app.authenticate(auth_data).await?;
+                app.database
+                    .get_internal_advisory(id)
+                    .await
+                    .map_err(|source| ApplicationError::GetAdvisories { source })
+            })
+            .await?;
+        let (advisory, registry) = match internal.filter(|advisory| advisory.withdrawn.is_none()) {
+            Some(advisory) => (advisory.to_simple(), None),
+            None => (
+                self.service_rustsec.get_advisory(id).await?.ok_or_else(error_not_found)?,
+                Some(CRATES_IO_REGISTRY_URI),
+            ),
+        };
+        let advisory = &advisory;
+        let impacted = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                if advisory.is_internal {
+                    app.check_can_read_crate(authentication.reader(), &advisory.package).await?;
+                }
+                let hidden = app.get_hidden_crates(authentication.reader()).await?;
+                let mut impacted = app.database.get_advisory_impact(advisory, registry).await.map_err(|source| {
+                    ApplicationError::GetAdvisoryImpact {
+                        source,
+                        id: advisory.id.clone(),
+                    }
+                })?;
+                impacted.retain(|version| !hidden.contains(&version.package));
+                Ok::<_, ApplicationError>(impacted)
+            })
+            .await?;
+        Ok(AdvisoryImpact {
+            advisory: advisory.clone(),
+            impacted,
+        })
+    }
+
+    /// Gets the internal advisories against a crate, including the withdrawn ones
+    pub async fn get_crate_advisories(&self, auth_data: &AuthData, package: &str) -> Result<Vec<InternalAdvisory>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await?;
+            app.database
+                .get_crate_advisories(package)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Creates an advisory against a crate
+    pub async fn create_crate_advisory(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        data: &InternalAdvisoryData,
+    ) -> Result<InternalAdvisory, ApiError> {
+        self.db_transaction_write("create_crate_advisory", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            let principal_uid = app
+                .check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
+                    source,
+                    package: package.into(),
+                })?;
+            app.database
+                .create_crate_advisory(principal_uid, package, data)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Updates an advisory against a crate
+    pub async fn update_crate_advisory(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        id: &str,
+        data: &InternalAdvisoryData,
+    ) -> Result<InternalAdvisory, ApiError> {
+        self.db_transaction_write("update_crate_advisory", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
+                    source,
+                    package: package.into(),
+                })?;
+            app.database
+                .update_crate_advisory(package, id, data)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Withdraws an advisory against a crate
+    pub async fn withdraw_crate_advisory(&self, auth_data: &AuthData, package: &str, id: &str) -> Result<(), ApiError> {
+        self.db_transaction_write("withdraw_crate_advisory", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
+                    source,
+                    package: package.into(),
+                })?;
+            app.database
+                .withdraw_crate_advisory(package, id)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Gets the suppressions of advisories for a crate, including the expired ones
+    pub async fn get_advisory_suppressions(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+    ) -> Result<Vec<AdvisorySuppression>, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let reader = app.authenticate_reader(auth_data).await?;
+            app.check_can_read_crate(reader, package).await?;
+            app.database
+                .get_advisory_suppressions(package)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Suppresses an advisory against the dependencies of a crate, or one of its versions
+    pub async fn create_advisory_suppression(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        creation: &AdvisorySuppressionCreation,
+    ) -> Result<AdvisorySuppression, ApiError> {
+        self.db_transaction_write("create_advisory_suppression", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            let uid = app
+                .check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
+                    source,
+                    package: package.into(),
+                })?;
+            app.database
+                .create_advisory_suppression(uid, package, creation)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Removes the suppression of an advisory for a crate
+    pub async fn delete_advisory_suppression(&self, auth_data: &AuthData, package: &str, id: i64) -> Result<(), ApiError> {
+        self.db_transaction_write("delete_advisory_suppression", |app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_manage_crate(&authentication, package, None)
+                .await
+                .map_err(|source| ApplicationError::CanManageCrate {
+                    source,
+                    package: package.into(),
+                })?;
+            app.database
+                .delete_advisory_suppression(package, id)
+                .await
+                .map_err(|source| ApplicationError::Advisories {
+                    source,
+                    package: package.into(),
+                })
+        })
+        .await
+        .map_err(ApiError::from)
+    }
+
+    /// Gets all the internal advisories in the OSV format, excluding those against hidden crates
+    pub async fn get_advisories_osv(&self, auth_data: &AuthData) -> Result<Vec<Advisory>, ApiError> {
+        let local_registry = self.configuration.get_self_as_external().index;
+        let advisories = self
+            .db_transaction_read(|app| async move {
+                let reader = app.authenticate_reader(auth_data).await?;
+                let hidden = app.get_hidden_crates(reader).await?;
+                let mut advisories = app
+                    .database
+                    .get_internal_advisories()
+                    .await
+                    .map_err(|source| ApplicationError::GetAdvisories { source })?;
+                advisories.retain(|advisory| !hidden.contains(&advisory.package));
+                Ok::<_, ApplicationError>(advisories)
+            })
+            .await?;
+        Ok(advisories.iter().map(|advisory| advisory.to_osv(&local_registry)).collect())
+    }
+
+    /// Gets an internal advisory in the OSV format
+    pub async fn get_advisory_osv(&self, auth_data: &AuthData, id: &str) -> Result<Advisory, ApiError> {
+        let advisory = self
+            .db_transaction_read(|app| async move {
+                let reader = app.authenticate_reader(auth_data).await?;
+                let advisory = app
+                    .database
+                    .get_internal_advisory(id)
+                    .await
+                    .map_err(|source| ApplicationError::GetAdvisories { source })?;
+                if let Some(advisory) = &advisory {
+                    app.check_can_read_crate(reader, &advisory.package).await?;
+                }
+                Ok::<_, ApplicationError>(advisory)
+            })
+            .await?
+            .ok_or_else(error_not_found)?;
+        Ok(advisory.to_osv(&self.configuration.get_self_as_external().index))
+    }
+
+    /// Gets the status of the database of advisories against the crates on crates.io
+    pub async fn get_advisory_db_status(&self, auth_data: &AuthData) -> Result<AdvisoryDbStatus, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            Ok::<_, ApplicationError>(())
+        })
+        .await?;
+        self.service_rustsec.get_db_status().await
+    }
+
+    /// Replaces the database of advisories against the crates on crates.io with an uploaded `.tar.gz` archive of OSV files
+    pub async fn upload_advisory_db(&self, auth_data: &AuthData, content: &[u8]) -> Result<AdvisoryDbStatus, ApiError> {
+        self.db_transaction_read(|app| async move {
+            let authentication = app.authenticate(auth_data).await?;
+            app.check_can_admin_registry(&authentication).await?;
+            Ok::<_, ApplicationError>(())
+        })
+        .await?;
+        self.service_rustsec.upload_db(content).await
+    }
+
+    /// Gets the SBOM of a local crate, in a specific format
+    pub async fn get_crate_version_sbom(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+        format: SbomFormat,
+    ) -> Result<Vec<u8>, ApiError> {
+        let is_resolved = self
+            .db_transaction_read(|app| async move {
+                let authentication = app.authenticate(auth_data).await?;
+                app.check_can_read_crate(authentication.reader(), package).await?;
+                app.database
+                    .get_crate_deps_resolved(package, version)
+                    .await
+                    .map_err(|source| ApplicationError::CheckCrateExists {
+                        source,
+                        package: package.into(),
+                        version: version.into(),
+                    })
+            })
+            .await?;
+        // the stored documents are only up to date when the dependencies are resolved
+        let content = if is_resolved {
+            self.service_storage.download_crate_sbom(package, version, format).await?
+        } else {
+            None
+        };
+        content.ok_or_else(|| {
+            specialize(
+                error_not_found(),
+                format!("the SBOM for {package} {version} is not generated yet, retry later"),
+            )
+        })
+    }
+
+    /// Gets the targets for the resolution of the dependencies of a local crate, when it can be read
+    async fn get_crate_version_targets(
+        &self,
+        auth_data: &AuthData,
+        package: &str,
+        version: &str,
+    ) -> Result<Vec<String>, ApiError> {
         let targets = self
             .db_transaction_read(|app| async move {
-                let _authentication = app.authenticate(auth_data).await?;
+                let authentication = app.authenticate(auth_data).await?;
+                app.check_can_read_crate(authentication.reader(), package).await?;
                 app.database.check_crate_exists(package, version).await.map_err(|source| {
                     ApplicationError::CheckCrateExists {
                         source,
@@ -1246,8 +2586,7 @@ impl Application {
                     })
             })
             .await?;
-        let targets = targets.into_iter().map(|info| info.target).collect::<Vec<_>>();
-        self.service_deps_checker.check_crate(package, version, &targets).await
+        Ok(targets.into_iter().map(|info| info.target).collect())
     }
 }
 
@@ -1271,6 +2610,9 @@ pub enum AuthenticationError {
     #[error("writing is forbidden for this authentication")]
     WriteIsForbidden,
 
+    #[error("the operation is outside the scopes of this token")]
+    OutOfScope,
+
     #[error("failed to check global token")]
     GlobalToken(#[source] sqlx::Error),
 
@@ -1285,19 +2627,33 @@ pub enum AuthenticationError {
 
     #[error("expected a user to be authenticated")]
     NoUserAuthenticated,
+
+    #[error("invalid asymmetric token")]
+    AsymmetricToken(#[from] PasetoError),
+
+    #[error("invalid or mismatching `{claim}` claim in asymmetric token")]
+    AsymmetricTokenClaim { claim: &'static str },
+
+    #[error("failed to check user key")]
+    UserKey(#[source] sqlx::Error),
 }
 
 impl AsStatusCode for AuthenticationError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::Unauthorized | Self::CookieMissing => StatusCode::UNAUTHORIZED,
+            Self::Unauthorized | Self::CookieMissing | Self::AsymmetricToken(_) | Self::AsymmetricTokenClaim { .. } => {
+                StatusCode::UNAUTHORIZED
+            }
             Self::CookieDeserialization(_)
             | Self::GlobalToken(_)
             | Self::UserToken(_)
+            | Self::UserKey(_)
             | Self::CheckUser(_)
             | Self::CheckRoles(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::NoUserAuthenticated => StatusCode::BAD_REQUEST,
-            Self::Forbidden | Self::AdministrationIsForbidden | Self::WriteIsForbidden => StatusCode::FORBIDDEN,
+            Self::Forbidden | Self::AdministrationIsForbidden | Self::WriteIsForbidden | Self::OutOfScope => {
+                StatusCode::FORBIDDEN
+            }
         }
     }
 }
@@ -1317,13 +2673,16 @@ enum ApplicationError {
     #[error(transparent)]
     CanAdminRegistry(#[from] CanAdminRegistryError),
 
-    #[error("failed to publish crate '{package}' by '{user_login}'")]
+    #[error("failed to publish crate '{package}' by '{user_login}': {source}")]
     PublishVersion {
         source: CratesError,
         package: SmolStr,
         user_login: SmolStr,
     },
 
+    #[error("failed to check whether crate '{package}' is registered")]
+    GetCrateExists { source: sqlx::Error, package: SmolStr },
+
     #[error("failed to get uid from request")]
     ExtractUid {
         #[source]
@@ -1365,6 +2724,18 @@ enum ApplicationError {
         name: SmolStr,
     },
 
+    #[error(transparent)]
+    InvalidTokenScope(#[from] InvalidTokenScope),
+
+    #[error(transparent)]
+    InvalidTokenExpiry(#[from] InvalidTokenExpiry),
+
+    #[error("failed to rotate token '{token_id}'")]
+    RotateToken { source: sqlx::Error, token_id: i64 },
+
+    #[error("token '{token_id}' not found")]
+    TokenNotFound { token_id: i64 },
+
     #[error("failed to revoke token '{token_id}'")]
     RevokeToken {
         #[source]
@@ -1372,6 +2743,70 @@ enum ApplicationError {
         token_id: i64,
     },
 
+    // teams
+    #[error("failed to get teams")]
+    GetTeams { source: TeamError },
+
+    #[error("failed to manage team '{team}'")]
+    Team { source: TeamError, team: SmolStr },
+
+    // name reservations
+    #[error("failed to manage the reservations of crate names")]
+    NameReservations { source: ReservationError },
+
+    // crates held for review
+    #[error("failed to get the crates held for review")]
+    GetCrateReviews {
+        #[source]
+        source: sqlx::Error,
+    },
+
+    #[error("failed to approve package '{package}'")]
+    ApproveCrateReview {
+        #[source]
+        source: CratesError,
+        package: SmolStr,
+    },
+
+    // publish policy
+    #[error("failed to manage the publish policy")]
+    PublishPolicy { source: PolicyError },
+
+    // license policy
+    #[error("failed to manage the license policy")]
+    LicensePolicy { source: PolicyError },
+
+    // device authorization
+    #[error("failed to process device authorization request")]
+    DeviceAuthorization(#[source] sqlx::Error),
+
+    #[error("no pending device authorization request for code '{user_code}'")]
+    DeviceAuthorizationNotFound { user_code: String },
+
+    #[error("too many pending device authorization requests, retry later")]
+    TooManyDeviceAuthorizations,
+
+    // keys
+    #[error("failed to get keys")]
+    GetKeys {
+        #[source]
+        source: sqlx::Error,
+    },
+
+    #[error("failed to add key '{name}'")]
+    AddKey {
+        #[source]
+        source: AddKeyError,
+        name: SmolStr,
+    },
+
+    #[error("failed to remove key '{key_id}'")]
+    RemoveKey {
+        #[source]
+        source: sqlx::Error,
+        key_id: i64,
+    },
+
     #[error("failed to get global tokens")]
     GetGlobalTokens { source: sqlx::Error },
 
@@ -1431,6 +2866,25 @@ enum ApplicationError {
     #[error("failed to get dl stats for crate '{package}'")]
     GetDlStats { source: CratesError, package: SmolStr },
 
+    #[error("failed to get dependents for crate '{package}'")]
+    GetDependents { source: CratesError, package: SmolStr },
+
+    #[error("failed to index the dependencies of '{package} {version}'")]
+    SetCrateDependencies {
+        source: sqlx::Error,
+        package: SmolStr,
+        version: SmolStr,
+    },
+
+    #[error("failed to get the impact of advisory '{id}'")]
+    GetAdvisoryImpact { source: CratesError, id: String },
+
+    #[error("failed to manage the advisories of crate '{package}'")]
+    Advisories { source: AdvisoryError, package: SmolStr },
+
+    #[error("failed to get the internal advisories")]
+    GetAdvisories { source: AdvisoryError },
+
     #[error("failed to get owners for crate '{package}'")]
     GetOwners { source: CratesError, package: SmolStr },
 
@@ -1488,6 +2942,36 @@ enum ApplicationError {
         package: SmolStr,
     },
 
+    #[error("cannot read crate '{package}'")]
+    CanReadCrate {
+        #[source]
+        source: CratesError,
+        package: SmolStr,
+    },
+
+    #[error("failed to get the hidden crates")]
+    GetHiddenCrates {
+        #[source]
+        source: CratesError,
+    },
+
+    #[error("the git index is not available because some crates are restricted, use the sparse index instead")]
+    GitIndexRestricted,
+
+    #[error("failed to get 'readers' of package '{package}'")]
+    GetReaders {
+        #[source]
+        source: CratesError,
+        package: SmolStr,
+    },
+
+    #[error("failed to set 'readers' to package '{package}'")]
+    SetReaders {
+        #[source]
+        source: CratesError,
+        package: SmolStr,
+    },
+
     #[error("failed to set 'can_remove': {can_remove} to package '{package}'")]
     SetCanRemove {
         #[source]
@@ -1530,34 +3014,69 @@ impl AsStatusCode for ApplicationError {
             | Self::GetCrateTargets { source, .. }
             | Self::GetOutdatedHeads(source)
             | Self::GetDlStats { source, .. }
+            | Self::GetDependents { source, .. }
+            | Self::GetAdvisoryImpact { source, .. }
             | Self::GetOwners { source, .. }
             | Self::AddOwners { source, .. }
             | Self::RemoveOwners { source, .. }
             | Self::SetCrateTarget { source, .. }
             | Self::GetRequireCapabilities { source, .. }
-            | Self::SetRequiredCapabilities { source, .. } => source.status_code(),
+            | Self::SetRequiredCapabilities { source, .. }
+            | Self::CanReadCrate { source, .. }
+            | Self::GetHiddenCrates { source }
+            | Self::GetReaders { source, .. }
+            | Self::SetReaders { source, .. }
+            | Self::ApproveCrateReview { source, .. } => source.status_code(),
 
             Self::CreateGlobalToken { source, .. } => source.status_code(),
+            Self::GetTeams { source } | Self::Team { source, .. } => source.status_code(),
+            Self::NameReservations { source } => source.status_code(),
+            Self::PublishPolicy { source } | Self::LicensePolicy { source } => source.status_code(),
+            Self::Advisories { source, .. } | Self::GetAdvisories { source } => source.status_code(),
+            Self::AddKey { source, .. } => source.status_code(),
             Self::GetCrateData { source, .. } | Self::RemoveVersionFromIndex { source, .. } => source.status_code(),
 
             Self::UnknownTarget { .. } => StatusCode::BAD_REQUEST,
+            Self::InvalidTokenScope(source) => source.status_code(),
+            Self::InvalidTokenExpiry(source) => source.status_code(),
+            Self::TokenNotFound { .. } | Self::DeviceAuthorizationNotFound { .. } => StatusCode::NOT_FOUND,
+            Self::TooManyDeviceAuthorizations => StatusCode::TOO_MANY_REQUESTS,
+            Self::GitIndexRestricted => StatusCode::FORBIDDEN,
             Self::GetUsers { .. }
             | Self::ReactivateUser { .. }
             | Self::GetTokens { .. }
             | Self::GetGlobalTokens { .. }
             | Self::RevokeGlobalToken { .. }
             | Self::CreateToken { .. }
+            | Self::GetCrateExists { .. }
+            | Self::RotateToken { .. }
             | Self::RevokeToken { .. }
+            | Self::DeviceAuthorization(_)
+            | Self::GetKeys { .. }
+            | Self::RemoveKey { .. }
             | Self::GetCratesStats { .. }
             | Self::GetUndocumentedCrates { .. }
             | Self::SearchCrates { .. }
             | Self::SetCrateDocumentation { .. }
+            | Self::SetCrateDependencies { .. }
             | Self::SetCanRemove { .. }
+            | Self::GetCrateReviews { .. }
             | Self::SetDeprecation { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+#[derive(Debug, Error)]
+#[error("invalid validity for token: {days} day(s)")]
+struct InvalidTokenExpiry {
+    days: i64,
+}
+impl AsStatusCode for InvalidTokenExpiry {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("the current user can't administrate registry")]
 struct CanAdminRegistryError(#[from] AuthenticationError);
@@ -1595,7 +3114,31 @@ pub(crate) struct ApplicationWithTransaction<'a> {
 impl ApplicationWithTransaction<'_> {
     /// Attempts the authentication of a user
     async fn authenticate(&self, auth_data: &AuthData) -> Result<Authentication, AuthenticationError> {
-        if let Some(token) = &auth_data.token {
+        self.authenticate_for(auth_data, None).await
+    }
+
+    /// Attempts the authentication of a user for a request that may perform a mutation
+    async fn authenticate_for(
+        &self,
+        auth_data: &AuthData,
+        mutation: Option<&RegistryMutation<'_>>,
+    ) -> Result<Authentication, AuthenticationError> {
+        if let Some(token) = &auth_data.asymmetric_token {
+            self.database
+                .check_asymmetric_token(
+                    token,
+                    &self.application.configuration.web_public_uri,
+                    mutation,
+                    &|usage| async move {
+                        self.application
+                            .app_events_sender
+                            .send(AppEvent::TokenUse(usage))
+                            .await
+                            .unwrap();
+                    },
+                )
+                .await
+        } else if let Some(token) = &auth_data.token {
             self.authenticate_token(token).await
         } else {
             let authentication = auth_data
@@ -1629,17 +3172,75 @@ impl ApplicationWithTransaction<'_> {
         Ok(user)
     }
 
+    /// Attempts the authentication of a reader of crates
+    /// When the registry allows public reads, a failed authentication falls back to an anonymous reader.
+    async fn authenticate_reader(&self, auth_data: &AuthData) -> Result<CrateReader, AuthenticationError> {
+        match self.authenticate(auth_data).await {
+            Ok(authentication) => Ok(authentication.reader()),
+            Err(_) if self.application.configuration.self_public_read => Ok(CrateReader::Anonymous),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Checks that the given reader can see a crate
+    async fn check_can_read_crate(&self, reader: CrateReader, package: &str) -> Result<(), ApplicationError> {
+        self.database
+            .check_can_read_crate(reader, package)
+            .await
+            .map_err(|source| ApplicationError::CanReadCrate {
+                source,
+                package: package.into(),
+            })
+    }
+
+    /// Gets the crates that cannot be seen by the given reader
+    async fn get_hidden_crates(&self, reader: CrateReader) -> Result<HashSet<String>, ApplicationError> {
+        self.database
+            .get_hidden_crates(reader)
+            .await
+            .map_err(|source| ApplicationError::GetHiddenCrates { source })
+    }
+
     /// Checks that the given authentication can perform admin tasks
     async fn check_can_admin_registry(&self, authentication: &Authentication) -> Result<i64, CanAdminRegistryError> {
         authentication.check_can_admin()?;
+        authentication.check_unscoped()?;
         let principal_uid = authentication.uid()?;
         self.database.check_is_admin(principal_uid).await?;
         Ok(principal_uid)
     }
 
+    /// Hides the members of the teams the authenticated user is not part of
+    /// Administrators can see the members of all teams
+    async fn redact_team_members(
+        &self,
+        authentication: &Authentication,
+        teams: &mut [Team],
+    ) -> Result<(), AuthenticationError> {
+        let uid = authentication.uid().ok();
+        if let Some(uid) = uid
+            && self.database.get_is_admin(uid).await?
+        {
+            return Ok(());
+        }
+        for team in teams {
+            if !team.members.iter().any(|member| Some(member.uid) == uid) {
+                team.members.clear();
+            }
+        }
+        Ok(())
+    }
+
     /// Checks that the given authentication can manage a given crate
-    async fn check_can_manage_crate(&self, authentication: &Authentication, package: &str) -> Result<i64, CanManageCrateError> {
+    /// The endpoint scope is the one covering the operation, if any
+    async fn check_can_manage_crate(
+        &self,
+        authentication: &Authentication,
+        package: &str,
+        endpoint: Option<EndpointScope>,
+    ) -> Result<i64, CanManageCrateError> {
         authentication.check_can_write()?;
+        authentication.scopes.check(endpoint, package)?;
         let principal_uid = authentication.uid()?;
         self.database.check_is_crate_manager(principal_uid, package).await?;
         Ok(principal_uid)
@@ -37,6 +37,8 @@ mod tests;
 
 /// The name of this program
 pub const CRATE_NAME: &str = env!("CARGO_PKG_NAME");
+/// The version of this program
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
 /// The commit that was used to build the application
 pub const GIT_HASH: &str = env!("GIT_HASH");
 /// The git tag that was used to build the application
@@ -85,6 +87,16 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
         .route("/me", get(routes::webapp_me))
         // serve the documentation
         .route("/docs/{*path}", get(routes::get_docs_resource))
+        // pull-through caching proxy to crates.io
+        .nest(
+            "/crates-io",
+            Router::new()
+                .route("/index/{*path}", get(routes::proxy_crates_io_index_serve))
+                .route(
+                    "/api/v1/crates/{package}/{version}/download",
+                    get(routes::proxy_crates_io_download_crate),
+                ),
+        )
         // API
         .nest(
             "/api/v1",
@@ -93,16 +105,39 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                 .route("/registry-information", get(routes::api_v1_get_registry_information))
                 .nest(
                     "/me",
-                    Router::new().route("/", get(routes::api_v1_get_current_user)).nest(
-                        "/tokens",
-                        Router::new()
-                            .route("/", get(routes::api_v1_get_user_tokens))
-                            .route("/", put(routes::api_v1_create_user_token))
-                            .route("/{token_id}", delete(routes::api_v1_revoke_user_token)),
-                    ),
+                    Router::new()
+                        .route("/", get(routes::api_v1_get_current_user))
+                        .nest(
+                            "/tokens",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_user_tokens))
+                                .route("/", put(routes::api_v1_create_user_token))
+                                .route("/{token_id}/rotate", post(routes::api_v1_rotate_user_token))
+                                .route("/{token_id}", delete(routes::api_v1_revoke_user_token)),
+                        )
+                        .nest(
+                            "/keys",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_user_keys))
+                                .route("/", put(routes::api_v1_add_user_key))
+                                .route("/{key_id}", delete(routes::api_v1_remove_user_key)),
+                        )
+                        .route("/device", post(routes::api_v1_decide_device_authorization)),
                 )
                 .route("/oauth/code", post(routes::api_v1_login_with_oauth_code))
+                .route("/device/code", post(routes::api_v1_request_device_code))
+                .route("/device/token", post(routes::api_v1_poll_device_token))
                 .route("/logout", post(routes::api_v1_logout))
+                .nest(
+                    "/teams",
+                    Router::new()
+                        .route("/", get(routes::api_v1_get_teams))
+                        .route("/", put(routes::api_v1_create_team))
+                        .route("/{team}", get(routes::api_v1_get_team))
+                        .route("/{team}", delete(routes::api_v1_delete_team))
+                        .route("/{team}/members", put(routes::api_v1_set_team_member))
+                        .route("/{team}/members/{member}", delete(routes::api_v1_remove_team_member)),
+                )
                 .nest(
                     "/admin",
                     Router::new()
@@ -120,8 +155,34 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                             Router::new()
                                 .route("/", get(routes::api_v1_get_global_tokens))
                                 .route("/", put(routes::api_v1_create_global_token))
+                                .route("/{token_id}/rotate", post(routes::api_v1_rotate_global_token))
                                 .route("/{token_id}", delete(routes::api_v1_revoke_global_token)),
                         )
+                        .nest(
+                            "/reservations",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_name_reservations))
+                                .route("/", put(routes::api_v1_create_name_reservation))
+                                .route("/{reservation_id}", delete(routes::api_v1_delete_name_reservation)),
+                        )
+                        .route("/reviews", get(routes::api_v1_get_crate_reviews))
+                        .route("/reviews/{package}/approve", post(routes::api_v1_approve_crate_review))
+                        .nest(
+                            "/policy",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_publish_rules))
+                                .route("/", put(routes::api_v1_create_publish_rule))
+                                .route("/{rule_id}", delete(routes::api_v1_delete_publish_rule)),
+                        )
+                        .nest(
+                            "/licenses",
+                            Router::new()
+                                .route("/", get(routes::api_v1_get_license_rules))
+                                .route("/", put(routes::api_v1_create_license_rule))
+                                .route("/{rule_id}", delete(routes::api_v1_delete_license_rule)),
+                        )
+                        .route("/advisories/db", get(routes::api_v1_get_advisory_db_status))
+                        .route("/advisories/db", put(routes::api_v1_upload_advisory_db))
                         .route("/jobs/docgen", get(routes::api_v1_get_doc_gen_jobs))
                         .route("/jobs/docgen/updates", get(routes::api_v1_get_doc_gen_job_updates))
                         .route("/jobs/docgen/{job_id}/log", get(routes::api_v1_get_doc_gen_job_log))
@@ -129,6 +190,13 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                         .route("/workers/updates", get(routes::api_v1_get_workers_updates))
                         .route("/workers/connect", get(routes::api_v1_worker_connect)),
                 )
+                .nest(
+                    "/advisories",
+                    Router::new()
+                        .route("/osv", get(routes::api_v1_get_advisories_osv))
+                        .route("/{id}/osv", get(routes::api_v1_get_advisory_osv))
+                        .route("/{id}/impact", get(routes::api_v1_get_advisory_impact)),
+                )
                 .nest(
                     "/crates",
                     Router::new()
@@ -146,7 +214,19 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                         .route("/{package}/{version}/unyank", put(routes::api_v1_cargo_unyank))
                         .route("/{package}/{version}/docsregen", post(routes::api_v1_regen_crate_version_doc))
                         .route("/{package}/{version}/checkdeps", get(routes::api_v1_check_crate_version))
+                        .route("/{package}/{version}/sbom", get(routes::api_v1_get_crate_sbom))
                         .route("/{package}/dlstats", get(routes::api_v1_get_crate_dl_stats))
+                        .route("/{package}/dependents", get(routes::api_v1_get_crate_dependents))
+                        .route("/{package}/advisories", get(routes::api_v1_get_crate_advisories))
+                        .route("/{package}/advisories", put(routes::api_v1_create_crate_advisory))
+                        .route("/{package}/advisories/{id}", patch(routes::api_v1_update_crate_advisory))
+                        .route("/{package}/advisories/{id}", delete(routes::api_v1_withdraw_crate_advisory))
+                        .route("/{package}/suppressions", get(routes::api_v1_get_advisory_suppressions))
+                        .route("/{package}/suppressions", put(routes::api_v1_create_advisory_suppression))
+                        .route(
+                            "/{package}/suppressions/{suppression_id}",
+                            delete(routes::api_v1_delete_advisory_suppression),
+                        )
                         .route("/{package}/owners", get(routes::api_v1_cargo_get_crate_owners))
                         .route("/{package}/owners", put(routes::api_v1_cargo_add_crate_owners))
                         .route("/{package}/owners", delete(routes::api_v1_cargo_remove_crate_owners))
@@ -157,6 +237,8 @@ async fn main_serve_app(application: Arc<Application>, cookie_key: Key) -> Resul
                             "/{package}/capabilities",
                             patch(routes::api_v1_set_crate_required_capabilities),
                         )
+                        .route("/{package}/readers", get(routes::api_v1_get_crate_readers))
+                        .route("/{package}/readers", patch(routes::api_v1_set_crate_readers))
                         .route("/{package}/deprecated", patch(routes::api_v1_set_crate_deprecation))
                         .route("/{package}/canremove", patch(routes::api_v1_set_crate_can_remove)),
                 ),
@@ -0,0 +1,161 @@
+CREATE TABLE RegistryUserKey (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user INTEGER NOT NULL REFERENCES RegistryUser(id),
+    name TEXT NOT NULL,
+    keyId TEXT NOT NULL UNIQUE,
+    publicKey TEXT NOT NULL,
+    lastUsed TIMESTAMP NOT NULL,
+    canWrite BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexRegistryUserKey ON RegistryUserKey (user);
+
+ALTER TABLE RegistryUserToken ADD COLUMN crateScopes TEXT NOT NULL DEFAULT '';
+ALTER TABLE RegistryUserToken ADD COLUMN endpointScopes TEXT NOT NULL DEFAULT '';
+ALTER TABLE RegistryUserToken ADD COLUMN expiresAt TIMESTAMP;
+ALTER TABLE RegistryUserToken ADD COLUMN validity INTEGER;
+ALTER TABLE RegistryUserToken ADD COLUMN expiryNotified BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE RegistryUserToken ADD COLUMN previousToken TEXT;
+ALTER TABLE RegistryUserToken ADD COLUMN previousExpiresAt TIMESTAMP;
+ALTER TABLE RegistryGlobalToken ADD COLUMN expiresAt TIMESTAMP;
+ALTER TABLE RegistryGlobalToken ADD COLUMN validity INTEGER;
+ALTER TABLE RegistryGlobalToken ADD COLUMN expiryNotified BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE RegistryGlobalToken ADD COLUMN previousToken TEXT;
+ALTER TABLE RegistryGlobalToken ADD COLUMN previousExpiresAt TIMESTAMP;
+ALTER TABLE RegistryUserToken ADD COLUMN publicId TEXT;
+CREATE UNIQUE INDEX IndexRegistryUserTokenPublicId ON RegistryUserToken (publicId);
+
+CREATE TABLE DeviceAuthorization (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    deviceCode TEXT NOT NULL UNIQUE,
+    userCode TEXT NOT NULL UNIQUE,
+    expiresAt TIMESTAMP NOT NULL,
+    interval INTEGER NOT NULL,
+    lastPolled TIMESTAMP,
+    isDenied BOOLEAN NOT NULL DEFAULT FALSE,
+    user INTEGER REFERENCES RegistryUser(id),
+    tokenName TEXT,
+    canWrite BOOLEAN NOT NULL DEFAULT FALSE,
+    canAdmin BOOLEAN NOT NULL DEFAULT FALSE,
+    crateScopes TEXT NOT NULL DEFAULT '',
+    endpointScopes TEXT NOT NULL DEFAULT '',
+    tokenValidity INTEGER
+);
+
+CREATE TABLE Team (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE TeamMember (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    team INTEGER NOT NULL REFERENCES Team(id),
+    user INTEGER NOT NULL REFERENCES RegistryUser(id),
+    isMaintainer BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexTeamMember ON TeamMember (team);
+CREATE INDEX IndexTeamMemberByUser ON TeamMember (user);
+
+CREATE TABLE PackageTeamOwner (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    team INTEGER NOT NULL REFERENCES Team(id)
+);
+
+CREATE INDEX IndexPackageTeamOwner ON PackageTeamOwner (package);
+
+ALTER TABLE Package ADD COLUMN readers TEXT NOT NULL DEFAULT '';
+ALTER TABLE Package ADD COLUMN reviewReason TEXT;
+
+CREATE TABLE PackageNameReservation (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    pattern TEXT NOT NULL UNIQUE,
+    user INTEGER REFERENCES RegistryUser(id),
+    team INTEGER REFERENCES Team(id)
+);
+
+CREATE TABLE PublishRule (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    parameter TEXT NOT NULL,
+    isBlocking BOOLEAN NOT NULL
+);
+
+ALTER TABLE PackageVersion ADD COLUMN vcsSha1 TEXT;
+ALTER TABLE PackageVersion ADD COLUMN vcsDirty BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE PackageVersion ADD COLUMN vcsPath TEXT;
+
+CREATE TABLE LicenseRule (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    license TEXT NOT NULL UNIQUE,
+    isAllowed BOOLEAN NOT NULL
+);
+
+ALTER TABLE PackageVersion ADD COLUMN depsHasLicenseViolations BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE PackageDependency (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    dependency TEXT NOT NULL,
+    versionReq TEXT NOT NULL,
+    kind TEXT NOT NULL
+);
+
+CREATE INDEX IndexPackageDependency ON PackageDependency(dependency);
+CREATE INDEX IndexPackageDependencyByVersion ON PackageDependency(package, version);
+
+ALTER TABLE PackageVersion ADD COLUMN depsIndexed BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE PackageVersionResolution (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    registry TEXT,
+    dependency TEXT NOT NULL,
+    resolved TEXT NOT NULL,
+    isDirect BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexPackageVersionResolution ON PackageVersionResolution(dependency);
+CREATE INDEX IndexPackageVersionResolutionByVersion ON PackageVersionResolution(package, version);
+
+ALTER TABLE PackageVersion ADD COLUMN depsResolved BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE PackageAdvisory (
+    id TEXT NOT NULL PRIMARY KEY,
+    package TEXT NOT NULL REFERENCES Package(name),
+    summary TEXT NOT NULL,
+    details TEXT NOT NULL,
+    severity TEXT,
+    author INTEGER REFERENCES RegistryUser(id),
+    published TIMESTAMP NOT NULL,
+    modified TIMESTAMP NOT NULL,
+    withdrawn TIMESTAMP
+);
+
+CREATE INDEX IndexPackageAdvisory ON PackageAdvisory(package);
+
+CREATE TABLE PackageAdvisoryRange (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    advisory TEXT NOT NULL REFERENCES PackageAdvisory(id),
+    introduced TEXT NOT NULL,
+    fixed TEXT,
+    lastAffected TEXT
+);
+
+CREATE INDEX IndexPackageAdvisoryRange ON PackageAdvisoryRange(advisory);
+
+CREATE TABLE PackageAdvisorySuppression (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT,
+    advisory TEXT NOT NULL,
+    justification TEXT NOT NULL,
+    author INTEGER REFERENCES RegistryUser(id),
+    created TIMESTAMP NOT NULL,
+    expires TIMESTAMP
+);
+
+CREATE INDEX IndexPackageAdvisorySuppression ON PackageAdvisorySuppression(package);
@@ -0,0 +1,401 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data model for the security advisories authored in this registry against local crates
+//! and for the database of advisories against the crates on crates.io
+
+use std::fmt::{Display, Formatter};
+
+use chrono::{NaiveDateTime, SecondsFormat};
+use serde_derive::{Deserialize, Serialize};
+
+use super::osv::{
+    Advisory, AdvisoryAffected, AdvisoryAffectedPackage, AdvisoryAffectedRange, AdvisoryAffectedRangeEvent, AdvisorySeverity,
+    SimpleAdvisory, SimpleAdvisoryRange,
+};
+
+/// The prefix for the identifiers of internal advisories, as in `CRATERY-2024-0001`
+pub const INTERNAL_ADVISORY_PREFIX: &str = "CRATERY";
+
+/// The version of the OSV schema for the produced advisories
+const OSV_SCHEMA_VERSION: &str = "1.6.0";
+
+/// A security advisory authored in this registry against a local crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InternalAdvisory {
+    /// The unique identifier, e.g. `CRATERY-2024-0001`
+    pub id: String,
+    /// The affected crate
+    pub package: String,
+    /// The one-line summary
+    pub summary: String,
+    /// The full description, in markdown
+    pub details: String,
+    /// The CVSS v3 or v4 vector for the severity, if any
+    pub severity: Option<String>,
+    /// The affected ranges, the fixed version of a range being the patched version
+    pub ranges: Vec<SimpleAdvisoryRange>,
+    /// The email of the author, if the user still exists
+    pub author: Option<String>,
+    /// Datetime on initial publication
+    pub published: NaiveDateTime,
+    /// Datetime on last modification
+    pub modified: NaiveDateTime,
+    /// Datetime on withdrawal, if any
+    pub withdrawn: Option<NaiveDateTime>,
+}
+
+impl InternalAdvisory {
+    /// Gets the simplified advisory to be used in services
+    #[must_use]
+    pub fn to_simple(&self) -> SimpleAdvisory {
+        SimpleAdvisory {
+            package: self.package.clone(),
+            id: self.id.clone(),
+            published: osv_timestamp(&self.published),
+            modified: osv_timestamp(&self.modified),
+            summary: self.summary.clone(),
+            ranges: self.ranges.clone(),
+            versions: Vec::new(),
+            is_internal: true,
+            aliases: Vec::new(),
+            severity: self.severity.clone(),
+            severity_level: self
+                .severity
+                .as_deref()
+                .and_then(cvss_score)
+                .map(AdvisorySeverityLevel::from_score),
+            informational: None,
+        }
+    }
+
+    /// Gets the OSV representation of this advisory
+    /// The local crates are identified by their package URL with the URI of this registry
+    #[must_use]
+    pub fn to_osv(&self, local_registry: &str) -> Advisory {
+        let event =
+            |introduced: Option<String>, fixed: Option<String>, last_affected: Option<String>| AdvisoryAffectedRangeEvent {
+                introduced,
+                fixed,
+                last_affected,
+                limit: None,
+            };
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|range| {
+                let mut events = vec![event(Some(range.introduced.to_string()), None, None)];
+                if let Some(fixed) = &range.fixed {
+                    events.push(event(None, Some(fixed.to_string()), None));
+                } else if let Some(last_affected) = &range.last_affected {
+                    events.push(event(None, None, Some(last_affected.to_string())));
+                }
+                AdvisoryAffectedRange {
+                    type_value: String::from("SEMVER"),
+                    repo: None,
+                    events,
+                }
+            })
+            .collect();
+        Advisory {
+            schema_version: Some(String::from(OSV_SCHEMA_VERSION)),
+            id: self.id.clone(),
+            modified: osv_timestamp(&self.modified),
+            published: osv_timestamp(&self.published),
+            withdrawn: self.withdrawn.as_ref().map(osv_timestamp).unwrap_or_default(),
+            aliases: Vec::new(),
+            related: Vec::new(),
+            summary: self.summary.clone(),
+            detail: self.details.clone(),
+            severity: self
+                .severity
+                .iter()
+                .filter_map(|vector| {
+                    cvss_type(vector).map(|type_value| AdvisorySeverity {
+                        type_value: type_value.to_string(),
+                        score: vector.clone(),
+                    })
+                })
+                .collect(),
+            affected: vec![AdvisoryAffected {
+                package: AdvisoryAffectedPackage {
+                    ecosystem: String::from("crates.io"),
+                    name: self.package.clone(),
+                    purl: format!(
+                        "pkg:cargo/{}?repository_url={}",
+                        self.package,
+                        urlencoding::encode(local_registry)
+                    ),
+                },
+                severity: Vec::new(),
+                ranges,
+                versions: Vec::new(),
+                ecosystem_specific: None,
+                database_specific: None,
+            }],
+            references: Vec::new(),
+            credits: Vec::new(),
+            database_specific: None,
+        }
+    }
+}
+
+/// The data to create or update an internal advisory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InternalAdvisoryData {
+    /// The one-line summary
+    pub summary: String,
+    /// The full description, in markdown
+    #[serde(default)]
+    pub details: String,
+    /// The CVSS v3 or v4 vector for the severity, if any
+    #[serde(default)]
+    pub severity: Option<String>,
+    /// The affected ranges, the fixed version of a range being the patched version
+    pub ranges: Vec<SimpleAdvisoryRange>,
+}
+
+/// The acknowledgement by the owners of a crate that an advisory against its dependencies does not apply
+///
+/// Suppressed advisories are still reported, but do not flag the crate as vulnerable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvisorySuppression {
+    /// The unique identifier
+    pub id: i64,
+    /// The crate for which the advisory is suppressed
+    pub package: String,
+    /// The version of the crate for which the advisory is suppressed, or `None` for all the versions
+    pub version: Option<String>,
+    /// The identifier of the suppressed advisory
+    pub advisory: String,
+    /// The reason why the advisory does not apply
+    pub justification: String,
+    /// The email of the author, if the user still exists
+    pub author: Option<String>,
+    /// Datetime on creation
+    pub created: NaiveDateTime,
+    /// Datetime on expiration, if any
+    pub expires: Option<NaiveDateTime>,
+}
+
+impl AdvisorySuppression {
+    /// Gets whether this suppression applies to an advisory for a version of the crate
+    #[must_use]
+    pub fn applies_to(&self, version: &str, advisory: &str, now: NaiveDateTime) -> bool {
+        self.advisory == advisory
+            && self.version.as_deref().is_none_or(|v| v == version)
+            && self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+/// The request to suppress an advisory for a crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvisorySuppressionCreation {
+    /// The version of the crate for which the advisory is suppressed, or `None` for all the versions
+    #[serde(default)]
+    pub version: Option<String>,
+    /// The identifier of the suppressed advisory
+    pub advisory: String,
+    /// The reason why the advisory does not apply
+    pub justification: String,
+    /// Datetime on expiration, if any
+    #[serde(default)]
+    pub expires: Option<NaiveDateTime>,
+}
+
+/// The status of the database of advisories against the crates on crates.io
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdvisoryDbStatus {
+    /// The description of the source of the advisories
+    pub source: String,
+    /// Whether the advisories are uploaded by the administrators
+    #[serde(rename = "isUpload")]
+    pub is_upload: bool,
+    /// The number of loaded advisories
+    pub advisories: usize,
+    /// The number of files that could not be read as advisories in the last update
+    #[serde(rename = "invalidFiles")]
+    pub invalid_files: usize,
+    /// Datetime of the last attempt to update the advisories
+    #[serde(rename = "lastUpdate")]
+    pub last_update: Option<NaiveDateTime>,
+    /// Datetime of the last successful update
+    #[serde(rename = "lastSuccess")]
+    pub last_success: Option<NaiveDateTime>,
+    /// Datetime of the last failed update
+    #[serde(rename = "lastFailure")]
+    pub last_failure: Option<NaiveDateTime>,
+    /// The error for the last failed update
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+}
+
+/// The qualitative rating of the severity of an advisory, as defined by CVSS
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum AdvisorySeverityLevel {
+    /// Score of 0.0
+    #[default]
+    None,
+    /// Score between 0.1 and 3.9
+    Low,
+    /// Score between 4.0 and 6.9
+    Medium,
+    /// Score between 7.0 and 8.9
+    High,
+    /// Score between 9.0 and 10.0
+    Critical,
+}
+
+impl AdvisorySeverityLevel {
+    /// Parses the level from its name in the configuration
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "low" => Some(Self::Low),
+            "medium" => Some(Self::Medium),
+            "high" => Some(Self::High),
+            "critical" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+
+    /// Gets the level for a CVSS score
+    #[must_use]
+    pub fn from_score(score: f64) -> Self {
+        if score >= 9.0 {
+            Self::Critical
+        } else if score >= 7.0 {
+            Self::High
+        } else if score >= 4.0 {
+            Self::Medium
+        } else if score > 0.0 {
+            Self::Low
+        } else {
+            Self::None
+        }
+    }
+
+    /// Gets the name of this level
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl Display for AdvisorySeverityLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Gets the CVSS base score for a vector, if it can be computed
+///
+/// Only CVSS v3 vectors are scored, CVSS v4 scores require the lookup tables of the specification.
+#[must_use]
+pub fn cvss_score(vector: &str) -> Option<f64> {
+    let metrics = vector
+        .strip_prefix("CVSS:3.1/")
+        .or_else(|| vector.strip_prefix("CVSS:3.0/"))?;
+    let metric = |name: &str| {
+        metrics
+            .split('/')
+            .find_map(|metric| metric.strip_prefix(name)?.strip_prefix(':'))
+    };
+    let changed = match metric("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector = match metric("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let attack_complexity = match metric("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges = match (metric("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let user_interaction = match metric("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let impact_of = |name: &str| match metric(name)? {
+        "H" => Some(0.56),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let base_impact: f64 = 1.0 - (1.0 - impact_of("C")?) * (1.0 - impact_of("I")?) * (1.0 - impact_of("A")?);
+    let impact = if changed {
+        7.52 * (base_impact - 0.029) - 3.25 * (base_impact - 0.02).powi(15)
+    } else {
+        6.42 * base_impact
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * attack_complexity * privileges * user_interaction;
+    let score: f64 = if changed {
+        1.08 * (impact + exploitability)
+    } else {
+        impact + exploitability
+    };
+    Some(cvss_round_up(score.min(10.0)))
+}
+
+/// Rounds up a CVSS score to one decimal, as specified by CVSS v3.1
+fn cvss_round_up(value: f64) -> f64 {
+    let scaled = (value * 100_000.0).round();
+    if scaled % 10_000.0 < 0.5 {
+        scaled / 100_000.0
+    } else {
+        ((scaled / 10_000.0).floor() + 1.0) / 10.0
+    }
+}
+
+/// Gets the OSV type of severity for a CVSS vector, if the vector is valid
+///
+/// The vector must be a CVSS v3 or v4 vector, e.g. `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`.
+#[must_use]
+pub fn cvss_type(vector: &str) -> Option<&'static str> {
+    let (version, metrics) = vector.split_once('/')?;
+    let type_value = match version {
+        "CVSS:3.0" | "CVSS:3.1" => "CVSS_V3",
+        "CVSS:4.0" => "CVSS_V4",
+        _ => return None,
+    };
+    let is_valid = metrics.split('/').all(|metric| {
+        metric.split_once(':').is_some_and(|(name, value)| {
+            !name.is_empty()
+                && !value.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphabetic())
+                && value.chars().all(|c| c.is_ascii_alphabetic())
+        })
+    });
+    is_valid.then_some(type_value)
+}
+
+/// Formats a timestamp for OSV, in RFC3339 format
+fn osv_timestamp(timestamp: &NaiveDateTime) -> String {
+    timestamp.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
@@ -4,10 +4,20 @@
 
 //! Objects related to authentication
 
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use axum::http::StatusCode;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use chrono::NaiveDateTime;
 use serde_derive::{Deserialize, Serialize};
+use thiserror::Error;
 
+use super::config::OAuthClaimMapping;
 use crate::application::AuthenticationError;
+use crate::utils::apierror::AsStatusCode;
+use crate::utils::token::generate_hex_secret;
 
 /// The admin role
 pub const ROLE_ADMIN: &str = "admin";
@@ -23,6 +33,9 @@ pub struct Authentication {
     /// Whether administration can be done
     #[serde(rename = "canAdmin")]
     pub can_admin: bool,
+    /// The scopes restricting the authentication, if any
+    #[serde(default)]
+    pub scopes: TokenScopes,
 }
 
 impl Authentication {
@@ -33,6 +46,7 @@ impl Authentication {
             principal: AuthenticationPrincipal::SelfAuth,
             can_write: false,
             can_admin: false,
+            scopes: TokenScopes::unrestricted(),
         }
     }
 
@@ -43,6 +57,7 @@ impl Authentication {
             principal: AuthenticationPrincipal::Service { token_id },
             can_write: false,
             can_admin: false,
+            scopes: TokenScopes::unrestricted(),
         }
     }
 
@@ -53,6 +68,7 @@ impl Authentication {
             principal: AuthenticationPrincipal::User { uid, email },
             can_write: true,
             can_admin: true,
+            scopes: TokenScopes::unrestricted(),
         }
     }
 
@@ -91,6 +107,187 @@ impl Authentication {
             Err(AuthenticationError::AdministrationIsForbidden)
         }
     }
+
+    /// Checks that this authentication is not restricted by scopes
+    /// Scoped tokens cannot obtain new credentials, nor administrate the registry
+    pub fn check_unscoped(&self) -> Result<(), AuthenticationError> {
+        if self.scopes == TokenScopes::unrestricted() {
+            Ok(())
+        } else {
+            Err(AuthenticationError::OutOfScope)
+        }
+    }
+
+    /// Gets the reader to use for checking the read access to crates
+    #[must_use]
+    pub const fn reader(&self) -> CrateReader {
+        match &self.principal {
+            AuthenticationPrincipal::User { uid, email: _ } => CrateReader::User(*uid),
+            AuthenticationPrincipal::Service { .. } | AuthenticationPrincipal::SelfAuth => CrateReader::Registry,
+        }
+    }
+}
+
+/// The identity under which crates are read, for checking their read access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrateReader {
+    /// An anonymous reader, when the registry allows public reads
+    Anonymous,
+    /// An authenticated user
+    User(i64),
+    /// The registry itself or a service using a global token, can read all crates
+    Registry,
+}
+
+/// An endpoint scope for a token
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EndpointScope {
+    /// Publishing the first version of a new crate
+    PublishNew,
+    /// Publishing a new version of an existing crate
+    PublishUpdate,
+    /// Yanking and un-yanking versions
+    Yank,
+    /// Changing the owners of a crate
+    ChangeOwners,
+}
+
+impl EndpointScope {
+    /// Gets the name of the scope
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::PublishNew => "publish-new",
+            Self::PublishUpdate => "publish-update",
+            Self::Yank => "yank",
+            Self::ChangeOwners => "change-owners",
+        }
+    }
+}
+
+impl Display for EndpointScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EndpointScope {
+    type Err = InvalidTokenScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "publish-new" => Ok(Self::PublishNew),
+            "publish-update" => Ok(Self::PublishUpdate),
+            "yank" => Ok(Self::Yank),
+            "change-owners" => Ok(Self::ChangeOwners),
+            _ => Err(InvalidTokenScope(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid token scope: `{0}`")]
+pub struct InvalidTokenScope(pub String);
+
+impl AsStatusCode for InvalidTokenScope {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// The scopes restricting what a token can do
+/// Empty lists mean no restriction
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TokenScopes {
+    /// The patterns for the names of the crates the token can manage, e.g. `acme-net-*`
+    #[serde(rename = "crateScopes", default)]
+    pub crates: Vec<String>,
+    /// The endpoints the token can use
+    #[serde(rename = "endpointScopes", default)]
+    pub endpoints: Vec<EndpointScope>,
+}
+
+impl TokenScopes {
+    /// Gets scopes without restriction
+    #[must_use]
+    pub const fn unrestricted() -> Self {
+        Self {
+            crates: Vec::new(),
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Parses the scopes from comma-separated lists
+    pub fn parse(crates: &str, endpoints: &str) -> Result<Self, InvalidTokenScope> {
+        let crates = crates
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| {
+                if is_valid_crate_pattern(pattern) {
+                    Ok(pattern.to_string())
+                } else {
+                    Err(InvalidTokenScope(pattern.to_string()))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let endpoints = endpoints
+            .split(',')
+            .map(str::trim)
+            .filter(|scope| !scope.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { crates, endpoints })
+    }
+
+    /// Gets the comma-separated list of crate patterns
+    #[must_use]
+    pub fn crates_list(&self) -> String {
+        self.crates.join(",")
+    }
+
+    /// Gets the comma-separated list of endpoints
+    #[must_use]
+    pub fn endpoints_list(&self) -> String {
+        self.endpoints.iter().map(|e| e.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    /// Gets whether a crate matches the crate scopes
+    #[must_use]
+    pub fn is_crate_allowed(&self, package: &str) -> bool {
+        self.crates.is_empty() || self.crates.iter().any(|pattern| crate_pattern_matches(pattern, package))
+    }
+
+    /// Checks that an operation on a crate is allowed by the scopes
+    /// Operations that are not covered by an endpoint scope are forbidden when endpoint scopes are set
+    pub fn check(&self, endpoint: Option<EndpointScope>, package: &str) -> Result<(), AuthenticationError> {
+        if !self.is_crate_allowed(package) {
+            return Err(AuthenticationError::OutOfScope);
+        }
+        if !self.endpoints.is_empty() && !endpoint.is_some_and(|endpoint| self.endpoints.contains(&endpoint)) {
+            return Err(AuthenticationError::OutOfScope);
+        }
+        Ok(())
+    }
+}
+
+/// Gets whether a crate pattern for a token scope is valid
+/// The pattern is a crate name, optionally ending with a `*` wildcard
+fn is_valid_crate_pattern(pattern: &str) -> bool {
+    let name = pattern.strip_suffix('*').unwrap_or(pattern);
+    (!name.is_empty() || pattern == "*") && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Gets whether a crate name matches a pattern in a token scope
+/// The comparison is case-insensitive and does not distinguish `-` from `_`, as for crate names
+fn crate_pattern_matches(pattern: &str, package: &str) -> bool {
+    let normalize = |s: &str| s.to_ascii_lowercase().replace('_', "-");
+    let package = normalize(package);
+    pattern.strip_suffix('*').map_or_else(
+        || normalize(pattern) == package,
+        |prefix| package.starts_with(&normalize(prefix)),
+    )
 }
 
 /// The principal associated to an authentication
@@ -120,6 +317,15 @@ pub struct RegistryUserToken {
     /// Whether administration can be done using this token through the API
     #[serde(rename = "canAdmin")]
     pub can_admin: bool,
+    /// The scopes restricting the token
+    #[serde(flatten)]
+    pub scopes: TokenScopes,
+    /// The expiration date for the token, if any
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<NaiveDateTime>,
+    /// After a rotation, the date until which the previous secret remains valid
+    #[serde(rename = "previousExpiresAt")]
+    pub previous_expires_at: Option<NaiveDateTime>,
 }
 
 /// A token for a registry user
@@ -140,6 +346,263 @@ pub struct RegistryUserTokenWithSecret {
     /// Whether administration can be done using this token through the API
     #[serde(rename = "canAdmin")]
     pub can_admin: bool,
+    /// The scopes restricting the token
+    #[serde(flatten)]
+    pub scopes: TokenScopes,
+    /// The expiration date for the token, if any
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// The options for the creation of a user token
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct RegistryUserTokenCreation {
+    /// Whether a crate can be uploaded using this token
+    #[serde(rename = "canWrite")]
+    pub can_write: bool,
+    /// Whether administration can be done using this token through the API
+    #[serde(rename = "canAdmin")]
+    pub can_admin: bool,
+    /// Comma-separated patterns for the crates the token can manage
+    #[serde(rename = "crateScopes", default)]
+    pub crate_scopes: String,
+    /// Comma-separated endpoint scopes for the token
+    #[serde(rename = "endpointScopes", default)]
+    pub endpoint_scopes: String,
+    /// The number of days after which the token expires, if any
+    #[serde(rename = "expiresInDays")]
+    pub expires_in_days: Option<i64>,
+}
+
+/// The options for the expiration of a token, on creation or rotation
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+pub struct TokenExpiryOptions {
+    /// The number of days after which the token expires, if any
+    #[serde(rename = "expiresInDays")]
+    pub expires_in_days: Option<i64>,
+}
+
+/// A token that is about to expire, with the emails to notify
+#[derive(Debug, Clone)]
+pub struct ExpiringToken {
+    /// The kind of token
+    pub kind: TokenKind,
+    /// The unique identifier
+    pub id: i64,
+    /// The token name
+    pub name: String,
+    /// The expiration date for the token
+    pub expires_at: NaiveDateTime,
+    /// The emails of the users to notify
+    pub emails: Vec<String>,
+}
+
+/// The response to a device authorization request (RFC 8628, section 3.2)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceAuthorizationResponse {
+    /// The device verification code, to be used by the device when polling for the token
+    pub device_code: String,
+    /// The code to be entered by the user
+    pub user_code: String,
+    /// The URI where the user can approve the request
+    pub verification_uri: String,
+    /// The URI where the user can approve the request, with the user code already filled in
+    pub verification_uri_complete: String,
+    /// The lifetime in seconds of the codes
+    pub expires_in: i64,
+    /// The minimum amount of time in seconds to wait between polling requests
+    pub interval: i64,
+}
+
+/// The grant type for the device access token request
+pub const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// A device access token request (RFC 8628, section 3.4)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceTokenRequest {
+    /// The grant type, must be `urn:ietf:params:oauth:grant-type:device_code`
+    pub grant_type: String,
+    /// The device verification code
+    pub device_code: String,
+}
+
+/// The decision of a user on a device authorization request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceAuthorizationApproval {
+    /// The code entered by the user
+    #[serde(rename = "userCode")]
+    pub user_code: String,
+    /// Whether the request is approved
+    pub approve: bool,
+    /// The name for the token to create
+    #[serde(default)]
+    pub name: String,
+    /// The options for the token to create
+    #[serde(flatten)]
+    pub options: RegistryUserTokenCreation,
+}
+
+/// The outcome of a device access token request
+#[derive(Debug, Clone)]
+pub enum DeviceTokenPoll {
+    /// The request was approved and a token was created
+    Granted {
+        /// The login of the user that approved the request
+        login: String,
+        /// The new token
+        token: RegistryUserTokenWithSecret,
+    },
+    /// The user has not yet approved the request
+    Pending,
+    /// The device is polling too fast
+    SlowDown,
+    /// The user denied the request
+    Denied,
+    /// The device code expired
+    Expired,
+    /// The device code is unknown
+    InvalidGrant,
+    /// The grant type is not supported
+    UnsupportedGrantType,
+}
+
+impl DeviceTokenPoll {
+    /// Gets the error code for this outcome, as defined by RFC 8628, section 3.5
+    #[must_use]
+    pub const fn error_code(&self) -> Option<&'static str> {
+        match self {
+            Self::Granted { .. } => None,
+            Self::Pending => Some("authorization_pending"),
+            Self::SlowDown => Some("slow_down"),
+            Self::Denied => Some("access_denied"),
+            Self::Expired => Some("expired_token"),
+            Self::InvalidGrant => Some("invalid_grant"),
+            Self::UnsupportedGrantType => Some("unsupported_grant_type"),
+        }
+    }
+}
+
+/// The successful response to a device access token request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceTokenResponse {
+    /// The value to use as a token for cargo, i.e. `Basic base64(login:secret)`
+    pub access_token: String,
+    /// The type of token
+    pub token_type: String,
+    /// The created token
+    #[serde(flatten)]
+    pub token: RegistryUserTokenWithSecret,
+}
+
+impl DeviceTokenResponse {
+    /// Creates the response for a token created for a user
+    #[must_use]
+    pub fn new(login: &str, token: RegistryUserTokenWithSecret) -> Self {
+        let credentials = STANDARD.encode(format!("{login}:{}", token.secret));
+        Self {
+            access_token: format!("Basic {credentials}"),
+            token_type: String::from("Basic"),
+            token,
+        }
+    }
+}
+
+/// The error response to a device access token request (RFC 6749, section 5.2)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceTokenError {
+    /// The error code
+    pub error: String,
+}
+
+/// A public key registered by a user for asymmetric tokens
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegistryUserKey {
+    /// The unique identifier
+    pub id: i64,
+    /// The key name
+    pub name: String,
+    /// The PASERK identifier for the key (`k3.pid.`)
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    /// The public key in the PASERK format (`k3.public.`)
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    /// The last time the key was used
+    #[serde(rename = "lastUsed")]
+    pub last_used: NaiveDateTime,
+    /// Whether a crate can be uploaded using tokens signed with this key
+    #[serde(rename = "canWrite")]
+    pub can_write: bool,
+}
+
+/// The data to register a public key
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegistryUserKeyRegistration {
+    /// The key name
+    pub name: String,
+    /// The public key in the PASERK format (`k3.public.`)
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    /// Whether a crate can be uploaded using tokens signed with this key
+    #[serde(rename = "canWrite")]
+    pub can_write: bool,
+}
+
+/// The kind of mutation for an asymmetric token
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MutationKind {
+    /// Publishing a new crate version
+    Publish,
+    /// Yanking a crate version
+    Yank,
+    /// Un-yanking a crate version
+    Unyank,
+    /// Changing the owners of a crate
+    Owners,
+}
+
+/// A mutation performed by a request, to be matched by the claims of an asymmetric token
+#[derive(Debug, Clone, Copy)]
+pub struct RegistryMutation<'a> {
+    /// The kind of mutation
+    pub kind: MutationKind,
+    /// The name of the crate
+    pub name: &'a str,
+    /// The version of the crate, if relevant
+    pub vers: Option<&'a str>,
+    /// The checksum of the `.crate` file, when publishing
+    pub cksum: Option<&'a str>,
+}
+
+/// The footer of an asymmetric token
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AsymmetricTokenFooter {
+    /// The URL of the registry index
+    pub url: String,
+    /// The PASERK identifier of the key used to sign the token
+    pub kip: String,
+}
+
+/// The claims in the message of an asymmetric token
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AsymmetricTokenClaims {
+    /// The time the token was issued (RFC 3339)
+    pub iat: String,
+    /// The optional subject
+    pub sub: Option<String>,
+    /// The mutation, when the request is not read-only
+    pub mutation: Option<MutationKind>,
+    /// The name of the crate for the mutation
+    pub name: Option<String>,
+    /// The version of the crate for the mutation
+    pub vers: Option<String>,
+    /// The checksum of the `.crate` file, when publishing
+    pub cksum: Option<String>,
+    /// The challenge from the server, if any
+    pub challenge: Option<String>,
+    /// The version of the claims format for mutations
+    pub v: Option<u32>,
 }
 
 /// An OAuth access token
@@ -155,16 +618,83 @@ pub struct OAuthToken {
     pub refresh_token: Option<String>,
     /// The grant scope
     pub scope: Option<String>,
+    /// The OIDC ID token, if any
+    pub id_token: Option<String>,
 }
 
-/// Finds a field in a JSON blob
-#[must_use]
-pub fn find_field_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Option<&'v str> {
+/// The data of an OAuth login in progress, kept between the redirection to the identity provider and the callback
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OAuthLoginRequest {
+    /// The state to be returned by the identity provider
+    pub state: String,
+    /// The nonce expected in the ID token
+    pub nonce: String,
+    /// The PKCE code verifier
+    #[serde(rename = "codeVerifier")]
+    pub code_verifier: String,
+}
+
+impl OAuthLoginRequest {
+    /// Creates a new login request with fresh secrets
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: generate_hex_secret(),
+            nonce: generate_hex_secret(),
+            code_verifier: generate_hex_secret(),
+        }
+    }
+}
+
+impl Default for OAuthLoginRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Finds a value in a JSON blob
+fn find_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Option<&'v serde_json::Value> {
     let mut last = blob;
     for item in path.split('.') {
         last = last.as_object()?.get(item)?;
     }
-    last.as_str()
+    Some(last)
+}
+
+/// Finds a field in a JSON blob
+#[must_use]
+pub fn find_field_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Option<&'v str> {
+    find_in_blob(blob, path)?.as_str()
+}
+
+/// Finds the values of a field in a JSON blob, the field being either a string or an array of strings
+#[must_use]
+pub fn find_values_in_blob<'v>(blob: &'v serde_json::Value, path: &str) -> Vec<&'v str> {
+    match find_in_blob(blob, path) {
+        Some(serde_json::Value::String(value)) => vec![value.as_str()],
+        Some(serde_json::Value::Array(values)) => values.iter().filter_map(serde_json::Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Computes the roles of a user from the claims provided by the identity provider
+/// The roles managed by the mapping rules are granted or revoked, the other roles are kept as is.
+#[must_use]
+pub fn map_claims_to_roles(mappings: &[OAuthClaimMapping], claims: &serde_json::Value, roles: &str) -> String {
+    let is_managed = |role: &str| mappings.iter().any(|mapping| mapping.roles.iter().any(|r| r == role));
+    let mut result = roles
+        .split(',')
+        .map(str::trim)
+        .filter(|role| !role.is_empty() && !is_managed(role))
+        .collect::<Vec<_>>();
+    for mapping in mappings.iter().filter(|mapping| mapping.matches(claims)) {
+        for role in &mapping.roles {
+            if !result.contains(&role.as_str()) {
+                result.push(role);
+            }
+        }
+    }
+    result.join(",")
 }
 
 /// The kind of auth token
@@ -174,6 +704,8 @@ pub enum TokenKind {
     User,
     /// A registry-wide token
     Registry,
+    /// A user public key for asymmetric tokens
+    UserKey,
 }
 
 /// Event when a token was used
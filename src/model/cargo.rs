@@ -4,15 +4,20 @@
 
 //! Data model for the Cargo web API
 
-use std::collections::HashMap;
-use std::io::Cursor;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path};
 use std::str::FromStr;
 
 use axum::http::StatusCode;
 use byteorder::{LittleEndian, ReadBytesExt};
+use flate2::bufread::GzDecoder;
 use serde_derive::{Deserialize, Serialize};
+use tar::{Archive, EntryType};
 use thiserror::Error;
 
+use super::teams::Team;
 use crate::utils::apierror::{ApiError, AsStatusCode};
 use crate::utils::hashes::sha256;
 
@@ -105,12 +110,33 @@ impl YesNoMsgResult {
 pub struct OwnersQueryResult {
     /// The list of owners
     pub users: Vec<RegistryUser>,
+    /// The teams owning the crate
+    pub teams: Vec<Team>,
+}
+
+impl OwnersQueryResult {
+    /// Gets the emails of all the owners, including the members of the owning teams
+    #[must_use]
+    pub fn emails(&self) -> Vec<String> {
+        let mut emails = Vec::new();
+        let users = self.users.iter().map(|user| &user.email);
+        let members = self
+            .teams
+            .iter()
+            .flat_map(|team| team.members.iter().map(|member| &member.email));
+        for email in users.chain(members) {
+            if !emails.contains(email) {
+                emails.push(email.clone());
+            }
+        }
+        emails
+    }
 }
 
 /// The query for adding/removing owners to a crate
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct OwnersChangeQuery {
-    /// The login of the users
+    /// The login of the users, or `team:<name>` for teams
     pub users: Vec<String>,
 }
 
@@ -244,7 +270,7 @@ impl CrateMetadata {
 }
 
 /// The kind of dependency
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DependencyKind {
     /// A normal dependency
     #[default]
@@ -258,6 +284,18 @@ pub enum DependencyKind {
     Build,
 }
 
+impl DependencyKind {
+    /// Gets the serialized name of this kind
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Dev => "dev",
+            Self::Build => "build",
+        }
+    }
+}
+
 impl FromStr for DependencyKind {
     type Err = ();
 
@@ -328,22 +366,34 @@ pub struct CrateUploadData {
     pub metadata: CrateMetadata,
     /// The content of the .crate package
     pub content: Vec<u8>,
+    /// The files read from the packaged crate
+    pub files: PackagedFiles,
 }
 
 impl CrateUploadData {
     /// Deserialize the content of an input payload
-    pub fn new(buffer: &[u8]) -> Result<Self, ApiError> {
+    /// The content of the packaged crate is checked against the metadata.
+    pub fn new(buffer: &[u8]) -> Result<Self, CrateContentError> {
         let mut cursor = Cursor::new(buffer);
         // read the metadata
         let metadata_length = cursor.read_u32::<LittleEndian>()? as usize;
-        let metadata_buffer = &buffer[4..(4 + metadata_length)];
-        let metadata = serde_json::from_slice(metadata_buffer)?;
+        let metadata_buffer = buffer.get(4..(4 + metadata_length)).ok_or(CrateContentError::Truncated)?;
+        let metadata: CrateMetadata = serde_json::from_slice(metadata_buffer)?;
         // read the content
         cursor.set_position(4 + metadata_length as u64);
         let content_length = cursor.read_u32::<LittleEndian>()? as usize;
-        let mut content = vec![0_u8; content_length];
-        content.copy_from_slice(&buffer[(4 + metadata_length + 4)..]);
-        Ok(Self { metadata, content })
+        let content = buffer
+            .get((4 + metadata_length + 4)..)
+            .filter(|content| content.len() == content_length)
+            .ok_or(CrateContentError::Truncated)?
+            .to_vec();
+        let files = check_crate_archive(&metadata.name, &metadata.vers, &content)?;
+        check_manifest(&metadata, &files.manifest)?;
+        Ok(Self {
+            metadata,
+            content,
+            files,
+        })
     }
 
     /// Builds the metadata to be index for this version
@@ -364,6 +414,314 @@ impl CrateUploadData {
     }
 }
 
+/// The maximum size of the files read from a packaged crate, such as the manifest
+pub const PACKAGED_FILE_MAX_SIZE: u64 = 2 * 1024 * 1024;
+/// The maximum total size of the uncompressed content of a packaged crate
+pub const PACKAGED_ARCHIVE_MAX_SIZE: u64 = 512 * 1024 * 1024;
+/// The maximum number of entries in a packaged crate
+pub const PACKAGED_ARCHIVE_MAX_ENTRIES: usize = 100_000;
+
+/// The files at the root of a packaged crate that are read by the registry
+#[derive(Debug, Clone, Default)]
+pub struct PackagedFiles {
+    /// The normalized manifest, `Cargo.toml`
+    pub manifest: toml::Table,
+    /// The original manifest, `Cargo.toml.orig`, if any
+    pub manifest_orig: Option<Vec<u8>>,
+}
+
+/// The VCS information embedded by cargo in a packaged crate, in `.cargo_vcs_info.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CargoVcsInfo {
+    /// The git information, if the crate was packaged from a git repository
+    #[serde(default)]
+    pub git: Option<CargoVcsGitInfo>,
+    /// The path of the crate within the repository
+    #[serde(default)]
+    pub path_in_vcs: String,
+}
+
+/// The git information embedded by cargo in a packaged crate
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CargoVcsGitInfo {
+    /// The SHA-1 of the commit the crate was packaged from
+    pub sha1: String,
+    /// Whether the working directory had uncommitted changes
+    #[serde(default)]
+    pub dirty: bool,
+}
+
+/// Error when the content of a packaged crate is invalid
+#[derive(Debug, Error)]
+pub enum CrateContentError {
+    #[error("the upload payload is truncated")]
+    Truncated,
+
+    #[error("failed to read the upload payload")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid metadata")]
+    Metadata(#[from] serde_json::Error),
+
+    #[error("the archive entry `{path}` is outside of the `{prefix}/` folder")]
+    EntryOutside { path: String, prefix: String },
+
+    #[error("the archive entry `{path}` is a link or a special file")]
+    EntryKind { path: String },
+
+    #[error("the archive entry `{path}` is larger than the maximum of {maximum} bytes")]
+    EntryTooLarge { path: String, maximum: u64 },
+
+    #[error("the uncompressed archive is larger than the maximum of {maximum} bytes")]
+    ArchiveTooLarge { maximum: u64 },
+
+    #[error("the archive contains more than the maximum of {maximum} entries")]
+    TooManyEntries { maximum: usize },
+
+    #[error("the archive does not contain `{path}`")]
+    MissingManifest { path: String },
+
+    #[error("invalid Cargo.toml: {0}")]
+    Manifest(#[from] toml::de::Error),
+
+    #[error("the {field} `{manifest}` in Cargo.toml does not match the {field} `{metadata}` in the metadata")]
+    Mismatch {
+        field: &'static str,
+        manifest: String,
+        metadata: String,
+    },
+
+    #[error("the dependency `{name}` is declared in the {declared} but not in the {missing}")]
+    DependencyMismatch {
+        name: String,
+        declared: &'static str,
+        missing: &'static str,
+    },
+}
+
+impl AsStatusCode for CrateContentError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// Checks that the entries of a packaged crate are regular files and folders within the `{name}-{version}/` folder
+/// Returns the files read by the registry, with the parsed `Cargo.toml` manifest.
+pub fn check_crate_archive(name: &str, version: &str, content: &[u8]) -> Result<PackagedFiles, CrateContentError> {
+    check_crate_archive_with_limits(
+        name,
+        version,
+        content,
+        PACKAGED_ARCHIVE_MAX_SIZE,
+        PACKAGED_ARCHIVE_MAX_ENTRIES,
+    )
+}
+
+/// Checks a packaged crate like `check_crate_archive`,
+/// with limits on the total size of the uncompressed content and on the number of entries
+pub fn check_crate_archive_with_limits(
+    name: &str,
+    version: &str,
+    content: &[u8],
+    max_size: u64,
+    max_entries: usize,
+) -> Result<PackagedFiles, CrateContentError> {
+    // read one more byte than the limit to detect archives above it
+    let mut archive = Archive::new(GzDecoder::new(content).take(max_size + 1));
+    let result = read_crate_archive(name, version, &mut archive, max_entries);
+    if archive.into_inner().limit() == 0 {
+        return Err(CrateContentError::ArchiveTooLarge { maximum: max_size });
+    }
+    result
+}
+
+/// Reads the entries of a packaged crate
+fn read_crate_archive<R: Read>(
+    name: &str,
+    version: &str,
+    archive: &mut Archive<R>,
+    max_entries: usize,
+) -> Result<PackagedFiles, CrateContentError> {
+    let prefix = format!("{name}-{version}");
+    let manifest_path = Path::new(&prefix).join("Cargo.toml");
+    let mut manifest = None;
+    let mut files = PackagedFiles::default();
+    for (index, entry) in archive.entries()?.enumerate() {
+        if index >= max_entries {
+            return Err(CrateContentError::TooManyEntries { maximum: max_entries });
+        }
+        let entry = entry?;
+        if matches!(entry.header().entry_type(), EntryType::XHeader | EntryType::XGlobalHeader) {
+            // extended headers carry metadata for the other entries
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut components = path.components();
+        let is_inside = components.next() == Some(Component::Normal(OsStr::new(&prefix)))
+            && components.all(|component| matches!(component, Component::Normal(_)));
+        if !is_inside {
+            return Err(CrateContentError::EntryOutside {
+                path: path.to_string_lossy().to_string(),
+                prefix,
+            });
+        }
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            _ => {
+                return Err(CrateContentError::EntryKind {
+                    path: path.to_string_lossy().to_string(),
+                });
+            }
+        }
+        let target = match path.strip_prefix(&prefix).ok().and_then(Path::to_str) {
+            Some("Cargo.toml") => &mut manifest,
+            Some("Cargo.toml.orig") => &mut files.manifest_orig,
+            _ => continue,
+        };
+        if entry.size() > PACKAGED_FILE_MAX_SIZE {
+            return Err(CrateContentError::EntryTooLarge {
+                path: path.to_string_lossy().to_string(),
+                maximum: PACKAGED_FILE_MAX_SIZE,
+            });
+        }
+        let mut buffer = Vec::new();
+        entry.take(PACKAGED_FILE_MAX_SIZE).read_to_end(&mut buffer)?;
+        *target = Some(buffer);
+    }
+    let manifest = manifest.ok_or_else(|| CrateContentError::MissingManifest {
+        path: manifest_path.to_string_lossy().to_string(),
+    })?;
+    files.manifest = toml::from_slice(&manifest)?;
+    Ok(files)
+}
+
+/// Reads the SPDX license expression declared in the manifest of a packaged crate, if any
+#[must_use]
+pub fn read_crate_license(name: &str, version: &str, content: &[u8]) -> Option<String> {
+    let files = check_crate_archive(name, version, content).ok()?;
+    files.manifest.get("package")?.get("license")?.as_str().map(str::to_string)
+}
+
+/// A dependency, as declared either in the metadata or in the manifest
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct DeclaredDependency {
+    /// The kind of dependency
+    kind: DependencyKind,
+    /// The target platform, if any
+    target: Option<String>,
+    /// The name of the dependency in the manifest
+    name: String,
+    /// The name of the package
+    package: String,
+    /// The normalized version requirement
+    version_req: String,
+}
+
+impl DeclaredDependency {
+    /// Normalizes a version requirement, e.g. `1.0` becomes `^1.0`
+    fn normalize_req(req: &str) -> String {
+        semver::VersionReq::parse(req).map_or_else(|_| req.to_string(), |req| req.to_string())
+    }
+}
+
+impl From<&CrateMetadataDependency> for DeclaredDependency {
+    fn from(dep: &CrateMetadataDependency) -> Self {
+        Self {
+            kind: dep.kind,
+            target: dep.target.clone(),
+            name: dep.explicit_name_in_toml.as_ref().unwrap_or(&dep.name).clone(),
+            package: dep.name.clone(),
+            version_req: Self::normalize_req(&dep.version_req),
+        }
+    }
+}
+
+/// Collects the dependencies declared in a table of a manifest, either at the root or for a target
+fn collect_manifest_dependencies(table: &toml::Table, target: Option<&str>, results: &mut HashSet<DeclaredDependency>) {
+    for (key, kind) in [
+        ("dependencies", DependencyKind::Normal),
+        ("dev-dependencies", DependencyKind::Dev),
+        ("dev_dependencies", DependencyKind::Dev),
+        ("build-dependencies", DependencyKind::Build),
+        ("build_dependencies", DependencyKind::Build),
+    ] {
+        let Some(dependencies) = table.get(key).and_then(toml::Value::as_table) else {
+            continue;
+        };
+        for (name, spec) in dependencies {
+            let (package, version_req) = match spec {
+                toml::Value::String(req) => (name.as_str(), req.as_str()),
+                toml::Value::Table(spec) => (
+                    spec.get("package").and_then(toml::Value::as_str).unwrap_or(name),
+                    spec.get("version").and_then(toml::Value::as_str).unwrap_or("*"),
+                ),
+                _ => continue,
+            };
+            results.insert(DeclaredDependency {
+                kind,
+                target: target.map(str::to_string),
+                name: name.clone(),
+                package: package.to_string(),
+                version_req: DeclaredDependency::normalize_req(version_req),
+            });
+        }
+    }
+}
+
+/// Checks that the normalized manifest of a packaged crate matches its metadata
+fn check_manifest(metadata: &CrateMetadata, manifest: &toml::Table) -> Result<(), CrateContentError> {
+    let package = manifest.get("package").and_then(toml::Value::as_table);
+    let get_package_field = |field: &str| {
+        package
+            .and_then(|package| package.get(field))
+            .and_then(toml::Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    let name = get_package_field("name");
+    if name != metadata.name {
+        return Err(CrateContentError::Mismatch {
+            field: "name",
+            manifest: name,
+            metadata: metadata.name.clone(),
+        });
+    }
+    let version = get_package_field("version");
+    if version != metadata.vers {
+        return Err(CrateContentError::Mismatch {
+            field: "version",
+            manifest: version,
+            metadata: metadata.vers.clone(),
+        });
+    }
+
+    let mut in_manifest = HashSet::new();
+    collect_manifest_dependencies(manifest, None, &mut in_manifest);
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        for (target, table) in targets {
+            if let Some(table) = table.as_table() {
+                collect_manifest_dependencies(table, Some(target), &mut in_manifest);
+            }
+        }
+    }
+    let in_metadata = metadata.deps.iter().map(DeclaredDependency::from).collect::<HashSet<_>>();
+    if let Some(dep) = in_metadata.difference(&in_manifest).next() {
+        return Err(CrateContentError::DependencyMismatch {
+            name: dep.name.clone(),
+            declared: "metadata",
+            missing: "Cargo.toml",
+        });
+    }
+    if let Some(dep) = in_manifest.difference(&in_metadata).next() {
+        return Err(CrateContentError::DependencyMismatch {
+            name: dep.name.clone(),
+            declared: "Cargo.toml",
+            missing: "metadata",
+        });
+    }
+    Ok(())
+}
+
 /// The metadata for a crate inside the index
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct IndexCrateMetadata {
@@ -4,6 +4,7 @@
 
 //! Module for configuration management
 
+use std::fmt::{Display, Formatter};
 use std::net::{IpAddr, Ipv4Addr};
 use std::path::PathBuf;
 use std::process::Stdio;
@@ -18,6 +19,9 @@ use tokio::fs::{self, File};
 use tokio::io::{self, AsyncWrite, AsyncWriteExt, BufWriter};
 use tokio::process::Command;
 
+use super::advisories::AdvisorySeverityLevel;
+use super::auth::find_values_in_blob;
+use super::typosquat::{TyposquatPolicy, parse_popular_names};
 use super::{CHANNEL_NIGHTLY, CHANNEL_STABLE};
 use crate::model::errors::MissingEnvVar;
 use crate::utils::apierror::{ApiError, AsStatusCode, error_backend_failure, specialize};
@@ -131,6 +135,42 @@ impl ExternalRegistry {
     }
 }
 
+/// A rule mapping a claim provided by the identity provider to roles and teams
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OAuthClaimMapping {
+    /// The path to the claim in the user information, either a string or an array of strings
+    pub claim: String,
+    /// The value the claim must be, or contain, for the rule to apply
+    pub value: String,
+    /// The roles granted by this rule
+    pub roles: Vec<String>,
+    /// The teams joined by this rule
+    pub teams: Vec<String>,
+}
+
+impl OAuthClaimMapping {
+    /// Loads the configuration for a mapping rule from the environment
+    fn from_env(rule_index: usize) -> Option<Self> {
+        let value = get_var(format!("REGISTRY_OAUTH_MAPPING_{rule_index}_VALUE")).ok()?;
+        Some(Self {
+            claim: get_var(format!("REGISTRY_OAUTH_MAPPING_{rule_index}_CLAIM")).unwrap_or_else(|_| String::from("groups")),
+            value,
+            roles: get_var(format!("REGISTRY_OAUTH_MAPPING_{rule_index}_ROLES"))
+                .map(|v| comma_sep_to_vec(&v))
+                .unwrap_or_default(),
+            teams: get_var(format!("REGISTRY_OAUTH_MAPPING_{rule_index}_TEAMS"))
+                .map(|v| comma_sep_to_vec(&v))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Gets whether this rule applies to the claims of a user
+    #[must_use]
+    pub fn matches(&self, claims: &serde_json::Value) -> bool {
+        find_values_in_blob(claims, &self.claim).contains(&self.value.as_str())
+    }
+}
+
 /// The specification of the storage system to use
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum StorageConfig {
@@ -198,6 +238,67 @@ impl StorageConfig {
     }
 }
 
+/// The source of the advisories against the crates on crates.io, in the OSV format
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum AdvisoryDbSource {
+    /// A git repository, cloned in the data directory and fetched when stale
+    Git {
+        /// The URI of the repository
+        uri: String,
+        /// The branch with the advisories
+        branch: String,
+    },
+    /// A local directory, read again when stale
+    Local {
+        /// The path to the directory
+        path: String,
+    },
+    /// A tarball uploaded by an administrator
+    Upload,
+}
+
+impl Default for AdvisoryDbSource {
+    fn default() -> Self {
+        Self::Git {
+            uri: String::from(Self::DEFAULT_GIT_URI),
+            branch: String::from(Self::DEFAULT_GIT_BRANCH),
+        }
+    }
+}
+
+impl Display for AdvisoryDbSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Git { uri, branch } => write!(f, "git repository {uri} on branch {branch}"),
+            Self::Local { path } => write!(f, "local directory {path}"),
+            Self::Upload => write!(f, "uploaded archive"),
+        }
+    }
+}
+
+impl AdvisoryDbSource {
+    /// The URI of the git repo with the `RustSec` database
+    const DEFAULT_GIT_URI: &str = "https://github.com/rustsec/advisory-db";
+    /// The branch inside the `RustSec` repo with the data in the OSV format
+    const DEFAULT_GIT_BRANCH: &str = "osv";
+
+    /// Loads the configuration for a registry from the environment
+    fn from_env() -> Result<Self, MissingEnvVar> {
+        let source_kind = get_var("REGISTRY_ADVISORY_DB_SOURCE").unwrap_or_default();
+        Ok(match source_kind.as_str() {
+            "" | "git" => Self::Git {
+                uri: get_var("REGISTRY_ADVISORY_DB_GIT_URI").unwrap_or_else(|_| String::from(Self::DEFAULT_GIT_URI)),
+                branch: get_var("REGISTRY_ADVISORY_DB_GIT_BRANCH").unwrap_or_else(|_| String::from(Self::DEFAULT_GIT_BRANCH)),
+            },
+            "local" => Self::Local {
+                path: get_var("REGISTRY_ADVISORY_DB_PATH")?,
+            },
+            "upload" => Self::Upload,
+            _ => panic!("invalid REGISTRY_ADVISORY_DB_SOURCE"),
+        })
+    }
+}
+
 /// The parameters for the retry mechanism
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -251,12 +352,23 @@ pub struct S3Params {
     pub root: String,
 }
 
+/// The backend to use for the index
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum IndexBackend {
+    /// A local git repository, required to serve the git protocol
+    Git,
+    /// Files kept in the storage backend, for sparse-only deployments
+    Storage,
+}
+
 /// The configuration in the index
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IndexConfig {
     /// The home directory where the .cargo, .git are expected to be located
     #[serde(rename = "homeDir")]
     pub home_dir: String,
+    /// The backend to use for the index
+    pub backend: IndexBackend,
     /// The location in the file system
     pub location: String,
     /// Whether to allow the git protocol for clients fetching the index
@@ -287,17 +399,36 @@ pub struct IndexConfig {
 impl IndexConfig {
     /// Loads the configuration for a registry from the environment
     fn from_env(home_dir: &str, data_dir: &str, web_public_uri: &str) -> Result<Self, MissingEnvVar> {
+        let backend = match get_var("REGISTRY_INDEX_BACKEND").unwrap_or_default().as_str() {
+            "" | "git" => IndexBackend::Git,
+            "storage" => IndexBackend::Storage,
+            _ => panic!("invalid REGISTRY_INDEX_BACKEND"),
+        };
+        let allow_protocol_git = get_var("REGISTRY_INDEX_PROTOCOL_GIT").is_ok_and(|v| v == "true");
+        assert!(
+            !allow_protocol_git || backend == IndexBackend::Git,
+            "REGISTRY_INDEX_PROTOCOL_GIT requires the git index backend"
+        );
+        let (user_name, user_email) = if backend == IndexBackend::Git {
+            (get_var("REGISTRY_GIT_USER_NAME")?, get_var("REGISTRY_GIT_USER_EMAIL")?)
+        } else {
+            (
+                get_var("REGISTRY_GIT_USER_NAME").unwrap_or_default(),
+                get_var("REGISTRY_GIT_USER_EMAIL").unwrap_or_default(),
+            )
+        };
         Ok(Self {
             home_dir: home_dir.to_string(),
+            backend,
             location: format!("{data_dir}/index"),
-            allow_protocol_git: get_var("REGISTRY_INDEX_PROTOCOL_GIT").is_ok_and(|v| v == "true"),
+            allow_protocol_git,
             allow_protocol_sparse: get_var("REGISTRY_INDEX_PROTOCOL_SPARSE").map_or(true, |v| v == "true"),
             remote_origin: get_var("REGISTRY_GIT_REMOTE").ok(),
             remote_ssh_key_file_name: get_var("REGISTRY_GIT_REMOTE_SSH_KEY_FILENAME").ok(),
             remote_push_changes: get_var("REGISTRY_GIT_REMOTE_PUSH_CHANGES")
                 .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true")),
-            user_name: get_var("REGISTRY_GIT_USER_NAME")?,
-            user_email: get_var("REGISTRY_GIT_USER_EMAIL")?,
+            user_name,
+            user_email,
             public: IndexPublicConfig {
                 dl: format!("{web_public_uri}/api/v1/crates"),
                 api: web_public_uri.to_string(),
@@ -480,6 +611,9 @@ pub struct Configuration {
     /// Timeout (in milli-seconds) to use when interacting with the storage
     #[serde(rename = "storageTimeout")]
     pub storage_timeout: u64,
+    /// The uri of the OIDC issuer, used to discover the other endpoints and verify ID tokens
+    #[serde(rename = "oauthIssuerUri")]
+    pub oauth_issuer_uri: Option<String>,
     /// The uri of the OAuth login page
     #[serde(rename = "oauthLoginUri")]
     pub oauth_login_uri: String,
@@ -507,6 +641,9 @@ pub struct Configuration {
     /// The secret for the client to use
     #[serde(rename = "oauthClientScope")]
     pub oauth_client_scope: String,
+    /// The rules mapping the claims of users to roles and teams, applied on each login
+    #[serde(rename = "oauthClaimMappings")]
+    pub oauth_claim_mappings: Vec<OAuthClaimMapping>,
     /// The known external registries that require authentication
     #[serde(rename = "externalRegistries")]
     pub external_registries: Vec<ExternalRegistry>,
@@ -522,6 +659,9 @@ pub struct Configuration {
     /// Number of milliseconds after which the local data about an external registry are deemed stale and must be pulled again
     #[serde(rename = "depsStaleRegistry")]
     pub deps_stale_registry: u64,
+    /// The source of the advisories against the crates on crates.io
+    #[serde(rename = "depsAdvisoryDb")]
+    pub deps_advisory_db: AdvisoryDbSource,
     /// Number of minutes after which the saved analysis for a crate becomes stale
     /// A negative number deactivates background analysis of crates
     #[serde(rename = "depsStaleAnalysis")]
@@ -532,6 +672,47 @@ pub struct Configuration {
     /// Whether to send a notification by email to the owners of a crate when CVEs are discovered in its dependencies
     #[serde(rename = "depsNotifyCVEs")]
     pub deps_notify_cves: bool,
+    /// The minimum severity of the advisories against the dependencies of a crate that flag it as vulnerable
+    /// Advisories for which the severity is unknown always flag the crate.
+    #[serde(rename = "depsCVEsMinSeverity")]
+    pub deps_cves_min_severity: AdvisorySeverityLevel,
+    /// Whether informational advisories, e.g. for unmaintained or unsound crates, flag a crate as vulnerable
+    #[serde(rename = "depsCVEsInformational")]
+    pub deps_cves_informational: bool,
+    /// The minimum severity of the advisories that are notified by email to the owners of a crate
+    #[serde(rename = "depsNotifyCVEsMinSeverity")]
+    pub deps_notify_cves_min_severity: AdvisorySeverityLevel,
+    /// Whether to send a notification by email to the owners of a crate when its dependencies violate the license policy
+    #[serde(rename = "depsNotifyLicenses")]
+    pub deps_notify_licenses: bool,
+    /// Whether to act as a pull-through caching proxy for crates.io
+    #[serde(rename = "proxyCratesIo")]
+    pub proxy_crates_io: bool,
+    /// Number of milliseconds after which a cached index file for the crates.io proxy is deemed stale and must be fetched again
+    #[serde(rename = "proxyStaleIndex")]
+    pub proxy_stale_index: u64,
+    /// What to do when the name of a new crate could be confused with an existing or popular crate
+    #[serde(rename = "typosquatPolicy")]
+    pub typosquat_policy: TyposquatPolicy,
+    /// Additional names of popular crates that the names of new crates are compared to
+    #[serde(rename = "typosquatPopularNames")]
+    pub typosquat_popular_names: Vec<String>,
+    /// The number of the most downloaded crates on crates.io to fetch every day and compare the names of new crates to
+    #[serde(rename = "typosquatPopularCount")]
+    pub typosquat_popular_count: usize,
+    /// Whether to send a notification by email to the owners of tokens that are about to expire
+    #[serde(rename = "tokenExpiryNotify")]
+    pub token_expiry_notify: bool,
+    /// Number of days before the expiration of a token when its owners are notified
+    #[serde(rename = "tokenExpiryNotice")]
+    pub token_expiry_notice: i64,
+    /// The maximum validity for new tokens, in days, if any
+    /// When set, all new tokens expire, at the latest after this number of days
+    #[serde(rename = "tokenMaxValidity")]
+    pub token_max_validity: Option<i64>,
+    /// Number of minutes during which the previous secret of a rotated token remains valid
+    #[serde(rename = "tokenRotationGrace")]
+    pub token_rotation_grace: i64,
     /// The configuration for sending emails
     pub email: EmailConfig,
     /// The name to use for the local registry in cargo and git config
@@ -584,6 +765,7 @@ impl Default for Configuration {
             data_dir: String::from("/data"),
             index: IndexConfig {
                 home_dir: String::from("/home/cratery"),
+                backend: IndexBackend::Git,
                 location: String::from("/data/index"),
                 allow_protocol_git: true,
                 allow_protocol_sparse: true,
@@ -600,6 +782,7 @@ impl Default for Configuration {
             },
             storage: StorageConfig::FileSystem { retry_params: None },
             storage_timeout: 3000,
+            oauth_issuer_uri: None,
             oauth_login_uri: String::new(),
             oauth_token_uri: String::new(),
             oauth_callback_uri: String::new(),
@@ -609,14 +792,29 @@ impl Default for Configuration {
             oauth_client_id: String::new(),
             oauth_client_secret: String::new(),
             oauth_client_scope: String::new(),
+            oauth_claim_mappings: Vec::new(),
             external_registries: Vec::new(),
             docs_gen_mock: true,
             docs_autoinstall_targets: false,
             deps_check_period: 60,
             deps_stale_registry: 60 * 1000,
+            deps_advisory_db: AdvisoryDbSource::default(),
             deps_stale_analysis: 24 * 60,
             deps_notify_outdated: false,
             deps_notify_cves: false,
+            deps_cves_min_severity: AdvisorySeverityLevel::None,
+            deps_cves_informational: true,
+            deps_notify_cves_min_severity: AdvisorySeverityLevel::None,
+            deps_notify_licenses: false,
+            proxy_crates_io: false,
+            proxy_stale_index: 10 * 60 * 1000,
+            typosquat_policy: TyposquatPolicy::Warn,
+            typosquat_popular_names: Vec::new(),
+            typosquat_popular_count: 1000,
+            token_expiry_notify: false,
+            token_expiry_notice: 7,
+            token_max_validity: None,
+            token_rotation_grace: 24 * 60,
             email: EmailConfig::default(),
             self_local_name: String::from("localhost"),
             self_service_login: String::new(),
@@ -639,6 +837,7 @@ impl Configuration {
     /// # Errors
     ///
     /// Return a `VarError` when an expected environment variable is not present
+    #[expect(clippy::too_many_lines)]
     pub async fn from_env() -> Result<Self, MissingEnvVar> {
         let home_dir = get_var("REGISTRY_HOME_DIR")
             .or_else(|_| get_var("HOME"))
@@ -659,7 +858,9 @@ impl Configuration {
         let storage = StorageConfig::from_env()?;
         let deps_notify_outdated = get_var("REGISTRY_DEPS_NOTIFY_OUTDATED").is_ok_and(|v| v == "true");
         let deps_notify_cves = get_var("REGISTRY_DEPS_NOTIFY_CVES").is_ok_and(|v| v == "true");
-        let email = if deps_notify_outdated || deps_notify_cves {
+        let deps_notify_licenses = get_var("REGISTRY_DEPS_NOTIFY_LICENSES").is_ok_and(|v| v == "true");
+        let token_expiry_notify = get_var("REGISTRY_TOKEN_EXPIRY_NOTIFY").is_ok_and(|v| v == "true");
+        let email = if deps_notify_outdated || deps_notify_cves || deps_notify_licenses || token_expiry_notify {
             EmailConfig::from_env()?
         } else {
             EmailConfig::default()
@@ -670,7 +871,32 @@ impl Configuration {
             external_registries.push(registry);
             external_registry_index += 1;
         }
+        let mut oauth_claim_mappings = Vec::new();
+        let mut oauth_claim_mapping_index = 1;
+        while let Some(mapping) = OAuthClaimMapping::from_env(oauth_claim_mapping_index) {
+            oauth_claim_mappings.push(mapping);
+            oauth_claim_mapping_index += 1;
+        }
         let self_role = NodeRole::from_env()?;
+        let typosquat_popular_names = match get_var("REGISTRY_TYPOSQUAT_POPULAR_NAMES") {
+            Ok(path) => parse_popular_names(
+                &fs::read_to_string(&path)
+                    .await
+                    .expect("failed to read REGISTRY_TYPOSQUAT_POPULAR_NAMES"),
+            ),
+            Err(_) => Vec::new(),
+        };
+        // with OIDC, the endpoints are discovered from the issuer
+        let oauth_issuer_uri = get_var("REGISTRY_OAUTH_ISSUER_URI").ok();
+        let get_oauth_endpoint = |name: &str| {
+            if oauth_issuer_uri.is_some() {
+                Ok(get_var(name).unwrap_or_default())
+            } else {
+                get_var(name)
+            }
+        };
+        let oauth_callback_uri =
+            get_var("REGISTRY_OAUTH_CALLBACK_URI").unwrap_or_else(|_| format!("{web_public_uri}/webapp/oauthcallback.html"));
         Ok(Self {
             log_level: get_var("REGISTRY_LOG_LEVEL").unwrap_or_else(|_| String::from("INFO")),
             log_datetime_format: get_var("REGISTRY_LOG_DATE_TIME_FORMAT")
@@ -692,26 +918,59 @@ impl Configuration {
             storage,
             storage_timeout: get_var("REGISTRY_STORAGE_TIMEOUT")
                 .map_or(3000, |s| s.parse().expect("invalid REGISTRY_STORAGE_TIMEOUT")),
-            oauth_login_uri: get_var("REGISTRY_OAUTH_LOGIN_URI")?,
-            oauth_token_uri: get_var("REGISTRY_OAUTH_TOKEN_URI")?,
-            oauth_callback_uri: get_var("REGISTRY_OAUTH_CALLBACK_URI")?,
-            oauth_userinfo_uri: get_var("REGISTRY_OAUTH_USERINFO_URI")?,
+            oauth_login_uri: get_oauth_endpoint("REGISTRY_OAUTH_LOGIN_URI")?,
+            oauth_token_uri: get_oauth_endpoint("REGISTRY_OAUTH_TOKEN_URI")?,
+            oauth_callback_uri,
+            oauth_userinfo_uri: get_oauth_endpoint("REGISTRY_OAUTH_USERINFO_URI")?,
             oauth_userinfo_path_email: get_var("REGISTRY_OAUTH_USERINFO_PATH_EMAIL").unwrap_or_else(|_| String::from("email")),
             oauth_userinfo_path_fullname: get_var("REGISTRY_OAUTH_USERINFO_PATH_FULLNAME")
                 .unwrap_or_else(|_| String::from("name")),
             oauth_client_id: get_var("REGISTRY_OAUTH_CLIENT_ID")?,
             oauth_client_secret: get_var("REGISTRY_OAUTH_CLIENT_SECRET")?,
-            oauth_client_scope: get_var("REGISTRY_OAUTH_CLIENT_SCOPE")?,
+            oauth_client_scope: if oauth_issuer_uri.is_some() {
+                get_var("REGISTRY_OAUTH_CLIENT_SCOPE").unwrap_or_else(|_| String::from("openid email profile"))
+            } else {
+                get_var("REGISTRY_OAUTH_CLIENT_SCOPE")?
+            },
+            oauth_issuer_uri,
+            oauth_claim_mappings,
             docs_gen_mock: get_var("REGISTRY_DOCS_GEN_MOCK").is_ok_and(|v| v == "true"),
             docs_autoinstall_targets: get_var("REGISTRY_DOCS_AUTOINSTALL_TARGETS").is_ok_and(|v| v == "true"),
             deps_check_period: get_var("REGISTRY_DEPS_CHECK_PERIOD")
                 .map_or(60, |s| s.parse().expect("invalid REGISTRY_DEPS_CHECK_PERIOD")), // 1 minute
             deps_stale_registry: get_var("REGISTRY_DEPS_STALE_REGISTRY")
                 .map_or(60 * 1000, |s| s.parse().expect("invalid REGISTRY_DEPS_STALE_REGISTRY")), // 1 minute
+            deps_advisory_db: AdvisoryDbSource::from_env()?,
             deps_stale_analysis: get_var("REGISTRY_DEPS_STALE_ANALYSIS")
                 .map_or(24 * 60, |s| s.parse().expect("invalid REGISTRY_DEPS_STALE_ANALYSIS")), // 24 hours
             deps_notify_outdated,
             deps_notify_cves,
+            deps_cves_min_severity: get_var("REGISTRY_DEPS_CVES_MIN_SEVERITY").map_or(AdvisorySeverityLevel::None, |s| {
+                AdvisorySeverityLevel::parse(&s).expect("invalid REGISTRY_DEPS_CVES_MIN_SEVERITY")
+            }),
+            deps_cves_informational: get_var("REGISTRY_DEPS_CVES_INFORMATIONAL").map_or(true, |v| v == "true"),
+            deps_notify_cves_min_severity: get_var("REGISTRY_DEPS_NOTIFY_CVES_MIN_SEVERITY")
+                .map_or(AdvisorySeverityLevel::None, |s| {
+                    AdvisorySeverityLevel::parse(&s).expect("invalid REGISTRY_DEPS_NOTIFY_CVES_MIN_SEVERITY")
+                }),
+            deps_notify_licenses,
+            proxy_crates_io: get_var("REGISTRY_PROXY_CRATES_IO").is_ok_and(|v| v == "true"),
+            proxy_stale_index: get_var("REGISTRY_PROXY_STALE_INDEX")
+                .map_or(10 * 60 * 1000, |s| s.parse().expect("invalid REGISTRY_PROXY_STALE_INDEX")), // 10 minutes
+            typosquat_policy: get_var("REGISTRY_TYPOSQUAT_POLICY").map_or(TyposquatPolicy::Warn, |s| {
+                TyposquatPolicy::parse(&s).expect("invalid REGISTRY_TYPOSQUAT_POLICY")
+            }),
+            typosquat_popular_names,
+            typosquat_popular_count: get_var("REGISTRY_TYPOSQUAT_POPULAR_COUNT")
+                .map_or(1000, |s| s.parse().expect("invalid REGISTRY_TYPOSQUAT_POPULAR_COUNT")),
+            token_expiry_notify,
+            token_expiry_notice: get_var("REGISTRY_TOKEN_EXPIRY_NOTICE")
+                .map_or(7, |s| s.parse().expect("invalid REGISTRY_TOKEN_EXPIRY_NOTICE")), // 7 days
+            token_max_validity: get_var("REGISTRY_TOKEN_MAX_VALIDITY")
+                .ok()
+                .map(|s| s.parse().expect("invalid REGISTRY_TOKEN_MAX_VALIDITY")),
+            token_rotation_grace: get_var("REGISTRY_TOKEN_ROTATION_GRACE")
+                .map_or(24 * 60, |s| s.parse().expect("invalid REGISTRY_TOKEN_ROTATION_GRACE")), // 24 hours
             email,
             self_local_name,
             self_service_login: generate_token(16),
@@ -4,20 +4,25 @@
 
 //! Data types around dependency analysis
 
-use chrono::NaiveDateTime;
+use chrono::{Local, NaiveDateTime};
 use log::error;
 use semver::{Version, VersionReq};
 use serde_derive::{Deserialize, Serialize};
 
 use super::CrateVersion;
+use super::advisories::{AdvisorySeverityLevel, AdvisorySuppression};
 use super::cargo::{DependencyKind, IndexCrateDependency, IndexCrateMetadata};
 use super::osv::SimpleAdvisory;
+use super::policy::LicensePolicy;
 use crate::utils::apierror::ApiError;
 use crate::utils::push_if_not_present;
 
 /// The URI of the fake registry for built-in crates
 pub const BUILTIN_CRATES_REGISTRY_URI: &str = "<builtin>";
 
+/// The URI identifying crates.io as the registry for a dependency
+pub const CRATES_IO_REGISTRY_URI: &str = "https://github.com/rust-lang/crates.io-index";
+
 /// The list of built-in crates
 pub const BUILTIN_CRATES_LIST: &[&str] = &["core", "alloc", "std"];
 
@@ -79,12 +84,23 @@ pub struct DepsAnalysis {
     pub direct_dependencies: Vec<DirectDepInfo>,
     /// The advisories against dependencies
     pub advisories: Vec<DepAdvisory>,
+    /// The licenses of all the crates in the resolved graph, excluding dev-dependencies
+    #[serde(default)]
+    pub licenses: Vec<DepLicense>,
+    /// All the crate versions in the resolved graph
+    #[serde(default)]
+    pub resolutions: Vec<DepResolution>,
 }
 
 impl DepsAnalysis {
     /// Creates the analysis
     #[must_use]
-    pub fn new(graph: &DepsGraph, deps: &[IndexCrateDependency], advisories: Vec<DepAdvisory>) -> Self {
+    pub fn new(
+        graph: &DepsGraph,
+        deps: &[IndexCrateDependency],
+        advisories: Vec<DepAdvisory>,
+        licenses: Vec<DepLicense>,
+    ) -> Self {
         Self {
             direct_dependencies: deps
                 .iter()
@@ -106,8 +122,72 @@ impl DepsAnalysis {
                 })
                 .collect(),
             advisories,
+            licenses,
+            resolutions: graph
+                .crates
+                .iter()
+                .filter(|data| data.registry.as_deref() != Some(BUILTIN_CRATES_REGISTRY_URI))
+                .flat_map(|data| {
+                    data.resolutions.iter().map(|resolution| DepResolution {
+                        registry: data.registry.clone(),
+                        package: data.name.clone(),
+                        version: data.versions[resolution.version_index].semver.to_string(),
+                        is_direct: resolution
+                            .origins
+                            .iter()
+                            .any(|origin| matches!(origin, DepsGraphCrateOrigin::Direct(_))),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Evaluates the licenses of the dependencies against a license policy
+    pub fn check_licenses(&mut self, policy: &LicensePolicy) {
+        for dep in &mut self.licenses {
+            dep.violation = match dep.license.as_deref() {
+                Some(license) => policy.evaluate(license),
+                // an unknown license cannot be proven to be allowed
+                None if !policy.allowed.is_empty() => Some(String::from(
+                    "the license is unknown and cannot be checked against the allowed licenses",
+                )),
+                None => None,
+            };
         }
     }
+
+    /// Marks the advisories that are suppressed for the version of the analysed crate
+    pub fn apply_suppressions(&mut self, version: &str, suppressions: &[AdvisorySuppression]) {
+        let now = Local::now().naive_local();
+        for advisory in &mut self.advisories {
+            advisory.suppression = suppressions
+                .iter()
+                .find(|suppression| suppression.applies_to(version, &advisory.content.id, now))
+                .cloned();
+        }
+    }
+
+    /// Flags the advisories that are not suppressed and that reach the thresholds
+    /// The suppressions must be applied beforehand.
+    pub fn flag_advisories(&mut self, min_severity: AdvisorySeverityLevel, informational: bool) {
+        for advisory in &mut self.advisories {
+            advisory.is_flagged = advisory.suppression.is_none()
+                && (informational || advisory.content.informational.is_none())
+                && advisory.reaches_severity(min_severity);
+        }
+    }
+
+    /// Gets whether some advisories against the dependencies are flagged
+    #[must_use]
+    pub fn has_cves(&self) -> bool {
+        self.advisories.iter().any(|advisory| advisory.is_flagged)
+    }
+
+    /// Gets whether some dependencies violate the license policy
+    #[must_use]
+    pub fn has_license_violations(&self) -> bool {
+        self.licenses.iter().any(|dep| dep.violation.is_some())
+    }
 }
 
 /// The information about a direct dependency, resulting from an analysis
@@ -138,6 +218,79 @@ pub struct DepAdvisory {
     pub version: Version,
     /// The advisory itself
     pub content: SimpleAdvisory,
+    /// The suppression of the advisory by the owners of the analysed crate, if any
+    #[serde(default)]
+    pub suppression: Option<AdvisorySuppression>,
+    /// Whether the advisory flags the analysed crate as vulnerable
+    #[serde(default, rename = "isFlagged")]
+    pub is_flagged: bool,
+}
+
+impl DepAdvisory {
+    /// Gets whether the severity of the advisory reaches a minimum level
+    /// Advisories for which the level is unknown always reach it.
+    #[must_use]
+    pub fn reaches_severity(&self, min_severity: AdvisorySeverityLevel) -> bool {
+        self.content.severity_level.is_none_or(|level| level >= min_severity)
+    }
+}
+
+/// A crate version in the resolved graph of dependencies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepResolution {
+    /// URI for the owning registry, `None` for the local one
+    pub registry: Option<String>,
+    /// The name of the package
+    pub package: String,
+    /// The resolved version
+    pub version: String,
+    /// Whether this is a direct dependency
+    #[serde(rename = "isDirect")]
+    pub is_direct: bool,
+}
+
+/// The impact of an advisory on the crates of the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvisoryImpact {
+    /// The advisory
+    pub advisory: SimpleAdvisory,
+    /// The crate versions that resolve to an affected version in their dependency graph
+    pub impacted: Vec<AdvisoryImpactedVersion>,
+}
+
+/// A crate version impacted by an advisory, as found in its last dependency analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvisoryImpactedVersion {
+    /// The name of the impacted crate
+    pub package: String,
+    /// The impacted version
+    pub version: String,
+    /// The resolved version of the affected crate
+    pub resolved: String,
+    /// Whether the affected crate is a direct dependency
+    #[serde(rename = "isDirect")]
+    pub is_direct: bool,
+    /// Whether this is the latest version of the impacted crate
+    #[serde(rename = "isLatest")]
+    pub is_latest: bool,
+    /// When the dependencies of the impacted version were last analyzed
+    #[serde(rename = "depsLastCheck")]
+    pub deps_last_check: NaiveDateTime,
+}
+
+/// The license of a crate in the resolved graph of dependencies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepLicense {
+    /// URI for the owning registry, `None` for the local one
+    pub registry: Option<String>,
+    /// The name of the package
+    pub package: String,
+    /// The resolved version
+    pub version: String,
+    /// The SPDX license expression, if known
+    pub license: Option<String>,
+    /// The violation of the license policy, if any
+    pub violation: Option<String>,
 }
 
 impl IndexCrateMetadata {
@@ -4,6 +4,7 @@
 
 //! Data model
 
+pub mod advisories;
 pub mod auth;
 pub mod cargo;
 pub mod config;
@@ -13,7 +14,12 @@ pub mod errors;
 pub mod namegen;
 pub mod osv;
 pub mod packages;
+pub mod policy;
+pub mod reservations;
+pub mod sbom;
 pub mod stats;
+pub mod teams;
+pub mod typosquat;
 pub mod worker;
 
 use auth::TokenUsage;
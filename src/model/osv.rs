@@ -7,6 +7,8 @@
 use semver::Version;
 use serde_derive::{Deserialize, Serialize};
 
+use super::advisories::{AdvisorySeverityLevel, cvss_score, cvss_type};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdvisorySeverity {
     #[serde(rename = "type")]
@@ -23,16 +25,21 @@ pub struct AdvisoryAffectedPackage {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdvisoryAffectedRangeEvent {
-    introduced: Option<String>,
-    fixed: Option<String>,
-    last_affected: Option<String>,
-    limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub introduced: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixed: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_affected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AdvisoryAffectedRange {
     #[serde(rename = "type")]
     pub type_value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub repo: Option<String>,
     pub events: Vec<AdvisoryAffectedRangeEvent>,
 }
@@ -46,7 +53,9 @@ pub struct AdvisoryAffected {
     pub ranges: Vec<AdvisoryAffectedRange>,
     #[serde(default)]
     pub versions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ecosystem_specific: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub database_specific: Option<serde_json::Value>,
 }
 
@@ -67,11 +76,12 @@ pub struct AdvisoryCredit {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Advisory {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub schema_version: Option<String>,
     pub id: String,
     pub modified: String,
     pub published: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub withdrawn: String,
     #[serde(default)]
     pub aliases: Vec<String>,
@@ -89,6 +99,7 @@ pub struct Advisory {
     pub references: Vec<AdvisoryReference>,
     #[serde(default)]
     pub credits: Vec<AdvisoryCredit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub database_specific: Option<serde_json::Value>,
 }
 
@@ -136,6 +147,21 @@ pub struct SimpleAdvisory {
     pub ranges: Vec<SimpleAdvisoryRange>,
     /// The affected versions
     pub versions: Vec<Version>,
+    /// Whether this advisory was authored in this registry against a local crate
+    #[serde(default, rename = "isInternal")]
+    pub is_internal: bool,
+    /// The other identifiers for the advisory, e.g. CVE or GHSA identifiers
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The CVSS vector for the severity, if any
+    #[serde(default)]
+    pub severity: Option<String>,
+    /// The qualitative severity computed from the CVSS vector, if it can be computed
+    #[serde(default, rename = "severityLevel")]
+    pub severity_level: Option<AdvisorySeverityLevel>,
+    /// The kind of informational advisory, e.g. `unmaintained` or `unsound`, or `None` for a vulnerability
+    #[serde(default)]
+    pub informational: Option<String>,
 }
 
 impl SimpleAdvisory {
@@ -161,6 +187,24 @@ impl TryFrom<Advisory> for SimpleAdvisory {
             .into_iter()
             .find(|affected| affected.package.ecosystem == "crates.io")
             .ok_or(())?;
+        let severities = advisory
+            .severity
+            .iter()
+            .chain(&affected.severity)
+            .map(|severity| severity.score.as_str())
+            .chain(specific_str(affected.database_specific.as_ref(), "cvss"))
+            .filter(|vector| cvss_type(vector).is_some())
+            .collect::<Vec<_>>();
+        // prefer a vector that can be scored
+        let severity = severities
+            .iter()
+            .find(|vector| cvss_score(vector).is_some())
+            .or_else(|| severities.first())
+            .map(|vector| (*vector).to_string());
+        let informational = specific_str(affected.database_specific.as_ref(), "informational")
+            .or_else(|| specific_str(affected.ecosystem_specific.as_ref(), "informational"))
+            .or_else(|| specific_str(advisory.database_specific.as_ref(), "informational"))
+            .map(str::to_string);
         let ranges = affected
             .ranges
             .into_iter()
@@ -190,6 +234,19 @@ impl TryFrom<Advisory> for SimpleAdvisory {
             summary: advisory.summary,
             ranges,
             versions,
+            is_internal: false,
+            aliases: advisory.aliases,
+            severity_level: severity
+                .as_deref()
+                .and_then(cvss_score)
+                .map(AdvisorySeverityLevel::from_score),
+            severity,
+            informational,
         })
     }
 }
+
+/// Gets a string property in the database or ecosystem specific data of an advisory
+fn specific_str<'a>(data: Option<&'a serde_json::Value>, name: &str) -> Option<&'a str> {
+    data?.get(name)?.as_str().filter(|value| !value.is_empty())
+}
@@ -7,7 +7,10 @@
 use chrono::NaiveDateTime;
 use serde_derive::{Deserialize, Serialize};
 
-use super::cargo::{CrateMetadata, IndexCrateMetadata, RegistryUser};
+use super::cargo::{CargoVcsInfo, CrateMetadata, DependencyKind, IndexCrateMetadata, RegistryUser};
+
+/// The prefix for the readers of a crate that designate a role
+pub const READER_ROLE_PREFIX: &str = "role:";
 
 /// Gets the last info for a crate
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +29,8 @@ pub struct CrateInfo {
     pub targets: Vec<CrateInfoTarget>,
     /// The required capabilities for docs generation
     pub capabilities: Vec<String>,
+    /// The readers allowed to see this crate, in addition to its owners, empty when all users can
+    pub readers: Vec<String>,
 }
 
 /// A build targets to use (for docs generation and deps analysis)
@@ -60,8 +65,13 @@ pub struct CrateInfoVersion {
     /// Flag whether CVEs have been filed against dependencies of this crate
     #[serde(rename = "depsHasCVEs")]
     pub deps_has_cves: bool,
+    /// Flag whether dependencies of this crate violate the license policy
+    #[serde(rename = "depsHasLicenseViolations")]
+    pub deps_has_license_violations: bool,
     /// The documentation status
     pub docs: Vec<CrateInfoVersionDocs>,
+    /// The VCS information embedded by cargo, if any
+    pub vcs: Option<CargoVcsInfo>,
 }
 
 /// The documentation status for a crate version
@@ -76,3 +86,32 @@ pub struct CrateInfoVersionDocs {
     #[serde(rename = "isPresent")]
     pub is_present: bool,
 }
+
+/// The crates of the registry that depend on a crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateDependents {
+    /// The current head of the crate, i.e. its latest non-yanked version
+    pub head: Option<String>,
+    /// The versions of other crates that depend on the crate
+    pub dependents: Vec<CrateDependent>,
+}
+
+/// A version of a crate that depends on another crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateDependent {
+    /// The name of the dependent crate
+    pub package: String,
+    /// The version of the dependent crate
+    pub version: String,
+    /// The semver requirement on the crate
+    #[serde(rename = "versionReq")]
+    pub version_req: String,
+    /// The kind of dependency
+    pub kind: DependencyKind,
+    /// Whether this is the latest version of the dependent crate
+    #[serde(rename = "isLatest")]
+    pub is_latest: bool,
+    /// Whether the requirement is satisfied by the current head of the crate
+    #[serde(rename = "isCompatible")]
+    pub is_compatible: bool,
+}
@@ -0,0 +1,481 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data model for the policy evaluated when publishing crates
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::cargo::{CargoVcsInfo, CrateUploadData};
+use crate::utils::comma_sep_to_vec;
+
+/// The kind of a rule in the publish policy
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PublishRuleKind {
+    /// The license must be an SPDX expression satisfiable with the allowed licenses (comma-separated SPDX identifiers)
+    LicenseAllowlist,
+    /// The repository must start with one of the allowed prefixes (comma-separated)
+    RepositoryPrefix,
+    /// The dependencies must not be declared with only a `path` or a `git` source, without a version, in the original manifest
+    NoPathOrGitDependencies,
+    /// The packaged crate must not be larger than the maximum size (in bytes)
+    MaxTarballSize,
+    /// The `rust-version` must be set
+    RustVersionRequired,
+    /// The dependencies must only come from this registry or from the allowed registries (comma-separated URIs of the indices)
+    AllowedRegistries,
+    /// The description must be set
+    DescriptionRequired,
+    /// The crate must be packaged from a git repository without uncommitted changes
+    CleanVcsRequired,
+}
+
+impl PublishRuleKind {
+    /// Gets the name of this kind of rule
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::LicenseAllowlist => "licenseAllowlist",
+            Self::RepositoryPrefix => "repositoryPrefix",
+            Self::NoPathOrGitDependencies => "noPathOrGitDependencies",
+            Self::MaxTarballSize => "maxTarballSize",
+            Self::RustVersionRequired => "rustVersionRequired",
+            Self::AllowedRegistries => "allowedRegistries",
+            Self::DescriptionRequired => "descriptionRequired",
+            Self::CleanVcsRequired => "cleanVcsRequired",
+        }
+    }
+
+    /// Gets whether this kind of rule requires a parameter
+    #[must_use]
+    pub const fn has_parameter(self) -> bool {
+        matches!(
+            self,
+            Self::LicenseAllowlist | Self::RepositoryPrefix | Self::MaxTarballSize | Self::AllowedRegistries
+        )
+    }
+}
+
+impl Display for PublishRuleKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PublishRuleKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::LicenseAllowlist,
+            Self::RepositoryPrefix,
+            Self::NoPathOrGitDependencies,
+            Self::MaxTarballSize,
+            Self::RustVersionRequired,
+            Self::AllowedRegistries,
+            Self::DescriptionRequired,
+            Self::CleanVcsRequired,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == s)
+        .ok_or(())
+    }
+}
+
+/// A rule in the publish policy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublishRule {
+    /// The unique identifier
+    pub id: i64,
+    /// The kind of rule
+    pub kind: PublishRuleKind,
+    /// The parameter for the rule, if required by its kind
+    pub parameter: String,
+    /// Whether a violation rejects the crate, otherwise it is reported as a warning
+    #[serde(rename = "isBlocking")]
+    pub is_blocking: bool,
+}
+
+/// The request to add a rule to the publish policy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublishRuleCreation {
+    /// The kind of rule
+    pub kind: PublishRuleKind,
+    /// The parameter for the rule, if required by its kind
+    #[serde(default)]
+    pub parameter: String,
+    /// Whether a violation rejects the crate, otherwise it is reported as a warning
+    #[serde(rename = "isBlocking")]
+    pub is_blocking: bool,
+}
+
+impl PublishRuleCreation {
+    /// Gets whether the parameter is valid for the kind of rule
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        match self.kind {
+            PublishRuleKind::MaxTarballSize => self.parameter.trim().parse::<u64>().is_ok(),
+            kind if kind.has_parameter() => !comma_sep_to_vec(&self.parameter).is_empty(),
+            _ => true,
+        }
+    }
+}
+
+/// The result of the evaluation of the publish policy for a crate
+#[derive(Debug, Default, Clone)]
+pub struct PublishPolicyReport {
+    /// The violations of the blocking rules
+    pub violations: Vec<String>,
+    /// The violations of the other rules
+    pub warnings: Vec<String>,
+}
+
+impl PublishRule {
+    /// Evaluates this rule on a crate to be published, with the VCS information embedded in the packaged crate, if any
+    /// Returns the description of the violations, if any.
+    #[must_use]
+    pub fn evaluate(&self, package: &CrateUploadData, vcs_info: Option<&CargoVcsInfo>) -> Vec<String> {
+        let metadata = &package.metadata;
+        let parameters = comma_sep_to_vec(&self.parameter);
+        match self.kind {
+            PublishRuleKind::LicenseAllowlist => match metadata.license.as_deref() {
+                None => vec![String::from("the crate has no SPDX license expression")],
+                Some(license) if license_expression_allowed(license, &parameters) => Vec::new(),
+                Some(license) => vec![format!(
+                    "the license `{license}` is not allowed, allowed licenses are {}",
+                    parameters.join(", ")
+                )],
+            },
+            PublishRuleKind::RepositoryPrefix => match metadata.repository.as_deref() {
+                None => vec![String::from("the crate has no repository")],
+                Some(repository) if parameters.iter().any(|prefix| repository_has_prefix(repository, prefix)) => Vec::new(),
+                Some(repository) => vec![format!(
+                    "the repository `{repository}` is not allowed, it must start with {}",
+                    parameters.join(", ")
+                )],
+            },
+            PublishRuleKind::NoPathOrGitDependencies => {
+                // fail closed when the original manifest cannot be checked
+                let Some(manifest) = package.files.manifest_orig.as_deref() else {
+                    return vec![String::from(
+                        "the crate does not contain its original manifest `Cargo.toml.orig`",
+                    )];
+                };
+                let manifest = match toml::from_slice::<toml::Table>(manifest) {
+                    Ok(manifest) => manifest,
+                    Err(error) => {
+                        return vec![format!(
+                            "the original manifest `Cargo.toml.orig` is invalid: {}",
+                            error.message()
+                        )];
+                    }
+                };
+                find_local_dependencies(&manifest, &package.files.manifest)
+                    .into_iter()
+                    .map(|(name, source)| match source {
+                        "workspace" => format!("the dependency `{name}` is inherited from the workspace without a version"),
+                        _ => format!("the dependency `{name}` is declared with a `{source}` source and no version"),
+                    })
+                    .collect()
+            }
+            PublishRuleKind::MaxTarballSize => {
+                let maximum = self.parameter.trim().parse::<u64>().unwrap_or(u64::MAX);
+                let size = package.content.len() as u64;
+                if size > maximum {
+                    vec![format!(
+                        "the packaged crate is {size} bytes, more than the maximum of {maximum} bytes"
+                    )]
+                } else {
+                    Vec::new()
+                }
+            }
+            PublishRuleKind::RustVersionRequired => {
+                if metadata.rust_version.as_deref().is_none_or(str::is_empty) {
+                    vec![String::from("the crate does not declare a `rust-version`")]
+                } else {
+                    Vec::new()
+                }
+            }
+            PublishRuleKind::AllowedRegistries => metadata
+                .deps
+                .iter()
+                .filter_map(|dep| {
+                    dep.registry
+                        .as_ref()
+                        .filter(|registry| !parameters.contains(registry))
+                        .map(|registry| {
+                            format!(
+                                "the dependency `{}` comes from the registry `{registry}` that is not allowed",
+                                dep.name
+                            )
+                        })
+                })
+                .collect(),
+            PublishRuleKind::DescriptionRequired => {
+                if metadata.description.as_deref().is_none_or(|d| d.trim().is_empty()) {
+                    vec![String::from("the crate has no description")]
+                } else {
+                    Vec::new()
+                }
+            }
+            PublishRuleKind::CleanVcsRequired => match vcs_info.and_then(|info| info.git.as_ref()) {
+                None => vec![String::from("the crate was not packaged from a git repository")],
+                Some(git) if git.dirty => vec![format!(
+                    "the crate was packaged from a git repository with uncommitted changes (on commit {})",
+                    git.sha1
+                )],
+                Some(_) => Vec::new(),
+            },
+        }
+    }
+}
+
+/// Evaluates the rules of the publish policy on a crate to be published
+#[must_use]
+pub fn evaluate_publish_policy(
+    rules: &[PublishRule],
+    package: &CrateUploadData,
+    vcs_info: Option<&CargoVcsInfo>,
+) -> PublishPolicyReport {
+    let mut report = PublishPolicyReport::default();
+    for rule in rules {
+        let violations = rule.evaluate(package, vcs_info);
+        if rule.is_blocking {
+            report.violations.extend(violations);
+        } else {
+            report.warnings.extend(violations);
+        }
+    }
+    report
+}
+
+/// A rule of the license policy for the dependencies of crates
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LicenseRule {
+    /// The unique identifier
+    pub id: i64,
+    /// The SPDX license identifier, or a complete SPDX expression
+    pub license: String,
+    /// Whether the license is allowed, otherwise it is denied
+    #[serde(rename = "isAllowed")]
+    pub is_allowed: bool,
+}
+
+/// The request to add a rule to the license policy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LicenseRuleCreation {
+    /// The SPDX license identifier, or a complete SPDX expression
+    pub license: String,
+    /// Whether the license is allowed, otherwise it is denied
+    #[serde(rename = "isAllowed")]
+    pub is_allowed: bool,
+}
+
+/// The license policy for the dependencies of crates, built from its rules
+///
+/// When some licenses are allowed, the license of each dependency must be satisfiable using only these licenses.
+/// In all cases, the license of each dependency must be satisfiable without using the denied licenses.
+#[derive(Debug, Default, Clone)]
+pub struct LicensePolicy {
+    /// The allowed licenses
+    pub allowed: Vec<String>,
+    /// The denied licenses
+    pub denied: Vec<String>,
+}
+
+impl LicensePolicy {
+    /// Builds the policy from its rules
+    #[must_use]
+    pub fn new(rules: &[LicenseRule]) -> Self {
+        let (allowed, denied): (Vec<_>, Vec<_>) = rules.iter().partition(|rule| rule.is_allowed);
+        Self {
+            allowed: allowed.into_iter().map(|rule| rule.license.clone()).collect(),
+            denied: denied.into_iter().map(|rule| rule.license.clone()).collect(),
+        }
+    }
+
+    /// Evaluates the license of a dependency against this policy
+    /// Returns the description of the violation, if any.
+    #[must_use]
+    pub fn evaluate(&self, license: &str) -> Option<String> {
+        let matches = |candidate: &String, license: &str| {
+            candidate.split_whitespace().eq(license.split_whitespace()) || candidate.eq_ignore_ascii_case(license.trim())
+        };
+        if let Some(denied) = self.denied.iter().find(|candidate| matches(candidate, license)) {
+            return Some(format!("the license `{denied}` is denied"));
+        }
+        if self.allowed.iter().any(|candidate| matches(candidate, license)) {
+            return None;
+        }
+        let is_satisfiable = license_expression_satisfiable(license, &|id| {
+            !self.denied.iter().any(|candidate| candidate.eq_ignore_ascii_case(id))
+                && (self.allowed.is_empty() || self.allowed.iter().any(|candidate| candidate.eq_ignore_ascii_case(id)))
+        });
+        if is_satisfiable {
+            None
+        } else if self.allowed.is_empty() {
+            Some(format!(
+                "the license `{license}` requires a denied license among {}",
+                self.denied.join(", ")
+            ))
+        } else {
+            Some(format!(
+                "the license `{license}` is not satisfiable with the allowed licenses"
+            ))
+        }
+    }
+}
+
+/// Gets whether an SPDX license expression can be satisfied using only the allowed licenses
+///
+/// `OR` expressions (and the legacy `/` separator) require one of their alternatives to be allowed,
+/// `AND` expressions require all their members to be allowed, exceptions introduced by `WITH` are ignored.
+/// Identifiers are compared without regard to case.
+#[must_use]
+pub fn license_expression_allowed(expression: &str, allowed: &[String]) -> bool {
+    license_expression_satisfiable(expression, &|license| {
+        allowed.iter().any(|candidate| candidate.eq_ignore_ascii_case(license))
+    })
+}
+
+/// Gets whether an SPDX license expression can be satisfied using only the licenses accepted by a predicate
+/// The predicate receives the license identifiers, without the `+` suffix.
+#[must_use]
+pub fn license_expression_satisfiable(expression: &str, accept: &dyn Fn(&str) -> bool) -> bool {
+    let expression = expression.replace('/', " OR ").replace('(', " ( ").replace(')', " ) ");
+    let tokens = expression.split_whitespace().collect::<Vec<_>>();
+    let mut position = 0;
+    let result = parse_license_or(&tokens, &mut position, accept);
+    result.unwrap_or(false) && position == tokens.len()
+}
+
+/// Parses and evaluates a disjunction of licenses
+fn parse_license_or(tokens: &[&str], position: &mut usize, accept: &dyn Fn(&str) -> bool) -> Option<bool> {
+    let mut result = parse_license_and(tokens, position, accept)?;
+    while tokens.get(*position).is_some_and(|token| token.eq_ignore_ascii_case("OR")) {
+        *position += 1;
+        result |= parse_license_and(tokens, position, accept)?;
+    }
+    Some(result)
+}
+
+/// Parses and evaluates a conjunction of licenses
+fn parse_license_and(tokens: &[&str], position: &mut usize, accept: &dyn Fn(&str) -> bool) -> Option<bool> {
+    let mut result = parse_license_term(tokens, position, accept)?;
+    while tokens.get(*position).is_some_and(|token| token.eq_ignore_ascii_case("AND")) {
+        *position += 1;
+        result &= parse_license_term(tokens, position, accept)?;
+    }
+    Some(result)
+}
+
+/// Parses and evaluates a single license, or a parenthesized expression
+fn parse_license_term(tokens: &[&str], position: &mut usize, accept: &dyn Fn(&str) -> bool) -> Option<bool> {
+    let token = *tokens.get(*position)?;
+    *position += 1;
+    if token == "(" {
+        let result = parse_license_or(tokens, position, accept)?;
+        if tokens.get(*position) != Some(&")") {
+            return None;
+        }
+        *position += 1;
+        return Some(result);
+    }
+    if token == ")" || ["AND", "OR", "WITH"].iter().any(|op| token.eq_ignore_ascii_case(op)) {
+        return None;
+    }
+    if tokens.get(*position).is_some_and(|token| token.eq_ignore_ascii_case("WITH")) {
+        // skip the exception
+        tokens.get(*position + 1)?;
+        *position += 2;
+    }
+    Some(accept(token.trim_end_matches('+')))
+}
+
+/// Gets whether a repository starts with an allowed prefix, on a path segment boundary
+///
+/// For example, `https://git.acme.com/team` allows `https://git.acme.com/team/repo`, but not `https://git.acme.com/team-evil`.
+#[must_use]
+pub fn repository_has_prefix(repository: &str, prefix: &str) -> bool {
+    repository.strip_prefix(prefix).is_some_and(|rest| {
+        rest.is_empty() || prefix.ends_with('/') || rest.starts_with('/') || rest.starts_with('#') || rest.starts_with('?')
+    })
+}
+
+/// Finds the dependencies that can only be resolved from a `path` or a `git` source in an original manifest
+///
+/// A dependency declared with such a source and a `version` is published with the version and is not reported.
+/// The dev-dependencies are not reported either, cargo removes those without a version from the published crate.
+/// The dependencies inherited from the workspace are looked up in the normalized manifest, where they must have a version.
+/// Returns the names of the dependencies with the kind of their source.
+#[must_use]
+pub fn find_local_dependencies(manifest: &toml::Table, normalized: &toml::Table) -> Vec<(String, &'static str)> {
+    let mut results = Vec::new();
+    collect_local_dependencies(manifest, Some(normalized), &mut results);
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        let normalized_targets = normalized.get("target").and_then(toml::Value::as_table);
+        for (target, table) in targets {
+            let Some(table) = table.as_table() else {
+                continue;
+            };
+            let normalized = normalized_targets
+                .and_then(|targets| targets.get(target))
+                .and_then(toml::Value::as_table);
+            collect_local_dependencies(table, normalized, &mut results);
+        }
+    }
+    results
+}
+
+/// Collects the dependencies that can only be resolved from a `path` or a `git` source in the tables of dependencies of a table
+/// The inherited dependencies are looked up in the matching table of the normalized manifest and reported when missing from it.
+fn collect_local_dependencies(
+    table: &toml::Table,
+    normalized: Option<&toml::Table>,
+    results: &mut Vec<(String, &'static str)>,
+) {
+    for key in ["dependencies", "build-dependencies", "build_dependencies"] {
+        let Some(dependencies) = table.get(key).and_then(toml::Value::as_table) else {
+            continue;
+        };
+        // cargo normalizes the names of the tables with dashes
+        let normalized = normalized.and_then(|normalized| {
+            normalized
+                .get(&key.replace('_', "-"))
+                .or_else(|| normalized.get(key))
+                .and_then(toml::Value::as_table)
+        });
+        for (name, spec) in dependencies {
+            let Some(spec) = spec.as_table() else {
+                // a version requirement
+                continue;
+            };
+            let source = if spec.get("workspace").and_then(toml::Value::as_bool) == Some(true) {
+                let has_version = normalized
+                    .and_then(|normalized| normalized.get(name))
+                    .is_some_and(|spec| spec.is_str() || spec.get("version").is_some());
+                (!has_version).then_some("workspace")
+            } else if spec.contains_key("version") {
+                None
+            } else {
+                spec.keys().find_map(|key| local_source(key))
+            };
+            if let Some(source) = source {
+                results.push((name.clone(), source));
+            }
+        }
+    }
+}
+
+/// Gets the kind of local source for a key in the specification of a dependency
+fn local_source(key: &str) -> Option<&'static str> {
+    match key {
+        "path" => Some("path"),
+        "git" => Some("git"),
+        _ => None,
+    }
+}
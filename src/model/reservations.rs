@@ -0,0 +1,76 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data model for the reservations of crate names
+
+use serde_derive::{Deserialize, Serialize};
+
+/// A reservation of the names of new crates for a user or a team
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NameReservation {
+    /// The unique identifier
+    pub id: i64,
+    /// The pattern for the reserved names, e.g. `acme-payments-*`
+    pub pattern: String,
+    /// The holder of the reservation, the email of a user or the login of a team (`team:<name>`)
+    pub owner: String,
+}
+
+impl NameReservation {
+    /// Gets whether the name of a crate matches this reservation
+    #[must_use]
+    pub fn matches(&self, package: &str) -> bool {
+        name_pattern_matches(&self.pattern, package)
+    }
+}
+
+/// The request to create a reservation of crate names
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NameReservationCreation {
+    /// The pattern for the reserved names
+    pub pattern: String,
+    /// The holder of the reservation, the email of a user or the login of a team (`team:<name>`)
+    pub owner: String,
+}
+
+/// Gets whether a pattern for reserved names is valid
+/// The pattern is made of the characters of crate names and of the `*` and `?` wildcards, with at least one character.
+#[must_use]
+pub fn is_valid_name_pattern(pattern: &str) -> bool {
+    pattern.chars().any(|c| c.is_ascii_alphanumeric())
+        && pattern
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '*' || c == '?')
+}
+
+/// Gets whether a crate name matches a pattern for reserved names
+///
+/// `*` matches any sequence of characters and `?` a single character.
+/// The comparison is case-insensitive and does not distinguish `-` from `_`, as for crate names.
+#[must_use]
+pub fn name_pattern_matches(pattern: &str, package: &str) -> bool {
+    let normalize = |s: &str| s.to_ascii_lowercase().replace('_', "-").into_bytes();
+    let pattern = normalize(pattern);
+    let package = normalize(package);
+    let (mut p, mut n) = (0, 0);
+    // the position of the last `*` in the pattern and the matching position in the name
+    let mut backtrack = None;
+    while n < package.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == package[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // let the last `*` match one more character
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
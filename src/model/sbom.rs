@@ -0,0 +1,374 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data types for the software bills of materials (SBOM) of crate versions
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use semver::VersionReq;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::cargo::{DependencyKind, IndexCrateDependency, IndexCrateMetadata};
+use super::deps::{BUILTIN_CRATES_REGISTRY_URI, CRATES_IO_REGISTRY_URI, DepsGraph, DepsGraphCrateOrigin};
+
+/// The format of a SBOM document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SbomFormat {
+    /// `CycloneDX` 1.5, in JSON
+    CycloneDx,
+    /// SPDX 2.3, in JSON
+    Spdx,
+}
+
+impl SbomFormat {
+    /// All the supported formats
+    pub const ALL: [Self; 2] = [Self::CycloneDx, Self::Spdx];
+
+    /// Gets the name of this format
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::CycloneDx => "cyclonedx",
+            Self::Spdx => "spdx",
+        }
+    }
+
+    /// Gets the MIME type for documents in this format
+    #[must_use]
+    pub const fn content_type(self) -> &'static str {
+        match self {
+            Self::CycloneDx => "application/vnd.cyclonedx+json",
+            Self::Spdx => "application/spdx+json",
+        }
+    }
+}
+
+impl Display for SbomFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SbomFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// The query for getting a SBOM
+#[derive(Debug, Clone, Deserialize)]
+pub struct SbomQuery {
+    /// The requested format, `cyclonedx` by default
+    pub format: Option<String>,
+}
+
+/// A dependency between two components of a SBOM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SbomDependency {
+    /// The index of the component that is depended upon
+    pub component: usize,
+    /// Whether this is only required for the build
+    #[serde(rename = "isBuild")]
+    pub is_build: bool,
+}
+
+/// A component in a SBOM, i.e. a crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SbomComponent {
+    /// URI for the owning registry, `None` for the local one
+    pub registry: Option<String>,
+    /// The name of the crate
+    pub name: String,
+    /// The version of the crate
+    pub version: String,
+    /// The SHA-256 checksum of the packaged crate
+    pub checksum: String,
+    /// The SPDX license expression, if known
+    pub license: Option<String>,
+    /// The package URL
+    pub purl: String,
+    /// Whether this component is only required for the build
+    #[serde(rename = "isBuildOnly")]
+    pub is_build_only: bool,
+    /// The dependencies of this component
+    pub dependencies: Vec<SbomDependency>,
+}
+
+impl SbomComponent {
+    /// Creates a component for a crate version
+    /// `local_registry` is the URI of the index for this registry.
+    #[must_use]
+    pub fn new(registry: Option<&str>, metadata: &IndexCrateMetadata, local_registry: &str) -> Self {
+        Self {
+            registry: registry.map(str::to_string),
+            name: metadata.name.clone(),
+            version: metadata.vers.clone(),
+            checksum: metadata.cksum.clone(),
+            license: None,
+            purl: build_purl(registry, &metadata.name, &metadata.vers, local_registry),
+            is_build_only: false,
+            dependencies: Vec::new(),
+        }
+    }
+}
+
+/// Builds the package URL for a crate version
+#[must_use]
+pub fn build_purl(registry: Option<&str>, name: &str, version: &str, local_registry: &str) -> String {
+    match registry {
+        Some(CRATES_IO_REGISTRY_URI) => format!("pkg:cargo/{name}@{version}"),
+        registry => format!(
+            "pkg:cargo/{name}@{version}?repository_url={}",
+            urlencoding::encode(registry.unwrap_or(local_registry))
+        ),
+    }
+}
+
+/// The software bill of materials for a crate version, independent of the format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sbom {
+    /// The crate version the SBOM is for
+    pub root: SbomComponent,
+    /// The components in the resolved graph of dependencies, excluding dev-dependencies and built-in crates
+    pub components: Vec<SbomComponent>,
+    /// The targets used for the resolution
+    pub targets: Vec<String>,
+    /// The timestamp for the generation of this SBOM
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Sbom {
+    /// Builds the SBOM for a crate version from the resolved graph of its dependencies
+    #[must_use]
+    pub fn new(mut root: SbomComponent, directs: &[IndexCrateDependency], graph: &DepsGraph, local_registry: &str) -> Self {
+        // the components in the graph, identified by the index of the crate and of its resolution
+        let mut identifiers = Vec::new();
+        let mut components = Vec::new();
+        for (crate_index, data) in graph.crates.iter().enumerate() {
+            if data.registry.as_deref() == Some(BUILTIN_CRATES_REGISTRY_URI) {
+                continue;
+            }
+            for (resolution_index, resolution) in data.resolutions.iter().enumerate() {
+                let is_dev_only = resolution.origins.iter().all(|origin| {
+                    matches!(
+                        origin,
+                        DepsGraphCrateOrigin::Direct(DependencyKind::Dev)
+                            | DepsGraphCrateOrigin::DevNormalIndirect
+                            | DepsGraphCrateOrigin::DevBuildIndirect
+                    )
+                });
+                if is_dev_only {
+                    continue;
+                }
+                let mut component = SbomComponent::new(
+                    data.registry.as_deref(),
+                    &data.versions[resolution.version_index].metadata,
+                    local_registry,
+                );
+                component.is_build_only = !resolution.origins.iter().any(|origin| {
+                    matches!(
+                        origin,
+                        DepsGraphCrateOrigin::Direct(DependencyKind::Normal) | DepsGraphCrateOrigin::NormalIndirect
+                    )
+                });
+                identifiers.push((crate_index, resolution_index));
+                components.push(component);
+            }
+        }
+        let find_component = |dep: &IndexCrateDependency| {
+            let resolved = find_resolution(graph, dep)?;
+            let component = identifiers.iter().position(|&identifier| identifier == resolved)?;
+            Some(SbomDependency {
+                component,
+                is_build: dep.kind == DependencyKind::Build,
+            })
+        };
+        root.dependencies = directs
+            .iter()
+            .filter(|dep| dep.kind != DependencyKind::Dev && dep.is_active_for(&graph.targets, &[]))
+            .filter_map(find_component)
+            .collect();
+        for (component, &(crate_index, resolution_index)) in components.iter_mut().zip(&identifiers) {
+            component.dependencies = graph.crates[crate_index]
+                .get_active_deps_in(resolution_index, &graph.targets)
+                .filter(|(dep, _)| dep.kind != DependencyKind::Dev)
+                .filter_map(|(dep, _)| find_component(dep))
+                .collect();
+        }
+        Self {
+            root,
+            components,
+            targets: graph.targets.clone(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// Renders this SBOM in a format
+    /// `document_uri` is the URI for this SBOM, used to build unique identifiers.
+    #[must_use]
+    pub fn render(&self, format: SbomFormat, document_uri: &str) -> Value {
+        match format {
+            SbomFormat::CycloneDx => self.to_cyclonedx(),
+            SbomFormat::Spdx => self.to_spdx(document_uri),
+        }
+    }
+
+    /// Renders this SBOM as a `CycloneDX` 1.5 document
+    #[must_use]
+    pub fn to_cyclonedx(&self) -> Value {
+        let to_component = |component: &SbomComponent, is_root: bool| {
+            let mut value = json!({
+                "type": "library",
+                "bom-ref": component.purl,
+                "name": component.name,
+                "version": component.version,
+                "purl": component.purl,
+                "hashes": [{ "alg": "SHA-256", "content": component.checksum }],
+            });
+            if let Some(license) = &component.license {
+                value["licenses"] = json!([{ "expression": license }]);
+            }
+            if !is_root {
+                value["scope"] = json!(if component.is_build_only { "excluded" } else { "required" });
+            }
+            value
+        };
+        let to_dependencies = |component: &SbomComponent| {
+            json!({
+                "ref": component.purl,
+                "dependsOn": component
+                    .dependencies
+                    .iter()
+                    .map(|dep| self.components[dep.component].purl.as_str())
+                    .collect::<Vec<_>>(),
+            })
+        };
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "serialNumber": format!("urn:uuid:{}", uuid::Uuid::new_v4()),
+            "version": 1,
+            "metadata": {
+                "timestamp": self.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "tools": {
+                    "components": [{
+                        "type": "application",
+                        "name": crate::CRATE_NAME,
+                        "version": crate::CRATE_VERSION,
+                    }]
+                },
+                "component": to_component(&self.root, true),
+            },
+            "components": self.components.iter().map(|component| to_component(component, false)).collect::<Vec<_>>(),
+            "dependencies": std::iter::once(&self.root)
+                .chain(&self.components)
+                .map(to_dependencies)
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Renders this SBOM as a SPDX 2.3 document
+    #[must_use]
+    pub fn to_spdx(&self, document_uri: &str) -> Value {
+        const ROOT_ID: &str = "SPDXRef-Root";
+        let component_id = |index: usize| format!("SPDXRef-Component-{index}");
+        let to_package = |component: &SbomComponent, id: String| {
+            json!({
+                "name": component.name,
+                "SPDXID": id,
+                "versionInfo": component.version,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+                "copyrightText": "NOASSERTION",
+                "checksums": [{ "algorithm": "SHA256", "checksumValue": component.checksum }],
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": component.purl,
+                }],
+            })
+        };
+        let mut relationships = vec![json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": ROOT_ID,
+        })];
+        let dependants = std::iter::once((ROOT_ID.to_string(), &self.root)).chain(
+            self.components
+                .iter()
+                .enumerate()
+                .map(|(index, component)| (component_id(index), component)),
+        );
+        for (id, component) in dependants {
+            for dep in &component.dependencies {
+                relationships.push(if dep.is_build {
+                    json!({
+                        "spdxElementId": component_id(dep.component),
+                        "relationshipType": "BUILD_DEPENDENCY_OF",
+                        "relatedSpdxElement": id,
+                    })
+                } else {
+                    json!({
+                        "spdxElementId": id,
+                        "relationshipType": "DEPENDS_ON",
+                        "relatedSpdxElement": component_id(dep.component),
+                    })
+                });
+            }
+        }
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": format!("{}-{}", self.root.name, self.root.version),
+            "documentNamespace": format!("{document_uri}/{}", uuid::Uuid::new_v4()),
+            "creationInfo": {
+                "created": self.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "creators": [format!("Tool: {}-{}", crate::CRATE_NAME, crate::CRATE_VERSION)],
+            },
+            "packages": std::iter::once(to_package(&self.root, ROOT_ID.to_string()))
+                .chain(
+                    self.components
+                        .iter()
+                        .enumerate()
+                        .map(|(index, component)| to_package(component, component_id(index))),
+                )
+                .collect::<Vec<_>>(),
+            "relationships": relationships,
+        })
+    }
+}
+
+/// Finds the crate and resolution in a graph that satisfies a dependency
+fn find_resolution(graph: &DepsGraph, dep: &IndexCrateDependency) -> Option<(usize, usize)> {
+    let (crate_index, data) = graph
+        .crates
+        .iter()
+        .enumerate()
+        .find(|(_, data)| data.registry == dep.registry && data.name == dep.get_name())?;
+    let req = dep.req.parse::<VersionReq>().ok()?;
+    let version_index = data
+        .versions
+        .iter()
+        .enumerate()
+        .filter(|(_, version)| req.matches(&version.semver))
+        .max_by(|(_, v1), (_, v2)| v1.semver.cmp(&v2.semver))
+        .map(|(index, _)| index)?;
+    let resolution_index = data
+        .resolutions
+        .iter()
+        .position(|resolution| resolution.version_index == version_index)?;
+    Some((crate_index, resolution_index))
+}
@@ -0,0 +1,62 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data model for teams of users
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The prefix for the logins of teams when used as crate owners
+pub const TEAM_LOGIN_PREFIX: &str = "team:";
+
+/// A team of users
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Team {
+    /// The unique identifier
+    pub id: i64,
+    /// The name, unique for each team
+    pub name: String,
+    /// The members of the team
+    pub members: Vec<TeamMember>,
+}
+
+impl Team {
+    /// Gets the login to use for this team as a crate owner
+    #[must_use]
+    pub fn login(&self) -> String {
+        format!("{TEAM_LOGIN_PREFIX}{}", self.name)
+    }
+}
+
+/// A member of a team
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamMember {
+    /// The unique identifier of the user
+    pub uid: i64,
+    /// The email of the user
+    pub email: String,
+    /// The login of the user
+    pub login: String,
+    /// The user's name
+    pub name: String,
+    /// Whether the user can manage the members of the team
+    #[serde(rename = "isMaintainer")]
+    pub is_maintainer: bool,
+}
+
+/// The request to create a team
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamCreation {
+    /// The name for the team
+    pub name: String,
+}
+
+/// The request to add a member to a team, or to change its membership
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TeamMembership {
+    /// The email of the user
+    pub email: String,
+    /// Whether the user can manage the members of the team
+    #[serde(rename = "isMaintainer")]
+    pub is_maintainer: bool,
+}
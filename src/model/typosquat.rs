@@ -0,0 +1,173 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Data model for the detection of crate names that could be confused with others
+
+use std::fmt::{Display, Formatter};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The built-in list of popular crates on crates.io,
+/// only used until the list of the most downloaded crates is fetched from crates.io
+const POPULAR_CRATES: &str = include_str!("popular_crates.txt");
+
+/// The minimum length of a name for single typos to be reported
+const TYPO_MIN_LENGTH: usize = 4;
+
+/// What to do when the name of a new crate could be confused with another one
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TyposquatPolicy {
+    /// Do not check the names
+    Off,
+    /// Accept the crate, with a warning
+    #[default]
+    Warn,
+    /// Accept the crate, but hide it from the users other than its owners until an administrator approves it
+    Review,
+    /// Reject the crate, unless an administrator reserved the name for the publisher
+    Reject,
+}
+
+impl TyposquatPolicy {
+    /// Parses the policy from its name in the configuration
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "warn" => Some(Self::Warn),
+            "review" => Some(Self::Review),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// How two crate names are similar
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NameSimilarity {
+    /// The names are the same
+    Identical,
+    /// The names only differ by their `-` and `_` separators
+    Separators,
+    /// The names only differ by characters that look alike, e.g. `0` and `o`, or `rn` and `m`
+    Confusable,
+    /// The names differ by a single inserted, removed, replaced or swapped character
+    Typo,
+}
+
+/// A crate whose name could be confused with the name of a new crate
+#[derive(Debug, Clone)]
+pub struct NameCollision {
+    /// The name of the similar crate
+    pub similar: String,
+    /// Whether the similar crate is a popular crate on crates.io, or a crate in this registry
+    pub popular: bool,
+    /// How the names are similar
+    pub similarity: NameSimilarity,
+}
+
+impl Display for NameCollision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let origin = if self.popular {
+            "the popular crates.io crate"
+        } else {
+            "the existing crate"
+        };
+        let reason = match self.similarity {
+            NameSimilarity::Identical => "same name",
+            NameSimilarity::Separators => "only the separators differ",
+            NameSimilarity::Confusable => "the names look alike",
+            NameSimilarity::Typo => "a single character differs",
+        };
+        write!(f, "{origin} `{}` ({reason})", self.similar)
+    }
+}
+
+/// A new crate held for review by an administrator because its name could be confused with another crate
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrateReview {
+    /// The name of the crate
+    pub package: String,
+    /// Why the crate is held for review
+    pub reason: String,
+}
+
+/// Gets the built-in list of popular crates on crates.io
+#[must_use]
+pub fn builtin_popular_names() -> Vec<String> {
+    parse_popular_names(POPULAR_CRATES)
+}
+
+/// Parses a list of crate names, with one name per line
+/// Empty lines and lines starting with `#` are ignored.
+#[must_use]
+pub fn parse_popular_names(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Gets how the name of a new crate is similar to the name of another crate, if it is
+#[must_use]
+pub fn name_similarity(name: &str, other: &str) -> Option<NameSimilarity> {
+    let name = name.to_ascii_lowercase();
+    let other = other.to_ascii_lowercase();
+    if name == other {
+        return Some(NameSimilarity::Identical);
+    }
+    let name = name.replace(['-', '_'], "");
+    let other = other.replace(['-', '_'], "");
+    if name == other {
+        return Some(NameSimilarity::Separators);
+    }
+    if skeleton(&name) == skeleton(&other) {
+        return Some(NameSimilarity::Confusable);
+    }
+    if name.len().min(other.len()) >= TYPO_MIN_LENGTH
+        && name.len().abs_diff(other.len()) <= 1
+        && edit_distance(name.as_bytes(), other.as_bytes()) <= 1
+    {
+        return Some(NameSimilarity::Typo);
+    }
+    None
+}
+
+/// Gets the skeleton of a name, replacing the sequences of characters that look alike with a common one
+fn skeleton(name: &str) -> String {
+    name.replace("rn", "m")
+        .replace("vv", "w")
+        .replace("cl", "d")
+        .chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '5' => 's',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Computes the edit distance between two names,
+/// counting insertions, deletions, substitutions and swaps of adjacent characters
+fn edit_distance(left: &[u8], right: &[u8]) -> usize {
+    let mut before_previous = vec![0; right.len() + 1];
+    let mut previous = (0..=right.len()).collect::<Vec<_>>();
+    let mut current = vec![0; right.len() + 1];
+    for i in 1..=left.len() {
+        current[0] = i;
+        for j in 1..=right.len() {
+            let cost = usize::from(left[i - 1] != right[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[right.len()]
+}
@@ -13,36 +13,48 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use axum::Json;
 use axum::body::{Body, Bytes};
 use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{FromRequest, Path, Query, State, WebSocketUpgrade};
+use axum::extract::{Form, FromRequest, Path, Query, State, WebSocketUpgrade};
 use axum::http::header::{HeaderName, SET_COOKIE};
-use axum::http::{HeaderValue, Request, StatusCode, header};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode, header};
 use axum::response::{IntoResponse, Response};
-use axum::{BoxError, Json};
 use cookie::Key;
 use futures::future::select_all;
-use futures::{SinkExt, Stream, StreamExt};
+use futures::{SinkExt, StreamExt};
 use log::error;
 use serde::Deserialize;
-use tokio::fs::File;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::channel;
 use tokio_stream::wrappers::ReceiverStream;
-use tokio_util::io::ReaderStream;
 
 use crate::application::Application;
-use crate::model::auth::{Authentication, RegistryUserToken, RegistryUserTokenWithSecret};
+use crate::model::advisories::{
+    AdvisoryDbStatus, AdvisorySuppression, AdvisorySuppressionCreation, InternalAdvisory, InternalAdvisoryData,
+};
+use crate::model::auth::{
+    Authentication, DeviceAuthorizationApproval, DeviceAuthorizationResponse, DeviceTokenError, DeviceTokenPoll,
+    DeviceTokenRequest, DeviceTokenResponse, OAuthLoginRequest, RegistryUserKey, RegistryUserKeyRegistration,
+    RegistryUserToken, RegistryUserTokenCreation, RegistryUserTokenWithSecret, TokenExpiryOptions,
+};
 use crate::model::cargo::{
-    CrateUploadResult, OwnersChangeQuery, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult, YesNoResult,
+    ApiResponseErrors, CrateUploadResult, OwnersChangeQuery, OwnersQueryResult, RegistryUser, SearchResults, YesNoMsgResult,
+    YesNoResult,
 };
-use crate::model::deps::DepsAnalysis;
+use crate::model::deps::{AdvisoryImpact, DepsAnalysis};
 use crate::model::docs::{DocGenJob, DocGenJobSpec};
-use crate::model::packages::{CrateInfo, CrateInfoTarget};
+use crate::model::osv::Advisory;
+use crate::model::packages::{CrateDependents, CrateInfo, CrateInfoTarget};
+use crate::model::policy::{LicenseRule, LicenseRuleCreation, PublishRule, PublishRuleCreation};
+use crate::model::reservations::{NameReservation, NameReservationCreation};
+use crate::model::sbom::{SbomFormat, SbomQuery};
 use crate::model::stats::{DownloadStats, GlobalStats};
+use crate::model::teams::{Team, TeamCreation, TeamMembership};
+use crate::model::typosquat::CrateReview;
 use crate::model::worker::{JobSpecification, JobUpdate, WorkerDescriptor, WorkerPublicData, WorkerRegistrationData};
 use crate::model::{AppVersion, CrateVersion, RegistryInformation};
-use crate::services::index::Index;
+use crate::services::index::{Index, IndexFile};
 use crate::utils::apierror::{
     ApiError, error_backend_failure, error_invalid_request, error_not_found, error_unauthorized, specialize,
 };
@@ -50,8 +62,10 @@ use crate::utils::axum::auth::{AuthData, AxumStateForCookies};
 use crate::utils::axum::embedded::{EmbeddedResources, WebappResource};
 use crate::utils::axum::extractors::Base64;
 use crate::utils::axum::sse::{Event, ServerSentEventStream};
-use crate::utils::axum::{ApiResult, response, response_error};
-use crate::utils::token::generate_token;
+use crate::utils::axum::{ApiResult, response, response_error, response_ok};
+
+/// Name of the private cookie that keeps the data of an OAuth login in progress
+const OAUTH_LOGIN_COOKIE: &str = "cratery-oauth-login";
 
 /// The state of this application for axum
 pub struct AxumState {
@@ -110,6 +124,18 @@ pub struct PathInfoCrateVersion {
     version: String,
 }
 
+#[derive(Deserialize)]
+pub struct PathInfoCrateAdvisory {
+    package: String,
+    id: String,
+}
+
+#[derive(Deserialize)]
+pub struct PathInfoCrateSuppression {
+    package: String,
+    suppression_id: i64,
+}
+
 /// Response for a GET on the root
 /// Redirect to the web app
 pub async fn get_root(State(state): State<Arc<AxumState>>) -> (StatusCode, [(HeaderName, HeaderValue); 2]) {
@@ -137,30 +163,24 @@ pub async fn get_favicon(State(state): State<Arc<AxumState>>) -> (StatusCode, [(
 }
 
 /// Gets the redirection response when not authenticated
-fn get_auth_redirect(state: &AxumState) -> (StatusCode, [(HeaderName, HeaderValue); 2]) {
+async fn get_auth_redirect(state: &AxumState, auth_data: &mut AuthData) -> Response {
     // redirect to login
-    let nonce = generate_token(64);
-    let oauth_state = generate_token(32);
-    let target = format!(
-        "{}?response_type={}&redirect_uri={}&client_id={}&scope={}&nonce={}&state={}",
-        state.application.configuration.oauth_login_uri,
-        "code",
-        urlencoding::encode(&format!(
-            "{}/webapp/oauthcallback.html",
-            state.application.configuration.web_public_uri
-        )),
-        urlencoding::encode(&state.application.configuration.oauth_client_id),
-        urlencoding::encode(&state.application.configuration.oauth_client_scope),
-        nonce,
-        oauth_state
-    );
+    let login_request = OAuthLoginRequest::new();
+    let target = match state.application.get_oauth_login_uri(&login_request).await {
+        Ok(target) => target,
+        Err(error) => return response_error(error).into_response(),
+    };
+    // keep the login request for the callback
+    let cookie = auth_data.create_cookie(OAUTH_LOGIN_COOKIE, &serde_json::to_string(&login_request).unwrap(), true);
     (
         StatusCode::FOUND,
         [
             (header::LOCATION, HeaderValue::from_str(&target).unwrap()),
             (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+            (SET_COOKIE, HeaderValue::from_str(&cookie.to_string()).unwrap()),
         ],
     )
+        .into_response()
 }
 
 /// Gets the redirection for a crates shortcut
@@ -199,17 +219,17 @@ pub async fn get_redirection_crate_version(
 
 /// Gets the favicon
 pub async fn get_webapp_resource(
-    auth_data: AuthData,
+    mut auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     request: Request<Body>,
-) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Cow<'static, [u8]>), StatusCode> {
+) -> Response {
     let path = request.uri().path();
     let path = &path["/webapp/".len()..];
 
     if let Some(crate_name) = path.strip_prefix("crates/") {
         // URL shortcut for crates
         let target = format!("/webapp/crate.html?crate={crate_name}");
-        return Ok((
+        return (
             StatusCode::FOUND,
             [
                 (header::LOCATION, HeaderValue::from_str(&target).unwrap()),
@@ -218,20 +238,22 @@ pub async fn get_webapp_resource(
                     HeaderValue::from_static("public, max-age=3600, immutable"),
                 ),
             ],
-            Cow::Borrowed(&[]),
-        ));
+        )
+            .into_response();
     }
 
     if path == "index.html" {
         let is_authenticated = state.application.authenticate(&auth_data).await.is_ok();
         if !is_authenticated {
-            let (code, headers) = get_auth_redirect(&state);
-            return Ok((code, headers, Cow::Borrowed(&[])));
+            return get_auth_redirect(&state, &mut auth_data).await;
         }
     }
 
     let resource = state.get_webapp_resource(path).await;
-    resource.ok_or(StatusCode::NOT_FOUND).map(wrap_resource_with_header)
+    resource
+        .map(wrap_resource_with_header)
+        .ok_or(StatusCode::NOT_FOUND)
+        .into_response()
 }
 
 fn wrap_resource_with_header(resource: WebappResource) -> (StatusCode, [(HeaderName, HeaderValue); 2], Cow<'static, [u8]>) {
@@ -262,14 +284,13 @@ pub async fn webapp_me(State(state): State<Arc<AxumState>>) -> (StatusCode, [(He
 
 /// Gets a file from the documentation
 pub async fn get_docs_resource(
-    auth_data: AuthData,
+    mut auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     request: Request<Body>,
-) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Body), (StatusCode, [(HeaderName, HeaderValue); 1], Body)> {
+) -> Result<Response, (StatusCode, [(HeaderName, HeaderValue); 1], Body)> {
     let is_authenticated = state.application.authenticate(&auth_data).await.is_ok();
     if !is_authenticated {
-        let (code, headers) = get_auth_redirect(&state);
-        return Ok((code, headers, Body::empty()));
+        return Ok(get_auth_redirect(&state, &mut auth_data).await);
     }
 
     let elements = request.uri().path().split('/').filter(|e| !e.is_empty()).collect::<Vec<_>>();
@@ -287,6 +308,14 @@ pub async fn get_docs_resource(
             Body::empty(),
         ));
     }
+    if state.application.check_crate_readable(&auth_data, elements[1]).await.is_err() {
+        // the response depends on the user, shared caches must not keep it
+        return Err((
+            StatusCode::NOT_FOUND,
+            [(header::CACHE_CONTROL, HeaderValue::from_static("private, no-store"))],
+            Body::empty(),
+        ));
+    }
     // build the key
     let (target, rest_index) = if elements.len() >= 5
         && state
@@ -314,13 +343,15 @@ pub async fn get_docs_resource(
             StatusCode::OK,
             [
                 (header::CONTENT_TYPE, HeaderValue::from_static(extension)),
+                // only readers can see the documentation of crates with restricted access
                 (
                     header::CACHE_CONTROL,
-                    HeaderValue::from_static("public, max-age=3600, immutable"),
+                    HeaderValue::from_static("private, max-age=3600, immutable"),
                 ),
             ],
             Body::from(content),
-        )),
+        )
+            .into_response()),
         Err(e) => {
             let message = e.to_string();
             Err((
@@ -362,22 +393,37 @@ pub async fn api_v1_get_current_user(auth_data: AuthData, State(state): State<Ar
     response(state.application.get_current_user(&auth_data).await)
 }
 
+#[derive(Deserialize)]
+pub struct OAuthCallbackQuery {
+    #[serde(default)]
+    state: String,
+}
+
 /// Attempts to login using an OAuth code
 pub async fn api_v1_login_with_oauth_code(
     mut auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
+    Query(query): Query<OAuthCallbackQuery>,
     body: Bytes,
-) -> Result<(StatusCode, [(HeaderName, HeaderValue); 1], Json<RegistryUser>), (StatusCode, Json<ApiError>)> {
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Json<RegistryUser>), (StatusCode, Json<ApiError>)> {
     let code = String::from_utf8_lossy(&body);
+    let login_request = auth_data
+        .get_private_cookie(OAUTH_LOGIN_COOKIE)
+        .and_then(|value| serde_json::from_str::<OAuthLoginRequest>(&value).ok())
+        .ok_or_else(|| response_error(specialize(error_unauthorized(), String::from("no OAuth login in progress"))))?;
     let registry_user = state
         .application
-        .login_with_oauth_code(&code)
+        .login_with_oauth_code(&code, &query.state, &login_request)
         .await
-        .map_err(|err| response_error(ApiError::from(err)))?;
+        .map_err(response_error)?;
     let cookie = auth_data.create_id_cookie(&Authentication::new_user(registry_user.id, registry_user.email.clone()));
+    let login_cookie = auth_data.create_expired_cookie(OAUTH_LOGIN_COOKIE, true);
     Ok((
         StatusCode::OK,
-        [(SET_COOKIE, HeaderValue::from_str(&cookie.to_string()).unwrap())],
+        [
+            (SET_COOKIE, HeaderValue::from_str(&cookie.to_string()).unwrap()),
+            (SET_COOKIE, HeaderValue::from_str(&login_cookie.to_string()).unwrap()),
+        ],
         Json(registry_user),
     ))
 }
@@ -399,22 +445,24 @@ pub async fn api_v1_get_user_tokens(
     response(state.application.get_tokens(&auth_data).await)
 }
 
-#[derive(Deserialize)]
-pub struct CreateTokenQuery {
-    #[serde(rename = "canWrite")]
-    can_write: bool,
-    #[serde(rename = "canAdmin")]
-    can_admin: bool,
-}
-
 /// Creates a token for the current user
 pub async fn api_v1_create_user_token(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
-    Query(CreateTokenQuery { can_write, can_admin }): Query<CreateTokenQuery>,
+    Query(options): Query<RegistryUserTokenCreation>,
     name: String,
 ) -> ApiResult<RegistryUserTokenWithSecret> {
-    response(state.application.create_token(&auth_data, &name, can_write, can_admin).await)
+    response(state.application.create_token(&auth_data, &name, &options).await)
+}
+
+/// Rotates the secret of a token for the current user
+pub async fn api_v1_rotate_user_token(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(token_id): Path<i64>,
+    Query(options): Query<TokenExpiryOptions>,
+) -> ApiResult<RegistryUserTokenWithSecret> {
+    response(state.application.rotate_token(&auth_data, token_id, options).await)
 }
 
 /// Revoke a previous token
@@ -426,6 +474,66 @@ pub async fn api_v1_revoke_user_token(
     response(state.application.revoke_token(&auth_data, token_id).await)
 }
 
+/// Starts a device authorization request (RFC 8628)
+pub async fn api_v1_request_device_code(State(state): State<Arc<AxumState>>) -> ApiResult<DeviceAuthorizationResponse> {
+    response(state.application.request_device_authorization().await)
+}
+
+/// Polls for the token of a device authorization request (RFC 8628)
+/// Pending, denied and expired requests are reported with the error codes defined by the RFC.
+pub async fn api_v1_poll_device_token(
+    State(state): State<Arc<AxumState>>,
+    Form(request): Form<DeviceTokenRequest>,
+) -> Response {
+    match state.application.poll_device_authorization(&request).await {
+        Ok(DeviceTokenPoll::Granted { login, token }) => {
+            (StatusCode::OK, Json(DeviceTokenResponse::new(&login, token))).into_response()
+        }
+        Ok(poll) => {
+            let error = poll.error_code().unwrap_or_default().to_string();
+            (StatusCode::BAD_REQUEST, Json(DeviceTokenError { error })).into_response()
+        }
+        Err(error) => response_error(error).into_response(),
+    }
+}
+
+/// Approves or denies a device authorization request for the current user
+pub async fn api_v1_decide_device_authorization(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Json(approval): Json<DeviceAuthorizationApproval>,
+) -> ApiResult<()> {
+    response(state.application.decide_device_authorization(&auth_data, &approval).await)
+}
+
+/// Gets the public keys registered by the current user for asymmetric tokens
+pub async fn api_v1_get_user_keys(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<RegistryUserKey>> {
+    response(state.application.get_keys(&auth_data).await)
+}
+
+/// Registers a public key for the current user
+pub async fn api_v1_add_user_key(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<RegistryUserKeyRegistration>,
+) -> ApiResult<RegistryUserKey> {
+    response(
+        state
+            .application
+            .add_key(&auth_data, &input.name, &input.public_key, input.can_write)
+            .await,
+    )
+}
+
+/// Removes a previously registered public key
+pub async fn api_v1_remove_user_key(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(key_id): Path<i64>,
+) -> ApiResult<()> {
+    response(state.application.remove_key(&auth_data, key_id).await)
+}
+
 /// Gets the global tokens for the registry, usually for CI purposes
 pub async fn api_v1_get_global_tokens(
     auth_data: AuthData,
@@ -438,9 +546,20 @@ pub async fn api_v1_get_global_tokens(
 pub async fn api_v1_create_global_token(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
+    Query(options): Query<TokenExpiryOptions>,
     name: String,
 ) -> ApiResult<RegistryUserTokenWithSecret> {
-    response(state.application.create_global_token(&auth_data, &name).await)
+    response(state.application.create_global_token(&auth_data, &name, options).await)
+}
+
+/// Rotates the secret of a global token for the registry
+pub async fn api_v1_rotate_global_token(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(token_id): Path<i64>,
+    Query(options): Query<TokenExpiryOptions>,
+) -> ApiResult<RegistryUserTokenWithSecret> {
+    response(state.application.rotate_global_token(&auth_data, token_id, options).await)
 }
 
 /// Revokes a global token for the registry
@@ -452,6 +571,109 @@ pub async fn api_v1_revoke_global_token(
     response(state.application.revoke_global_token(&auth_data, token_id).await)
 }
 
+/// Gets the reservations of crate names
+pub async fn api_v1_get_name_reservations(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+) -> ApiResult<Vec<NameReservation>> {
+    response(state.application.get_name_reservations(&auth_data).await)
+}
+
+/// Reserves crate names for a user or a team
+pub async fn api_v1_create_name_reservation(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<NameReservationCreation>,
+) -> ApiResult<NameReservation> {
+    response(state.application.create_name_reservation(&auth_data, &input).await)
+}
+
+/// Removes a reservation of crate names
+pub async fn api_v1_delete_name_reservation(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(reservation_id): Path<i64>,
+) -> ApiResult<()> {
+    response(state.application.delete_name_reservation(&auth_data, reservation_id).await)
+}
+
+/// Gets the new crates held for review because their names could be confused with other crates
+pub async fn api_v1_get_crate_reviews(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<CrateReview>> {
+    response(state.application.get_crate_reviews(&auth_data).await)
+}
+
+/// Approves a crate held for review
+pub async fn api_v1_approve_crate_review(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(package): Path<String>,
+) -> ApiResult<()> {
+    response(state.application.approve_crate_review(&auth_data, &package).await)
+}
+
+/// Gets the rules of the publish policy
+pub async fn api_v1_get_publish_rules(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<PublishRule>> {
+    response(state.application.get_publish_rules(&auth_data).await)
+}
+
+/// Adds a rule to the publish policy
+pub async fn api_v1_create_publish_rule(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<PublishRuleCreation>,
+) -> ApiResult<PublishRule> {
+    response(state.application.create_publish_rule(&auth_data, &input).await)
+}
+
+/// Removes a rule from the publish policy
+pub async fn api_v1_delete_publish_rule(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(rule_id): Path<i64>,
+) -> ApiResult<()> {
+    response(state.application.delete_publish_rule(&auth_data, rule_id).await)
+}
+
+/// Gets the rules of the license policy for dependencies
+pub async fn api_v1_get_license_rules(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<LicenseRule>> {
+    response(state.application.get_license_rules(&auth_data).await)
+}
+
+/// Adds a rule to the license policy for dependencies
+pub async fn api_v1_create_license_rule(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<LicenseRuleCreation>,
+) -> ApiResult<LicenseRule> {
+    response(state.application.create_license_rule(&auth_data, &input).await)
+}
+
+/// Removes a rule from the license policy for dependencies
+pub async fn api_v1_delete_license_rule(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(rule_id): Path<i64>,
+) -> ApiResult<()> {
+    response(state.application.delete_license_rule(&auth_data, rule_id).await)
+}
+
+/// Gets the status of the database of advisories against the crates on crates.io
+pub async fn api_v1_get_advisory_db_status(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+) -> ApiResult<AdvisoryDbStatus> {
+    response(state.application.get_advisory_db_status(&auth_data).await)
+}
+
+/// Replaces the database of advisories against the crates on crates.io with an uploaded `.tar.gz` archive of OSV files
+pub async fn api_v1_upload_advisory_db(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    body: Bytes,
+) -> ApiResult<AdvisoryDbStatus> {
+    response(state.application.upload_advisory_db(&auth_data, &body).await)
+}
+
 /// Gets the documentation jobs
 pub async fn api_v1_get_doc_gen_jobs(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<DocGenJob>> {
     response(state.application.get_doc_gen_jobs(&auth_data).await)
@@ -645,6 +867,57 @@ pub async fn api_v1_get_users(auth_data: AuthData, State(state): State<Arc<AxumS
     response(state.application.get_users(&auth_data).await)
 }
 
+/// Gets the known teams
+pub async fn api_v1_get_teams(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<Team>> {
+    response(state.application.get_teams(&auth_data).await)
+}
+
+/// Creates a new team
+pub async fn api_v1_create_team(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    input: Json<TeamCreation>,
+) -> ApiResult<Team> {
+    response(state.application.create_team(&auth_data, &input.name).await)
+}
+
+/// Gets a team
+pub async fn api_v1_get_team(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+) -> ApiResult<Team> {
+    response(state.application.get_team(&auth_data, &team).await)
+}
+
+/// Deletes a team
+pub async fn api_v1_delete_team(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+) -> ApiResult<()> {
+    response(state.application.delete_team(&auth_data, &team).await)
+}
+
+/// Adds a member to a team, or updates its membership
+pub async fn api_v1_set_team_member(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(team): Path<String>,
+    input: Json<TeamMembership>,
+) -> ApiResult<Team> {
+    response(state.application.set_team_member(&auth_data, &team, &input).await)
+}
+
+/// Removes a member from a team
+pub async fn api_v1_remove_team_member(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path((team, Base64(email))): Path<(String, Base64)>,
+) -> ApiResult<Team> {
+    response(state.application.remove_team_member(&auth_data, &team, &email).await)
+}
+
 /// Updates the information of a user
 pub async fn api_v1_update_user(
     auth_data: AuthData,
@@ -729,12 +1002,22 @@ pub async fn api_v1_get_crates_outdated_heads(
     response(state.application.get_crates_outdated_heads(&auth_data).await)
 }
 
+/// Publishes a crate
+/// Errors are reported in the format expected by cargo so that their details are shown to the user
 pub async fn api_v1_cargo_publish_crate_version(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     body: Bytes,
-) -> ApiResult<CrateUploadResult> {
-    response(state.application.publish_crate_version(&auth_data, &body).await)
+) -> Result<(StatusCode, Json<CrateUploadResult>), (StatusCode, Json<ApiResponseErrors>)> {
+    state
+        .application
+        .publish_crate_version(&auth_data, &body)
+        .await
+        .map(response_ok)
+        .map_err(|error| {
+            let (status, Json(error)) = response_error(error);
+            (status, Json(ApiResponseErrors::from(error)))
+        })
 }
 
 pub async fn api_v1_get_crate_info(
@@ -852,6 +1135,138 @@ pub async fn api_v1_check_crate_version(
     )
 }
 
+/// Gets the SBOM for a crate version, in the `CycloneDX` (default) or SPDX format
+pub async fn api_v1_get_crate_sbom(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+    Query(query): Query<SbomQuery>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 1], Vec<u8>), (StatusCode, Json<ApiError>)> {
+    let format = match query.format.as_deref() {
+        None => SbomFormat::CycloneDx,
+        Some(format) => format.parse::<SbomFormat>().map_err(|()| {
+            response_error(specialize(
+                error_invalid_request(),
+                format!("unsupported SBOM format `{format}`, expected `cyclonedx` or `spdx`"),
+            ))
+        })?,
+    };
+    let data = state
+        .application
+        .get_crate_version_sbom(&auth_data, &package, &version, format)
+        .await
+        .map_err(response_error)?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()))],
+        data,
+    ))
+}
+
+/// Gets the crate versions of the registry impacted by an advisory
+pub async fn api_v1_get_advisory_impact(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(id): Path<String>,
+) -> ApiResult<AdvisoryImpact> {
+    response(state.application.get_advisory_impact(&auth_data, &id).await)
+}
+
+/// Gets all the internal advisories as an OSV feed
+pub async fn api_v1_get_advisories_osv(auth_data: AuthData, State(state): State<Arc<AxumState>>) -> ApiResult<Vec<Advisory>> {
+    response(state.application.get_advisories_osv(&auth_data).await)
+}
+
+/// Gets an internal advisory in the OSV format
+pub async fn api_v1_get_advisory_osv(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Advisory> {
+    response(state.application.get_advisory_osv(&auth_data, &id).await)
+}
+
+/// Gets the internal advisories against a crate
+pub async fn api_v1_get_crate_advisories(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<Vec<InternalAdvisory>> {
+    response(state.application.get_crate_advisories(&auth_data, &package).await)
+}
+
+/// Creates an advisory against a crate
+pub async fn api_v1_create_crate_advisory(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<InternalAdvisoryData>,
+) -> ApiResult<InternalAdvisory> {
+    response(state.application.create_crate_advisory(&auth_data, &package, &input).await)
+}
+
+/// Updates an advisory against a crate
+pub async fn api_v1_update_crate_advisory(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateAdvisory { package, id }): Path<PathInfoCrateAdvisory>,
+    input: Json<InternalAdvisoryData>,
+) -> ApiResult<InternalAdvisory> {
+    response(
+        state
+            .application
+            .update_crate_advisory(&auth_data, &package, &id, &input)
+            .await,
+    )
+}
+
+/// Withdraws an advisory against a crate
+pub async fn api_v1_withdraw_crate_advisory(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateAdvisory { package, id }): Path<PathInfoCrateAdvisory>,
+) -> ApiResult<()> {
+    response(state.application.withdraw_crate_advisory(&auth_data, &package, &id).await)
+}
+
+/// Gets the suppressions of advisories for a crate
+pub async fn api_v1_get_advisory_suppressions(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<Vec<AdvisorySuppression>> {
+    response(state.application.get_advisory_suppressions(&auth_data, &package).await)
+}
+
+/// Suppresses an advisory against the dependencies of a crate
+pub async fn api_v1_create_advisory_suppression(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<AdvisorySuppressionCreation>,
+) -> ApiResult<AdvisorySuppression> {
+    response(
+        state
+            .application
+            .create_advisory_suppression(&auth_data, &package, &input)
+            .await,
+    )
+}
+
+/// Removes the suppression of an advisory for a crate
+pub async fn api_v1_delete_advisory_suppression(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateSuppression { package, suppression_id }): Path<PathInfoCrateSuppression>,
+) -> ApiResult<()> {
+    response(
+        state
+            .application
+            .delete_advisory_suppression(&auth_data, &package, suppression_id)
+            .await,
+    )
+}
+
 /// Gets the download statistics for a crate
 pub async fn api_v1_get_crate_dl_stats(
     auth_data: AuthData,
@@ -861,6 +1276,15 @@ pub async fn api_v1_get_crate_dl_stats(
     response(state.application.get_crate_dl_stats(&auth_data, &package).await)
 }
 
+/// Gets the versions of other crates that depend on a crate
+pub async fn api_v1_get_crate_dependents(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<CrateDependents> {
+    response(state.application.get_crate_dependents(&auth_data, &package).await)
+}
+
 pub async fn api_v1_cargo_get_crate_owners(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
@@ -935,6 +1359,25 @@ pub async fn api_v1_set_crate_required_capabilities(
     )
 }
 
+/// Gets the readers for a crate
+pub async fn api_v1_get_crate_readers(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+) -> ApiResult<Vec<String>> {
+    response(state.application.get_crate_readers(&auth_data, &package).await)
+}
+
+/// Sets the readers for a crate
+pub async fn api_v1_set_crate_readers(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrate { package }): Path<PathInfoCrate>,
+    input: Json<Vec<String>>,
+) -> ApiResult<()> {
+    response(state.application.set_crate_readers(&auth_data, &package, &input).await)
+}
+
 /// Sets the deprecation status on a crate
 pub async fn api_v1_set_crate_deprecation(
     auth_data: AuthData,
@@ -955,29 +1398,18 @@ pub async fn api_v1_set_crate_can_remove(
     response(state.application.set_crate_can_remove(&auth_data, &package, input.0).await)
 }
 
-pub async fn index_serve_inner(
-    index: &(dyn Index + Send + Sync),
-    path: &str,
-) -> Result<
-    (
-        impl Stream<Item = Result<impl Into<Bytes> + use<>, impl Into<BoxError> + use<>>> + use<>,
-        HeaderValue,
-    ),
-    ApiError,
-> {
+pub async fn index_serve_inner(index: &(dyn Index + Send + Sync), path: &str) -> Result<(IndexFile, HeaderValue), ApiError> {
     let file_path: PathBuf = path.parse()?;
-    let file_path = index.get_index_file(&file_path).await?.ok_or_else(error_not_found)?;
-    let file = File::open(file_path).await.map_err(|_e| error_not_found())?;
-    let stream = ReaderStream::new(file);
+    let file = index.get_index_file(&file_path).await?.ok_or_else(error_not_found)?;
     if std::path::Path::new(path)
         .extension()
         .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
     {
-        Ok((stream, HeaderValue::from_static("application/json")))
+        Ok((file, HeaderValue::from_static("application/json")))
     } else if path == "/HEAD" || path.starts_with("/info") {
-        Ok((stream, HeaderValue::from_static("text/plain; charset=utf-8")))
+        Ok((file, HeaderValue::from_static("text/plain; charset=utf-8")))
     } else {
-        Ok((stream, HeaderValue::from_static("application/octet-stream")))
+        Ok((file, HeaderValue::from_static("application/octet-stream")))
     }
 }
 
@@ -996,6 +1428,16 @@ fn index_serve_map_err(e: ApiError, domain: &str) -> (StatusCode, [(HeaderName,
     )
 }
 
+/// Gets the name of the crate for the path of its file in the sparse index, if any
+fn index_path_package(path: &str) -> Option<&str> {
+    let elements = path.split('/').filter(|e| !e.is_empty()).collect::<Vec<_>>();
+    match elements.as_slice() {
+        ["1" | "2", name] | ["3", _, name] => Some(name),
+        [first, second, name] if first.len() == 2 && second.len() == 2 => Some(name),
+        _ => None,
+    }
+}
+
 pub async fn index_serve_check_auth(
     application: &Application,
     auth_data: &AuthData,
@@ -1014,28 +1456,97 @@ pub async fn index_serve(
     auth_data: AuthData,
     State(state): State<Arc<AxumState>>,
     request: Request<Body>,
-) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Body), (StatusCode, [(HeaderName, HeaderValue); 2], Json<ApiError>)> {
+) -> Result<(StatusCode, HeaderMap, Body), (StatusCode, [(HeaderName, HeaderValue); 2], Json<ApiError>)> {
     let map_err = |e| index_serve_map_err(e, &state.application.configuration.web_domain);
     let path = request.uri().path();
     if path != "/config.json" && !state.application.configuration.index.allow_protocol_sparse {
         // config.json is always allowed because it is always checked first by cargo
         return Err(map_err(error_not_found()));
     }
-    index_serve_check_auth(&state.application, &auth_data).await?;
-    let (stream, content_type) = index_serve_inner(state.application.get_service_index(), path)
+    if let Some(package) = index_path_package(path) {
+        // the index file of a crate, only for its readers
+        state
+            .application
+            .check_crate_readable(&auth_data, package)
+            .await
+            .map_err(map_err)?;
+    } else {
+        index_serve_check_auth(&state.application, &auth_data).await?;
+    }
+    let (file, content_type) = index_serve_inner(state.application.get_service_index(), path)
         .await
         .map_err(map_err)?;
-    let body = Body::from_stream(stream);
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(etag) = HeaderValue::from_str(&file.etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(last_modified) = file.last_modified_header().and_then(|v| HeaderValue::from_str(&v).ok()) {
+        headers.insert(header::LAST_MODIFIED, last_modified);
+    }
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok());
+    let if_modified_since = request.headers().get(header::IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok());
+    if file.is_current(if_none_match, if_modified_since) {
+        return Ok((StatusCode::NOT_MODIFIED, headers, Body::empty()));
+    }
+    headers.insert(header::CONTENT_TYPE, content_type);
+    Ok((StatusCode::OK, headers, Body::from(file.content)))
+}
+
+pub async fn proxy_crates_io_index_serve(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(path): Path<String>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 2], Vec<u8>), (StatusCode, [(HeaderName, HeaderValue); 2], Json<ApiError>)>
+{
+    let data = state
+        .application
+        .get_crates_io_index_file(&auth_data, &path)
+        .await
+        .map_err(|e| index_serve_map_err(e, &state.application.configuration.web_domain))?;
+    let content_type = if std::path::Path::new(&path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+    {
+        HeaderValue::from_static("application/json")
+    } else {
+        HeaderValue::from_static("application/octet-stream")
+    };
     Ok((
         StatusCode::OK,
         [
             (header::CONTENT_TYPE, content_type),
             (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
         ],
-        body,
+        data,
     ))
 }
 
+pub async fn proxy_crates_io_download_crate(
+    auth_data: AuthData,
+    State(state): State<Arc<AxumState>>,
+    Path(PathInfoCrateVersion { package, version }): Path<PathInfoCrateVersion>,
+) -> Result<(StatusCode, [(HeaderName, HeaderValue); 1], Vec<u8>), (StatusCode, Json<ApiError>)> {
+    match state
+        .application
+        .get_crates_io_crate_content(&auth_data, &package, &version)
+        .await
+    {
+        Ok(data) => Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"))],
+            data,
+        )),
+        Err(mut error) => {
+            if error.http == StatusCode::UNAUTHORIZED {
+                // map UNAUTHORIZED - 401 to FORBIDDEN - 403
+                error.http = StatusCode::FORBIDDEN;
+            }
+            Err(response_error(error))
+        }
+    }
+}
+
 #[expect(clippy::implicit_hasher)]
 pub async fn index_serve_info_refs(
     auth_data: AuthData,
@@ -1046,7 +1557,11 @@ pub async fn index_serve_info_refs(
     if !state.application.configuration.index.allow_protocol_git {
         return Err(map_err(error_not_found()));
     }
-    index_serve_check_auth(&state.application, &auth_data).await?;
+    state
+        .application
+        .check_git_index_readable(&auth_data)
+        .await
+        .map_err(map_err)?;
 
     if query.get("service").map(String::as_str) == Some("git-upload-pack") {
         // smart server response
@@ -1082,7 +1597,11 @@ pub async fn index_serve_git_upload_pack(
     if !state.application.configuration.index.allow_protocol_git {
         return Err(map_err(error_not_found()));
     }
-    index_serve_check_auth(&state.application, &auth_data).await?;
+    state
+        .application
+        .check_git_index_readable(&auth_data)
+        .await
+        .map_err(map_err)?;
     let data = state
         .application
         .get_service_index()
@@ -5,7 +5,7 @@ CREATE TABLE IF NOT EXISTS SchemaMetadata (
 
 CREATE INDEX IF NOT EXISTS SchemaMetadataIndex ON SchemaMetadata(name);
 
-INSERT INTO SchemaMetadata VALUES ('version', '1.11.0');
+INSERT INTO SchemaMetadata VALUES ('version', '1.14.0');
 
 CREATE TABLE RegistryUser (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
@@ -26,16 +26,74 @@ CREATE TABLE RegistryUserToken (
     token TEXT NOT NULL,
     lastUsed TIMESTAMP NOT NULL,
     canWrite BOOLEAN NOT NULL,
-    canAdmin BOOLEAN NOT NULL
+    canAdmin BOOLEAN NOT NULL,
+    crateScopes TEXT NOT NULL DEFAULT '',
+    endpointScopes TEXT NOT NULL DEFAULT '',
+    expiresAt TIMESTAMP,
+    validity INTEGER,
+    expiryNotified BOOLEAN NOT NULL DEFAULT FALSE,
+    previousToken TEXT,
+    previousExpiresAt TIMESTAMP,
+    publicId TEXT
 );
 
 CREATE INDEX IndexRegistryUserToken ON RegistryUserToken (user);
+CREATE UNIQUE INDEX IndexRegistryUserTokenPublicId ON RegistryUserToken (publicId);
+
+CREATE TABLE RegistryUserKey (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user INTEGER NOT NULL REFERENCES RegistryUser(id),
+    name TEXT NOT NULL,
+    keyId TEXT NOT NULL UNIQUE,
+    publicKey TEXT NOT NULL,
+    lastUsed TIMESTAMP NOT NULL,
+    canWrite BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexRegistryUserKey ON RegistryUserKey (user);
+
+CREATE TABLE DeviceAuthorization (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    deviceCode TEXT NOT NULL UNIQUE,
+    userCode TEXT NOT NULL UNIQUE,
+    expiresAt TIMESTAMP NOT NULL,
+    interval INTEGER NOT NULL,
+    lastPolled TIMESTAMP,
+    isDenied BOOLEAN NOT NULL DEFAULT FALSE,
+    user INTEGER REFERENCES RegistryUser(id),
+    tokenName TEXT,
+    canWrite BOOLEAN NOT NULL DEFAULT FALSE,
+    canAdmin BOOLEAN NOT NULL DEFAULT FALSE,
+    crateScopes TEXT NOT NULL DEFAULT '',
+    endpointScopes TEXT NOT NULL DEFAULT '',
+    tokenValidity INTEGER
+);
+
+CREATE TABLE Team (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE TeamMember (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    team INTEGER NOT NULL REFERENCES Team(id),
+    user INTEGER NOT NULL REFERENCES RegistryUser(id),
+    isMaintainer BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexTeamMember ON TeamMember (team);
+CREATE INDEX IndexTeamMemberByUser ON TeamMember (user);
 
 CREATE TABLE RegistryGlobalToken (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     name TEXT NOT NULL,
     token TEXT NOT NULL,
-    lastUsed TIMESTAMP NOT NULL
+    lastUsed TIMESTAMP NOT NULL,
+    expiresAt TIMESTAMP,
+    validity INTEGER,
+    expiryNotified BOOLEAN NOT NULL DEFAULT FALSE,
+    previousToken TEXT,
+    previousExpiresAt TIMESTAMP
 );
 
 CREATE TABLE Package (
@@ -45,7 +103,9 @@ CREATE TABLE Package (
     nativeTargets TEXT NOT NULL,
     capabilities TEXT NOT NULL,
     isDeprecated BOOLEAN NOT NULL,
-    canRemove BOOLEAN NOT NULL
+    canRemove BOOLEAN NOT NULL,
+    readers TEXT NOT NULL DEFAULT '',
+    reviewReason TEXT
 );
 
 CREATE INDEX IndexPackage ON Package (name);
@@ -58,6 +118,34 @@ CREATE TABLE PackageOwner (
 
 CREATE INDEX IndexPackageOwner ON PackageOwner (package);
 
+CREATE TABLE PackageTeamOwner (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    team INTEGER NOT NULL REFERENCES Team(id)
+);
+
+CREATE INDEX IndexPackageTeamOwner ON PackageTeamOwner (package);
+
+CREATE TABLE PackageNameReservation (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    pattern TEXT NOT NULL UNIQUE,
+    user INTEGER REFERENCES RegistryUser(id),
+    team INTEGER REFERENCES Team(id)
+);
+
+CREATE TABLE PublishRule (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    parameter TEXT NOT NULL,
+    isBlocking BOOLEAN NOT NULL
+);
+
+CREATE TABLE LicenseRule (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    license TEXT NOT NULL UNIQUE,
+    isAllowed BOOLEAN NOT NULL
+);
+
 CREATE TABLE PackageVersion (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     package TEXT NOT NULL REFERENCES Package(name),
@@ -70,11 +158,79 @@ CREATE TABLE PackageVersion (
     downloads BLOB,
     depsLastCheck TIMESTAMP NOT NULL,
     depsHasOutdated BOOLEAN NOT NULL,
-    depsHasCVEs BOOLEAN NOT NULL
+    depsHasCVEs BOOLEAN NOT NULL,
+    depsHasLicenseViolations BOOLEAN NOT NULL DEFAULT FALSE,
+    vcsSha1 TEXT,
+    vcsDirty BOOLEAN NOT NULL DEFAULT FALSE,
+    vcsPath TEXT,
+    depsIndexed BOOLEAN NOT NULL DEFAULT FALSE,
+    depsResolved BOOLEAN NOT NULL DEFAULT FALSE
 );
 
 CREATE INDEX IndexPackageVersion ON PackageVersion(package);
 
+CREATE TABLE PackageDependency (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    dependency TEXT NOT NULL,
+    versionReq TEXT NOT NULL,
+    kind TEXT NOT NULL
+);
+
+CREATE INDEX IndexPackageDependency ON PackageDependency(dependency);
+CREATE INDEX IndexPackageDependencyByVersion ON PackageDependency(package, version);
+
+CREATE TABLE PackageVersionResolution (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT NOT NULL,
+    registry TEXT,
+    dependency TEXT NOT NULL,
+    resolved TEXT NOT NULL,
+    isDirect BOOLEAN NOT NULL
+);
+
+CREATE INDEX IndexPackageVersionResolution ON PackageVersionResolution(dependency);
+CREATE INDEX IndexPackageVersionResolutionByVersion ON PackageVersionResolution(package, version);
+
+CREATE TABLE PackageAdvisory (
+    id TEXT NOT NULL PRIMARY KEY,
+    package TEXT NOT NULL REFERENCES Package(name),
+    summary TEXT NOT NULL,
+    details TEXT NOT NULL,
+    severity TEXT,
+    author INTEGER REFERENCES RegistryUser(id),
+    published TIMESTAMP NOT NULL,
+    modified TIMESTAMP NOT NULL,
+    withdrawn TIMESTAMP
+);
+
+CREATE INDEX IndexPackageAdvisory ON PackageAdvisory(package);
+
+CREATE TABLE PackageAdvisoryRange (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    advisory TEXT NOT NULL REFERENCES PackageAdvisory(id),
+    introduced TEXT NOT NULL,
+    fixed TEXT,
+    lastAffected TEXT
+);
+
+CREATE INDEX IndexPackageAdvisoryRange ON PackageAdvisoryRange(advisory);
+
+CREATE TABLE PackageAdvisorySuppression (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    package TEXT NOT NULL REFERENCES Package(name),
+    version TEXT,
+    advisory TEXT NOT NULL,
+    justification TEXT NOT NULL,
+    author INTEGER REFERENCES RegistryUser(id),
+    created TIMESTAMP NOT NULL,
+    expires TIMESTAMP
+);
+
+CREATE INDEX IndexPackageAdvisorySuppression ON PackageAdvisorySuppression(package);
+
 CREATE TABLE PackageVersionDocs (
     id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     package TEXT NOT NULL REFERENCES Package(name),
@@ -6,11 +6,12 @@
 //! API related to administration of the registry itself
 
 use axum::http::StatusCode;
-use chrono::Local;
+use chrono::{Local, NaiveDateTime};
 use thiserror::Error;
 
 use super::Database;
-use crate::model::auth::{RegistryUserToken, RegistryUserTokenWithSecret};
+use super::users::{rotated_token_validity, token_expires_at};
+use crate::model::auth::{RegistryUserToken, RegistryUserTokenWithSecret, TokenScopes};
 use crate::utils::apierror::AsStatusCode;
 use crate::utils::token::{generate_token, hash_token};
 
@@ -34,9 +35,11 @@ impl AsStatusCode for TokensError {
 impl Database {
     /// Gets the global tokens for the registry, usually for CI purposes
     pub async fn get_global_tokens(&self) -> Result<Vec<RegistryUserToken>, sqlx::Error> {
-        let rows = sqlx::query!("SELECT id, name, lastUsed AS last_used FROM RegistryGlobalToken ORDER BY id",)
-            .fetch_all(&mut *self.transaction.borrow().await)
-            .await?;
+        let rows = sqlx::query!(
+            "SELECT id, name, lastUsed AS last_used, expiresAt AS expires_at, previousExpiresAt AS previous_expires_at FROM RegistryGlobalToken ORDER BY id",
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
         Ok(rows
             .into_iter()
             .map(|row| RegistryUserToken {
@@ -45,12 +48,19 @@ impl Database {
                 last_used: row.last_used,
                 can_write: false,
                 can_admin: false,
+                scopes: TokenScopes::unrestricted(),
+                expires_at: row.expires_at,
+                previous_expires_at: row.previous_expires_at,
             })
             .collect())
     }
 
     /// Creates a global token for the registry
-    pub async fn create_global_token(&self, name: &str) -> Result<RegistryUserTokenWithSecret, TokensError> {
+    pub async fn create_global_token(
+        &self,
+        name: &str,
+        validity: Option<i64>,
+    ) -> Result<RegistryUserTokenWithSecret, TokensError> {
         let row = sqlx::query!("SELECT id FROM RegistryGlobalToken WHERE name = $1 LIMIT 1", name)
             .fetch_optional(&mut *self.transaction.borrow().await)
             .await?;
@@ -60,11 +70,14 @@ impl Database {
         let token_secret = generate_token(64);
         let token_hash = hash_token(&token_secret);
         let now = Local::now().naive_local();
+        let expires_at = token_expires_at(validity, now);
         let id = sqlx::query!(
-            "INSERT INTO RegistryGlobalToken (name, token, lastUsed) VALUES ($1, $2, $3) RETURNING id",
+            "INSERT INTO RegistryGlobalToken (name, token, lastUsed, expiresAt, validity) VALUES ($1, $2, $3, $4, $5) RETURNING id",
             name,
             token_hash,
             now,
+            expires_at,
+            validity,
         )
         .fetch_one(&mut *self.transaction.borrow().await)
         .await?
@@ -76,9 +89,56 @@ impl Database {
             last_used: now,
             can_write: false,
             can_admin: false,
+            scopes: TokenScopes::unrestricted(),
+            expires_at,
         })
     }
 
+    /// Rotates the secret of a global token for the registry
+    /// The previous secret remains valid until the end of the grace period, or the expiration of the token if it comes first.
+    /// The expiration date is recomputed from now, using the token's current validity when none is requested.
+    pub async fn rotate_global_token(
+        &self,
+        token_id: i64,
+        validity: Option<i64>,
+        max_validity: Option<i64>,
+        grace_until: NaiveDateTime,
+    ) -> Result<Option<RegistryUserTokenWithSecret>, sqlx::Error> {
+        let row = sqlx::query!(
+            "SELECT name, token, lastUsed AS last_used, expiresAt AS expires_at, validity FROM RegistryGlobalToken WHERE id = $1",
+            token_id
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        let Some(row) = row else { return Ok(None) };
+        let token_secret = generate_token(64);
+        let token_hash = hash_token(&token_secret);
+        let validity = rotated_token_validity(validity, row.validity, max_validity);
+        let expires_at = token_expires_at(validity, Local::now().naive_local());
+        let previous_expires_at = row.expires_at.map_or(grace_until, |old| old.min(grace_until));
+        sqlx::query!(
+            "UPDATE RegistryGlobalToken SET token = $2, expiresAt = $3, validity = $4, expiryNotified = FALSE, previousToken = $5, previousExpiresAt = $6 WHERE id = $1",
+            token_id,
+            token_hash,
+            expires_at,
+            validity,
+            row.token,
+            previous_expires_at
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(Some(RegistryUserTokenWithSecret {
+            id: token_id,
+            name: row.name,
+            secret: token_secret,
+            last_used: row.last_used,
+            can_write: false,
+            can_admin: false,
+            scopes: TokenScopes::unrestricted(),
+            expires_at,
+        }))
+    }
+
     /// Revokes a global token for the registry
     pub async fn revoke_global_token(&self, token_id: i64) -> Result<(), sqlx::Error> {
         sqlx::query!("DELETE FROM RegistryGlobalToken WHERE id = $1", token_id)
@@ -0,0 +1,411 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the security advisories authored in this registry and their suppressions
+
+use axum::http::StatusCode;
+use chrono::{Datelike, Local};
+use thiserror::Error;
+
+use super::Database;
+use crate::model::advisories::{
+    AdvisorySuppression, AdvisorySuppressionCreation, INTERNAL_ADVISORY_PREFIX, InternalAdvisory, InternalAdvisoryData,
+    cvss_type,
+};
+use crate::model::osv::SimpleAdvisoryRange;
+use crate::utils::apierror::AsStatusCode;
+
+#[derive(Debug, Error)]
+pub enum AdvisoryError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("crate `{package}` not found")]
+    PackageNotFound { package: String },
+
+    #[error("the summary of the advisory must not be empty")]
+    EmptySummary,
+
+    #[error("invalid severity `{severity}`, expected a CVSS v3 or v4 vector")]
+    InvalidSeverity { severity: String },
+
+    #[error("the advisory must have at least one affected range")]
+    NoRange,
+
+    #[error("invalid affected range introduced in {introduced}, the fixed and last affected versions must follow it")]
+    InvalidRange { introduced: String },
+
+    #[error("advisory {id} not found")]
+    AdvisoryNotFound { id: String },
+
+    #[error("advisory {id} is withdrawn")]
+    AdvisoryWithdrawn { id: String },
+
+    #[error("version {version} of crate `{package}` not found")]
+    VersionNotFound { package: String, version: String },
+
+    #[error("the identifier of the suppressed advisory must not be empty")]
+    EmptySuppressedAdvisory,
+
+    #[error("the justification for the suppression must not be empty")]
+    EmptyJustification,
+
+    #[error("the expiration of the suppression must be in the future")]
+    ExpiredSuppression,
+
+    #[error("advisory suppression {id} not found")]
+    SuppressionNotFound { id: i64 },
+}
+
+impl AsStatusCode for AdvisoryError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::EmptySummary
+            | Self::InvalidSeverity { .. }
+            | Self::NoRange
+            | Self::InvalidRange { .. }
+            | Self::EmptySuppressedAdvisory
+            | Self::EmptyJustification
+            | Self::ExpiredSuppression => StatusCode::BAD_REQUEST,
+            Self::PackageNotFound { .. }
+            | Self::AdvisoryNotFound { .. }
+            | Self::VersionNotFound { .. }
+            | Self::SuppressionNotFound { .. } => StatusCode::NOT_FOUND,
+            Self::AdvisoryWithdrawn { .. } => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl Database {
+    /// Gets all the internal advisories, including the withdrawn ones
+    pub async fn get_internal_advisories(&self) -> Result<Vec<InternalAdvisory>, AdvisoryError> {
+        self.get_internal_advisories_where(None, None).await
+    }
+
+    /// Gets the internal advisories against a crate, including the withdrawn ones
+    pub async fn get_crate_advisories(&self, package: &str) -> Result<Vec<InternalAdvisory>, AdvisoryError> {
+        self.get_internal_advisories_where(Some(package), None).await
+    }
+
+    /// Gets an internal advisory by its identifier
+    pub async fn get_internal_advisory(&self, id: &str) -> Result<Option<InternalAdvisory>, AdvisoryError> {
+        Ok(self.get_internal_advisories_where(None, Some(id)).await?.pop())
+    }
+
+    /// Gets the internal advisories, optionally only against a crate or with a specific identifier
+    async fn get_internal_advisories_where(
+        &self,
+        package: Option<&str>,
+        id: Option<&str>,
+    ) -> Result<Vec<InternalAdvisory>, AdvisoryError> {
+        let rows = sqlx::query!(
+            "SELECT PackageAdvisory.id, package, summary, details, severity, RegistryUser.email AS author, published, modified, withdrawn
+            FROM PackageAdvisory LEFT JOIN RegistryUser ON PackageAdvisory.author = RegistryUser.id
+            WHERE ($1 IS NULL OR package = $1) AND ($2 IS NULL OR PackageAdvisory.id = $2)
+            ORDER BY published DESC, PackageAdvisory.id DESC",
+            package,
+            id
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut advisories = Vec::with_capacity(rows.len());
+        for row in rows {
+            let ranges = self.get_internal_advisory_ranges(&row.id).await?;
+            advisories.push(InternalAdvisory {
+                id: row.id,
+                package: row.package,
+                summary: row.summary,
+                details: row.details,
+                severity: row.severity,
+                ranges,
+                author: row.author,
+                published: row.published,
+                modified: row.modified,
+                withdrawn: row.withdrawn,
+            });
+        }
+        Ok(advisories)
+    }
+
+    /// Gets the affected ranges for an internal advisory
+    async fn get_internal_advisory_ranges(&self, id: &str) -> Result<Vec<SimpleAdvisoryRange>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT introduced, fixed, lastAffected AS last_affected FROM PackageAdvisoryRange WHERE advisory = $1 ORDER BY id",
+            id
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let parse = |version: &str| version.parse().map_err(|e| sqlx::Error::Decode(Box::new(e)));
+        rows.into_iter()
+            .map(|row| {
+                Ok(SimpleAdvisoryRange {
+                    introduced: parse(&row.introduced)?,
+                    fixed: row.fixed.as_deref().map(parse).transpose()?,
+                    last_affected: row.last_affected.as_deref().map(parse).transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Creates an advisory against a local crate
+    pub async fn create_crate_advisory(
+        &self,
+        uid: i64,
+        package: &str,
+        data: &InternalAdvisoryData,
+    ) -> Result<InternalAdvisory, AdvisoryError> {
+        let exists = sqlx::query!("SELECT name FROM Package WHERE name = $1 LIMIT 1", package)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+        if exists.is_none() {
+            return Err(AdvisoryError::PackageNotFound {
+                package: package.to_string(),
+            });
+        }
+        validate_advisory_data(data)?;
+        let now = Local::now().naive_local();
+        let prefix = format!("{INTERNAL_ADVISORY_PREFIX}-{}-", now.year());
+        let pattern = format!("{prefix}%");
+        let count = sqlx::query!("SELECT COUNT(id) AS count FROM PackageAdvisory WHERE id LIKE $1", pattern)
+            .fetch_one(&mut *self.transaction.borrow().await)
+            .await?
+            .count;
+        let id = format!("{prefix}{:04}", count + 1);
+        let summary = data.summary.trim();
+        sqlx::query!(
+            "INSERT INTO PackageAdvisory (id, package, summary, details, severity, author, published, modified) VALUES ($1, $2, $3, $4, $5, $6, $7, $7)",
+            id,
+            package,
+            summary,
+            data.details,
+            data.severity,
+            uid,
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        self.set_internal_advisory_ranges(&id, &data.ranges).await?;
+        self.get_internal_advisory(&id)
+            .await?
+            .ok_or(AdvisoryError::AdvisoryNotFound { id })
+    }
+
+    /// Updates an advisory against a local crate
+    pub async fn update_crate_advisory(
+        &self,
+        package: &str,
+        id: &str,
+        data: &InternalAdvisoryData,
+    ) -> Result<InternalAdvisory, AdvisoryError> {
+        self.check_crate_advisory_active(package, id).await?;
+        validate_advisory_data(data)?;
+        let now = Local::now().naive_local();
+        let summary = data.summary.trim();
+        sqlx::query!(
+            "UPDATE PackageAdvisory SET summary = $2, details = $3, severity = $4, modified = $5 WHERE id = $1",
+            id,
+            summary,
+            data.details,
+            data.severity,
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        self.set_internal_advisory_ranges(id, &data.ranges).await?;
+        self.get_internal_advisory(id)
+            .await?
+            .ok_or_else(|| AdvisoryError::AdvisoryNotFound { id: id.to_string() })
+    }
+
+    /// Withdraws an advisory against a local crate
+    /// The advisory is kept so that consumers of the OSV feed are notified of the withdrawal.
+    pub async fn withdraw_crate_advisory(&self, package: &str, id: &str) -> Result<(), AdvisoryError> {
+        self.check_crate_advisory_active(package, id).await?;
+        let now = Local::now().naive_local();
+        sqlx::query!(
+            "UPDATE PackageAdvisory SET withdrawn = $2, modified = $2 WHERE id = $1",
+            id,
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Gets the suppressions of advisories for a crate, including the expired ones
+    pub async fn get_advisory_suppressions(&self, package: &str) -> Result<Vec<AdvisorySuppression>, AdvisoryError> {
+        let rows = sqlx::query_as!(
+            AdvisorySuppression,
+            "SELECT PackageAdvisorySuppression.id, package, version, advisory, justification, RegistryUser.email AS \"author?\", created, expires
+            FROM PackageAdvisorySuppression LEFT JOIN RegistryUser ON PackageAdvisorySuppression.author = RegistryUser.id
+            WHERE package = $1
+            ORDER BY created DESC, PackageAdvisorySuppression.id DESC",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Suppresses an advisory for a crate, or one of its versions
+    /// The crate versions concerned by the suppression are scheduled for a new analysis.
+    pub async fn create_advisory_suppression(
+        &self,
+        uid: i64,
+        package: &str,
+        creation: &AdvisorySuppressionCreation,
+    ) -> Result<AdvisorySuppression, AdvisoryError> {
+        let exists = sqlx::query!("SELECT name FROM Package WHERE name = $1 LIMIT 1", package)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+        if exists.is_none() {
+            return Err(AdvisoryError::PackageNotFound {
+                package: package.to_string(),
+            });
+        }
+        if let Some(version) = &creation.version {
+            let exists = sqlx::query!(
+                "SELECT version FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
+                package,
+                version
+            )
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+            if exists.is_none() {
+                return Err(AdvisoryError::VersionNotFound {
+                    package: package.to_string(),
+                    version: version.clone(),
+                });
+            }
+        }
+        let advisory = creation.advisory.trim();
+        if advisory.is_empty() {
+            return Err(AdvisoryError::EmptySuppressedAdvisory);
+        }
+        let justification = creation.justification.trim();
+        if justification.is_empty() {
+            return Err(AdvisoryError::EmptyJustification);
+        }
+        let now = Local::now().naive_local();
+        if creation.expires.is_some_and(|expires| expires <= now) {
+            return Err(AdvisoryError::ExpiredSuppression);
+        }
+        let id = sqlx::query!(
+            "INSERT INTO PackageAdvisorySuppression (package, version, advisory, justification, author, created, expires) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+            package,
+            creation.version,
+            advisory,
+            justification,
+            uid,
+            now,
+            creation.expires
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        self.reset_crate_deps_analysis(package, creation.version.as_deref()).await?;
+        self.get_advisory_suppressions(package)
+            .await?
+            .into_iter()
+            .find(|suppression| suppression.id == id)
+            .ok_or(AdvisoryError::SuppressionNotFound { id })
+    }
+
+    /// Removes the suppression of an advisory for a crate
+    /// The crate versions concerned by the suppression are scheduled for a new analysis.
+    pub async fn delete_advisory_suppression(&self, package: &str, id: i64) -> Result<(), AdvisoryError> {
+        let row = sqlx::query!(
+            "DELETE FROM PackageAdvisorySuppression WHERE id = $1 AND package = $2 RETURNING version",
+            id,
+            package
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .ok_or(AdvisoryError::SuppressionNotFound { id })?;
+        self.reset_crate_deps_analysis(package, row.version.as_deref()).await?;
+        Ok(())
+    }
+
+    /// Marks the dependency analysis of a crate, or one of its versions, as stale
+    async fn reset_crate_deps_analysis(&self, package: &str, version: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE PackageVersion SET depsLastCheck = 0 WHERE package = $1 AND ($2 IS NULL OR version = $2)",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Checks that an advisory exists against a crate and is not withdrawn
+    async fn check_crate_advisory_active(&self, package: &str, id: &str) -> Result<(), AdvisoryError> {
+        let row = sqlx::query!(
+            "SELECT withdrawn FROM PackageAdvisory WHERE id = $1 AND package = $2 LIMIT 1",
+            id,
+            package
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .ok_or_else(|| AdvisoryError::AdvisoryNotFound { id: id.to_string() })?;
+        if row.withdrawn.is_some() {
+            return Err(AdvisoryError::AdvisoryWithdrawn { id: id.to_string() });
+        }
+        Ok(())
+    }
+
+    /// Sets the affected ranges for an internal advisory, replacing the previous ones
+    async fn set_internal_advisory_ranges(&self, id: &str, ranges: &[SimpleAdvisoryRange]) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM PackageAdvisoryRange WHERE advisory = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        for range in ranges {
+            let introduced = range.introduced.to_string();
+            let fixed = range.fixed.as_ref().map(ToString::to_string);
+            let last_affected = range.last_affected.as_ref().map(ToString::to_string);
+            sqlx::query!(
+                "INSERT INTO PackageAdvisoryRange (advisory, introduced, fixed, lastAffected) VALUES ($1, $2, $3, $4)",
+                id,
+                introduced,
+                fixed,
+                last_affected
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Validates the data for an internal advisory
+fn validate_advisory_data(data: &InternalAdvisoryData) -> Result<(), AdvisoryError> {
+    if data.summary.trim().is_empty() {
+        return Err(AdvisoryError::EmptySummary);
+    }
+    if let Some(severity) = &data.severity
+        && cvss_type(severity).is_none()
+    {
+        return Err(AdvisoryError::InvalidSeverity {
+            severity: severity.clone(),
+        });
+    }
+    if data.ranges.is_empty() {
+        return Err(AdvisoryError::NoRange);
+    }
+    for range in &data.ranges {
+        let is_valid = range.fixed.as_ref().is_none_or(|fixed| fixed > &range.introduced)
+            && range
+                .last_affected
+                .as_ref()
+                .is_none_or(|last_affected| last_affected >= &range.introduced);
+        if !is_valid {
+            return Err(AdvisoryError::InvalidRange {
+                introduced: range.introduced.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
@@ -5,9 +5,13 @@
 //! Service for persisting information in the database
 
 pub mod admin;
+pub mod advisories;
 pub mod jobs;
 pub mod packages;
+pub mod policy;
+pub mod reservations;
 pub mod stats;
+pub mod teams;
 pub mod users;
 
 use std::future::Future;
@@ -248,13 +252,16 @@ impl Database {
         Ok(())
     }
 
-    /// Checks the ownership of a package
+    /// Checks the ownership of a package, either directly or through a team
     pub async fn check_is_crate_manager(&self, uid: i64, package: &str) -> Result<i64, IsCrateManagerError> {
         if self.check_is_admin(uid).await.is_ok() {
             return Ok(uid);
         }
         let row = sqlx::query!(
-            "SELECT id from PackageOwner WHERE package = $1 AND owner = $2 LIMIT 1",
+            "SELECT id from PackageOwner WHERE package = $1 AND owner = $2
+            UNION SELECT PackageTeamOwner.id FROM PackageTeamOwner INNER JOIN TeamMember ON PackageTeamOwner.team = TeamMember.team
+            WHERE PackageTeamOwner.package = $1 AND TeamMember.user = $2
+            LIMIT 1",
             package,
             uid
         )
@@ -5,28 +5,39 @@
 //! Service for persisting information in the database
 //! API related to the management of packages (crates)
 
-use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 
 use axum::http::StatusCode;
 use byteorder::ByteOrder;
 use chrono::{Datelike, Duration, Local, NaiveDateTime};
 use futures::StreamExt;
-use semver::Version;
+use semver::{Version, VersionReq};
 use smol_str::SmolStr;
 use thiserror::Error;
 
+use super::policy::PolicyError;
+use super::reservations::ReservationError;
+use super::teams::TeamError;
 use super::{Database, IsCrateManagerError, users::UserError};
 use crate::application::AuthenticationError;
 use crate::model::CrateVersion;
+use crate::model::auth::{CrateReader, ROLE_ADMIN};
 use crate::model::cargo::{
-    CrateNameError, CrateUploadData, CrateUploadResult, IndexCrateMetadata, OwnersQueryResult, RegistryUser, SearchResultCrate,
-    SearchResults, SearchResultsMeta, YesNoMsgResult, YesNoResult,
+    CargoVcsGitInfo, CargoVcsInfo, CrateNameError, CrateUploadData, CrateUploadResult, IndexCrateDependency,
+    IndexCrateMetadata, OwnersQueryResult, RegistryUser, SearchResultCrate, SearchResults, SearchResultsMeta, YesNoMsgResult,
+    YesNoResult,
 };
-use crate::model::deps::{DepsAnalysisJobSpec, DepsAnalysisState};
+use crate::model::deps::{AdvisoryImpactedVersion, DepResolution, DepsAnalysisJobSpec, DepsAnalysisState};
 use crate::model::docs::DocGenJobSpec;
-use crate::model::packages::{CrateInfo, CrateInfoTarget, CrateInfoVersion, CrateInfoVersionDocs};
+use crate::model::osv::SimpleAdvisory;
+use crate::model::packages::{
+    CrateDependent, CrateDependents, CrateInfo, CrateInfoTarget, CrateInfoVersion, CrateInfoVersionDocs, READER_ROLE_PREFIX,
+};
+use crate::model::policy::evaluate_publish_policy;
 use crate::model::stats::{DownloadStats, SERIES_LENGTH};
+use crate::model::teams::{TEAM_LOGIN_PREFIX, Team};
+use crate::model::typosquat::{CrateReview, NameCollision, TyposquatPolicy, name_similarity};
 use crate::utils::apierror::AsStatusCode;
 use crate::utils::comma_sep_to_vec;
 
@@ -104,6 +115,24 @@ pub enum CratesError {
 
     #[error("cannot remove all owners")]
     RemoveAllOwners,
+
+    #[error(transparent)]
+    Team(#[from] TeamError),
+
+    #[error("invalid reader `{reader}`")]
+    InvalidReader { reader: String },
+
+    #[error(transparent)]
+    Reservation(#[from] ReservationError),
+
+    #[error("the name `{package}` is too close to {collision}, ask an administrator to reserve the name for you to publish it")]
+    ConfusableName { package: String, collision: NameCollision },
+
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+
+    #[error("the crate does not comply with the publish policy: {}", violations.join("; "))]
+    PolicyViolations { violations: Vec<String> },
 }
 impl AsStatusCode for CratesError {
     fn status_code(&self) -> StatusCode {
@@ -114,17 +143,50 @@ impl AsStatusCode for CratesError {
             }
             Self::Metadata(crate_name_error) => crate_name_error.status_code(),
             Self::CrateManager(err) => err.status_code(),
+            Self::Team(err) => err.status_code(),
+            Self::Reservation(err) => err.status_code(),
+            Self::ConfusableName { .. } => StatusCode::FORBIDDEN,
+            Self::Policy(err) => err.status_code(),
             Self::PackageAlreadyExistInVersion { .. }
             | Self::PackageAlreadyExist(_)
             | Self::PackageNotAllowRemoveVersion { .. }
             | Self::PackageNotExistInVersion { .. }
             | Self::AlreadyYanked { .. }
             | Self::RemoveAllOwners
+            | Self::InvalidReader { .. }
+            | Self::PolicyViolations { .. }
             | Self::PackageVersionNotYanked { .. } => StatusCode::BAD_REQUEST,
         }
     }
 }
 
+/// The identity of a reader, as matched against the readers of a crate
+#[derive(Debug, Default)]
+struct ReaderIdentity {
+    /// The identifier of the user, none for the registry itself
+    uid: Option<i64>,
+    /// The email of the user
+    email: String,
+    /// The roles of the user
+    roles: Vec<String>,
+    /// The names of the teams of the user
+    teams: Vec<String>,
+}
+
+impl ReaderIdentity {
+    /// Gets whether this identity matches an entry in the readers of a crate
+    fn matches(&self, reader: &str) -> bool {
+        match (
+            reader.strip_prefix(TEAM_LOGIN_PREFIX),
+            reader.strip_prefix(READER_ROLE_PREFIX),
+        ) {
+            (Some(team), _) => self.teams.iter().any(|t| t == team),
+            (None, Some(role)) => self.roles.iter().any(|r| r == role),
+            (None, None) => self.email == reader,
+        }
+    }
+}
+
 impl Database {
     /// Search for crates
     pub async fn search_crates(
@@ -132,6 +194,7 @@ impl Database {
         query: &str,
         per_page: Option<usize>,
         deprecated: Option<bool>,
+        hidden: &HashSet<String>,
     ) -> Result<SearchResults, sqlx::Error> {
         let per_page = match per_page {
             None => 10,
@@ -151,6 +214,9 @@ impl Database {
         .await?;
         let mut crates = Vec::new();
         for row_name in rows {
+            if hidden.contains(&row_name.name) {
+                continue;
+            }
             let row = sqlx::query!("SELECT version, description FROM PackageVersion WHERE package = $1 AND yanked = FALSE ORDER BY id DESC LIMIT 1", row_name.name).fetch_optional(&mut *self.transaction.borrow().await).await?;
             if let Some(row) = row {
                 crates.push(SearchResultCrate {
@@ -180,6 +246,17 @@ impl Database {
             .is_none())
     }
 
+    /// Gets whether a package is already registered, regardless of the case of its name
+    pub async fn get_crate_exists(&self, package: &str) -> Result<bool, sqlx::Error> {
+        let lowercase = package.to_ascii_lowercase();
+        Ok(
+            sqlx::query!("SELECT name FROM Package WHERE lowercase = $1 LIMIT 1", lowercase)
+                .fetch_optional(&mut *self.transaction.borrow().await)
+                .await?
+                .is_some(),
+        )
+    }
+
     /// Gets the last version number for a package
     pub async fn get_crate_last_version(&self, package: &str) -> Result<String, CratesError> {
         let row = sqlx::query!(
@@ -199,7 +276,7 @@ impl Database {
         versions_in_index: Vec<IndexCrateMetadata>,
     ) -> Result<CrateInfo, CratesError> {
         let row = sqlx::query!(
-            "SELECT isDeprecated AS is_deprecated, canRemove AS can_remove, targets, nativeTargets AS nativetargets, capabilities FROM Package WHERE name = $1 LIMIT 1",
+            "SELECT isDeprecated AS is_deprecated, canRemove AS can_remove, targets, nativeTargets AS nativetargets, capabilities, readers FROM Package WHERE name = $1 LIMIT 1",
             package
         )
         .fetch_optional(&mut *self.transaction.borrow().await)
@@ -210,11 +287,14 @@ impl Database {
         let targets = comma_sep_to_vec(&row.targets);
         let native_targets = comma_sep_to_vec(&row.nativetargets);
         let capabilities = comma_sep_to_vec(&row.capabilities);
+        let readers = comma_sep_to_vec(&row.readers);
 
         let rows = sqlx::query!(
             "SELECT version, upload, uploadedBy AS uploaded_by,
                     downloadCount AS download_count,
-                    depsLastCheck AS deps_last_check, depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves
+                    depsLastCheck AS deps_last_check, depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves,
+                    depsHasLicenseViolations AS deps_has_license_violations,
+                    vcsSha1 AS vcs_sha1, vcsDirty AS vcs_dirty, vcsPath AS vcs_path
             FROM PackageVersion WHERE package = $1 ORDER BY id",
             package
         )
@@ -238,7 +318,15 @@ impl Database {
                     deps_last_check: row.deps_last_check,
                     deps_has_outdated: row.deps_has_outdated,
                     deps_has_cves: row.deps_has_cves,
+                    deps_has_license_violations: row.deps_has_license_violations,
                     docs: Vec::new(),
+                    vcs: row.vcs_sha1.as_ref().map(|sha1| CargoVcsInfo {
+                        git: Some(CargoVcsGitInfo {
+                            sha1: sha1.clone(),
+                            dirty: row.vcs_dirty,
+                        }),
+                        path_in_vcs: row.vcs_path.clone().unwrap_or_default(),
+                    }),
                 });
             }
         }
@@ -272,12 +360,28 @@ impl Database {
                 })
                 .collect(),
             capabilities,
+            readers,
         })
     }
 
-    /// Publish a crate
-    pub async fn publish_crate_version(&self, uid: i64, package: &CrateUploadData) -> Result<CrateUploadResult, CratesError> {
-        let warnings = package.metadata.validate()?;
+    /// Publish a crate, with the VCS information embedded in the packaged crate, if any
+    pub async fn publish_crate_version(
+        &self,
+        uid: i64,
+        package: &CrateUploadData,
+        vcs_info: Option<&CargoVcsInfo>,
+        typosquat_policy: TyposquatPolicy,
+        popular_names: &[String],
+    ) -> Result<CrateUploadResult, CratesError> {
+        let mut result = package.metadata.validate()?;
+        // evaluate the publish policy
+        let report = evaluate_publish_policy(&self.get_publish_rules().await?, package, vcs_info);
+        if !report.violations.is_empty() {
+            return Err(CratesError::PolicyViolations {
+                violations: report.violations,
+            });
+        }
+        result.warnings.other.extend(report.warnings);
         let lowercase = package.metadata.name.to_ascii_lowercase();
         let row = sqlx::query!(
             "SELECT upload FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
@@ -305,11 +409,37 @@ impl Database {
             // check the ownership
             self.check_is_crate_manager(uid, &package.metadata.name).await?;
         } else {
+            // check the reserved names
+            let is_reserved = self.check_name_reservation(uid, &package.metadata.name).await?;
+            // check for names that could be confused with other crates, unless the name was reserved for the user
+            let mut review_reason = None;
+            if typosquat_policy != TyposquatPolicy::Off
+                && !is_reserved
+                && let Some(collision) = self.find_confusable_crate(uid, &package.metadata.name, popular_names).await?
+            {
+                let reason = format!("the name `{}` is close to {collision}", package.metadata.name);
+                match typosquat_policy {
+                    TyposquatPolicy::Reject => {
+                        return Err(CratesError::ConfusableName {
+                            package: package.metadata.name.clone(),
+                            collision,
+                        });
+                    }
+                    TyposquatPolicy::Review => {
+                        result.warnings.other.push(format!(
+                            "{reason}, the crate is only visible to its owners until an administrator approves it"
+                        ));
+                        review_reason = Some(reason);
+                    }
+                    TyposquatPolicy::Off | TyposquatPolicy::Warn => result.warnings.other.push(reason),
+                }
+            }
             // create the package
             sqlx::query!(
-                "INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove) VALUES ($1, $2, '', '', '', FALSE, FALSE)",
+                "INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove, reviewReason) VALUES ($1, $2, '', '', '', FALSE, FALSE, $3)",
                 package.metadata.name,
-                lowercase
+                lowercase,
+                review_reason
             )
             .execute(&mut *self.transaction.borrow().await)
             .await?;
@@ -325,17 +455,62 @@ impl Database {
         let now = Local::now().naive_local();
         // create the version
         let description = package.metadata.description.as_ref().map_or("", String::as_str);
+        let vcs_git = vcs_info.and_then(|info| info.git.as_ref());
+        let vcs_sha1 = vcs_git.map(|git| git.sha1.as_str());
+        let vcs_dirty = vcs_git.is_some_and(|git| git.dirty);
+        let vcs_path = vcs_info
+            .filter(|info| info.git.is_some())
+            .map(|info| info.path_in_vcs.as_str());
         sqlx::query!(
-            "INSERT INTO PackageVersion (package, version, description, upload, uploadedBy, yanked, downloadCount, downloads, depsLastCheck, depsHasOutdated, depsHasCVEs) VALUES ($1, $2, $3, $4, $5, false, 0, NULL, 0, false, false)",
+            "INSERT INTO PackageVersion (package, version, description, upload, uploadedBy, yanked, downloadCount, downloads, depsLastCheck, depsHasOutdated, depsHasCVEs, vcsSha1, vcsDirty, vcsPath) VALUES ($1, $2, $3, $4, $5, false, 0, NULL, 0, false, false, $6, $7, $8)",
             package.metadata.name,
             package.metadata.vers,
             description,
             now,
             uid,
+            vcs_sha1,
+            vcs_dirty,
+            vcs_path,
         )
         .execute(&mut *self.transaction.borrow().await)
         .await?;
-        Ok(warnings)
+        Ok(result)
+    }
+
+    /// Looks for a crate whose name could be confused with the name of a new crate,
+    /// either in this registry or in the popular crates of crates.io
+    /// The crates that the user already owns, directly or through a team, are not considered,
+    /// nor are the crates hidden from the user, so that their names do not leak.
+    pub async fn find_confusable_crate(
+        &self,
+        uid: i64,
+        package: &str,
+        popular_names: &[String],
+    ) -> Result<Option<NameCollision>, CratesError> {
+        let rows = sqlx::query!(
+            "SELECT name FROM Package
+            WHERE NOT EXISTS (SELECT id FROM PackageOwner WHERE PackageOwner.package = Package.name AND PackageOwner.owner = $1)
+            AND NOT EXISTS (
+                SELECT PackageTeamOwner.id FROM PackageTeamOwner INNER JOIN TeamMember ON PackageTeamOwner.team = TeamMember.team
+                WHERE PackageTeamOwner.package = Package.name AND TeamMember.user = $1
+            )",
+            uid
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let hidden = self.get_hidden_crates(CrateReader::User(uid)).await?;
+        let local = rows
+            .iter()
+            .filter(|row| !hidden.contains(&row.name))
+            .map(|row| (row.name.as_str(), false));
+        let popular = popular_names.iter().map(|name| (name.as_str(), true));
+        Ok(local.chain(popular).find_map(|(similar, popular)| {
+            name_similarity(package, similar).map(|similarity| NameCollision {
+                similar: similar.to_string(),
+                popular,
+                similarity,
+            })
+        }))
     }
 
     /// Completely removes a version from the registry
@@ -379,6 +554,20 @@ impl Database {
         )
         .execute(&mut *self.transaction.borrow().await)
         .await?;
+        sqlx::query!(
+            "DELETE FROM PackageDependency WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM PackageVersionResolution WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
 
         Ok(())
     }
@@ -405,7 +594,15 @@ impl Database {
                     })
                 } else {
                     sqlx::query!(
-                        "UPDATE PackageVersion SET yanked = TRUE WHERE package = $1 AND version = $2",
+                        "UPDATE PackageVersion SET yanked = TRUE, depsIndexed = FALSE WHERE package = $1 AND version = $2",
+                        package,
+                        version
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                    // yanked versions are no longer dependents of other crates
+                    sqlx::query!(
+                        "DELETE FROM PackageDependency WHERE package = $1 AND version = $2",
                         package,
                         version
                     )
@@ -451,6 +648,99 @@ impl Database {
         }
     }
 
+    /// Saves the dependencies of a crate version on other crates of this registry, in the index of reverse dependencies
+    pub async fn set_crate_version_dependencies(
+        &self,
+        package: &str,
+        version: &str,
+        deps: &[IndexCrateDependency],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM PackageDependency WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        // dependencies without a registry are in this registry
+        for dep in deps.iter().filter(|dep| dep.registry.is_none()) {
+            let dependency = dep.get_name();
+            let kind = dep.kind.name();
+            sqlx::query!(
+                "INSERT INTO PackageDependency (package, version, dependency, versionReq, kind) VALUES ($1, $2, $3, $4, $5)",
+                package,
+                version,
+                dependency,
+                dep.req,
+                kind
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
+        sqlx::query!(
+            "UPDATE PackageVersion SET depsIndexed = TRUE WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Gets the non-yanked crate versions that are missing from the index of reverse dependencies
+    pub async fn get_unindexed_crate_versions(&self) -> Result<Vec<CrateVersion>, sqlx::Error> {
+        let rows = sqlx::query_as!(
+            CrateVersion,
+            "SELECT package, version FROM PackageVersion WHERE depsIndexed = FALSE AND yanked = FALSE ORDER BY id"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Gets the versions of other crates of this registry that depend on a crate
+    pub async fn get_crate_dependents(&self, package: &str) -> Result<CrateDependents, CratesError> {
+        let head = match self.get_crate_last_version(package).await {
+            Ok(version) => Some(version),
+            Err(CratesError::PackageNotFound { .. }) => None,
+            Err(error) => return Err(error),
+        };
+        let head_semver = head.as_deref().and_then(|head| head.parse::<Version>().ok());
+        let rows = sqlx::query!(
+            "SELECT PackageDependency.package AS package, PackageDependency.version AS version, versionReq AS version_req, kind
+            FROM PackageDependency INNER JOIN PackageVersion
+                ON PackageVersion.package = PackageDependency.package AND PackageVersion.version = PackageDependency.version
+            WHERE dependency = $1 AND yanked = FALSE
+            ORDER BY PackageDependency.package, PackageVersion.id DESC",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut latest_versions = HashMap::new();
+        let mut dependents = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Ok(kind) = row.kind.parse() else {
+                continue;
+            };
+            let latest = match latest_versions.entry(row.package.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.get_crate_last_version(&row.package).await?),
+            };
+            let is_compatible = head_semver
+                .as_ref()
+                .is_some_and(|head| VersionReq::parse(&row.version_req).is_ok_and(|req| req.matches(head)));
+            dependents.push(CrateDependent {
+                is_latest: *latest == row.version,
+                package: row.package,
+                version: row.version,
+                version_req: row.version_req,
+                kind,
+                is_compatible,
+            });
+        }
+        Ok(CrateDependents { head, dependents })
+    }
+
     /// Gets the packages that need documentation generation
     pub async fn get_undocumented_crates(&self, default_target: &str) -> Result<Vec<DocGenJobSpec>, sqlx::Error> {
         struct PackageData {
@@ -725,10 +1015,12 @@ impl Database {
         version: &str,
         has_outdated: bool,
         has_cves: bool,
-    ) -> Result<(bool, bool), DepsError> {
+        has_license_violations: bool,
+    ) -> Result<(bool, bool, bool), DepsError> {
         let now = Local::now().naive_local();
         let row = sqlx::query!(
-            "SELECT depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves
+            "SELECT depsHasOutdated AS deps_has_outdated, depsHasCVEs AS deps_has_cves,
+                depsHasLicenseViolations AS deps_has_license_violations
             FROM PackageVersion
             WHERE package = $1 AND version = $2
             LIMIT 1",
@@ -743,17 +1035,145 @@ impl Database {
         })?;
         let deps_has_outdated = row.deps_has_outdated;
         let deps_has_cves = row.deps_has_cves;
+        let deps_has_license_violations = row.deps_has_license_violations;
         sqlx::query!(
-            "UPDATE PackageVersion SET depsLastCheck = $3, depsHasOutdated = $4, depsHasCVEs = $5 WHERE package = $1 AND version = $2",
+            "UPDATE PackageVersion SET depsLastCheck = $3, depsHasOutdated = $4, depsHasCVEs = $5, depsHasLicenseViolations = $6
+            WHERE package = $1 AND version = $2",
             package,
             version,
             now,
             has_outdated,
-            has_cves
+            has_cves,
+            has_license_violations
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok((deps_has_outdated, deps_has_cves, deps_has_license_violations))
+    }
+
+    /// Saves the crate versions in the resolved dependency graph of a crate version, replacing the previous ones
+    pub async fn set_crate_deps_resolutions(
+        &self,
+        package: &str,
+        version: &str,
+        resolutions: &[DepResolution],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM PackageVersionResolution WHERE package = $1 AND version = $2",
+            package,
+            version
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        for resolution in resolutions {
+            sqlx::query!(
+                "INSERT INTO PackageVersionResolution (package, version, registry, dependency, resolved, isDirect) VALUES ($1, $2, $3, $4, $5, $6)",
+                package,
+                version,
+                resolution.registry,
+                resolution.package,
+                resolution.version,
+                resolution.is_direct
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
+        sqlx::query!(
+            "UPDATE PackageVersion SET depsResolved = TRUE WHERE package = $1 AND version = $2",
+            package,
+            version
         )
         .execute(&mut *self.transaction.borrow().await)
         .await?;
-        Ok((deps_has_outdated, deps_has_cves))
+        Ok(())
+    }
+
+    /// Gets the non-yanked crate versions for which the resolved dependency graph was never saved
+    pub async fn get_unresolved_crate_versions(&self) -> Result<Vec<DepsAnalysisJobSpec>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT package, version, targets
+            FROM PackageVersion
+            INNER JOIN Package ON PackageVersion.package = Package.name
+            WHERE depsResolved = FALSE AND yanked = FALSE
+            ORDER BY PackageVersion.id"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| DepsAnalysisJobSpec {
+                package: row.package,
+                version: row.version,
+                targets: comma_sep_to_vec(&row.targets),
+            })
+            .collect())
+    }
+
+    /// Gets whether the dependency graph of a crate version is resolved for the current targets of the crate
+    pub async fn get_crate_deps_resolved(&self, package: &str, version: &str) -> Result<bool, CratesError> {
+        let row = sqlx::query!(
+            "SELECT depsResolved AS deps_resolved FROM PackageVersion WHERE package = $1 AND version = $2 LIMIT 1",
+            package,
+            version
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        .ok_or_else(|| CratesError::PackageVersionNotFound {
+            package: package.into(),
+            version: version.into(),
+        })?;
+        Ok(row.deps_resolved)
+    }
+
+    /// Gets the crate versions that resolve to an affected version of a crate in their last dependency analysis
+    pub async fn get_advisory_impact(
+        &self,
+        advisory: &SimpleAdvisory,
+        registry: Option<&str>,
+    ) -> Result<Vec<AdvisoryImpactedVersion>, CratesError> {
+        let rows = sqlx::query!(
+            "SELECT PackageVersionResolution.package AS package, PackageVersionResolution.version AS version,
+                resolved, isDirect AS is_direct, depsLastCheck AS deps_last_check
+            FROM PackageVersionResolution INNER JOIN PackageVersion
+                ON PackageVersion.package = PackageVersionResolution.package AND PackageVersion.version = PackageVersionResolution.version
+            WHERE dependency = $1 AND registry IS $2 AND yanked = FALSE
+            ORDER BY PackageVersionResolution.package, PackageVersion.id DESC",
+            advisory.package,
+            registry
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut latest_versions = HashMap::new();
+        let mut impacted = Vec::new();
+        for row in rows {
+            if !row
+                .resolved
+                .parse::<Version>()
+                .is_ok_and(|resolved| advisory.affects(&resolved))
+            {
+                continue;
+            }
+            let latest = match latest_versions.entry(row.package.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.get_crate_last_version(&row.package).await?),
+            };
+            // the same crate version may resolve to multiple versions of the affected crate
+            if impacted
+                .last()
+                .is_some_and(|last: &AdvisoryImpactedVersion| last.package == row.package && last.version == row.version)
+            {
+                continue;
+            }
+            impacted.push(AdvisoryImpactedVersion {
+                is_latest: *latest == row.version,
+                package: row.package,
+                version: row.version,
+                resolved: row.resolved,
+                is_direct: row.is_direct,
+                deps_last_check: row.deps_last_check,
+            });
+        }
+        Ok(impacted)
     }
 
     /// Increments the counter of downloads for a crate version
@@ -802,18 +1222,51 @@ impl Database {
     pub async fn get_crate_owners(&self, package: &str) -> Result<OwnersQueryResult, CratesError> {
         let users = sqlx::query_as!(RegistryUser, "SELECT RegistryUser.id, isActive AS is_active, email, login, name, roles FROM RegistryUser INNER JOIN PackageOwner ON PackageOwner.owner = RegistryUser.id WHERE package = $1", package)
             .fetch_all(&mut *self.transaction.borrow().await).await?;
-        Ok(OwnersQueryResult { users })
+        let rows = sqlx::query!(
+            "SELECT Team.id, Team.name FROM Team INNER JOIN PackageTeamOwner ON PackageTeamOwner.team = Team.id WHERE package = $1 ORDER BY Team.name",
+            package
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut teams = Vec::with_capacity(rows.len());
+        for row in rows {
+            teams.push(Team {
+                id: row.id,
+                members: self.get_team_members(row.id).await?,
+                name: row.name,
+            });
+        }
+        Ok(OwnersQueryResult { users, teams })
     }
 
     /// Add owners to a package
+    /// Teams are specified with the `team:<name>` login.
     pub async fn add_crate_owners(&self, package: &str, new_users: &[String]) -> Result<YesNoMsgResult, CratesError> {
         // get all current owners
         let rows = sqlx::query!("SELECT owner FROM PackageOwner WHERE package = $1", package,)
             .fetch_all(&mut *self.transaction.borrow().await)
             .await?;
+        let team_rows = sqlx::query!("SELECT team FROM PackageTeamOwner WHERE package = $1", package)
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
         // add new users
         let mut added = Vec::new();
         for new_user in new_users {
+            if let Some(team) = new_user.strip_prefix(TEAM_LOGIN_PREFIX) {
+                let team_id = self.get_team_id(team).await?;
+                if team_rows.iter().all(|r| r.team != team_id) {
+                    // not already an owner
+                    sqlx::query!(
+                        "INSERT INTO PackageTeamOwner (package, team) VALUES ($1, $2)",
+                        package,
+                        team_id
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                    added.push(new_user.as_str());
+                }
+                continue;
+            }
             let new_uid = self.check_is_user(new_user).await.map_err(|source| CratesError::IsUser {
                 source,
                 email: new_user.into(),
@@ -835,14 +1288,37 @@ impl Database {
     }
 
     /// Remove owners from a package
+    /// Teams are specified with the `team:<name>` login.
     pub async fn remove_crate_owners(&self, package: &str, old_users: &[String]) -> Result<YesNoResult, CratesError> {
         // get all current owners
         let rows = sqlx::query!("SELECT owner FROM PackageOwner WHERE package = $1", package,)
             .fetch_all(&mut *self.transaction.borrow().await)
             .await?;
+        let team_rows = sqlx::query!("SELECT team FROM PackageTeamOwner WHERE package = $1", package)
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
         let mut current_owners: Vec<i64> = rows.into_iter().map(|r| r.owner).collect();
+        let mut current_teams: Vec<i64> = team_rows.into_iter().map(|r| r.team).collect();
         // remove old users
         for old_user in old_users {
+            if let Some(team) = old_user.strip_prefix(TEAM_LOGIN_PREFIX) {
+                let team_id = self.get_team_id(team).await?;
+                if let Some(index) = current_teams.iter().position(|&x| x == team_id) {
+                    if current_owners.len() + current_teams.len() == 1 {
+                        // cannot remove the last one
+                        return Err(CratesError::RemoveAllOwners);
+                    }
+                    sqlx::query!(
+                        "DELETE FROM PackageTeamOwner WHERE package = $1 AND team = $2",
+                        package,
+                        team_id
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                    current_teams.remove(index);
+                }
+                continue;
+            }
             let old_uid = self.check_is_user(old_user).await.map_err(|source| CratesError::IsUser {
                 source,
                 email: old_user.into(),
@@ -853,7 +1329,7 @@ impl Database {
                 .find(|&(_, &x)| x == old_uid)
                 .map(|(i, _)| i);
             if let Some(index) = index {
-                if current_owners.len() == 1 {
+                if current_owners.len() + current_teams.len() == 1 {
                     // cannot remove the last one
                     return Err(CratesError::RemoveAllOwners);
                 }
@@ -926,6 +1402,15 @@ impl Database {
         )
         .execute(&mut *self.transaction.borrow().await)
         .await?;
+        if old_targets.len() != targets.len() || !added_targets.is_empty() {
+            // the dependency graphs and the SBOMs of all the versions must be generated again
+            sqlx::query!(
+                "UPDATE PackageVersion SET depsResolved = FALSE, depsLastCheck = 0 WHERE package = $1",
+                package
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
 
         // get versions
         let capabilities = comma_sep_to_vec(
@@ -971,6 +1456,193 @@ impl Database {
         Ok(())
     }
 
+    /// Gets the readers for a crate
+    pub async fn get_crate_readers(&self, package: &str) -> Result<Vec<String>, CratesError> {
+        let row = sqlx::query!("SELECT readers FROM Package WHERE name = $1 LIMIT 1", package)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .ok_or_else(|| CratesError::PackageNotFound { package: package.into() })?;
+        Ok(comma_sep_to_vec(&row.readers))
+    }
+
+    /// Sets the readers for a crate
+    /// Each reader is either the email of a user, a team (`team:<name>`) or a role (`role:<name>`).
+    pub async fn set_crate_readers(&self, package: &str, readers: &[String]) -> Result<(), CratesError> {
+        let _ = self.get_crate_readers(package).await?;
+        for reader in readers {
+            if reader.trim().is_empty() || reader.contains(',') || reader == READER_ROLE_PREFIX {
+                return Err(CratesError::InvalidReader { reader: reader.clone() });
+            }
+            if let Some(team) = reader.strip_prefix(TEAM_LOGIN_PREFIX) {
+                self.get_team_id(team).await?;
+            } else if !reader.starts_with(READER_ROLE_PREFIX) {
+                self.check_is_user(reader).await.map_err(|source| CratesError::IsUser {
+                    source,
+                    email: reader.clone(),
+                })?;
+            }
+        }
+        let readers = readers.join(",");
+        sqlx::query!("UPDATE Package SET readers = $2 WHERE name = $1", package, readers)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Checks that a crate can be seen by a reader
+    /// A crate that cannot be seen is reported as not found, so that its existence does not leak.
+    pub async fn check_can_read_crate(&self, reader: CrateReader, package: &str) -> Result<(), CratesError> {
+        let lowercase = package.to_ascii_lowercase();
+        let Some(row) = sqlx::query!(
+            "SELECT name, readers, reviewReason AS review_reason FROM Package WHERE lowercase = $1 LIMIT 1",
+            lowercase
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?
+        else {
+            // not a crate of this registry, let the caller report it
+            return Ok(());
+        };
+        // a crate held for review can only be seen by its owners and the administrators
+        let readers = if row.review_reason.is_some() {
+            Vec::new()
+        } else {
+            let readers = comma_sep_to_vec(&row.readers);
+            if readers.is_empty() {
+                return Ok(());
+            }
+            readers
+        };
+        let identity = self.get_reader_identity(reader).await?;
+        if self.is_crate_reader(identity.as_ref(), &row.name, &readers).await? {
+            Ok(())
+        } else {
+            Err(CratesError::PackageNotFound { package: package.into() })
+        }
+    }
+
+    /// Gets the names of the crates that cannot be seen by a reader
+    pub async fn get_hidden_crates(&self, reader: CrateReader) -> Result<HashSet<String>, CratesError> {
+        let mut hidden = HashSet::new();
+        if reader == CrateReader::Registry {
+            return Ok(hidden);
+        }
+        let identity = self.get_reader_identity(reader).await?;
+        let uid = identity.as_ref().and_then(|identity| identity.uid);
+        if let Some(identity) = &identity
+            && (identity.uid.is_none() || identity.roles.iter().any(|role| role == ROLE_ADMIN))
+        {
+            // the registry and administrators can see all crates
+            return Ok(hidden);
+        }
+        // resolve the ownership of all the restricted crates at once
+        let rows = sqlx::query!(
+            "SELECT Package.name, Package.readers, Package.reviewReason AS review_reason, Owned.package IS NOT NULL AS \"is_owner!: bool\"
+            FROM Package LEFT JOIN (
+                SELECT package FROM PackageOwner WHERE owner = $1
+                UNION SELECT PackageTeamOwner.package FROM PackageTeamOwner INNER JOIN TeamMember ON PackageTeamOwner.team = TeamMember.team
+                WHERE TeamMember.user = $1
+            ) AS Owned ON Owned.package = Package.name
+            WHERE Package.readers != '' OR Package.reviewReason IS NOT NULL",
+            uid
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        for row in rows {
+            let readers = comma_sep_to_vec(&row.readers);
+            let is_reader = row.is_owner
+                || (row.review_reason.is_none()
+                    && identity
+                        .as_ref()
+                        .is_some_and(|identity| readers.iter().any(|reader| identity.matches(reader))));
+            if !is_reader {
+                hidden.insert(row.name);
+            }
+        }
+        Ok(hidden)
+    }
+
+    /// Gets the new crates held for review because their names could be confused with other crates
+    pub async fn get_crate_reviews(&self) -> Result<Vec<CrateReview>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT name, reviewReason AS \"review_reason!\" FROM Package WHERE reviewReason IS NOT NULL ORDER BY name"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| CrateReview {
+                package: row.name,
+                reason: row.review_reason,
+            })
+            .collect())
+    }
+
+    /// Approves a crate held for review, making it visible to its readers
+    pub async fn approve_crate_review(&self, package: &str) -> Result<(), CratesError> {
+        let _ = self.get_crate_readers(package).await?;
+        sqlx::query!("UPDATE Package SET reviewReason = NULL WHERE name = $1", package)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Gets the identity of a reader to be matched against the readers of crates
+    async fn get_reader_identity(&self, reader: CrateReader) -> Result<Option<ReaderIdentity>, CratesError> {
+        match reader {
+            CrateReader::Anonymous => Ok(None),
+            CrateReader::Registry => Ok(Some(ReaderIdentity::default())),
+            CrateReader::User(uid) => {
+                let Some(row) = sqlx::query!("SELECT email, roles FROM RegistryUser WHERE id = $1 LIMIT 1", uid)
+                    .fetch_optional(&mut *self.transaction.borrow().await)
+                    .await?
+                else {
+                    return Ok(None);
+                };
+                let teams = sqlx::query!(
+                    "SELECT Team.name FROM TeamMember INNER JOIN Team ON TeamMember.team = Team.id WHERE TeamMember.user = $1",
+                    uid
+                )
+                .fetch_all(&mut *self.transaction.borrow().await)
+                .await?
+                .into_iter()
+                .map(|row| row.name)
+                .collect();
+                Ok(Some(ReaderIdentity {
+                    uid: Some(uid),
+                    email: row.email,
+                    roles: comma_sep_to_vec(&row.roles),
+                    teams,
+                }))
+            }
+        }
+    }
+
+    /// Gets whether a reader can see a crate with restricted readers
+    async fn is_crate_reader(
+        &self,
+        identity: Option<&ReaderIdentity>,
+        package: &str,
+        readers: &[String],
+    ) -> Result<bool, CratesError> {
+        let Some(identity) = identity else {
+            return Ok(false);
+        };
+        let Some(uid) = identity.uid else {
+            // the registry itself
+            return Ok(true);
+        };
+        if readers.iter().any(|reader| identity.matches(reader)) {
+            return Ok(true);
+        }
+        // owners and administrators can always see the crate
+        match self.check_is_crate_manager(uid, package).await {
+            Ok(_) => Ok(true),
+            Err(IsCrateManagerError::NotOwnerOfPackage) => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+
     /// Sets the deprecation status on a crate
     pub async fn set_crate_deprecation(&self, package: &str, deprecated: bool) -> Result<(), sqlx::Error> {
         sqlx::query!("UPDATE Package SET isDeprecated = $2 WHERE name = $1", package, deprecated)
@@ -0,0 +1,157 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the policy evaluated when publishing crates
+
+use axum::http::StatusCode;
+use thiserror::Error;
+
+use super::Database;
+use crate::model::policy::{LicenseRule, LicenseRuleCreation, PublishRule, PublishRuleCreation, PublishRuleKind};
+use crate::utils::apierror::AsStatusCode;
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("invalid parameter `{parameter}` for a rule of kind `{kind}`")]
+    InvalidParameter { kind: PublishRuleKind, parameter: String },
+
+    #[error("invalid license expression `{license}`")]
+    InvalidLicense { license: String },
+
+    #[error("a rule already exists for the license `{license}`")]
+    LicenseAlreadyExists { license: String },
+
+    #[error("rule {id} not found")]
+    RuleNotFound { id: i64 },
+}
+
+impl AsStatusCode for PolicyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidParameter { .. } | Self::InvalidLicense { .. } => StatusCode::BAD_REQUEST,
+            Self::LicenseAlreadyExists { .. } => StatusCode::CONFLICT,
+            Self::RuleNotFound { .. } => StatusCode::NOT_FOUND,
+        }
+    }
+}
+
+impl Database {
+    /// Gets the rules of the publish policy
+    pub async fn get_publish_rules(&self) -> Result<Vec<PublishRule>, PolicyError> {
+        let rows = sqlx::query!("SELECT id, kind, parameter, isBlocking AS is_blocking FROM PublishRule ORDER BY id")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(PublishRule {
+                    id: row.id,
+                    kind: row.kind.parse().ok()?,
+                    parameter: row.parameter,
+                    is_blocking: row.is_blocking,
+                })
+            })
+            .collect())
+    }
+
+    /// Adds a rule to the publish policy
+    pub async fn create_publish_rule(&self, creation: &PublishRuleCreation) -> Result<PublishRule, PolicyError> {
+        if !creation.is_valid() {
+            return Err(PolicyError::InvalidParameter {
+                kind: creation.kind,
+                parameter: creation.parameter.clone(),
+            });
+        }
+        let kind = creation.kind.name();
+        let parameter = if creation.kind.has_parameter() {
+            creation.parameter.trim()
+        } else {
+            ""
+        };
+        let id = sqlx::query!(
+            "INSERT INTO PublishRule (kind, parameter, isBlocking) VALUES ($1, $2, $3) RETURNING id",
+            kind,
+            parameter,
+            creation.is_blocking
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(PublishRule {
+            id,
+            kind: creation.kind,
+            parameter: parameter.to_string(),
+            is_blocking: creation.is_blocking,
+        })
+    }
+
+    /// Removes a rule from the publish policy
+    pub async fn delete_publish_rule(&self, id: i64) -> Result<(), PolicyError> {
+        let deleted = sqlx::query!("DELETE FROM PublishRule WHERE id = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(PolicyError::RuleNotFound { id });
+        }
+        Ok(())
+    }
+
+    /// Gets the rules of the license policy for dependencies
+    pub async fn get_license_rules(&self) -> Result<Vec<LicenseRule>, PolicyError> {
+        let rows = sqlx::query_as!(
+            LicenseRule,
+            "SELECT id, license, isAllowed AS is_allowed FROM LicenseRule ORDER BY id"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Adds a rule to the license policy for dependencies
+    pub async fn create_license_rule(&self, creation: &LicenseRuleCreation) -> Result<LicenseRule, PolicyError> {
+        let license = creation.license.split_whitespace().collect::<Vec<_>>().join(" ");
+        if license.is_empty() {
+            return Err(PolicyError::InvalidLicense {
+                license: creation.license.clone(),
+            });
+        }
+        let existing = sqlx::query!("SELECT id FROM LicenseRule WHERE license = $1 LIMIT 1", license)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+        if existing.is_some() {
+            return Err(PolicyError::LicenseAlreadyExists { license });
+        }
+        let id = sqlx::query!(
+            "INSERT INTO LicenseRule (license, isAllowed) VALUES ($1, $2) RETURNING id",
+            license,
+            creation.is_allowed
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(LicenseRule {
+            id,
+            license,
+            is_allowed: creation.is_allowed,
+        })
+    }
+
+    /// Removes a rule from the license policy for dependencies
+    pub async fn delete_license_rule(&self, id: i64) -> Result<(), PolicyError> {
+        let deleted = sqlx::query!("DELETE FROM LicenseRule WHERE id = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(PolicyError::RuleNotFound { id });
+        }
+        Ok(())
+    }
+}
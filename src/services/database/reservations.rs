@@ -0,0 +1,177 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the reservations of crate names
+
+use axum::http::StatusCode;
+use thiserror::Error;
+
+use super::Database;
+use super::teams::TeamError;
+use crate::model::reservations::{NameReservation, NameReservationCreation, is_valid_name_pattern, name_pattern_matches};
+use crate::model::teams::TEAM_LOGIN_PREFIX;
+use crate::utils::apierror::AsStatusCode;
+
+#[derive(Debug, Error)]
+pub enum ReservationError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    Team(#[from] TeamError),
+
+    #[error("invalid pattern `{pattern}`, it must only contain alphanumeric, -, _ and the * and ? wildcards")]
+    InvalidPattern { pattern: String },
+
+    #[error("the pattern `{pattern}` is already reserved")]
+    AlreadyReserved { pattern: String },
+
+    #[error("reservation {id} not found")]
+    ReservationNotFound { id: i64 },
+
+    #[error("the user '{email}' is not known")]
+    UserNotFound { email: String },
+
+    #[error("the name `{package}` is reserved for {owner} by the pattern `{pattern}`, ask an administrator for access")]
+    Reserved {
+        package: String,
+        pattern: String,
+        owner: String,
+    },
+}
+
+impl AsStatusCode for ReservationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Team(error) => error.status_code(),
+            Self::InvalidPattern { .. } => StatusCode::BAD_REQUEST,
+            Self::AlreadyReserved { .. } => StatusCode::CONFLICT,
+            Self::ReservationNotFound { .. } | Self::UserNotFound { .. } => StatusCode::NOT_FOUND,
+            Self::Reserved { .. } => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl Database {
+    /// Gets the reservations of crate names
+    pub async fn get_name_reservations(&self) -> Result<Vec<NameReservation>, ReservationError> {
+        let rows = sqlx::query!(
+            "SELECT PackageNameReservation.id, pattern, RegistryUser.email AS \"email?\", Team.name AS \"team?\"
+            FROM PackageNameReservation
+            LEFT JOIN RegistryUser ON PackageNameReservation.user = RegistryUser.id
+            LEFT JOIN Team ON PackageNameReservation.team = Team.id
+            ORDER BY pattern"
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| NameReservation {
+                id: row.id,
+                pattern: row.pattern,
+                owner: row
+                    .team
+                    .map_or_else(|| row.email.unwrap_or_default(), |team| format!("{TEAM_LOGIN_PREFIX}{team}")),
+            })
+            .collect())
+    }
+
+    /// Reserves crate names for a user or a team
+    pub async fn create_name_reservation(
+        &self,
+        creation: &NameReservationCreation,
+    ) -> Result<NameReservation, ReservationError> {
+        let pattern = creation.pattern.trim();
+        if !is_valid_name_pattern(pattern) {
+            return Err(ReservationError::InvalidPattern {
+                pattern: pattern.to_string(),
+            });
+        }
+        let existing = sqlx::query!("SELECT id FROM PackageNameReservation WHERE pattern = $1 LIMIT 1", pattern)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+        if existing.is_some() {
+            return Err(ReservationError::AlreadyReserved {
+                pattern: pattern.to_string(),
+            });
+        }
+        let (user, team) = if let Some(team) = creation.owner.strip_prefix(TEAM_LOGIN_PREFIX) {
+            (None, Some(self.get_team_id(team).await?))
+        } else {
+            let uid = sqlx::query!("SELECT id FROM RegistryUser WHERE email = $1 LIMIT 1", creation.owner)
+                .fetch_optional(&mut *self.transaction.borrow().await)
+                .await?
+                .ok_or_else(|| ReservationError::UserNotFound {
+                    email: creation.owner.clone(),
+                })?
+                .id;
+            (Some(uid), None)
+        };
+        let id = sqlx::query!(
+            "INSERT INTO PackageNameReservation (pattern, user, team) VALUES ($1, $2, $3) RETURNING id",
+            pattern,
+            user,
+            team
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(NameReservation {
+            id,
+            pattern: pattern.to_string(),
+            owner: creation.owner.clone(),
+        })
+    }
+
+    /// Removes a reservation of crate names
+    pub async fn delete_name_reservation(&self, id: i64) -> Result<(), ReservationError> {
+        let deleted = sqlx::query!("DELETE FROM PackageNameReservation WHERE id = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(ReservationError::ReservationNotFound { id });
+        }
+        Ok(())
+    }
+
+    /// Checks that a user can publish a new crate with regards to the reserved names
+    /// When the name matches reservations, the user must hold one of them, either directly or through a team.
+    /// Returns whether the name is reserved for the user.
+    pub async fn check_name_reservation(&self, uid: i64, package: &str) -> Result<bool, ReservationError> {
+        let rows = sqlx::query!(
+            "SELECT PackageNameReservation.pattern, PackageNameReservation.user, Team.name AS \"team?\",
+                EXISTS (SELECT TeamMember.id FROM TeamMember WHERE TeamMember.team = PackageNameReservation.team AND TeamMember.user = $1) AS \"is_member!: bool\",
+                RegistryUser.email AS \"email?\"
+            FROM PackageNameReservation
+            LEFT JOIN RegistryUser ON PackageNameReservation.user = RegistryUser.id
+            LEFT JOIN Team ON PackageNameReservation.team = Team.id",
+            uid
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        let mut matching = rows
+            .into_iter()
+            .filter(|row| name_pattern_matches(&row.pattern, package))
+            .peekable();
+        let Some(first) = matching.peek() else {
+            return Ok(false);
+        };
+        let error = ReservationError::Reserved {
+            package: package.to_string(),
+            pattern: first.pattern.clone(),
+            owner: first.team.as_ref().map_or_else(
+                || first.email.clone().unwrap_or_default(),
+                |team| format!("{TEAM_LOGIN_PREFIX}{team}"),
+            ),
+        };
+        if matching.any(|row| row.user == Some(uid) || row.is_member) {
+            Ok(true)
+        } else {
+            Err(error)
+        }
+    }
+}
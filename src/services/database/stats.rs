@@ -5,6 +5,8 @@
 //! Service for persisting information in the database
 //! API related to statistics
 
+use std::collections::HashSet;
+
 use thiserror::Error;
 
 use super::Database;
@@ -27,15 +29,22 @@ pub enum CratesStatsError {
 }
 
 impl Database {
-    /// Gets the global statistics for the registry
-    pub async fn get_crates_stats(&self) -> Result<GlobalStats, CratesStatsError> {
-        let total_crates = sqlx::query!("SELECT COUNT(name) AS total_crates FROM Package")
-            .fetch_one(&mut *self.transaction.borrow().await)
-            .await
-            .map_err(CratesStatsError::CountCrates)?
-            .total_crates;
-        let total_downloads = sqlx::query!("SELECT SUM(downloadCount) AS total_downloads FROM PackageVersion")
-            .fetch_one(&mut *self.transaction.borrow().await)
+    /// Gets the global statistics for the registry, excluding the hidden crates
+    pub async fn get_crates_stats(&self, hidden: &HashSet<String>) -> Result<GlobalStats, CratesStatsError> {
+        let hidden = serde_json::to_string(hidden).unwrap_or_default();
+        let total_crates = sqlx::query!(
+            "SELECT COUNT(name) AS total_crates FROM Package WHERE name NOT IN (SELECT value FROM json_each($1))",
+            hidden
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await
+        .map_err(CratesStatsError::CountCrates)?
+        .total_crates;
+        let total_downloads = sqlx::query!(
+            "SELECT SUM(downloadCount) AS total_downloads FROM PackageVersion WHERE package NOT IN (SELECT value FROM json_each($1))",
+            hidden
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
             .await
             .map_err(CratesStatsError::CountDownload)?
             .total_downloads
@@ -45,8 +54,10 @@ impl Database {
             "SELECT name, version, upload
             FROM Package INNER JOIN PackageVersion ON package = name
             WHERE (SELECT COUNT(version) FROM PackageVersion WHERE package = name) = 1
+            AND name NOT IN (SELECT value FROM json_each($1))
             ORDER BY upload DESC
-            LIMIT 10"
+            LIMIT 10",
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await
@@ -62,9 +73,11 @@ impl Database {
         let rows = sqlx::query!(
             "SELECT name, SUM(downloadCount) AS download_count
             FROM Package INNER JOIN PackageVersion ON package = name
+            WHERE name NOT IN (SELECT value FROM json_each($1))
             GROUP BY package
             ORDER BY download_count DESC
-            LIMIT 10"
+            LIMIT 10",
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await
@@ -80,8 +93,10 @@ impl Database {
         let rows = sqlx::query!(
             "SELECT package, version, upload
             FROM PackageVersion
+            WHERE package NOT IN (SELECT value FROM json_each($1))
             ORDER BY upload DESC
-            LIMIT 10"
+            LIMIT 10",
+            hidden
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await
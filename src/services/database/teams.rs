@@ -0,0 +1,233 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for persisting information in the database
+//! API related to the management of teams
+
+use axum::http::StatusCode;
+use thiserror::Error;
+
+use super::Database;
+use crate::model::teams::{Team, TeamMember};
+use crate::utils::apierror::AsStatusCode;
+
+/// The maximum length of the name of a team
+const TEAM_NAME_MAX_LENGTH: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum TeamError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("team `{name}` not found")]
+    TeamNotFound { name: String },
+
+    #[error("a team named `{name}` already exists")]
+    TeamAlreadyExists { name: String },
+
+    #[error("invalid team name `{name}`, it must only contain alphanumeric, -, _")]
+    InvalidName { name: String },
+
+    #[error("the user '{email}' is not known")]
+    UserNotFound { email: String },
+
+    #[error("user is not a maintainer of this team")]
+    NotMaintainer,
+
+    #[error("team `{name}` is the last owner of crate `{package}`")]
+    LastOwnerOfPackage { name: String, package: String },
+}
+
+impl AsStatusCode for TeamError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TeamNotFound { .. } | Self::UserNotFound { .. } => StatusCode::NOT_FOUND,
+            Self::TeamAlreadyExists { .. } | Self::LastOwnerOfPackage { .. } => StatusCode::CONFLICT,
+            Self::InvalidName { .. } => StatusCode::BAD_REQUEST,
+            Self::NotMaintainer => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+/// Checks that the name of a team is valid
+fn validate_team_name(name: &str) -> Result<(), TeamError> {
+    if name.is_empty()
+        || name.len() > TEAM_NAME_MAX_LENGTH
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(TeamError::InvalidName { name: name.to_string() });
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Gets the known teams
+    pub async fn get_teams(&self) -> Result<Vec<Team>, TeamError> {
+        let rows = sqlx::query!("SELECT id, name FROM Team ORDER BY name")
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?;
+        let mut teams = Vec::with_capacity(rows.len());
+        for row in rows {
+            teams.push(Team {
+                id: row.id,
+                members: self.get_team_members(row.id).await?,
+                name: row.name,
+            });
+        }
+        Ok(teams)
+    }
+
+    /// Gets a team
+    pub async fn get_team(&self, name: &str) -> Result<Team, TeamError> {
+        let id = self.get_team_id(name).await?;
+        Ok(Team {
+            id,
+            name: name.to_string(),
+            members: self.get_team_members(id).await?,
+        })
+    }
+
+    /// Gets the identifier of a team
+    pub async fn get_team_id(&self, name: &str) -> Result<i64, TeamError> {
+        sqlx::query!("SELECT id FROM Team WHERE name = $1 LIMIT 1", name)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .map(|row| row.id)
+            .ok_or_else(|| TeamError::TeamNotFound { name: name.to_string() })
+    }
+
+    /// Gets the members of a team
+    pub async fn get_team_members(&self, team_id: i64) -> Result<Vec<TeamMember>, TeamError> {
+        let members = sqlx::query_as!(
+            TeamMember,
+            "SELECT RegistryUser.id AS uid, email, login, name, isMaintainer AS is_maintainer
+            FROM TeamMember INNER JOIN RegistryUser ON TeamMember.user = RegistryUser.id
+            WHERE team = $1 ORDER BY login",
+            team_id
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(members)
+    }
+
+    /// Creates a new team
+    pub async fn create_team(&self, name: &str) -> Result<Team, TeamError> {
+        validate_team_name(name)?;
+        let existing = sqlx::query!("SELECT id FROM Team WHERE name = $1 LIMIT 1", name)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?;
+        if existing.is_some() {
+            return Err(TeamError::TeamAlreadyExists { name: name.to_string() });
+        }
+        let id = sqlx::query!("INSERT INTO Team (name) VALUES ($1) RETURNING id", name)
+            .fetch_one(&mut *self.transaction.borrow().await)
+            .await?
+            .id;
+        Ok(Team {
+            id,
+            name: name.to_string(),
+            members: Vec::new(),
+        })
+    }
+
+    /// Deletes a team
+    /// The team cannot be deleted while it is the last owner of a crate.
+    pub async fn delete_team(&self, name: &str) -> Result<(), TeamError> {
+        let id = self.get_team_id(name).await?;
+        let orphan = sqlx::query!(
+            "SELECT package FROM PackageTeamOwner
+            WHERE team = $1
+            AND NOT EXISTS (SELECT id FROM PackageOwner WHERE PackageOwner.package = PackageTeamOwner.package)
+            AND NOT EXISTS (SELECT id FROM PackageTeamOwner AS Other WHERE Other.package = PackageTeamOwner.package AND Other.team != $1)
+            LIMIT 1",
+            id
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        if let Some(orphan) = orphan {
+            return Err(TeamError::LastOwnerOfPackage {
+                name: name.to_string(),
+                package: orphan.package,
+            });
+        }
+        sqlx::query!("DELETE FROM PackageTeamOwner WHERE team = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM PackageNameReservation WHERE team = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM TeamMember WHERE team = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        sqlx::query!("DELETE FROM Team WHERE id = $1", id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Checks that a user can manage the members of a team, i.e. is a maintainer of the team or an admin
+    pub async fn check_is_team_maintainer(&self, uid: i64, name: &str) -> Result<(), TeamError> {
+        if self.check_is_admin(uid).await.is_ok() {
+            return Ok(());
+        }
+        let row = sqlx::query!(
+            "SELECT TeamMember.id FROM TeamMember INNER JOIN Team ON TeamMember.team = Team.id
+            WHERE Team.name = $1 AND TeamMember.user = $2 AND TeamMember.isMaintainer = TRUE LIMIT 1",
+            name,
+            uid
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        match row {
+            Some(_) => Ok(()),
+            None => Err(TeamError::NotMaintainer),
+        }
+    }
+
+    /// Adds a member to a team, or updates its membership
+    pub async fn set_team_member(&self, name: &str, email: &str, is_maintainer: bool) -> Result<Team, TeamError> {
+        let team_id = self.get_team_id(name).await?;
+        let uid = sqlx::query!("SELECT id FROM RegistryUser WHERE email = $1 LIMIT 1", email)
+            .fetch_optional(&mut *self.transaction.borrow().await)
+            .await?
+            .ok_or_else(|| TeamError::UserNotFound {
+                email: email.to_string(),
+            })?
+            .id;
+        let updated = sqlx::query!(
+            "UPDATE TeamMember SET isMaintainer = $3 WHERE team = $1 AND user = $2",
+            team_id,
+            uid,
+            is_maintainer
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            sqlx::query!(
+                "INSERT INTO TeamMember (team, user, isMaintainer) VALUES ($1, $2, $3)",
+                team_id,
+                uid,
+                is_maintainer
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        }
+        self.get_team(name).await
+    }
+
+    /// Removes a member from a team
+    pub async fn remove_team_member(&self, name: &str, email: &str) -> Result<Team, TeamError> {
+        let team_id = self.get_team_id(name).await?;
+        sqlx::query!(
+            "DELETE FROM TeamMember WHERE team = $1 AND user IN (SELECT id FROM RegistryUser WHERE email = $2)",
+            team_id,
+            email
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        self.get_team(name).await
+    }
+}
@@ -5,23 +5,33 @@
 //! Service for persisting information in the database
 //! API related to the management of users and authentication
 
+use std::collections::BTreeSet;
 use std::future::Future;
 
 use axum::http::StatusCode;
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta};
 use thiserror::Error;
 
 use super::Database;
 use crate::application::AuthenticationError;
 use crate::model::auth::{
-    Authentication, AuthenticationPrincipal, OAuthToken, ROLE_ADMIN, RegistryUserToken, RegistryUserTokenWithSecret, TokenKind,
-    TokenUsage, find_field_in_blob,
+    AsymmetricTokenClaims, AsymmetricTokenFooter, Authentication, AuthenticationPrincipal, DeviceTokenPoll, ExpiringToken,
+    ROLE_ADMIN, RegistryMutation, RegistryUserKey, RegistryUserToken, RegistryUserTokenWithSecret, TokenKind, TokenScopes,
+    TokenUsage, find_field_in_blob, map_claims_to_roles,
 };
 use crate::model::cargo::RegistryUser;
-use crate::model::config::Configuration;
+use crate::model::config::{Configuration, OAuthClaimMapping};
 use crate::model::namegen::generate_name;
 use crate::utils::apierror::AsStatusCode;
-use crate::utils::token::{check_hash, generate_token, hash_token};
+use crate::utils::paseto::{PasetoError, PasetoToken, parse_public_key, public_key_id};
+use crate::utils::token::{check_hash, generate_token_id, generate_token_with_id, hash_token, parse_token_id};
+
+/// The maximum age of an asymmetric token, in seconds
+const ASYMMETRIC_TOKEN_MAX_AGE: i64 = 15 * 60;
+/// The maximum tolerated clock skew for asymmetric tokens issued in the future, in seconds
+const ASYMMETRIC_TOKEN_MAX_SKEW: i64 = 60;
+/// The number of seconds added to the polling interval of a device that polls too fast
+const DEVICE_POLL_SLOW_DOWN: i64 = 5;
 
 #[derive(Debug, Error)]
 pub enum UserError {
@@ -89,9 +99,27 @@ pub enum UpdateUserError {
     #[error("failed to execute request to remove user token")]
     SqlRemoveUserToken(#[source] sqlx::Error),
 
+    #[error("failed to execute request to remove user key")]
+    SqlRemoveUserKey(#[source] sqlx::Error),
+
+    #[error("failed to execute request to remove the device authorizations of user")]
+    SqlRemoveDeviceAuthorizations(#[source] sqlx::Error),
+
     #[error("failed to execute request to remove user as package owner")]
     SqlRemoveFromPackageOwner(#[source] sqlx::Error),
 
+    #[error("failed to execute request to remove user from teams")]
+    SqlRemoveFromTeams(#[source] sqlx::Error),
+
+    #[error("failed to execute request to remove the name reservations of user")]
+    SqlRemoveNameReservations(#[source] sqlx::Error),
+
+    #[error("failed to execute request to clear the user as author of advisories")]
+    SqlClearAdvisoriesAuthor(#[source] sqlx::Error),
+
+    #[error("failed to execute request to clear the user as author of advisory suppressions")]
+    SqlClearSuppressionsAuthor(#[source] sqlx::Error),
+
     #[error("failed to execute request to remove user")]
     SqlRemoveUser(#[source] sqlx::Error),
 }
@@ -106,7 +134,13 @@ impl AsStatusCode for UpdateUserError {
             | Self::SqlSelectUserByEmail(_)
             | Self::ActiveUserSql { .. }
             | Self::SqlRemoveUserToken(_)
+            | Self::SqlRemoveUserKey(_)
+            | Self::SqlRemoveDeviceAuthorizations(_)
             | Self::SqlRemoveFromPackageOwner(_)
+            | Self::SqlRemoveFromTeams(_)
+            | Self::SqlRemoveNameReservations(_)
+            | Self::SqlClearAdvisoriesAuthor(_)
+            | Self::SqlClearSuppressionsAuthor(_)
             | Self::SqlRemoveUser(_) => StatusCode::INTERNAL_SERVER_ERROR,
 
             Self::AdminCantRemoveThemselves | Self::OnlyAdminCanChangeRoles | Self::SelfDeactivate | Self::CannotDeleteSelf => {
@@ -121,37 +155,29 @@ impl AsStatusCode for UpdateUserError {
 }
 
 #[derive(Debug, Error)]
-pub enum OAuthLoginError {
-    #[error("failed to retrieve token from '{oauth_token_uri}'")]
-    GetRetrieveToken {
-        source: reqwest::Error,
-        oauth_token_uri: String,
-    },
-
-    #[error("failed to get retrieve token response")]
-    RetrieveTokenResponse { source: reqwest::Error },
+pub enum AddKeyError {
+    #[error(transparent)]
+    InvalidKey(#[from] PasetoError),
 
-    #[error("failed to parse retrieve token response")]
-    ParseRetrieveTokenResponse { source: serde_json::Error, body: String },
+    #[error("this public key is already registered")]
+    AlreadyRegistered,
 
-    #[error("failed to retried token for authentication: {status} '{body}'")]
-    RetrieveTokenFailed { status: StatusCode, body: String },
-
-    #[error("failed to autentify at '{uri}': {status}")]
-    RetrieveUserProfile { status: StatusCode, uri: String },
-
-    #[error("failed to retrieve user profile from '{oauth_userinfo_uri}'")]
-    GetUserProfile {
-        source: reqwest::Error,
-        oauth_userinfo_uri: String,
-    },
-
-    #[error("failed to get user profile response")]
-    GetUserProfileResponse { source: reqwest::Error },
+    #[error("failed to execute request to register the key")]
+    Sqlx(#[from] sqlx::Error),
+}
 
-    #[error("failed to parse get user profile response")]
-    ParseGetUserProfileResponse { source: serde_json::Error, body: String },
+impl AsStatusCode for AddKeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            Self::AlreadyRegistered => StatusCode::CONFLICT,
+            Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
 
+#[derive(Debug, Error)]
+pub enum OAuthLoginError {
     #[error("failed to execute user database request")]
     UserDatabaseRequest(#[source] sqlx::Error),
 
@@ -160,22 +186,15 @@ pub enum OAuthLoginError {
 
     #[error("no email in returned user info:\n{0}")]
     EmailMissingInUserInfo(String),
+
+    #[error("the state of the OAuth login does not match")]
+    InvalidState,
 }
 impl AsStatusCode for OAuthLoginError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::EmailMissingInUserInfo(_)
-            | Self::RetrieveTokenFailed { .. }
-            | Self::RetrieveUserProfile { .. }
-            | Self::InactiveUser => StatusCode::UNAUTHORIZED,
-
-            Self::GetRetrieveToken { .. }
-            | Self::RetrieveTokenResponse { .. }
-            | Self::ParseRetrieveTokenResponse { .. }
-            | Self::GetUserProfile { .. }
-            | Self::GetUserProfileResponse { .. }
-            | Self::ParseGetUserProfileResponse { .. }
-            | Self::UserDatabaseRequest(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::EmailMissingInUserInfo(_) | Self::InactiveUser | Self::InvalidState => StatusCode::UNAUTHORIZED,
+            Self::UserDatabaseRequest(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -194,77 +213,38 @@ impl Database {
         maybe_row.ok_or(UserError::UserNotFound { uid })
     }
 
-    /// Attempts to login using an OAuth code
-    #[expect(clippy::too_many_lines)]
-    pub async fn login_with_oauth_code(
+    /// Logs a user in from the claims provided by the identity provider
+    /// The user is created on the first login.
+    /// The roles and teams of the user are then updated according to the configured mapping rules.
+    pub async fn login_with_oauth_claims(
         &self,
         configuration: &Configuration,
-        code: &str,
+        user_info: &serde_json::Value,
     ) -> Result<RegistryUser, OAuthLoginError> {
-        let client = reqwest::Client::new();
-        // retrieve the token
-        let response = client
-            .post(&configuration.oauth_token_uri)
-            .form(&[
-                ("grant_type", "authorization_code"),
-                ("code", code),
-                ("redirect_uri", &configuration.oauth_callback_uri),
-                ("client_id", &configuration.oauth_client_id),
-                ("client_secret", &configuration.oauth_client_secret),
-            ])
-            .header(reqwest::header::ACCEPT, "application/json")
-            .send()
-            .await
-            .map_err(|source| OAuthLoginError::GetRetrieveToken {
-                source,
-                oauth_token_uri: configuration.oauth_token_uri.as_str().into(),
-            })?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.bytes().await.unwrap();
-            return Err(OAuthLoginError::RetrieveTokenFailed {
-                status,
-                body: String::from_utf8_lossy(&body).into(),
-            });
+        let mut user = self.resolve_oauth_user(configuration, user_info).await?;
+        if !configuration.oauth_claim_mappings.is_empty() {
+            let roles = map_claims_to_roles(&configuration.oauth_claim_mappings, user_info, &user.roles);
+            if roles != user.roles {
+                sqlx::query!("UPDATE RegistryUser SET roles = $2 WHERE id = $1", user.id, roles)
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await
+                    .map_err(OAuthLoginError::UserDatabaseRequest)?;
+                user.roles = roles;
+            }
+            self.map_claims_to_teams(&configuration.oauth_claim_mappings, user_info, user.id)
+                .await
+                .map_err(OAuthLoginError::UserDatabaseRequest)?;
         }
-        let body = response
-            .bytes()
-            .await
-            .map_err(|source| OAuthLoginError::RetrieveTokenResponse { source })?;
-        let token =
-            serde_json::from_slice::<OAuthToken>(&body).map_err(|source| OAuthLoginError::ParseRetrieveTokenResponse {
-                source,
-                body: String::from_utf8_lossy(&body).into(),
-            })?;
+        Ok(user)
+    }
 
-        // retrieve the user profile
-        let response = client
-            .get(&configuration.oauth_userinfo_uri)
-            .header("authorization", format!("Bearer {}", token.access_token))
-            .send()
-            .await
-            .map_err(|source| OAuthLoginError::GetUserProfile {
-                source,
-                oauth_userinfo_uri: configuration.oauth_userinfo_uri.as_str().into(),
-            })?;
-        if !response.status().is_success() {
-            let status = response.status();
-            return Err(OAuthLoginError::RetrieveUserProfile {
-                status,
-                uri: configuration.oauth_userinfo_uri.as_str().into(),
-            });
-        }
-        let body = response
-            .bytes()
-            .await
-            .map_err(|source| OAuthLoginError::GetUserProfileResponse { source })?;
-        let user_info = serde_json::from_slice::<serde_json::Value>(&body).map_err(|source| {
-            OAuthLoginError::ParseGetUserProfileResponse {
-                source,
-                body: String::from_utf8_lossy(&body).into(),
-            }
-        })?;
-        let email = find_field_in_blob(&user_info, &configuration.oauth_userinfo_path_email)
+    /// Resolves the user matching the claims provided by the identity provider, creating it if necessary
+    async fn resolve_oauth_user(
+        &self,
+        configuration: &Configuration,
+        user_info: &serde_json::Value,
+    ) -> Result<RegistryUser, OAuthLoginError> {
+        let email = find_field_in_blob(user_info, &configuration.oauth_userinfo_path_email)
             .ok_or_else(|| OAuthLoginError::EmailMissingInUserInfo(user_info.to_string()))?;
 
         // resolve the user
@@ -305,7 +285,7 @@ impl Database {
         {
             login = generate_name();
         }
-        let full_name = find_field_in_blob(&user_info, &configuration.oauth_userinfo_path_fullname).unwrap_or(&login);
+        let full_name = find_field_in_blob(user_info, &configuration.oauth_userinfo_path_fullname).unwrap_or(&login);
         let roles = if count == 0 { ROLE_ADMIN } else { "" };
         let id = sqlx::query!(
             "INSERT INTO RegistryUser (isActive, email, login, name, roles) VALUES (TRUE, $1, $2, $3, $4) RETURNING id",
@@ -328,6 +308,61 @@ impl Database {
         })
     }
 
+    /// Updates the membership of a user to the teams managed by the mapping rules
+    async fn map_claims_to_teams(
+        &self,
+        mappings: &[OAuthClaimMapping],
+        user_info: &serde_json::Value,
+        uid: i64,
+    ) -> Result<(), sqlx::Error> {
+        let managed = mappings
+            .iter()
+            .flat_map(|mapping| mapping.teams.iter().map(String::as_str))
+            .collect::<BTreeSet<_>>();
+        let granted = mappings
+            .iter()
+            .filter(|mapping| mapping.matches(user_info))
+            .flat_map(|mapping| mapping.teams.iter().map(String::as_str))
+            .collect::<BTreeSet<_>>();
+        for team in managed {
+            if granted.contains(team) {
+                sqlx::query!("INSERT INTO Team (name) VALUES ($1) ON CONFLICT DO NOTHING", team)
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                let team_id = sqlx::query!("SELECT id FROM Team WHERE name = $1", team)
+                    .fetch_one(&mut *self.transaction.borrow().await)
+                    .await?
+                    .id;
+                let count = sqlx::query!(
+                    "SELECT COUNT(id) AS count FROM TeamMember WHERE team = $1 AND user = $2",
+                    team_id,
+                    uid
+                )
+                .fetch_one(&mut *self.transaction.borrow().await)
+                .await?
+                .count;
+                if count == 0 {
+                    sqlx::query!(
+                        "INSERT INTO TeamMember (team, user, isMaintainer) VALUES ($1, $2, FALSE)",
+                        team_id,
+                        uid
+                    )
+                    .execute(&mut *self.transaction.borrow().await)
+                    .await?;
+                }
+            } else {
+                sqlx::query!(
+                    "DELETE FROM TeamMember WHERE user = $1 AND team IN (SELECT id FROM Team WHERE name = $2)",
+                    uid,
+                    team
+                )
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
     /// Gets the known users
     pub async fn get_users(&self) -> Result<Vec<RegistryUser>, sqlx::Error> {
         let rows = sqlx::query_as!(
@@ -430,10 +465,37 @@ impl Database {
             .execute(&mut *self.transaction.borrow().await)
             .await
             .map_err(UpdateUserError::SqlRemoveUserToken)?;
+        sqlx::query!("DELETE FROM RegistryUserKey WHERE user = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await
+            .map_err(UpdateUserError::SqlRemoveUserKey)?;
+        sqlx::query!("DELETE FROM DeviceAuthorization WHERE user = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await
+            .map_err(UpdateUserError::SqlRemoveDeviceAuthorizations)?;
         sqlx::query!("DELETE FROM PackageOwner WHERE owner = $1", target_uid)
             .execute(&mut *self.transaction.borrow().await)
             .await
             .map_err(UpdateUserError::SqlRemoveFromPackageOwner)?;
+        sqlx::query!("DELETE FROM TeamMember WHERE user = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await
+            .map_err(UpdateUserError::SqlRemoveFromTeams)?;
+        sqlx::query!("DELETE FROM PackageNameReservation WHERE user = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await
+            .map_err(UpdateUserError::SqlRemoveNameReservations)?;
+        sqlx::query!("UPDATE PackageAdvisory SET author = NULL WHERE author = $1", target_uid)
+            .execute(&mut *self.transaction.borrow().await)
+            .await
+            .map_err(UpdateUserError::SqlClearAdvisoriesAuthor)?;
+        sqlx::query!(
+            "UPDATE PackageAdvisorySuppression SET author = NULL WHERE author = $1",
+            target_uid
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await
+        .map_err(UpdateUserError::SqlClearSuppressionsAuthor)?;
         sqlx::query!("DELETE FROM RegistryUser WHERE id = $1", target_uid)
             .execute(&mut *self.transaction.borrow().await)
             .await
@@ -444,21 +506,28 @@ impl Database {
     /// Gets the tokens for a user
     pub async fn get_tokens(&self, uid: i64) -> Result<Vec<RegistryUserToken>, sqlx::Error> {
         let rows = sqlx::query!(
-            "SELECT id, name, lastUsed AS last_used, canWrite AS can_write, canAdmin AS can_admin FROM RegistryUserToken WHERE user = $1 ORDER BY id",
+            "SELECT id, name, lastUsed AS last_used, canWrite AS can_write, canAdmin AS can_admin, crateScopes AS crate_scopes, endpointScopes AS endpoint_scopes,
+                expiresAt AS expires_at, previousExpiresAt AS previous_expires_at
+            FROM RegistryUserToken WHERE user = $1 ORDER BY id",
             uid
         )
         .fetch_all(&mut *self.transaction.borrow().await)
         .await?;
-        Ok(rows
-            .into_iter()
-            .map(|row| RegistryUserToken {
-                id: row.id,
-                name: row.name,
-                last_used: row.last_used,
-                can_write: row.can_write,
-                can_admin: row.can_admin,
+        rows.into_iter()
+            .map(|row| {
+                Ok(RegistryUserToken {
+                    id: row.id,
+                    name: row.name,
+                    last_used: row.last_used,
+                    can_write: row.can_write,
+                    can_admin: row.can_admin,
+                    scopes: TokenScopes::parse(&row.crate_scopes, &row.endpoint_scopes)
+                        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+                    expires_at: row.expires_at,
+                    previous_expires_at: row.previous_expires_at,
+                })
             })
-            .collect())
+            .collect()
     }
 
     /// Creates a token for the current user
@@ -468,18 +537,29 @@ impl Database {
         name: &str,
         can_write: bool,
         can_admin: bool,
+        scopes: TokenScopes,
+        validity: Option<i64>,
     ) -> Result<RegistryUserTokenWithSecret, sqlx::Error> {
-        let token_secret = generate_token(64);
+        let public_id = generate_token_id();
+        let token_secret = generate_token_with_id(&public_id, 64);
         let token_hash = hash_token(&token_secret);
         let now = Local::now().naive_local();
+        let expires_at = token_expires_at(validity, now);
+        let crate_scopes = scopes.crates_list();
+        let endpoint_scopes = scopes.endpoints_list();
         let id = sqlx::query!(
-            "INSERT INTO RegistryUserToken (user, name, token, lastUsed, canWrite, canAdmin) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+            "INSERT INTO RegistryUserToken (user, name, token, lastUsed, canWrite, canAdmin, crateScopes, endpointScopes, expiresAt, validity, publicId) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING id",
             uid,
             name,
             token_hash,
             now,
             can_write,
-            can_admin
+            can_admin,
+            crate_scopes,
+            endpoint_scopes,
+            expires_at,
+            validity,
+            public_id
         )
         .fetch_one(&mut *self.transaction.borrow().await)
         .await?
@@ -491,9 +571,145 @@ impl Database {
             last_used: now,
             can_write,
             can_admin,
+            scopes,
+            expires_at,
         })
     }
 
+    /// Rotates the secret of a token for the current user
+    /// The previous secret remains valid until the end of the grace period, or the expiration of the token if it comes first.
+    /// The expiration date is recomputed from now, using the token's current validity when none is requested.
+    pub async fn rotate_token(
+        &self,
+        uid: i64,
+        token_id: i64,
+        validity: Option<i64>,
+        max_validity: Option<i64>,
+        grace_until: NaiveDateTime,
+    ) -> Result<Option<RegistryUserTokenWithSecret>, sqlx::Error> {
+        let row = sqlx::query!(
+            "SELECT name, token, lastUsed AS last_used, canWrite AS can_write, canAdmin AS can_admin, crateScopes AS crate_scopes, endpointScopes AS endpoint_scopes,
+                expiresAt AS expires_at, validity, publicId AS public_id
+            FROM RegistryUserToken WHERE user = $1 AND id = $2",
+            uid,
+            token_id
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        let Some(row) = row else { return Ok(None) };
+        // tokens in the legacy format are given an identifier on rotation
+        let public_id = row.public_id.unwrap_or_else(generate_token_id);
+        let token_secret = generate_token_with_id(&public_id, 64);
+        let token_hash = hash_token(&token_secret);
+        let validity = rotated_token_validity(validity, row.validity, max_validity);
+        let expires_at = token_expires_at(validity, Local::now().naive_local());
+        let previous_expires_at = row.expires_at.map_or(grace_until, |old| old.min(grace_until));
+        sqlx::query!(
+            "UPDATE RegistryUserToken SET token = $2, expiresAt = $3, validity = $4, expiryNotified = FALSE, previousToken = $5, previousExpiresAt = $6, publicId = $7 WHERE id = $1",
+            token_id,
+            token_hash,
+            expires_at,
+            validity,
+            row.token,
+            previous_expires_at,
+            public_id
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(Some(RegistryUserTokenWithSecret {
+            id: token_id,
+            name: row.name,
+            secret: token_secret,
+            last_used: row.last_used,
+            can_write: row.can_write,
+            can_admin: row.can_admin,
+            scopes: TokenScopes::parse(&row.crate_scopes, &row.endpoint_scopes)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            expires_at,
+        }))
+    }
+
+    /// Gets the user and global tokens that expire before a date and for which no notification was sent yet
+    pub async fn get_expiring_tokens(&self, until: NaiveDateTime) -> Result<Vec<ExpiringToken>, sqlx::Error> {
+        let now = Local::now().naive_local();
+        let mut results = sqlx::query!(
+            "SELECT RegistryUserToken.id, RegistryUserToken.name, expiresAt AS \"expires_at!: NaiveDateTime\", email
+            FROM RegistryUserToken INNER JOIN RegistryUser ON RegistryUser.id = RegistryUserToken.user
+            WHERE isActive = TRUE AND expiryNotified = FALSE AND expiresAt IS NOT NULL AND expiresAt > $1 AND expiresAt <= $2",
+            now,
+            until
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?
+        .into_iter()
+        .map(|row| ExpiringToken {
+            kind: TokenKind::User,
+            id: row.id,
+            name: row.name,
+            expires_at: row.expires_at,
+            emails: vec![row.email],
+        })
+        .collect::<Vec<_>>();
+        let global_tokens = sqlx::query!(
+            "SELECT id, name, expiresAt AS \"expires_at!: NaiveDateTime\" FROM RegistryGlobalToken
+            WHERE expiryNotified = FALSE AND expiresAt IS NOT NULL AND expiresAt > $1 AND expiresAt <= $2",
+            now,
+            until
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        if !global_tokens.is_empty() {
+            // global tokens are managed by the administrators
+            let admins = sqlx::query!("SELECT email, roles FROM RegistryUser WHERE isActive = TRUE")
+                .fetch_all(&mut *self.transaction.borrow().await)
+                .await?
+                .into_iter()
+                .filter(|row| row.roles.split(',').any(|role| role.trim() == ROLE_ADMIN))
+                .map(|row| row.email)
+                .collect::<Vec<_>>();
+            results.extend(global_tokens.into_iter().map(|row| ExpiringToken {
+                kind: TokenKind::Registry,
+                id: row.id,
+                name: row.name,
+                expires_at: row.expires_at,
+                emails: admins.clone(),
+            }));
+        }
+        Ok(results)
+    }
+
+    /// Forgets the previous secrets of the rotated tokens once their grace period is over
+    pub async fn purge_rotated_secrets(&self) -> Result<(), sqlx::Error> {
+        let now = Local::now().naive_local();
+        sqlx::query!(
+            "UPDATE RegistryUserToken SET previousToken = NULL, previousExpiresAt = NULL WHERE previousExpiresAt <= $1",
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        sqlx::query!(
+            "UPDATE RegistryGlobalToken SET previousToken = NULL, previousExpiresAt = NULL WHERE previousExpiresAt <= $1",
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(())
+    }
+
+    /// Records that the notification for an expiring token was sent
+    pub async fn set_token_expiry_notified(&self, kind: TokenKind, token_id: i64) -> Result<(), sqlx::Error> {
+        if kind == TokenKind::User {
+            sqlx::query!("UPDATE RegistryUserToken SET expiryNotified = TRUE WHERE id = $1", token_id)
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+        } else if kind == TokenKind::Registry {
+            sqlx::query!("UPDATE RegistryGlobalToken SET expiryNotified = TRUE WHERE id = $1", token_id)
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+        }
+        Ok(())
+    }
+
     /// Revoke a previous token
     pub async fn revoke_token(&self, uid: i64, token_id: i64) -> Result<(), sqlx::Error> {
         sqlx::query!("DELETE FROM RegistryUserToken WHERE user = $1 AND id = $2", uid, token_id)
@@ -502,6 +718,290 @@ impl Database {
         Ok(())
     }
 
+    /// Removes the expired device authorization requests
+    pub async fn purge_device_authorizations(&self) -> Result<(), sqlx::Error> {
+        let now = Local::now().naive_local();
+        sqlx::query!("DELETE FROM DeviceAuthorization WHERE expiresAt < $1", now)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Registers a new device authorization request
+    /// Expired requests are cleaned up on the way.
+    /// Returns `false` when the maximum number of pending requests is reached
+    pub async fn create_device_authorization(
+        &self,
+        device_code: &str,
+        user_code: &str,
+        expires_at: NaiveDateTime,
+        interval: i64,
+        max_pending: i64,
+    ) -> Result<bool, sqlx::Error> {
+        self.purge_device_authorizations().await?;
+        let pending = sqlx::query_scalar!("SELECT COUNT(*) FROM DeviceAuthorization")
+            .fetch_one(&mut *self.transaction.borrow().await)
+            .await?;
+        if pending >= max_pending {
+            return Ok(false);
+        }
+        let device_code_hash = hash_token(device_code);
+        sqlx::query!(
+            "INSERT INTO DeviceAuthorization (deviceCode, userCode, expiresAt, interval) VALUES ($1, $2, $3, $4)",
+            device_code_hash,
+            user_code,
+            expires_at,
+            interval
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(true)
+    }
+
+    /// Approves a pending device authorization request on behalf of a user
+    /// The token to be created when the device polls next is recorded with the request.
+    /// Returns `false` when there is no pending request for the user code
+    #[expect(clippy::too_many_arguments)]
+    pub async fn approve_device_authorization(
+        &self,
+        user_code: &str,
+        uid: i64,
+        name: &str,
+        can_write: bool,
+        can_admin: bool,
+        scopes: &TokenScopes,
+        validity: Option<i64>,
+    ) -> Result<bool, sqlx::Error> {
+        let now = Local::now().naive_local();
+        let crate_scopes = scopes.crates_list();
+        let endpoint_scopes = scopes.endpoints_list();
+        let result = sqlx::query!(
+            "UPDATE DeviceAuthorization SET user = $2, tokenName = $3, canWrite = $4, canAdmin = $5, crateScopes = $6, endpointScopes = $7, tokenValidity = $8
+            WHERE userCode = $1 AND user IS NULL AND NOT isDenied AND expiresAt > $9",
+            user_code,
+            uid,
+            name,
+            can_write,
+            can_admin,
+            crate_scopes,
+            endpoint_scopes,
+            validity,
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Denies a pending device authorization request
+    /// Returns `false` when there is no pending request for the user code
+    pub async fn deny_device_authorization(&self, user_code: &str) -> Result<bool, sqlx::Error> {
+        let now = Local::now().naive_local();
+        let result = sqlx::query!(
+            "UPDATE DeviceAuthorization SET isDenied = TRUE WHERE userCode = $1 AND user IS NULL AND NOT isDenied AND expiresAt > $2",
+            user_code,
+            now
+        )
+        .execute(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Polls for the token of a device authorization request
+    /// When the request has been approved, the token is created and the request is removed.
+    pub async fn poll_device_authorization(&self, device_code: &str) -> Result<DeviceTokenPoll, sqlx::Error> {
+        let now = Local::now().naive_local();
+        let device_code_hash = hash_token(device_code);
+        let row = sqlx::query!(
+            "SELECT DeviceAuthorization.id, expiresAt AS expires_at, interval, lastPolled AS last_polled, isDenied AS is_denied,
+                user, login AS \"login?\", tokenName AS token_name, DeviceAuthorization.canWrite AS can_write, DeviceAuthorization.canAdmin AS can_admin,
+                crateScopes AS crate_scopes, endpointScopes AS endpoint_scopes, tokenValidity AS token_validity
+            FROM DeviceAuthorization LEFT JOIN RegistryUser ON DeviceAuthorization.user = RegistryUser.id
+            WHERE deviceCode = $1 LIMIT 1",
+            device_code_hash
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
+        let Some(row) = row else {
+            return Ok(DeviceTokenPoll::InvalidGrant);
+        };
+        if row.expires_at <= now || row.is_denied {
+            sqlx::query!("DELETE FROM DeviceAuthorization WHERE id = $1", row.id)
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+            return Ok(if row.is_denied {
+                DeviceTokenPoll::Denied
+            } else {
+                DeviceTokenPoll::Expired
+            });
+        }
+        if row
+            .last_polled
+            .is_some_and(|last_polled| last_polled + TimeDelta::seconds(row.interval) > now)
+        {
+            // the device shall wait longer between requests (RFC 8628, section 3.5)
+            let interval = row.interval + DEVICE_POLL_SLOW_DOWN;
+            sqlx::query!(
+                "UPDATE DeviceAuthorization SET lastPolled = $2, interval = $3 WHERE id = $1",
+                row.id,
+                now,
+                interval
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+            return Ok(DeviceTokenPoll::SlowDown);
+        }
+        let (Some(uid), Some(login), Some(name)) = (row.user, row.login, row.token_name) else {
+            sqlx::query!("UPDATE DeviceAuthorization SET lastPolled = $2 WHERE id = $1", row.id, now)
+                .execute(&mut *self.transaction.borrow().await)
+                .await?;
+            return Ok(DeviceTokenPoll::Pending);
+        };
+        sqlx::query!("DELETE FROM DeviceAuthorization WHERE id = $1", row.id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        let scopes =
+            TokenScopes::parse(&row.crate_scopes, &row.endpoint_scopes).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        let token = self
+            .create_token(uid, &name, row.can_write, row.can_admin, scopes, row.token_validity)
+            .await?;
+        Ok(DeviceTokenPoll::Granted { login, token })
+    }
+
+    /// Gets the public keys for a user
+    pub async fn get_keys(&self, uid: i64) -> Result<Vec<RegistryUserKey>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT id, name, keyId AS key_id, publicKey AS public_key, lastUsed AS last_used, canWrite AS can_write FROM RegistryUserKey WHERE user = $1 ORDER BY id",
+            uid
+        )
+        .fetch_all(&mut *self.transaction.borrow().await)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| RegistryUserKey {
+                id: row.id,
+                name: row.name,
+                key_id: row.key_id,
+                public_key: row.public_key,
+                last_used: row.last_used,
+                can_write: row.can_write,
+            })
+            .collect())
+    }
+
+    /// Registers a public key for the current user
+    pub async fn add_key(
+        &self,
+        uid: i64,
+        name: &str,
+        public_key: &str,
+        can_write: bool,
+    ) -> Result<RegistryUserKey, AddKeyError> {
+        let public_key = public_key.trim();
+        parse_public_key(public_key)?;
+        let key_id = public_key_id(public_key);
+        let count = sqlx::query!("SELECT COUNT(id) AS count FROM RegistryUserKey WHERE keyId = $1", key_id)
+            .fetch_one(&mut *self.transaction.borrow().await)
+            .await?
+            .count;
+        if count != 0 {
+            return Err(AddKeyError::AlreadyRegistered);
+        }
+        let now = Local::now().naive_local();
+        let id = sqlx::query!(
+            "INSERT INTO RegistryUserKey (user, name, keyId, publicKey, lastUsed, canWrite) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+            uid,
+            name,
+            key_id,
+            public_key,
+            now,
+            can_write
+        )
+        .fetch_one(&mut *self.transaction.borrow().await)
+        .await?
+        .id;
+        Ok(RegistryUserKey {
+            id,
+            name: name.to_string(),
+            key_id,
+            public_key: public_key.to_string(),
+            last_used: now,
+            can_write,
+        })
+    }
+
+    /// Removes a previously registered public key
+    pub async fn remove_key(&self, uid: i64, key_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM RegistryUserKey WHERE user = $1 AND id = $2", uid, key_id)
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
+        Ok(())
+    }
+
+    /// Checks an authentication request with an asymmetric token (PASETO v3)
+    /// When the request performs a mutation, the claims of the token must match it
+    pub async fn check_asymmetric_token<F, FUT>(
+        &self,
+        token: &str,
+        index_url: &str,
+        mutation: Option<&RegistryMutation<'_>>,
+        on_usage: &F,
+    ) -> Result<Authentication, AuthenticationError>
+    where
+        F: Fn(TokenUsage) -> FUT + Sync,
+        FUT: Future<Output = ()>,
+    {
+        let token = PasetoToken::parse(token)?;
+        let footer: AsymmetricTokenFooter =
+            serde_json::from_slice(&token.footer).map_err(|_| AuthenticationError::AsymmetricTokenClaim { claim: "footer" })?;
+        if normalize_index_url(&footer.url) != normalize_index_url(index_url) {
+            return Err(AuthenticationError::AsymmetricTokenClaim { claim: "url" });
+        }
+        let row = sqlx::query!(
+            "SELECT RegistryUser.id AS uid, email, RegistryUserKey.id, publicKey AS public_key, canWrite AS can_write
+            FROM RegistryUser INNER JOIN RegistryUserKey ON RegistryUser.id = RegistryUserKey.user
+            WHERE isActive = TRUE AND keyId = $1",
+            footer.kip
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await
+        .map_err(AuthenticationError::UserKey)?
+        .ok_or(AuthenticationError::Unauthorized)?;
+        token.verify(&row.public_key)?;
+
+        let claims: AsymmetricTokenClaims = serde_json::from_slice(&token.message)
+            .map_err(|_| AuthenticationError::AsymmetricTokenClaim { claim: "message" })?;
+        let issued_at = DateTime::parse_from_rfc3339(&claims.iat)
+            .map_err(|_| AuthenticationError::AsymmetricTokenClaim { claim: "iat" })?;
+        let age = Local::now().signed_duration_since(issued_at);
+        if age > TimeDelta::seconds(ASYMMETRIC_TOKEN_MAX_AGE) || age < TimeDelta::seconds(-ASYMMETRIC_TOKEN_MAX_SKEW) {
+            return Err(AuthenticationError::AsymmetricTokenClaim { claim: "iat" });
+        }
+        let can_write = if let Some(mutation) = mutation {
+            check_mutation_claims(&claims, mutation)?;
+            row.can_write
+        } else {
+            // a token without a mutation is only good for reading
+            false
+        };
+
+        on_usage(TokenUsage {
+            kind: TokenKind::UserKey,
+            token_id: row.id,
+            timestamp: Local::now().naive_local(),
+        })
+        .await;
+        Ok(Authentication {
+            principal: AuthenticationPrincipal::User {
+                uid: row.uid,
+                email: row.email,
+            },
+            can_write,
+            can_admin: false,
+            scopes: TokenScopes::unrestricted(),
+        })
+    }
+
     /// Checks an authentication request with a token
     pub async fn check_token<F, FUT>(
         &self,
@@ -541,17 +1041,45 @@ impl Database {
         F: Fn(TokenUsage) -> FUT + Sync,
         FUT: Future<Output = ()>,
     {
-        let rows = sqlx::query!(
-            "SELECT RegistryUser.id AS uid, email, RegistryUserToken.id, token, canWrite AS can_write, canAdmin AS can_admin
-            FROM RegistryUser INNER JOIN RegistryUserToken ON RegistryUser.id = RegistryUserToken.user
-            WHERE isActive = TRUE AND login = $1",
-            login
-        )
-        .fetch_all(&mut *self.transaction.borrow().await)
-        .await?;
+        let now = Local::now().naive_local();
+        let rows = if let Some(public_id) = parse_token_id(token_secret) {
+            // a single row to check
+            sqlx::query_as!(
+                UserTokenRow,
+                "SELECT RegistryUser.id AS uid, email, RegistryUserToken.id, token, canWrite AS can_write, canAdmin AS can_admin,
+                    crateScopes AS crate_scopes, endpointScopes AS endpoint_scopes,
+                    expiresAt AS expires_at, previousToken AS previous_token, previousExpiresAt AS previous_expires_at
+                FROM RegistryUser INNER JOIN RegistryUserToken ON RegistryUser.id = RegistryUserToken.user
+                WHERE isActive = TRUE AND login = $1 AND publicId = $2",
+                login,
+                public_id
+            )
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?
+        } else {
+            // legacy format: check the tokens without identifier,
+            // as well as the tokens that were just rotated from the legacy format
+            sqlx::query_as!(
+                UserTokenRow,
+                "SELECT RegistryUser.id AS uid, email, RegistryUserToken.id, token, canWrite AS can_write, canAdmin AS can_admin,
+                    crateScopes AS crate_scopes, endpointScopes AS endpoint_scopes,
+                    expiresAt AS expires_at, previousToken AS previous_token, previousExpiresAt AS previous_expires_at
+                FROM RegistryUser INNER JOIN RegistryUserToken ON RegistryUser.id = RegistryUserToken.user
+                WHERE isActive = TRUE AND login = $1 AND (publicId IS NULL OR previousExpiresAt > $2)",
+                login,
+                now
+            )
+            .fetch_all(&mut *self.transaction.borrow().await)
+            .await?
+        };
         for row in rows {
-            if check_hash(token_secret, &row.token).is_ok() {
-                let now = Local::now().naive_local();
+            let secrets = TokenSecrets {
+                token: &row.token,
+                expires_at: row.expires_at,
+                previous_token: row.previous_token.as_deref(),
+                previous_expires_at: row.previous_expires_at,
+            };
+            if secrets.check(token_secret, now) {
                 on_usage(TokenUsage {
                     kind: TokenKind::User,
                     token_id: row.id,
@@ -565,6 +1093,8 @@ impl Database {
                     },
                     can_write: row.can_write,
                     can_admin: row.can_admin,
+                    scopes: TokenScopes::parse(&row.crate_scopes, &row.endpoint_scopes)
+                        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
                 }));
             }
         }
@@ -582,12 +1112,22 @@ impl Database {
         F: Fn(TokenUsage) -> FUT + Sync,
         FUT: Future<Output = ()>,
     {
-        let row = sqlx::query!("SELECT id, token FROM RegistryGlobalToken WHERE name = $1 LIMIT 1", login)
-            .fetch_optional(&mut *self.transaction.borrow().await)
-            .await?;
+        let row = sqlx::query!(
+            "SELECT id, token, expiresAt AS expires_at, previousToken AS previous_token, previousExpiresAt AS previous_expires_at
+            FROM RegistryGlobalToken WHERE name = $1 LIMIT 1",
+            login
+        )
+        .fetch_optional(&mut *self.transaction.borrow().await)
+        .await?;
         let Some(row) = row else { return Ok(None) };
-        if check_hash(token_secret, &row.token).is_ok() {
-            let now = Local::now().naive_local();
+        let now = Local::now().naive_local();
+        let secrets = TokenSecrets {
+            token: &row.token,
+            expires_at: row.expires_at,
+            previous_token: row.previous_token.as_deref(),
+            previous_expires_at: row.previous_expires_at,
+        };
+        if secrets.check(token_secret, now) {
             on_usage(TokenUsage {
                 kind: TokenKind::Registry,
                 token_id: row.id,
@@ -618,7 +1158,98 @@ impl Database {
             )
             .execute(&mut *self.transaction.borrow().await)
             .await?;
+        } else if event.kind == TokenKind::UserKey {
+            sqlx::query!(
+                "UPDATE RegistryUserKey SET lastUsed = $2 WHERE id = $1",
+                event.token_id,
+                event.timestamp
+            )
+            .execute(&mut *self.transaction.borrow().await)
+            .await?;
         }
         Ok(())
     }
 }
+
+/// A user token row, as used for authentication
+struct UserTokenRow {
+    uid: i64,
+    email: String,
+    id: i64,
+    token: String,
+    can_write: bool,
+    can_admin: bool,
+    crate_scopes: String,
+    endpoint_scopes: String,
+    expires_at: Option<NaiveDateTime>,
+    previous_token: Option<String>,
+    previous_expires_at: Option<NaiveDateTime>,
+}
+
+/// Gets the validity in days of a rotated token
+/// The requested validity takes precedence over the current validity of the token, both being capped by the maximum validity
+pub(super) fn rotated_token_validity(requested: Option<i64>, current: Option<i64>, max_validity: Option<i64>) -> Option<i64> {
+    match (requested.or(current), max_validity) {
+        (Some(days), Some(max)) => Some(days.min(max)),
+        (days, None) => days,
+        (None, max) => max,
+    }
+}
+
+/// Gets the expiration date of a token created or rotated now, for a validity in days
+pub(super) fn token_expires_at(validity: Option<i64>, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    validity.map(|days| now + TimeDelta::days(days))
+}
+
+/// The hashed secrets for a token, as stored
+struct TokenSecrets<'a> {
+    /// The hash of the current secret
+    token: &'a str,
+    /// The expiration date for the token, if any
+    expires_at: Option<NaiveDateTime>,
+    /// The hash of the previous secret, after a rotation
+    previous_token: Option<&'a str>,
+    /// The date until which the previous secret remains valid
+    previous_expires_at: Option<NaiveDateTime>,
+}
+
+impl TokenSecrets<'_> {
+    /// Checks a secret against the current secret and the previous one still in its grace period
+    fn check(&self, secret: &str, now: NaiveDateTime) -> bool {
+        if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return false;
+        }
+        if check_hash(secret, self.token).is_ok() {
+            return true;
+        }
+        match (self.previous_token, self.previous_expires_at) {
+            (Some(previous), Some(previous_expires_at)) if now < previous_expires_at => check_hash(secret, previous).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Normalizes the URL of an index for comparison
+fn normalize_index_url(url: &str) -> &str {
+    url.strip_prefix("sparse+").unwrap_or(url).trim_end_matches('/')
+}
+
+/// Checks that the claims of an asymmetric token match the mutation performed by the request
+fn check_mutation_claims(claims: &AsymmetricTokenClaims, mutation: &RegistryMutation<'_>) -> Result<(), AuthenticationError> {
+    if claims.v.is_some_and(|v| v != 1) {
+        return Err(AuthenticationError::AsymmetricTokenClaim { claim: "v" });
+    }
+    if claims.mutation != Some(mutation.kind) {
+        return Err(AuthenticationError::AsymmetricTokenClaim { claim: "mutation" });
+    }
+    if claims.name.as_deref() != Some(mutation.name) {
+        return Err(AuthenticationError::AsymmetricTokenClaim { claim: "name" });
+    }
+    if mutation.vers.is_some() && claims.vers.as_deref() != mutation.vers {
+        return Err(AuthenticationError::AsymmetricTokenClaim { claim: "vers" });
+    }
+    if mutation.cksum.is_some() && claims.cksum.as_deref() != mutation.cksum {
+        return Err(AuthenticationError::AsymmetricTokenClaim { claim: "cksum" });
+    }
+    Ok(())
+}
@@ -15,19 +15,24 @@ use std::time::{Duration, Instant};
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
 use futures::lock::Mutex;
-use log::{error, info};
+use log::{error, info, warn};
 use tokio::fs::File;
 use tokio::io::AsyncBufReadExt;
 
-use crate::model::cargo::{IndexCrateDependency, IndexCrateMetadata};
+use crate::model::cargo::{IndexCrateDependency, IndexCrateMetadata, read_crate_license};
 use crate::model::config::{Configuration, ExternalRegistryProtocol};
 use crate::model::deps::{
-    BUILTIN_CRATES_REGISTRY_URI, DepAdvisory, DepsAnalysis, DepsAnalysisJobSpec, DepsGraph, DepsGraphCrateOrigin,
+    BUILTIN_CRATES_REGISTRY_URI, CRATES_IO_REGISTRY_URI, DepAdvisory, DepLicense, DepsAnalysis, DepsAnalysisJobSpec, DepsGraph,
+    DepsGraphCrateOrigin,
 };
+use crate::model::policy::LicensePolicy;
+use crate::model::sbom::{Sbom, SbomComponent, SbomFormat};
 use crate::services::database::{db_transaction_read, db_transaction_write};
 use crate::services::emails::EmailSender;
 use crate::services::index::Index;
+use crate::services::proxy::CratesIoProxy;
 use crate::services::rustsec::RustSecChecker;
+use crate::services::storage::Storage;
 use crate::utils::apierror::{ApiError, error_backend_failure, error_not_found, specialize};
 use crate::utils::db::RwSqlitePool;
 use crate::utils::{FaillibleFuture, stale_instant};
@@ -119,9 +124,48 @@ async fn deps_worker_job(
         )
         .await?;
     }
+    resolve_crate_versions(pool, service_deps_checker.as_ref()).await
+}
+
+/// Resolves the dependency graph and generates the SBOM of the crate versions for which they are not up to date
+///
+/// These are the versions that were not analyzed as the latest version of their crate since their publication,
+/// or since the targets of their crate changed.
+/// The graphs are used to compute the impact of advisories across all crate versions.
+/// Crate versions that cannot be resolved are skipped and retried on the next run.
+pub async fn resolve_crate_versions(
+    pool: &RwSqlitePool,
+    service_deps_checker: &(dyn DepsChecker + Send + Sync),
+) -> Result<(), ApiError> {
+    let unresolved =
+        db_transaction_read(pool, |database| async move { database.get_unresolved_crate_versions().await }).await?;
+    if unresolved.is_empty() {
+        return Ok(());
+    }
+    info!("resolving the dependencies of {} crate version(s)", unresolved.len());
+    for job in &unresolved {
+        let analysis = match service_deps_checker
+            .analyze_crate(&job.package, &job.version, &job.targets)
+            .await
+        {
+            Ok(analysis) => analysis,
+            Err(e) => {
+                warn!("failed to resolve the dependencies of {} {}: {e}", job.package, job.version);
+                continue;
+            }
+        };
+        let resolutions = &analysis.resolutions;
+        db_transaction_write(pool, "resolve_crate_versions", |database| async move {
+            database
+                .set_crate_deps_resolutions(&job.package, &job.version, resolutions)
+                .await
+        })
+        .await?;
+    }
     Ok(())
 }
 
+#[expect(clippy::too_many_lines)]
 async fn deps_worker_job_on_crate_version(
     configuration: &Configuration,
     service_deps_checker: &(dyn DepsChecker + Send + Sync),
@@ -130,23 +174,38 @@ async fn deps_worker_job_on_crate_version(
     job: &DepsAnalysisJobSpec,
 ) -> Result<(), ApiError> {
     info!("checking deps for {} {}", job.package, job.version);
-    let analysis = service_deps_checker
-        .check_crate(&job.package, &job.version, &job.targets)
+    let mut analysis = service_deps_checker
+        .analyze_crate(&job.package, &job.version, &job.targets)
         .await?;
-    let has_outdated = analysis.direct_dependencies.iter().any(|info| info.is_outdated);
-    let has_cves = !analysis.advisories.is_empty();
-    let (old_has_outdated, old_has_cves) = db_transaction_write(pool, "set_crate_deps_analysis", |database| async move {
-        database
-            .set_crate_deps_analysis(&job.package, &job.version, has_outdated, has_cves)
-            .await
+    let rules = db_transaction_read(pool, |database| async move { database.get_license_rules().await }).await?;
+    analysis.check_licenses(&LicensePolicy::new(&rules));
+    let suppressions = db_transaction_read(pool, |database| async move {
+        database.get_advisory_suppressions(&job.package).await
     })
     .await?;
+    analysis.apply_suppressions(&job.version, &suppressions);
+    analysis.flag_advisories(configuration.deps_cves_min_severity, configuration.deps_cves_informational);
+    let has_outdated = analysis.direct_dependencies.iter().any(|info| info.is_outdated);
+    let has_cves = analysis.has_cves();
+    let has_license_violations = analysis.has_license_violations();
+    let resolutions = &analysis.resolutions;
+    let (old_has_outdated, old_has_cves, old_has_license_violations) =
+        db_transaction_write(pool, "set_crate_deps_analysis", |database| async move {
+            database
+                .set_crate_deps_resolutions(&job.package, &job.version, resolutions)
+                .await?;
+            database
+                .set_crate_deps_analysis(&job.package, &job.version, has_outdated, has_cves, has_license_violations)
+                .await
+        })
+        .await?;
     if (has_outdated != old_has_outdated && configuration.deps_notify_outdated)
         || (has_cves != old_has_cves && configuration.deps_notify_cves)
+        || (has_license_violations != old_has_license_violations && configuration.deps_notify_licenses)
     {
         // must send some notification
         let owners = db_transaction_read(pool, |database| async move { database.get_crate_owners(&job.package).await }).await?;
-        let owners = owners.users.into_iter().map(|owner| owner.email).collect::<Vec<_>>();
+        let owners = owners.emails();
         if has_outdated != old_has_outdated {
             // new outdated dependencies ...
             let mut body = String::new();
@@ -181,7 +240,12 @@ async fn deps_worker_job_on_crate_version(
                 )
                 .await?;
         }
-        if has_cves != old_has_cves {
+        let notified = analysis
+            .advisories
+            .iter()
+            .filter(|adv| adv.is_flagged && adv.reaches_severity(configuration.deps_notify_cves_min_severity))
+            .collect::<Vec<_>>();
+        if has_cves && !old_has_cves && !notified.is_empty() {
             // new CVEs ...
             let mut body = String::new();
             writeln!(
@@ -197,14 +261,29 @@ async fn deps_worker_job_on_crate_version(
             )
             .unwrap();
             writeln!(body).unwrap();
-            for adv in &analysis.advisories {
-                writeln!(
-                    body,
-                    "- {} resolved version {} is vulnerable to CVE https://rustsec.org/advisories/{}.html",
-                    adv.package, adv.version, adv.content.id
-                )
-                .unwrap();
+            for adv in notified {
+                if adv.content.is_internal {
+                    writeln!(
+                        body,
+                        "- {} resolved version {} is vulnerable to {}, see {}/crates/{}",
+                        adv.package, adv.version, adv.content.id, configuration.web_public_uri, adv.package
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(
+                        body,
+                        "- {} resolved version {} is vulnerable to CVE https://rustsec.org/advisories/{}.html",
+                        adv.package, adv.version, adv.content.id
+                    )
+                    .unwrap();
+                }
                 writeln!(body, "  => {}", adv.content.summary).unwrap();
+                if let Some(level) = adv.content.severity_level {
+                    writeln!(body, "  severity: {level}").unwrap();
+                }
+                if !adv.content.aliases.is_empty() {
+                    writeln!(body, "  aliases: {}", adv.content.aliases.join(", ")).unwrap();
+                }
             }
             service_email_sender
                 .send_email(
@@ -214,6 +293,35 @@ async fn deps_worker_job_on_crate_version(
                 )
                 .await?;
         }
+        if has_license_violations != old_has_license_violations {
+            // new license violations ...
+            let mut body = String::new();
+            writeln!(
+                body,
+                "New dependencies violating the license policy have been found for {} {}",
+                job.package, job.version
+            )
+            .unwrap();
+            writeln!(
+                body,
+                "See {}/crates/{}/{}",
+                configuration.web_public_uri, job.package, job.version
+            )
+            .unwrap();
+            writeln!(body).unwrap();
+            for dep in &analysis.licenses {
+                if let Some(violation) = &dep.violation {
+                    writeln!(body, "- {} resolved version {}: {violation}", dep.package, dep.version).unwrap();
+                }
+            }
+            service_email_sender
+                .send_email(
+                    &owners,
+                    &format!("Cratery - license violations for {} {}", job.package, job.version),
+                    body,
+                )
+                .await?;
+        }
     }
     Ok(())
 }
@@ -230,6 +338,14 @@ pub trait DepsChecker {
         version: &'a str,
         targets: &'a [String],
     ) -> FaillibleFuture<'a, DepsAnalysis>;
+
+    /// Checks the dependencies of a local crate and stores its SBOM documents, replacing the previous ones
+    fn analyze_crate<'a>(
+        &'a self,
+        package: &'a str,
+        version: &'a str,
+        targets: &'a [String],
+    ) -> FaillibleFuture<'a, DepsAnalysis>;
 }
 
 /// Gets the dependencies checker service
@@ -237,12 +353,18 @@ pub fn get_service(
     configuration: Arc<Configuration>,
     service_index: Arc<dyn Index + Send + Sync>,
     service_rustsec: Arc<dyn RustSecChecker + Send + Sync>,
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    service_crates_io_proxy: Arc<dyn CratesIoProxy + Send + Sync>,
+    client: reqwest::Client,
 ) -> Arc<dyn DepsChecker + Send + Sync> {
     Arc::new(DepsCheckerImpl {
         data: Mutex::new(DepsCheckerData::default()),
         configuration,
         service_index,
         service_rustsec,
+        service_storage,
+        service_crates_io_proxy,
+        client,
     })
 }
 
@@ -263,14 +385,18 @@ struct DepsCheckerImpl {
     service_index: Arc<dyn Index + Send + Sync>,
     /// The `RustSec` service
     service_rustsec: Arc<dyn RustSecChecker + Send + Sync>,
+    /// The storage layer
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    /// The pull-through caching proxy to crates.io, used to get the licenses of crates
+    service_crates_io_proxy: Arc<dyn CratesIoProxy + Send + Sync>,
+    /// The HTTP client to fetch from sparse registries, shared with the other services
+    client: reqwest::Client,
 }
 
-/// The URI identifying crates.io as the registry for a dependency
-const CRATES_IO_REGISTRY_URI: &str = "https://github.com/rust-lang/crates.io-index";
 /// The prefixes URI for the index for dependencies on crates.io
-const _CRATES_IO_INDEX_SPARSE_URI: &str = "https://index.crates.io/";
+pub const CRATES_IO_INDEX_SPARSE_URI: &str = "https://index.crates.io/";
 /// Registry name for crates.io
-const CRATES_IO_NAME: &str = "crates.io";
+pub const CRATES_IO_NAME: &str = "crates.io";
 /// Name of the sub-directory to use within the data directory
 const DATA_SUB_DIR: &str = "deps";
 
@@ -289,6 +415,16 @@ impl DepsChecker for DepsCheckerImpl {
     ) -> FaillibleFuture<'a, DepsAnalysis> {
         Box::pin(async move { self.do_check_crate(package, version, targets).await })
     }
+
+    /// Checks the dependencies of a local crate and stores its SBOM documents, replacing the previous ones
+    fn analyze_crate<'a>(
+        &'a self,
+        package: &'a str,
+        version: &'a str,
+        targets: &'a [String],
+    ) -> FaillibleFuture<'a, DepsAnalysis> {
+        Box::pin(async move { self.do_analyze_crate(package, version, targets).await })
+    }
 }
 
 impl DepsCheckerImpl {
@@ -301,6 +437,25 @@ impl DepsCheckerImpl {
 
     /// Checks the dependencies of a local crate
     async fn do_check_crate(&self, package: &str, version: &str, targets: &[String]) -> Result<DepsAnalysis, ApiError> {
+        let (analysis, _sbom) = self.resolve_crate(package, version, targets).await?;
+        Ok(analysis)
+    }
+
+    /// Checks the dependencies of a local crate and stores its SBOM documents, replacing the previous ones
+    async fn do_analyze_crate(&self, package: &str, version: &str, targets: &[String]) -> Result<DepsAnalysis, ApiError> {
+        let (analysis, sbom) = self.resolve_crate(package, version, targets).await?;
+        let document_uri = format!("{}/api/v1/crates/{package}/{version}/sbom", self.configuration.web_public_uri);
+        for format in SbomFormat::ALL {
+            let content = serde_json::to_vec_pretty(&sbom.render(format, &document_uri))?;
+            self.service_storage
+                .store_crate_sbom(package, version, format, content)
+                .await?;
+        }
+        Ok(analysis)
+    }
+
+    /// Resolves the dependencies of a local crate and gets the analysis and the SBOM for the resolved graph
+    async fn resolve_crate(&self, package: &str, version: &str, targets: &[String]) -> Result<(DepsAnalysis, Sbom), ApiError> {
         let metadata = self.service_index.get_crate_data(package).await?;
         let metadata = metadata
             .iter()
@@ -312,7 +467,10 @@ impl DepsCheckerImpl {
         for dep in &graph.crates {
             for resolution in &dep.resolutions {
                 let version = dep.versions[resolution.version_index].semver.clone();
-                let simples = self.service_rustsec.check_crate(&dep.name, &version).await?;
+                let simples = self
+                    .service_rustsec
+                    .check_crate(dep.registry.as_deref(), &dep.name, &version)
+                    .await?;
                 for simple in simples {
                     if advisories
                         .iter()
@@ -322,12 +480,58 @@ impl DepsCheckerImpl {
                             package: dep.name.clone(),
                             version: version.clone(),
                             content: simple,
+                            suppression: None,
+                            is_flagged: false,
                         });
                     }
                 }
             }
         }
-        Ok(DepsAnalysis::new(&graph, &metadata.deps, advisories))
+        let sbom = self.build_sbom(metadata, &graph).await;
+        let licenses = sbom
+            .components
+            .iter()
+            .map(|component| DepLicense {
+                registry: component.registry.clone(),
+                package: component.name.clone(),
+                version: component.version.clone(),
+                license: component.license.clone(),
+                violation: None,
+            })
+            .collect();
+        Ok((DepsAnalysis::new(&graph, &metadata.deps, advisories, licenses), sbom))
+    }
+
+    /// Builds the SBOM for a local crate from the resolved graph of its dependencies, including the licenses
+    async fn build_sbom(&self, metadata: &IndexCrateMetadata, graph: &DepsGraph) -> Sbom {
+        let local_registry = self.configuration.get_self_as_external().index;
+        let mut root = SbomComponent::new(None, metadata, &local_registry);
+        root.license = self.get_crate_license(None, &metadata.name, &metadata.vers).await;
+        let mut sbom = Sbom::new(root, &metadata.deps, graph, &local_registry);
+        for component in &mut sbom.components {
+            component.license = self
+                .get_crate_license(component.registry.as_deref(), &component.name, &component.version)
+                .await;
+        }
+        sbom
+    }
+
+    /// Gets the license of a crate version, when it can be known
+    async fn get_crate_license(&self, registry: Option<&str>, name: &str, version: &str) -> Option<String> {
+        match registry {
+            None => {
+                let metadata = self.service_storage.download_crate_metadata(name, version).await;
+                metadata.ok().flatten().and_then(|metadata| metadata.license)
+            }
+            Some(CRATES_IO_REGISTRY_URI) => match self.service_crates_io_proxy.download_crate(name, version).await {
+                Ok(content) => read_crate_license(name, version, &content),
+                Err(e) => {
+                    warn!("deps: failed to get the license of {name} {version} from crates.io: {e}");
+                    None
+                }
+            },
+            Some(_) => None,
+        }
     }
 
     /// Gets the transitive closure of dependencies
@@ -516,7 +720,7 @@ impl DepsCheckerImpl {
         credentials: Option<(&str, &str)>,
         data: &mut DepsCheckerData,
     ) -> Result<Vec<IndexCrateMetadata>, ApiError> {
-        let mut request = reqwest::Client::new().get(&target_uri);
+        let mut request = self.client.get(&target_uri);
         if let Some((login, password)) = credentials {
             let value = STANDARD.encode(format!("{login}:{password}"));
             request = request.header("Authorization", format!("Basic {value}"));
@@ -21,6 +21,7 @@ use tokio::sync::mpsc::Sender;
 use tokio::time::interval;
 
 use crate::model::CHANNEL_NIGHTLY;
+use crate::model::cargo::check_crate_archive;
 use crate::model::config::Configuration;
 use crate::model::docs::{DocGenEvent, DocGenJob, DocGenJobSpec, DocGenJobState, DocGenJobUpdate, DocGenTrigger};
 use crate::model::worker::{JobIdentifier, JobSpecification, JobUpdate, WorkersManager};
@@ -361,6 +362,8 @@ async fn on_job_check_target(configuration: &Configuration, job: &DocGenJob) ->
 
 /// Generates and upload the documentation for a crate
 fn extract_content(name: &str, version: &str, content: &[u8]) -> Result<PathBuf, ApiError> {
+    // refuse to unpack archives with entries outside of the crate folder
+    check_crate_archive(name, version, content)?;
     let decoder = GzDecoder::new(content);
     let mut archive = Archive::new(decoder);
     let target = format!("/tmp/{name}_{version}");
@@ -6,6 +6,7 @@
 
 use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use log::{error, info};
 use thiserror::Error;
@@ -13,7 +14,7 @@ use tokio::fs::{File, OpenOptions, create_dir_all};
 use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::sync::Mutex;
 
-use super::{Index, IndexError, build_package_file_path};
+use super::{Index, IndexError, IndexFile, build_package_file_path};
 use crate::model::cargo::IndexCrateMetadata;
 use crate::model::config::IndexConfig;
 use crate::utils::apierror::{ApiError, AsStatusCode};
@@ -173,8 +174,8 @@ impl GitIndex {
 }
 
 impl Index for GitIndex {
-    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<PathBuf>> {
-        Box::pin(async move { Ok(self.inner.lock().await.get_index_file(file_path)) })
+    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>> {
+        Box::pin(async move { self.inner.lock().await.get_index_file(file_path).await })
     }
 
     fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>> {
@@ -347,17 +348,27 @@ impl GitIndexImpl {
         Ok(())
     }
 
-    /// Gets the full path to a file in the bare git repository
-    fn get_index_file(&self, file_path: &Path) -> Option<PathBuf> {
+    /// Gets the content of a file in the bare git repository
+    async fn get_index_file(&self, file_path: &Path) -> Result<Option<IndexFile>, ApiError> {
         let mut full_path = PathBuf::from(&self.config.location);
         if file_path.iter().nth(1).is_some_and(|elem| elem == ".git") {
             // exclude .git folder
-            return None;
+            return Ok(None);
         }
         for elem in file_path.iter().skip(1) {
             full_path.push(elem);
         }
-        if full_path.exists() { Some(full_path) } else { None }
+        if full_path.is_file() {
+            let content = tokio::fs::read(&full_path).await?;
+            let last_modified = tokio::fs::metadata(&full_path)
+                .await?
+                .modified()
+                .ok()
+                .map(DateTime::<Utc>::from);
+            Ok(Some(IndexFile::new(content, last_modified)))
+        } else {
+            Ok(None)
+        }
     }
 
     /// Gets the upload pack advertisement for /info/refs
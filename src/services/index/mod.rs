@@ -5,20 +5,25 @@
 //! API for index manipulation
 
 mod git;
+mod sparse;
 
 pub use git::GitIndexError;
+pub use sparse::SparseIndexError;
 
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use thiserror::Error;
 
 use crate::model::cargo::IndexCrateMetadata;
-use crate::model::config::Configuration;
+use crate::model::config::{Configuration, IndexBackend};
+use crate::services::storage::Storage;
 use crate::utils::FaillibleFuture;
 use crate::utils::apierror::AsStatusCode;
+use crate::utils::hashes::sha256;
 
 #[derive(Debug, Error)]
 pub enum IndexError {
@@ -27,20 +32,73 @@ pub enum IndexError {
 
     #[error(transparent)]
     GitIndexError(#[from] GitIndexError),
+
+    #[error(transparent)]
+    SparseIndexError(#[from] SparseIndexError),
 }
 impl AsStatusCode for IndexError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::PackageNotInRegistry { .. } => StatusCode::NOT_FOUND,
             Self::GitIndexError(err) => err.status_code(),
+            Self::SparseIndexError(err) => err.status_code(),
+        }
+    }
+}
+
+/// A file in the index, with its validators for conditional requests
+#[derive(Debug, Clone)]
+pub struct IndexFile {
+    /// The content of the file
+    pub content: Vec<u8>,
+    /// The entity tag for the content, quoted
+    pub etag: String,
+    /// The last modification date of the file, if known
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl IndexFile {
+    /// Creates the file data, computing the entity tag from the content
+    #[must_use]
+    pub fn new(content: Vec<u8>, last_modified: Option<DateTime<Utc>>) -> Self {
+        let etag = format!("\"{}\"", sha256(&content));
+        Self {
+            content,
+            etag,
+            last_modified,
+        }
+    }
+
+    /// Gets the value for the `Last-Modified` header, if the modification date is known
+    #[must_use]
+    pub fn last_modified_header(&self) -> Option<String> {
+        self.last_modified
+            .map(|date| date.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+    }
+
+    /// Gets whether a client's copy is still current,
+    /// given the values of the `If-None-Match` and `If-Modified-Since` headers of its request
+    #[must_use]
+    pub fn is_current(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> bool {
+        if let Some(if_none_match) = if_none_match {
+            // If-None-Match takes precedence over If-Modified-Since
+            return if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
         }
+        let Some(since) = if_modified_since.and_then(|value| DateTime::parse_from_rfc2822(value).ok()) else {
+            return false;
+        };
+        self.last_modified
+            .is_some_and(|last_modified| last_modified.timestamp() <= since.timestamp())
     }
 }
 
 /// Index implementations
 pub trait Index {
-    /// Gets the full path to a file in the index
-    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<PathBuf>>;
+    /// Gets the content of a file in the index, with its validators
+    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>>;
 
     /// Gets the upload pack advertisement for /info/refs
     fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>>;
@@ -85,8 +143,45 @@ pub fn build_package_file_path(mut root: PathBuf, name: &str) -> PathBuf {
     root
 }
 
+/// Produce the path in the sparse index to the file that contains the metadata for the crate
+#[must_use]
+pub fn build_package_index_path(name: &str) -> String {
+    let lowercase = name.to_ascii_lowercase();
+    let (first, second) = package_file_path(&lowercase);
+    second.map_or_else(
+        || format!("{first}/{lowercase}"),
+        |second| format!("{first}/{second}/{lowercase}"),
+    )
+}
+
+/// Parses a path in the sparse index (without the leading `/`) and gets the name of the corresponding crate
+#[must_use]
+pub fn parse_package_index_path(path: &str) -> Option<&str> {
+    let name = path.rsplit('/').next()?;
+    let is_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !is_valid || build_package_index_path(name) != path {
+        return None;
+    }
+    Some(name)
+}
+
 /// Gets the index service
-pub async fn get_service(config: &Configuration, expect_empty: bool) -> Result<Arc<dyn Index + Send + Sync>, GitIndexError> {
-    let index = git::GitIndex::new(config.get_index_git_config(), expect_empty).await?;
-    Ok(Arc::new(index))
+pub async fn get_service(
+    config: &Configuration,
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    expect_empty: bool,
+) -> Result<Arc<dyn Index + Send + Sync>, IndexError> {
+    match config.index.backend {
+        IndexBackend::Git => {
+            let index = git::GitIndex::new(config.get_index_git_config(), expect_empty).await?;
+            Ok(Arc::new(index))
+        }
+        IndexBackend::Storage => {
+            let index = sparse::SparseIndex::new(config.get_index_git_config(), service_storage).await?;
+            Ok(Arc::new(index))
+        }
+    }
 }
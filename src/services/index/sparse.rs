@@ -0,0 +1,315 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Implementation of a sparse-only index with files kept in the storage backend
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use log::{info, warn};
+use thiserror::Error;
+use tokio::io;
+use tokio::sync::Mutex;
+
+use super::{Index, IndexError, IndexFile, build_package_index_path, parse_package_index_path};
+use crate::model::cargo::IndexCrateMetadata;
+use crate::model::config::IndexConfig;
+use crate::services::storage::Storage;
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::{ApiError, AsStatusCode, error_not_found, specialize};
+
+#[derive(Debug, Error)]
+pub enum SparseIndexError {
+    #[error("failed to read index file `{path}` from storage: {error}")]
+    ReadStorage { error: Box<ApiError>, path: String },
+
+    #[error("failed to write index file `{path}` to storage: {error}")]
+    WriteStorage { error: Box<ApiError>, path: String },
+
+    #[error("fail to read dir : '{location}'")]
+    ReadDir {
+        #[source]
+        source: io::Error,
+        location: PathBuf,
+    },
+
+    #[error("failed to read `{path}`")]
+    ReadFile {
+        #[source]
+        source: io::Error,
+        path: PathBuf,
+    },
+
+    #[error("failed to deserialise line `{line_idx}` of `{path}`")]
+    DeserialiseLine {
+        #[source]
+        source: serde_json::Error,
+        path: String,
+        line_idx: usize,
+    },
+
+    #[error("failed to update index file `{path}`: it was concurrently modified {attempts} times")]
+    ConcurrentModifications { path: String, attempts: usize },
+
+    #[error("failed to serialise package version : `{version}`")]
+    SerializeVersion {
+        #[source]
+        source: serde_json::Error,
+        version: String,
+    },
+}
+impl AsStatusCode for SparseIndexError {}
+
+/// Name of the marker file recording that the content of a previous git index was imported
+const GIT_IMPORT_MARKER: &str = ".git-imported";
+
+/// The maximum number of attempts to update an index file that is concurrently modified
+const UPDATE_MAX_ATTEMPTS: usize = 5;
+
+/// Manages a sparse-only index, with the files kept in the storage backend
+///
+/// The index files are updated with conditional writes, so that the modifications of another instance sharing
+/// the storage are not lost, an update being retried when the file changed since it was read.
+/// This requires a storage backend with entity tags, such as S3; on the file system, a single instance is supported.
+pub struct SparseIndex {
+    /// The configuration
+    config: IndexConfig,
+    /// The storage layer
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    /// Lock to serialize the modifications of the index files within this instance
+    lock: Mutex<()>,
+}
+
+impl SparseIndex {
+    /// When the application is launched
+    pub async fn new(config: IndexConfig, service_storage: Arc<dyn Storage + Send + Sync>) -> Result<Self, SparseIndexError> {
+        let index = Self {
+            config,
+            service_storage,
+            lock: Mutex::new(()),
+        };
+        index.import_git_location().await?;
+        Ok(index)
+    }
+
+    /// Imports the files of a git index previously used at the configured location, if any
+    async fn import_git_location(&self) -> Result<(), SparseIndexError> {
+        let location = PathBuf::from(&self.config.location);
+        if !location.exists() || self.read_file(GIT_IMPORT_MARKER).await?.is_some() {
+            return Ok(());
+        }
+        info!("index: importing files from the git index at {}", location.display());
+        let mut count = 0;
+        let mut to_visit = vec![location.clone()];
+        while let Some(directory) = to_visit.pop() {
+            let mut entries = tokio::fs::read_dir(&directory)
+                .await
+                .map_err(|source| SparseIndexError::ReadDir {
+                    source,
+                    location: directory.clone(),
+                })?;
+            while let Some(entry) = entries.next_entry().await.map_err(|source| SparseIndexError::ReadDir {
+                source,
+                location: directory.clone(),
+            })? {
+                let path = entry.path();
+                if path.is_dir() {
+                    if entry.file_name() != ".git" {
+                        to_visit.push(path);
+                    }
+                    continue;
+                }
+                let Some(relative) = path.strip_prefix(&location).ok().and_then(Path::to_str) else {
+                    continue;
+                };
+                if parse_package_index_path(relative).is_none() || self.read_file(relative).await?.is_some() {
+                    continue;
+                }
+                let content = tokio::fs::read(&path).await.map_err(|source| SparseIndexError::ReadFile {
+                    source,
+                    path: path.clone(),
+                })?;
+                self.write_file(relative, content).await?;
+                count += 1;
+            }
+        }
+        self.write_file(GIT_IMPORT_MARKER, Vec::new()).await?;
+        info!("index: imported {count} file(s) from the git index");
+        Ok(())
+    }
+
+    /// Reads a file of the index from the storage
+    async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>, SparseIndexError> {
+        self.service_storage
+            .download_index_file(path)
+            .await
+            .map_err(|error| SparseIndexError::ReadStorage {
+                error: Box::new(error),
+                path: path.to_string(),
+            })
+    }
+
+    /// Writes a file of the index to the storage
+    async fn write_file(&self, path: &str, content: Vec<u8>) -> Result<(), SparseIndexError> {
+        self.service_storage
+            .store_index_file(path, content)
+            .await
+            .map_err(|error| SparseIndexError::WriteStorage {
+                error: Box::new(error),
+                path: path.to_string(),
+            })
+    }
+
+    /// Updates a file of the index by applying a modification to its current content, if any
+    /// The modification is applied again when the file was concurrently modified by another instance.
+    async fn update_file<F>(&self, path: &str, update: F) -> Result<(), SparseIndexError>
+    where
+        F: Fn(Option<Vec<u8>>) -> Result<Vec<u8>, SparseIndexError>,
+    {
+        let _guard = self.lock.lock().await;
+        for _ in 0..UPDATE_MAX_ATTEMPTS {
+            let (content, version) = self
+                .service_storage
+                .download_index_file_versioned(path)
+                .await
+                .map_err(|error| SparseIndexError::ReadStorage {
+                    error: Box::new(error),
+                    path: path.to_string(),
+                })?;
+            let content = update(content)?;
+            if self
+                .service_storage
+                .store_index_file_if_unmodified(path, content, &version)
+                .await
+                .map_err(|error| SparseIndexError::WriteStorage {
+                    error: Box::new(error),
+                    path: path.to_string(),
+                })?
+            {
+                return Ok(());
+            }
+            warn!("index: file `{path}` was concurrently modified, retrying");
+        }
+        Err(SparseIndexError::ConcurrentModifications {
+            path: path.to_string(),
+            attempts: UPDATE_MAX_ATTEMPTS,
+        })
+    }
+
+    /// Gets the content of a file in the index
+    async fn get_index_file(&self, file_path: &Path) -> Result<Option<IndexFile>, ApiError> {
+        let Some(path) = file_path.to_str().map(|path| path.trim_start_matches('/')) else {
+            return Ok(None);
+        };
+        if path == "config.json" {
+            return Ok(Some(IndexFile::new(serde_json::to_vec(&self.config.public)?, None)));
+        }
+        if parse_package_index_path(path).is_none() {
+            return Ok(None);
+        }
+        Ok(self.read_file(path).await?.map(|content| IndexFile::new(content, None)))
+    }
+
+    /// Publish a new version for a crate
+    async fn publish_crate_version(&self, metadata: &IndexCrateMetadata) -> Result<(), ApiError> {
+        let path = build_package_index_path(&metadata.name);
+        let line = serde_json::to_vec(metadata)?;
+        self.update_file(&path, |content| {
+            let mut content = content.unwrap_or_default();
+            content.extend_from_slice(&line);
+            content.push(0x0A); // add line end
+            Ok(content)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Completely removes a version from the registry
+    async fn remove_crate_version(&self, package: &str, version: &str) -> Result<(), IndexError> {
+        let path = build_package_index_path(package);
+        // check the crate is in the index
+        let _ = self.read_versions(package, &path).await?;
+        self.update_file(&path, |content| {
+            let mut versions = parse_versions(&path, &content.unwrap_or_default())?;
+            versions.retain(|v| v.vers != version);
+            let mut content = Vec::new();
+            for version in versions {
+                serde_json::to_writer(&mut content, &version).map_err(|source| SparseIndexError::SerializeVersion {
+                    source,
+                    version: format!("{version:#?}"),
+                })?;
+                content.push(0x0A); // add line end
+            }
+            Ok(content)
+        })
+        .await?;
+        Ok(())
+    }
+
+    ///  Gets the data for a crate
+    async fn get_crate_data(&self, package: &str) -> Result<Vec<IndexCrateMetadata>, IndexError> {
+        let path = build_package_index_path(package);
+        self.read_versions(package, &path).await
+    }
+
+    /// Reads all the versions in the index file for a crate
+    async fn read_versions(&self, package: &str, path: &str) -> Result<Vec<IndexCrateMetadata>, IndexError> {
+        let content = self
+            .read_file(path)
+            .await?
+            .ok_or_else(|| IndexError::PackageNotInRegistry { package: package.into() })?;
+        Ok(parse_versions(path, &content)?)
+    }
+}
+
+/// Parses the versions in the content of the index file for a crate
+fn parse_versions(path: &str, content: &[u8]) -> Result<Vec<IndexCrateMetadata>, SparseIndexError> {
+    let mut results = Vec::new();
+    for (line_idx, line) in content.split(|&c| c == 0x0A).filter(|line| !line.is_empty()).enumerate() {
+        let data = serde_json::from_slice(line).map_err(|source| SparseIndexError::DeserialiseLine {
+            source,
+            path: path.to_string(),
+            line_idx,
+        })?;
+        results.push(data);
+    }
+    Ok(results)
+}
+
+impl Index for SparseIndex {
+    fn get_index_file<'a>(&'a self, file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>> {
+        Box::pin(async move { self.get_index_file(file_path).await })
+    }
+
+    fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>> {
+        Box::pin(async move {
+            Err(specialize(
+                error_not_found(),
+                String::from("the git protocol is not available with the storage index backend"),
+            ))
+        })
+    }
+
+    fn get_upload_pack_for<'a>(&'a self, _input: &'a [u8]) -> FaillibleFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            Err(specialize(
+                error_not_found(),
+                String::from("the git protocol is not available with the storage index backend"),
+            ))
+        })
+    }
+
+    fn publish_crate_version<'a>(&'a self, metadata: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.publish_crate_version(metadata).await })
+    }
+
+    fn remove_crate_version<'a>(&'a self, package: &'a str, version: &'a str) -> BoxFuture<'a, Result<(), IndexError>> {
+        Box::pin(async move { self.remove_crate_version(package, version).await })
+    }
+
+    fn get_crate_data<'a>(&'a self, package: &'a str) -> BoxFuture<'a, Result<Vec<IndexCrateMetadata>, IndexError>> {
+        Box::pin(async move { self.get_crate_data(package).await })
+    }
+}
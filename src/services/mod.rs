@@ -4,9 +4,10 @@
 
 //! Service implementations
 
+use std::time::Duration;
 use std::{io, path::PathBuf, sync::Arc};
 
-use index::GitIndexError;
+use index::IndexError;
 use thiserror::Error;
 
 use crate::model::config::{Configuration, WriteAuthConfigError};
@@ -14,12 +15,15 @@ use crate::model::errors::MissingEnvVar;
 use crate::model::worker::WorkersManager;
 use crate::utils::apierror::AsStatusCode;
 use crate::utils::db::RwSqlitePool;
+use crate::{CRATE_NAME, CRATE_VERSION};
 
 pub mod database;
 pub mod deps;
 pub mod docs;
 pub mod emails;
 pub mod index;
+pub mod oauth;
+pub mod proxy;
 pub mod rustsec;
 pub mod storage;
 
@@ -40,6 +44,27 @@ pub enum ConfigurationError {
 }
 impl AsStatusCode for ConfigurationError {}
 
+/// The timeout to connect to external services
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The timeout for each read of a response from external services
+const HTTP_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates the HTTP client shared by the services that fetch data from external registries
+///
+/// Without timeouts, an unresponsive registry would block the requests instead of falling back to cached data.
+/// The user agent identifies the registry, as required by the crawler policy of crates.io.
+///
+/// # Errors
+///
+/// Returns an error when the TLS backend cannot be initialized
+pub fn create_http_client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .user_agent(format!("{CRATE_NAME}/{CRATE_VERSION}"))
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .read_timeout(HTTP_READ_TIMEOUT)
+        .build()
+}
+
 /// Factory responsible for building services
 #[expect(async_fn_in_trait)]
 pub trait ServiceProvider {
@@ -52,17 +77,21 @@ pub trait ServiceProvider {
     /// Gets the index service
     async fn get_index(
         config: &Configuration,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
         expect_empty: bool,
-    ) -> Result<Arc<dyn index::Index + Send + Sync>, GitIndexError>;
+    ) -> Result<Arc<dyn index::Index + Send + Sync>, IndexError>;
 
     /// Gets the rustsec service
-    fn get_rustsec(config: &Configuration) -> Arc<dyn rustsec::RustSecChecker + Send + Sync>;
+    fn get_rustsec(config: &Configuration, service_db_pool: RwSqlitePool) -> Arc<dyn rustsec::RustSecChecker + Send + Sync>;
 
     /// Gets the dependencies checker service
     fn get_deps_checker(
         configuration: Arc<Configuration>,
         service_index: Arc<dyn index::Index + Send + Sync>,
         service_rustsec: Arc<dyn rustsec::RustSecChecker + Send + Sync>,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+        service_crates_io_proxy: Arc<dyn proxy::CratesIoProxy + Send + Sync>,
+        http_client: reqwest::Client,
     ) -> Arc<dyn deps::DepsChecker + Send + Sync>;
 
     /// Gets the email sender service
@@ -75,6 +104,16 @@ pub trait ServiceProvider {
         service_storage: Arc<dyn storage::Storage + Send + Sync>,
         worker_nodes: WorkersManager,
     ) -> Arc<dyn docs::DocsGenerator + Send + Sync>;
+
+    /// Gets the crates.io proxy service
+    fn get_crates_io_proxy(
+        configuration: Arc<Configuration>,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+        http_client: reqwest::Client,
+    ) -> Arc<dyn proxy::CratesIoProxy + Send + Sync>;
+
+    /// Gets the OAuth identity provider service
+    fn get_oauth_provider(configuration: Arc<Configuration>) -> Arc<dyn oauth::OAuthProvider + Send + Sync>;
 }
 
 /// Provides the standard implementations for services
@@ -101,14 +140,15 @@ impl ServiceProvider for StandardServiceProvider {
     /// Gets the index service
     async fn get_index(
         config: &Configuration,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
         expect_empty: bool,
-    ) -> Result<Arc<dyn index::Index + Send + Sync>, GitIndexError> {
-        index::get_service(config, expect_empty).await
+    ) -> Result<Arc<dyn index::Index + Send + Sync>, IndexError> {
+        index::get_service(config, service_storage, expect_empty).await
     }
 
     /// Gets the rustsec service
-    fn get_rustsec(config: &Configuration) -> Arc<dyn rustsec::RustSecChecker + Send + Sync> {
-        rustsec::get_service(config)
+    fn get_rustsec(config: &Configuration, service_db_pool: RwSqlitePool) -> Arc<dyn rustsec::RustSecChecker + Send + Sync> {
+        rustsec::get_service(config, service_db_pool)
     }
 
     /// Gets the dependencies checker service
@@ -116,8 +156,18 @@ impl ServiceProvider for StandardServiceProvider {
         configuration: Arc<Configuration>,
         service_index: Arc<dyn index::Index + Send + Sync>,
         service_rustsec: Arc<dyn rustsec::RustSecChecker + Send + Sync>,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+        service_crates_io_proxy: Arc<dyn proxy::CratesIoProxy + Send + Sync>,
+        http_client: reqwest::Client,
     ) -> Arc<dyn deps::DepsChecker + Send + Sync> {
-        deps::get_service(configuration, service_index, service_rustsec)
+        deps::get_service(
+            configuration,
+            service_index,
+            service_rustsec,
+            service_storage,
+            service_crates_io_proxy,
+            http_client,
+        )
     }
 
     /// Gets the email sender service
@@ -134,4 +184,18 @@ impl ServiceProvider for StandardServiceProvider {
     ) -> Arc<dyn docs::DocsGenerator + Send + Sync> {
         docs::get_service(configuration, service_db_pool, service_storage, worker_nodes)
     }
+
+    /// Gets the crates.io proxy service
+    fn get_crates_io_proxy(
+        configuration: Arc<Configuration>,
+        service_storage: Arc<dyn storage::Storage + Send + Sync>,
+        http_client: reqwest::Client,
+    ) -> Arc<dyn proxy::CratesIoProxy + Send + Sync> {
+        proxy::get_service(configuration, service_storage, http_client)
+    }
+
+    /// Gets the OAuth identity provider service
+    fn get_oauth_provider(configuration: Arc<Configuration>) -> Arc<dyn oauth::OAuthProvider + Send + Sync> {
+        oauth::get_service(configuration)
+    }
 }
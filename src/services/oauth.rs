@@ -0,0 +1,358 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for the login of users through an OAuth identity provider
+//! When an issuer is configured, OIDC is used: the endpoints are discovered and the ID token is verified.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures::lock::Mutex;
+use reqwest::StatusCode;
+use serde_derive::Deserialize;
+use thiserror::Error;
+
+use crate::model::auth::{OAuthLoginRequest, OAuthToken, find_field_in_blob};
+use crate::model::config::Configuration;
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::AsStatusCode;
+use crate::utils::jwt::{JwkSet, Jwt, JwtError, pkce_challenge};
+
+/// The path to the discovery document, relative to the issuer (OIDC Discovery, section 4)
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+/// The minimum time between two refreshes of the provider's keys, when encountering an unknown key
+const KEYS_REFRESH_PERIOD: Duration = Duration::from_mins(1);
+/// The tolerated clock skew when checking the expiration of ID tokens, in seconds
+const ID_TOKEN_LEEWAY: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum OAuthError {
+    #[error("failed to send request to '{uri}'")]
+    Request { source: reqwest::Error, uri: String },
+
+    #[error("request to '{uri}' failed: {status} '{body}'")]
+    RequestFailed { status: StatusCode, uri: String, body: String },
+
+    #[error("failed to parse response from '{uri}'")]
+    ParseResponse {
+        source: serde_json::Error,
+        uri: String,
+        body: String,
+    },
+
+    #[error("the identity provider did not return an ID token")]
+    MissingIdToken,
+
+    #[error("invalid ID token")]
+    InvalidIdToken(#[source] JwtError),
+
+    #[error("invalid ID token, wrong claim `{0}`")]
+    InvalidIdTokenClaim(&'static str),
+
+    #[error("the user info does not match the ID token")]
+    UserInfoMismatch,
+}
+
+impl AsStatusCode for OAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::RequestFailed { .. }
+            | Self::MissingIdToken
+            | Self::InvalidIdToken(_)
+            | Self::InvalidIdTokenClaim(_)
+            | Self::UserInfoMismatch => StatusCode::UNAUTHORIZED,
+            Self::Request { .. } | Self::ParseResponse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Service for the login of users through an OAuth identity provider
+pub trait OAuthProvider {
+    /// Gets the URI of the login page on the identity provider for a login request
+    fn get_login_uri<'a>(&'a self, request: &'a OAuthLoginRequest) -> FaillibleFuture<'a, String>;
+
+    /// Exchanges an authorization code for the claims about the user
+    /// With OIDC, the claims come from the verified ID token.
+    fn get_user_claims<'a>(&'a self, code: &'a str, request: &'a OAuthLoginRequest) -> FaillibleFuture<'a, serde_json::Value>;
+}
+
+/// Gets the OAuth provider service
+#[must_use]
+pub fn get_service(configuration: Arc<Configuration>) -> Arc<dyn OAuthProvider + Send + Sync> {
+    Arc::new(OAuthProviderImpl {
+        configuration,
+        client: reqwest::Client::new(),
+        data: Mutex::new(OAuthProviderData::default()),
+    })
+}
+
+/// The metadata of an OIDC provider (OIDC Discovery, section 3)
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    /// The identifier of the issuer
+    issuer: String,
+    /// The URI of the authorization endpoint
+    authorization_endpoint: String,
+    /// The URI of the token endpoint
+    token_endpoint: String,
+    /// The URI of the userinfo endpoint
+    userinfo_endpoint: Option<String>,
+    /// The URI of the provider's keys
+    jwks_uri: String,
+}
+
+/// The endpoints to use for the identity provider
+#[derive(Debug, Clone)]
+struct Endpoints {
+    /// The URI of the login page
+    login: String,
+    /// The URI of the token endpoint
+    token: String,
+    /// The URI of the userinfo endpoint, if any
+    userinfo: Option<String>,
+}
+
+/// Data for the OAuth provider service
+#[derive(Debug, Clone, Default)]
+struct OAuthProviderData {
+    /// The discovered metadata, if any
+    metadata: Option<ProviderMetadata>,
+    /// The cached keys of the provider
+    keys: JwkSet,
+    /// The last time the keys were fetched
+    keys_fetched: Option<Instant>,
+}
+
+/// Service for the login of users through an OAuth identity provider
+struct OAuthProviderImpl {
+    /// The app configuration
+    configuration: Arc<Configuration>,
+    /// The client for requests to the identity provider
+    client: reqwest::Client,
+    /// The data for the service
+    data: Mutex<OAuthProviderData>,
+}
+
+impl OAuthProvider for OAuthProviderImpl {
+    fn get_login_uri<'a>(&'a self, request: &'a OAuthLoginRequest) -> FaillibleFuture<'a, String> {
+        Box::pin(async move { Ok(self.do_get_login_uri(request).await?) })
+    }
+
+    fn get_user_claims<'a>(&'a self, code: &'a str, request: &'a OAuthLoginRequest) -> FaillibleFuture<'a, serde_json::Value> {
+        Box::pin(async move { Ok(self.do_get_user_claims(code, request).await?) })
+    }
+}
+
+impl OAuthProviderImpl {
+    /// Gets the URI of the login page on the identity provider for a login request
+    async fn do_get_login_uri(&self, request: &OAuthLoginRequest) -> Result<String, OAuthError> {
+        let endpoints = self.get_endpoints().await?;
+        let separator = if endpoints.login.contains('?') { '&' } else { '?' };
+        Ok(format!(
+            "{}{separator}response_type=code&redirect_uri={}&client_id={}&scope={}&nonce={}&state={}&code_challenge={}&code_challenge_method=S256",
+            endpoints.login,
+            urlencoding::encode(&self.configuration.oauth_callback_uri),
+            urlencoding::encode(&self.configuration.oauth_client_id),
+            urlencoding::encode(&self.configuration.oauth_client_scope),
+            urlencoding::encode(&request.nonce),
+            urlencoding::encode(&request.state),
+            pkce_challenge(&request.code_verifier),
+        ))
+    }
+
+    /// Exchanges an authorization code for the claims about the user
+    async fn do_get_user_claims(&self, code: &str, request: &OAuthLoginRequest) -> Result<serde_json::Value, OAuthError> {
+        let endpoints = self.get_endpoints().await?;
+        let response = self
+            .client
+            .post(&endpoints.token)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.configuration.oauth_callback_uri),
+                ("client_id", &self.configuration.oauth_client_id),
+                ("client_secret", &self.configuration.oauth_client_secret),
+                ("code_verifier", &request.code_verifier),
+            ])
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await;
+        let token = parse_response::<OAuthToken>(&endpoints.token, response).await?;
+
+        let Some(metadata) = self.get_metadata().await? else {
+            // plain OAuth, trust the userinfo endpoint
+            let uri = endpoints.userinfo.as_deref().unwrap_or_default();
+            return self.get_user_info(uri, &token.access_token).await;
+        };
+        let id_token = token.id_token.as_deref().ok_or(OAuthError::MissingIdToken)?;
+        let mut claims = self.verify_id_token(&metadata, id_token, request).await?;
+        if find_field_in_blob(&claims, &self.configuration.oauth_userinfo_path_email).is_none() {
+            // complete with the user info, provided it is about the same subject (OIDC Core, section 5.3.2)
+            if let Some(uri) = &endpoints.userinfo {
+                let user_info = self.get_user_info(uri, &token.access_token).await?;
+                if user_info.get("sub").is_none() || user_info.get("sub") != claims.get("sub") {
+                    return Err(OAuthError::UserInfoMismatch);
+                }
+                if let (Some(claims), serde_json::Value::Object(user_info)) = (claims.as_object_mut(), user_info) {
+                    for (name, value) in user_info {
+                        claims.entry(name).or_insert(value);
+                    }
+                }
+            }
+        }
+        Ok(claims)
+    }
+
+    /// Verifies an ID token and gets its claims (OIDC Core, section 3.1.3.7)
+    async fn verify_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+        request: &OAuthLoginRequest,
+    ) -> Result<serde_json::Value, OAuthError> {
+        let jwt = Jwt::parse(id_token).map_err(OAuthError::InvalidIdToken)?;
+        let keys = self.get_keys(metadata, false).await?;
+        match jwt.verify(&keys) {
+            Err(JwtError::NoMatchingKey) => {
+                // the provider may have rotated its keys
+                let keys = self.get_keys(metadata, true).await?;
+                jwt.verify(&keys).map_err(OAuthError::InvalidIdToken)?;
+            }
+            result => result.map_err(OAuthError::InvalidIdToken)?,
+        }
+        let claims = jwt.claims;
+        if claims.get("iss").and_then(serde_json::Value::as_str) != Some(&metadata.issuer) {
+            return Err(OAuthError::InvalidIdTokenClaim("iss"));
+        }
+        let client_id = self.configuration.oauth_client_id.as_str();
+        let audiences = match claims.get("aud") {
+            Some(serde_json::Value::String(audience)) => vec![audience.as_str()],
+            Some(serde_json::Value::Array(audiences)) => audiences.iter().filter_map(serde_json::Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !audiences.contains(&client_id) {
+            return Err(OAuthError::InvalidIdTokenClaim("aud"));
+        }
+        if let Some(azp) = claims.get("azp")
+            && azp.as_str() != Some(client_id)
+        {
+            return Err(OAuthError::InvalidIdTokenClaim("azp"));
+        }
+        let expires_at = claims
+            .get("exp")
+            .and_then(serde_json::Value::as_i64)
+            .ok_or(OAuthError::InvalidIdTokenClaim("exp"))?;
+        if expires_at + ID_TOKEN_LEEWAY < Utc::now().timestamp() {
+            return Err(OAuthError::InvalidIdTokenClaim("exp"));
+        }
+        if claims.get("nonce").and_then(serde_json::Value::as_str) != Some(&request.nonce) {
+            return Err(OAuthError::InvalidIdTokenClaim("nonce"));
+        }
+        Ok(claims)
+    }
+
+    /// Gets the user info from the userinfo endpoint
+    async fn get_user_info(&self, uri: &str, access_token: &str) -> Result<serde_json::Value, OAuthError> {
+        let response = self
+            .client
+            .get(uri)
+            .header("authorization", format!("Bearer {access_token}"))
+            .send()
+            .await;
+        parse_response(uri, response).await
+    }
+
+    /// Gets the endpoints to use, from the configuration and the discovered metadata
+    /// Explicitly configured endpoints take precedence over discovered ones.
+    async fn get_endpoints(&self) -> Result<Endpoints, OAuthError> {
+        let metadata = self.get_metadata().await?;
+        let configured = |uri: &String| (!uri.is_empty()).then(|| uri.clone());
+        let configuration = &self.configuration;
+        Ok(Endpoints {
+            login: configured(&configuration.oauth_login_uri)
+                .or_else(|| metadata.as_ref().map(|m| m.authorization_endpoint.clone()))
+                .unwrap_or_default(),
+            token: configured(&configuration.oauth_token_uri)
+                .or_else(|| metadata.as_ref().map(|m| m.token_endpoint.clone()))
+                .unwrap_or_default(),
+            userinfo: configured(&configuration.oauth_userinfo_uri)
+                .or_else(|| metadata.as_ref().and_then(|m| m.userinfo_endpoint.clone())),
+        })
+    }
+
+    /// Gets the metadata of the OIDC provider, when an issuer is configured
+    async fn get_metadata(&self) -> Result<Option<ProviderMetadata>, OAuthError> {
+        let Some(issuer) = &self.configuration.oauth_issuer_uri else {
+            return Ok(None);
+        };
+        if let Some(metadata) = &self.data.lock().await.metadata {
+            return Ok(Some(metadata.clone()));
+        }
+        let uri = format!("{}{DISCOVERY_PATH}", issuer.trim_end_matches('/'));
+        let response = self.client.get(&uri).send().await;
+        let metadata = parse_response::<ProviderMetadata>(&uri, response).await?;
+        if metadata.issuer != *issuer {
+            return Err(OAuthError::RequestFailed {
+                status: StatusCode::OK,
+                uri,
+                body: format!(
+                    "the discovered issuer '{}' does not match the configured one",
+                    metadata.issuer
+                ),
+            });
+        }
+        self.data.lock().await.metadata = Some(metadata.clone());
+        Ok(Some(metadata))
+    }
+
+    /// Gets the keys of the provider
+    /// When `refresh` is set, the keys are fetched again unless they were recently.
+    async fn get_keys(&self, metadata: &ProviderMetadata, refresh: bool) -> Result<JwkSet, OAuthError> {
+        {
+            let data = self.data.lock().await;
+            if let Some(fetched) = data.keys_fetched
+                && (!refresh || fetched.elapsed() < KEYS_REFRESH_PERIOD)
+            {
+                return Ok(data.keys.clone());
+            }
+        }
+        let response = self.client.get(&metadata.jwks_uri).send().await;
+        let keys = parse_response::<JwkSet>(&metadata.jwks_uri, response).await?;
+        {
+            let mut data = self.data.lock().await;
+            data.keys = keys.clone();
+            data.keys_fetched = Some(Instant::now());
+        }
+        Ok(keys)
+    }
+}
+
+/// Parses the JSON response to a request to the identity provider
+async fn parse_response<T: serde::de::DeserializeOwned>(
+    uri: &str,
+    response: Result<reqwest::Response, reqwest::Error>,
+) -> Result<T, OAuthError> {
+    let response = response.map_err(|source| OAuthError::Request {
+        source,
+        uri: uri.to_string(),
+    })?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(|source| OAuthError::Request {
+        source,
+        uri: uri.to_string(),
+    })?;
+    if !status.is_success() {
+        return Err(OAuthError::RequestFailed {
+            status,
+            uri: uri.to_string(),
+            body: String::from_utf8_lossy(&body).into(),
+        });
+    }
+    serde_json::from_slice(&body).map_err(|source| OAuthError::ParseResponse {
+        source,
+        uri: uri.to_string(),
+        body: String::from_utf8_lossy(&body).into(),
+    })
+}
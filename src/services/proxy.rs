@@ -0,0 +1,354 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Service for the pull-through caching proxy to crates.io
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::lock::Mutex;
+use log::warn;
+use reqwest::StatusCode;
+use serde_derive::Deserialize;
+
+use crate::model::cargo::IndexCrateMetadata;
+use crate::model::config::{Configuration, IndexPublicConfig};
+use crate::model::typosquat::parse_popular_names;
+use crate::services::deps::{CRATES_IO_INDEX_SPARSE_URI, CRATES_IO_NAME};
+use crate::services::index::{build_package_file_path, build_package_index_path, parse_package_index_path};
+use crate::services::storage::Storage;
+use crate::utils::apierror::{ApiError, error_backend_failure, error_invalid_request, error_not_found, specialize};
+use crate::utils::hashes::sha256;
+use crate::utils::token::generate_hex_secret;
+use crate::utils::{FaillibleFuture, stale_instant};
+
+/// Service for the pull-through caching proxy to crates.io
+pub trait CratesIoProxy {
+    /// Gets the content of a file in the mirrored crates.io sparse index
+    fn get_index_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
+
+    /// Gets the content of a crate from crates.io, going through the local cache
+    fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
+
+    /// Gets the names of the most downloaded crates on crates.io, as last fetched by `refresh_popular_crates`
+    /// The list is empty when it was never fetched.
+    fn get_popular_crates(&self) -> FaillibleFuture<'_, Vec<String>>;
+
+    /// Fetches the names of the most downloaded crates on crates.io and caches them
+    fn refresh_popular_crates(&self, count: usize) -> FaillibleFuture<'_, Vec<String>>;
+}
+
+/// Gets the crates.io proxy service
+pub fn get_service(
+    configuration: Arc<Configuration>,
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    client: reqwest::Client,
+) -> Arc<dyn CratesIoProxy + Send + Sync> {
+    get_service_for_upstream(configuration, service_storage, client, ProxyUpstream::default())
+}
+
+/// Gets the proxy service for an upstream registry other than crates.io
+pub fn get_service_for_upstream(
+    configuration: Arc<Configuration>,
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    client: reqwest::Client,
+    upstream: ProxyUpstream,
+) -> Arc<dyn CratesIoProxy + Send + Sync> {
+    Arc::new(CratesIoProxyImpl {
+        data: Mutex::new(CratesIoProxyData::default()),
+        configuration,
+        service_storage,
+        client,
+        upstream,
+    })
+}
+
+/// The upstream registry of the proxy
+#[derive(Debug, Clone)]
+pub struct ProxyUpstream {
+    /// The URI of the sparse index, with a trailing `/`
+    pub index_uri: String,
+    /// The prefix URI to download crates
+    pub download_uri: String,
+    /// The URI of the web API, without a trailing `/`
+    pub api_uri: String,
+}
+
+impl Default for ProxyUpstream {
+    fn default() -> Self {
+        Self {
+            index_uri: CRATES_IO_INDEX_SPARSE_URI.to_string(),
+            download_uri: CRATES_IO_DOWNLOAD_URI.to_string(),
+            api_uri: CRATES_IO_API_URI.to_string(),
+        }
+    }
+}
+
+/// Data for the crates.io proxy service
+#[derive(Debug, Clone, Default)]
+struct CratesIoProxyData {
+    /// The last time an index file was fetched from upstream
+    last_touch: HashMap<String, Instant>,
+    /// The names of the most downloaded crates, when loaded
+    popular_crates: Option<Vec<String>>,
+}
+
+/// A page of crates returned by the web API of crates.io
+#[derive(Debug, Deserialize)]
+struct CratesPage {
+    /// The crates in the page
+    crates: Vec<CratesPageEntry>,
+    /// The metadata for the pagination
+    meta: CratesPageMeta,
+}
+
+/// A crate in a page returned by the web API of crates.io
+#[derive(Debug, Deserialize)]
+struct CratesPageEntry {
+    /// The name of the crate
+    name: String,
+}
+
+/// The metadata for the pagination of crates returned by the web API of crates.io
+#[derive(Debug, Deserialize)]
+struct CratesPageMeta {
+    /// The query for the next page, if any
+    next_page: Option<String>,
+}
+
+/// Service for the pull-through caching proxy to crates.io
+struct CratesIoProxyImpl {
+    /// The data for the service
+    data: Mutex<CratesIoProxyData>,
+    /// The app configuration
+    configuration: Arc<Configuration>,
+    /// The storage layer
+    service_storage: Arc<dyn Storage + Send + Sync>,
+    /// The HTTP client to fetch from upstream, shared with the other services
+    client: reqwest::Client,
+    /// The upstream registry
+    upstream: ProxyUpstream,
+}
+
+/// The prefix URI to download crates from crates.io
+const CRATES_IO_DOWNLOAD_URI: &str = "https://static.crates.io/crates";
+/// The URI of the web API of crates.io
+const CRATES_IO_API_URI: &str = "https://crates.io/api/v1";
+/// The maximum number of crates in a page from the web API
+const API_PAGE_SIZE: usize = 100;
+/// The delay between two requests to the web API, as required by the crawler policy of crates.io
+const API_REQUEST_DELAY: Duration = Duration::from_secs(1);
+/// Name of the sub-directory to use within the data directory
+const DATA_SUB_DIR: &str = "proxy";
+/// Name of the configuration file for the sparse index
+const INDEX_CONFIG_FILE: &str = "config.json";
+/// Name of the file for the cached names of the most downloaded crates
+const POPULAR_CRATES_FILE: &str = "popular-crates.txt";
+
+impl CratesIoProxy for CratesIoProxyImpl {
+    fn get_index_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        Box::pin(async move { self.do_get_index_file(path).await })
+    }
+
+    fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        Box::pin(async move { self.do_download_crate(name, version).await })
+    }
+
+    fn get_popular_crates(&self) -> FaillibleFuture<'_, Vec<String>> {
+        Box::pin(async move { self.do_get_popular_crates().await })
+    }
+
+    fn refresh_popular_crates(&self, count: usize) -> FaillibleFuture<'_, Vec<String>> {
+        Box::pin(async move { self.do_refresh_popular_crates(count).await })
+    }
+}
+
+impl CratesIoProxyImpl {
+    /// Gets the content of a file in the mirrored crates.io sparse index
+    async fn do_get_index_file(&self, path: &str) -> Result<Vec<u8>, ApiError> {
+        if path == INDEX_CONFIG_FILE {
+            return self.get_index_config();
+        }
+        let name = parse_package_index_path(path).ok_or_else(error_not_found)?;
+        self.get_index_file_for(name).await
+    }
+
+    /// Gets the configuration for the mirrored index
+    fn get_index_config(&self) -> Result<Vec<u8>, ApiError> {
+        let config = IndexPublicConfig {
+            dl: format!("{}/crates-io/api/v1/crates", self.configuration.web_public_uri),
+            api: self.configuration.web_public_uri.clone(),
+            auth_required: self.configuration.index.public.auth_required,
+        };
+        Ok(serde_json::to_vec(&config)?)
+    }
+
+    /// Gets the index file for a crate, fetching it from upstream when the cached copy is missing or stale
+    async fn get_index_file_for(&self, name: &str) -> Result<Vec<u8>, ApiError> {
+        let lowercase = name.to_ascii_lowercase();
+        let file_path = build_package_file_path(self.get_cache_location(), &lowercase);
+        let is_cached = tokio::fs::try_exists(&file_path).await?;
+        if is_cached {
+            let last_touch = self
+                .data
+                .lock()
+                .await
+                .last_touch
+                .get(&lowercase)
+                .copied()
+                .unwrap_or_else(stale_instant);
+            let is_stale =
+                Instant::now().duration_since(last_touch) > Duration::from_millis(self.configuration.proxy_stale_index);
+            if !is_stale {
+                return Ok(tokio::fs::read(&file_path).await?);
+            }
+        }
+
+        let target_uri = format!("{}{}", self.upstream.index_uri, build_package_index_path(&lowercase));
+        match self.fetch(&target_uri).await {
+            Ok(Some(content)) => {
+                write_file_atomic(&file_path, &content).await?;
+                self.data.lock().await.last_touch.insert(lowercase, Instant::now());
+                Ok(content)
+            }
+            Ok(None) => Err(specialize(
+                error_not_found(),
+                format!("crate {name} is not available on crates.io"),
+            )),
+            Err(error) if is_cached => {
+                // crates.io is unreachable, fallback to the cached copy
+                warn!("failed to refresh index file for {name} from crates.io, using cached copy: {error}");
+                Ok(tokio::fs::read(&file_path).await?)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Gets the content of a crate from crates.io, going through the local cache
+    async fn do_download_crate(&self, name: &str, version: &str) -> Result<Vec<u8>, ApiError> {
+        if !is_valid_crate_name(name) || version.parse::<semver::Version>().is_err() {
+            return Err(error_invalid_request());
+        }
+        if let Some(content) = self
+            .service_storage
+            .download_proxied_crate(CRATES_IO_NAME, name, version)
+            .await?
+        {
+            return Ok(content);
+        }
+
+        // find the expected checksum in the index
+        let index_content = self.get_index_file_for(name).await?;
+        let mut entry = None;
+        for line in index_content.split(|&c| c == b'\n').filter(|line| !line.is_empty()) {
+            let metadata: IndexCrateMetadata = serde_json::from_slice(line)?;
+            if metadata.vers == version {
+                entry = Some(metadata);
+                break;
+            }
+        }
+        let entry = entry.ok_or_else(error_not_found)?;
+
+        let target_uri = format!("{0}/{1}/{1}-{2}.crate", self.upstream.download_uri, entry.name, entry.vers);
+        let content = self.fetch(&target_uri).await?.ok_or_else(error_not_found)?;
+        if sha256(&content) != entry.cksum {
+            return Err(specialize(
+                error_backend_failure(),
+                format!("checksum mismatch for crate {name} {version} downloaded from crates.io"),
+            ));
+        }
+        self.service_storage
+            .store_proxied_crate(CRATES_IO_NAME, name, version, content.clone())
+            .await?;
+        Ok(content)
+    }
+
+    /// Gets the names of the most downloaded crates, loading them from the local cache the first time
+    async fn do_get_popular_crates(&self) -> Result<Vec<String>, ApiError> {
+        let mut data = self.data.lock().await;
+        if let Some(names) = &data.popular_crates {
+            return Ok(names.clone());
+        }
+        let mut file_path = self.get_cache_location();
+        file_path.push(POPULAR_CRATES_FILE);
+        let names = if tokio::fs::try_exists(&file_path).await? {
+            parse_popular_names(&tokio::fs::read_to_string(&file_path).await?)
+        } else {
+            Vec::new()
+        };
+        data.popular_crates = Some(names.clone());
+        drop(data);
+        Ok(names)
+    }
+
+    /// Fetches the names of the most downloaded crates by going through the pages of the web API
+    async fn do_refresh_popular_crates(&self, count: usize) -> Result<Vec<String>, ApiError> {
+        let mut names = Vec::with_capacity(count);
+        let mut query = format!("?sort=downloads&per_page={}", count.min(API_PAGE_SIZE));
+        while names.len() < count {
+            let target_uri = format!("{}/crates{query}", self.upstream.api_uri);
+            let content = self.fetch(&target_uri).await?.ok_or_else(error_not_found)?;
+            let page: CratesPage = serde_json::from_slice(&content)?;
+            if page.crates.is_empty() {
+                break;
+            }
+            names.extend(page.crates.into_iter().map(|entry| entry.name));
+            match page.meta.next_page {
+                Some(next_page) if names.len() < count => {
+                    query = next_page;
+                    tokio::time::sleep(API_REQUEST_DELAY).await;
+                }
+                _ => break,
+            }
+        }
+        names.truncate(count);
+        let mut file_path = self.get_cache_location();
+        file_path.push(POPULAR_CRATES_FILE);
+        write_file_atomic(&file_path, names.join("\n").as_bytes()).await?;
+        self.data.lock().await.popular_crates = Some(names.clone());
+        Ok(names)
+    }
+
+    /// Gets the location of the local cache for the index files
+    fn get_cache_location(&self) -> PathBuf {
+        let mut location = PathBuf::from(&self.configuration.data_dir);
+        location.push(DATA_SUB_DIR);
+        location.push(CRATES_IO_NAME);
+        location
+    }
+
+    /// Fetches a resource from upstream
+    /// Returns `None` when the resource does not exist
+    async fn fetch(&self, target_uri: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        let response = self.client.get(target_uri).send().await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND || status == StatusCode::GONE || status == StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+        {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(specialize(
+                error_backend_failure(),
+                format!("failed to fetch {target_uri}: error code {}", status.as_u16()),
+            ));
+        }
+        Ok(Some(response.bytes().await?.to_vec()))
+    }
+}
+
+/// Writes a file by going through a temporary file so that concurrent readers never see partial content
+async fn write_file_atomic(file_path: &std::path::Path, content: &[u8]) -> Result<(), ApiError> {
+    tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
+    let temp_path = file_path.with_extension(format!("{}.tmp", generate_hex_secret()));
+    tokio::fs::write(&temp_path, content).await?;
+    tokio::fs::rename(&temp_path, file_path).await?;
+    Ok(())
+}
+
+/// Gets whether a crate name is acceptable
+fn is_valid_crate_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
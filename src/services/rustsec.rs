@@ -2,51 +2,97 @@
  * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
  ******************************************************************************/
 
-//! Service to fetch data about advisories against Rust crates on crates.io
+//! Service to fetch data about advisories against Rust crates on crates.io and against the local crates
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use chrono::Local;
+use flate2::bufread::GzDecoder;
 use futures::StreamExt;
 use futures::lock::Mutex;
 use log::error;
 use semver::Version;
-use tokio_stream::wrappers::ReadDirStream;
+use tar::Archive;
 
-use crate::model::config::Configuration;
+use crate::model::advisories::{AdvisoryDbStatus, InternalAdvisory};
+use crate::model::config::{AdvisoryDbSource, Configuration};
 use crate::model::osv::{Advisory, SimpleAdvisory};
-use crate::utils::apierror::ApiError;
+use crate::services::database::db_transaction_read;
+use crate::utils::apierror::{ApiError, error_conflict, error_invalid_request, error_not_found, specialize};
 use crate::utils::concurrent::n_at_a_time_stream;
+use crate::utils::db::RwSqlitePool;
 use crate::utils::{FaillibleFuture, stale_instant};
 
 /// Service to use the [RustSec](https://github.com/rustsec) data about crates
+/// and the advisories authored in this registry against the local crates
 pub trait RustSecChecker {
     /// Gets the advisories against a crate
-    fn check_crate<'a>(&'a self, package: &'a str, version: &'a Version) -> FaillibleFuture<'a, Vec<SimpleAdvisory>>;
+    /// The registry is `None` for local crates, which are checked against the internal advisories.
+    fn check_crate<'a>(
+        &'a self,
+        registry: Option<&'a str>,
+        package: &'a str,
+        version: &'a Version,
+    ) -> FaillibleFuture<'a, Vec<SimpleAdvisory>>;
+
+    /// Gets an advisory by its identifier
+    fn get_advisory<'a>(&'a self, id: &'a str) -> FaillibleFuture<'a, Option<SimpleAdvisory>>;
+
+    /// Gets the status of the advisories against the crates on crates.io
+    fn get_db_status(&self) -> FaillibleFuture<'_, AdvisoryDbStatus>;
+
+    /// Replaces the advisories against the crates on crates.io with the content of a `.tar.gz` archive of OSV files
+    fn upload_db<'a>(&'a self, content: &'a [u8]) -> FaillibleFuture<'a, AdvisoryDbStatus>;
 }
 
 /// Gets the rustsec service
 #[must_use]
-pub fn get_service(config: &Configuration) -> Arc<dyn RustSecChecker + Send + Sync> {
+pub fn get_service(config: &Configuration, service_db_pool: RwSqlitePool) -> Arc<dyn RustSecChecker + Send + Sync> {
     Arc::new(RustSecCheckerImpl {
-        data: Mutex::new(RustSecData::new(config.data_dir.clone(), config.deps_stale_registry)),
+        service_db_pool,
+        data: Mutex::new(RustSecData::new(
+            config.data_dir.clone(),
+            config.deps_stale_registry,
+            config.deps_advisory_db.clone(),
+        )),
     })
 }
 
 struct RustSecCheckerImpl {
+    /// The connection pool, for the internal advisories
+    service_db_pool: RwSqlitePool,
     /// The data for the service
     data: Mutex<RustSecData>,
 }
 
 impl RustSecChecker for RustSecCheckerImpl {
     /// Gets the advisories against a crate
-    fn check_crate<'a>(&'a self, package: &'a str, version: &'a Version) -> FaillibleFuture<'a, Vec<SimpleAdvisory>> {
+    /// The registry is `None` for local crates, which are checked against the internal advisories.
+    fn check_crate<'a>(
+        &'a self,
+        registry: Option<&'a str>,
+        package: &'a str,
+        version: &'a Version,
+    ) -> FaillibleFuture<'a, Vec<SimpleAdvisory>> {
         #[expect(clippy::significant_drop_tightening)]
         Box::pin(async move {
+            if registry.is_none() {
+                let advisories = db_transaction_read(&self.service_db_pool, |database| async move {
+                    database.get_crate_advisories(package).await
+                })
+                .await?;
+                return Ok(advisories
+                    .iter()
+                    .filter(|advisory| advisory.withdrawn.is_none())
+                    .map(InternalAdvisory::to_simple)
+                    .filter(|advisory| advisory.affects(version))
+                    .collect());
+            }
             let mut data = self.data.lock().await;
-            data.update_data().await?;
+            data.update_data().await;
             let db = data.db.lock().unwrap();
             Ok(db
                 .get(package)
@@ -60,6 +106,31 @@ impl RustSecChecker for RustSecCheckerImpl {
                 .unwrap_or_default())
         })
     }
+
+    /// Gets an advisory by its identifier
+    fn get_advisory<'a>(&'a self, id: &'a str) -> FaillibleFuture<'a, Option<SimpleAdvisory>> {
+        #[expect(clippy::significant_drop_tightening)]
+        Box::pin(async move {
+            let mut data = self.data.lock().await;
+            data.update_data().await;
+            let db = data.db.lock().unwrap();
+            Ok(db.values().flatten().find(|advisory| advisory.id == id).cloned())
+        })
+    }
+
+    /// Gets the status of the advisories against the crates on crates.io
+    fn get_db_status(&self) -> FaillibleFuture<'_, AdvisoryDbStatus> {
+        Box::pin(async move { Ok(self.data.lock().await.status.clone()) })
+    }
+
+    /// Replaces the advisories against the crates on crates.io with the content of a `.tar.gz` archive of OSV files
+    fn upload_db<'a>(&'a self, content: &'a [u8]) -> FaillibleFuture<'a, AdvisoryDbStatus> {
+        Box::pin(async move {
+            let mut data = self.data.lock().await;
+            data.upload(content).await?;
+            Ok(data.status.clone())
+        })
+    }
 }
 
 /// Service to use the [RustSec](https://github.com/rustsec) data about crates
@@ -69,79 +140,213 @@ struct RustSecData {
     data_dir: String,
     /// Number of milliseconds after which the local data about an external registry are deemed stale and must be pulled again
     stale_registry: u64,
+    /// The source of the advisories
+    source: AdvisoryDbSource,
     /// The last time the data was updated
     last_touch: Instant,
     /// The known advisories
     db: Arc<std::sync::Mutex<HashMap<String, Vec<SimpleAdvisory>>>>,
+    /// The status of the advisories
+    status: AdvisoryDbStatus,
 }
 
-/// The URI of the git repo with the `RustSec` database
-const RUSTSEC_DB_GIT_URI: &str = "https://github.com/rustsec/advisory-db";
-/// The branch inside the repo with the actual data
-const RUSTSEC_DB_GIT_BRANCH: &str = "osv";
-/// Name of the sub-directory to use within the data directory
+/// Name of the sub-directory to use within the data directory for a git repository
 const DATA_SUB_DIR: &str = "rustsec";
+/// Name of the sub-directory to use within the data directory for an uploaded archive
+const UPLOAD_SUB_DIR: &str = "rustsec-upload";
+/// Name of the sub-directory to use within the data directory when unpacking an uploaded archive
+const UPLOAD_STAGING_SUB_DIR: &str = "rustsec-upload-staging";
 
 impl RustSecData {
-    fn new(data_dir: String, stale_registry: u64) -> Self {
+    fn new(data_dir: String, stale_registry: u64, source: AdvisoryDbSource) -> Self {
         Self {
             data_dir,
             stale_registry,
+            status: AdvisoryDbStatus {
+                source: source.to_string(),
+                is_upload: source == AdvisoryDbSource::Upload,
+                ..Default::default()
+            },
+            source,
             last_touch: stale_instant(),
             db: Arc::new(std::sync::Mutex::new(HashMap::new())),
         }
     }
 
+    /// Gets the location of the advisories on the file system
+    fn get_location(&self) -> PathBuf {
+        match &self.source {
+            AdvisoryDbSource::Git { .. } => PathBuf::from(&self.data_dir).join(DATA_SUB_DIR),
+            AdvisoryDbSource::Local { path } => PathBuf::from(path),
+            AdvisoryDbSource::Upload => PathBuf::from(&self.data_dir).join(UPLOAD_SUB_DIR),
+        }
+    }
+
     /// Updates the data
-    async fn update_data(&mut self) -> Result<(), ApiError> {
+    /// A failed update is recorded in the status and the previous data are kept.
+    async fn update_data(&mut self) {
         let now = Instant::now();
         let is_stale = now.duration_since(self.last_touch) > Duration::from_millis(self.stale_registry);
-        let mut reg_location = PathBuf::from(&self.data_dir);
-        reg_location.push(DATA_SUB_DIR);
-        if is_stale {
-            if tokio::fs::try_exists(&reg_location).await? {
-                crate::utils::execute_git(&reg_location, &["fetch", "origin", RUSTSEC_DB_GIT_BRANCH]).await?;
-                crate::utils::execute_git(
-                    &reg_location,
-                    &["reset", "--hard", &format!("origin/{RUSTSEC_DB_GIT_BRANCH}")],
-                )
-                .await?;
-            } else {
-                tokio::fs::create_dir_all(&reg_location).await?;
-                crate::utils::execute_git(
-                    &reg_location,
-                    &["clone", "--branch", RUSTSEC_DB_GIT_BRANCH, RUSTSEC_DB_GIT_URI, "."],
-                )
-                .await?;
+        // uploaded data only change on upload
+        let is_stale = is_stale && (self.source != AdvisoryDbSource::Upload || self.status.last_update.is_none());
+        if !is_stale {
+            return;
+        }
+        self.last_touch = now;
+        let location = self.get_location();
+        let result = match self.fetch(&location).await {
+            Ok(()) => self.load(&location).await,
+            Err(error) => {
+                // fall back to the data from a previous run, if any
+                if self.status.advisories == 0
+                    && tokio::fs::try_exists(&location).await.unwrap_or(false)
+                    && let Err(e) = self.load(&location).await
+                {
+                    error!("{e}");
+                }
+                Err(error)
+            }
+        };
+        self.on_updated(result);
+    }
+
+    /// Fetches the advisories from the source
+    async fn fetch(&self, location: &Path) -> Result<(), ApiError> {
+        match &self.source {
+            AdvisoryDbSource::Git { uri, branch } => {
+                if tokio::fs::try_exists(location.join(".git")).await? {
+                    crate::utils::execute_git(location, &["remote", "set-url", "origin", uri]).await?;
+                    crate::utils::execute_git(location, &["fetch", "origin", branch]).await?;
+                    crate::utils::execute_git(location, &["reset", "--hard", &format!("origin/{branch}")]).await?;
+                } else {
+                    tokio::fs::create_dir_all(location).await?;
+                    crate::utils::execute_git(location, &["clone", "--branch", branch, uri, "."]).await?;
+                }
+            }
+            AdvisoryDbSource::Local { .. } | AdvisoryDbSource::Upload => {
+                if !tokio::fs::try_exists(location).await? {
+                    return Err(specialize(
+                        error_not_found(),
+                        format!("no advisory database at {}", location.display()),
+                    ));
+                }
             }
-            self.last_touch = Instant::now();
-            reg_location.push("crates");
-            self.db.lock().unwrap().clear();
-            let _results = n_at_a_time_stream(
-                ReadDirStream::new(tokio::fs::read_dir(&reg_location).await?).map(|entry| {
-                    let db = self.db.clone();
-                    Box::pin(async move {
-                        let content = tokio::fs::read(&entry?.path()).await?;
-                        let advisory = serde_json::from_slice::<Advisory>(&content)?;
-                        if let Ok(simple) = SimpleAdvisory::try_from(advisory) {
-                            db.lock().unwrap().entry(simple.package.clone()).or_default().push(simple);
-                        }
-                        Ok::<_, ApiError>(())
-                    })
-                }),
-                10,
-                |r| {
-                    if let Err(e) = r {
-                        error!("{e}");
-                        if let Some(backtrace) = &e.backtrace {
-                            error!("{backtrace}");
-                        }
-                    }
-                    false
-                },
-            )
-            .await;
         }
         Ok(())
     }
+
+    /// Loads the advisories from the files in the OSV format at a location, replacing the current ones
+    async fn load(&mut self, location: &Path) -> Result<(), ApiError> {
+        let files = find_osv_files(location).await?;
+        let results = n_at_a_time_stream(
+            futures::stream::iter(files).map(|path| {
+                Box::pin(async move {
+                    let content = tokio::fs::read(&path).await?;
+                    let advisory = serde_json::from_slice::<Advisory>(&content)?;
+                    Ok::<_, ApiError>(SimpleAdvisory::try_from(advisory).ok())
+                })
+            }),
+            10,
+            |_| false,
+        )
+        .await;
+        let mut db = HashMap::<String, Vec<SimpleAdvisory>>::new();
+        let mut advisories = 0;
+        let mut invalid_files = 0;
+        for result in results {
+            match result {
+                Ok(Some(simple)) => {
+                    advisories += 1;
+                    db.entry(simple.package.clone()).or_default().push(simple);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    invalid_files += 1;
+                    error!("{e}");
+                }
+            }
+        }
+        *self.db.lock().unwrap() = db;
+        self.status.advisories = advisories;
+        self.status.invalid_files = invalid_files;
+        Ok(())
+    }
+
+    /// Records the result of an update in the status
+    fn on_updated(&mut self, result: Result<(), ApiError>) {
+        let now = Local::now().naive_local();
+        self.status.last_update = Some(now);
+        match result {
+            Ok(()) => {
+                self.status.last_success = Some(now);
+            }
+            Err(e) => {
+                error!("failed to update the advisory database from {}: {e}", self.source);
+                if let Some(backtrace) = &e.backtrace {
+                    error!("{backtrace}");
+                }
+                self.status.last_failure = Some(now);
+                self.status.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Replaces the advisories with the content of an uploaded archive
+    async fn upload(&mut self, content: &[u8]) -> Result<(), ApiError> {
+        if self.source != AdvisoryDbSource::Upload {
+            return Err(specialize(
+                error_conflict(),
+                format!("the advisory database is configured to use the {}", self.source),
+            ));
+        }
+        let staging = PathBuf::from(&self.data_dir).join(UPLOAD_STAGING_SUB_DIR);
+        if tokio::fs::try_exists(&staging).await? {
+            tokio::fs::remove_dir_all(&staging).await?;
+        }
+        tokio::fs::create_dir_all(&staging).await?;
+        let unpacked = Archive::new(GzDecoder::new(content)).unpack(&staging);
+        // check the content before replacing the current data
+        let found = match unpacked {
+            Ok(()) => find_osv_files(&staging).await.map(|files| !files.is_empty()),
+            Err(e) => Err(specialize(error_invalid_request(), format!("invalid archive: {e}"))),
+        };
+        if !matches!(found, Ok(true)) {
+            tokio::fs::remove_dir_all(&staging).await?;
+            found?;
+            return Err(specialize(
+                error_invalid_request(),
+                String::from("no advisory found in the archive"),
+            ));
+        }
+        let location = self.get_location();
+        if tokio::fs::try_exists(&location).await? {
+            tokio::fs::remove_dir_all(&location).await?;
+        }
+        tokio::fs::rename(&staging, &location).await?;
+        self.last_touch = Instant::now();
+        let result = self.load(&location).await;
+        self.on_updated(result.clone());
+        result
+    }
+}
+
+/// Finds the files for advisories in the OSV format in a directory and its sub-directories, ignoring hidden ones
+async fn find_osv_files(location: &Path) -> Result<Vec<PathBuf>, ApiError> {
+    let mut files = Vec::new();
+    let mut to_visit = vec![location.to_path_buf()];
+    while let Some(directory) = to_visit.pop() {
+        let mut entries = tokio::fs::read_dir(&directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                to_visit.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "json") {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
 }
@@ -13,15 +13,16 @@ use opendal::layers::{LoggingLayer, RetryLayer};
 use opendal::{ErrorKind, Operator};
 use tar::Archive;
 
-use crate::model::cargo::CrateMetadata;
+use crate::model::cargo::{CargoVcsInfo, CrateMetadata, PACKAGED_FILE_MAX_SIZE};
 use crate::model::config::{Configuration, RetryParams, StorageConfig};
+use crate::model::sbom::SbomFormat;
 use crate::utils::FaillibleFuture;
 use crate::utils::apierror::ApiError;
 
 /// Backing storage implementations
 pub trait Storage {
-    /// Stores the data for a crate
-    fn store_crate<'a>(&'a self, metadata: &'a CrateMetadata, content: Vec<u8>) -> FaillibleFuture<'a, ()>;
+    /// Stores the data for a crate, with its README as extracted by `extract_crate_files`
+    fn store_crate<'a>(&'a self, metadata: &'a CrateMetadata, content: Vec<u8>, readme: Vec<u8>) -> FaillibleFuture<'a, ()>;
 
     /// Downloads a crate
     fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
@@ -32,6 +33,23 @@ pub trait Storage {
     /// Downloads the last README for a crate
     fn download_crate_readme<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
 
+    /// Stores a SBOM document for a crate
+    fn store_crate_sbom<'a>(
+        &'a self,
+        name: &'a str,
+        version: &'a str,
+        format: SbomFormat,
+        content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()>;
+
+    /// Downloads a SBOM document for a crate, if it exists
+    fn download_crate_sbom<'a>(
+        &'a self,
+        name: &'a str,
+        version: &'a str,
+        format: SbomFormat,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>>;
+
     /// Stores a documentation file
     fn store_doc_file<'a>(&'a self, path: &'a str, file: &'a Path) -> FaillibleFuture<'a, ()>;
 
@@ -40,6 +58,41 @@ pub trait Storage {
 
     /// Gets the content of a documentation file
     fn download_doc_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Vec<u8>>;
+
+    /// Stores a file of the index
+    fn store_index_file<'a>(&'a self, path: &'a str, content: Vec<u8>) -> FaillibleFuture<'a, ()>;
+
+    /// Gets the content of a file of the index, if it exists
+    fn download_index_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>>;
+
+    /// Gets the content of a file of the index, if it exists, with its version for a later conditional write
+    fn download_index_file_versioned<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, (Option<Vec<u8>>, FileVersion)>;
+
+    /// Stores a file of the index only if it was not modified since its version was read
+    /// Returns `false` when the file was modified in the meantime, by another instance of the registry.
+    fn store_index_file_if_unmodified<'a>(
+        &'a self,
+        path: &'a str,
+        content: Vec<u8>,
+        version: &'a FileVersion,
+    ) -> FaillibleFuture<'a, bool>;
+
+    /// Stores the content of a crate proxied from an external registry
+    fn store_proxied_crate<'a>(
+        &'a self,
+        registry: &'a str,
+        name: &'a str,
+        version: &'a str,
+        content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()>;
+
+    /// Downloads the content of a crate proxied from an external registry, if it is cached
+    fn download_proxied_crate<'a>(
+        &'a self,
+        registry: &'a str,
+        name: &'a str,
+        version: &'a str,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>>;
 }
 
 /// Gets the backing storage for the documentation
@@ -107,8 +160,8 @@ impl TryFrom<&Configuration> for StorageImpl {
 }
 
 impl Storage for StorageImpl {
-    fn store_crate<'a>(&'a self, metadata: &'a CrateMetadata, content: Vec<u8>) -> FaillibleFuture<'a, ()> {
-        Box::pin(async move { self.store_crate(metadata, content).await })
+    fn store_crate<'a>(&'a self, metadata: &'a CrateMetadata, content: Vec<u8>, readme: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.store_crate(metadata, content, readme).await })
     }
 
     fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
@@ -123,6 +176,25 @@ impl Storage for StorageImpl {
         Box::pin(async move { self.download_crate_readme(name, version).await })
     }
 
+    fn store_crate_sbom<'a>(
+        &'a self,
+        name: &'a str,
+        version: &'a str,
+        format: SbomFormat,
+        content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.store_crate_sbom(name, version, format, content).await })
+    }
+
+    fn download_crate_sbom<'a>(
+        &'a self,
+        name: &'a str,
+        version: &'a str,
+        format: SbomFormat,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { self.download_crate_sbom(name, version, format).await })
+    }
+
     fn store_doc_file<'a>(&'a self, path: &'a str, file: &'a Path) -> FaillibleFuture<'a, ()> {
         Box::pin(async move { self.store_doc_file(path, file).await })
     }
@@ -134,12 +206,51 @@ impl Storage for StorageImpl {
     fn download_doc_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
         Box::pin(async move { self.download_doc_file(path).await })
     }
+
+    fn store_index_file<'a>(&'a self, path: &'a str, content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.store_index_file(path, content).await })
+    }
+
+    fn download_index_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { self.download_index_file(path).await })
+    }
+
+    fn download_index_file_versioned<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, (Option<Vec<u8>>, FileVersion)> {
+        Box::pin(async move { self.download_index_file_versioned(path).await })
+    }
+
+    fn store_index_file_if_unmodified<'a>(
+        &'a self,
+        path: &'a str,
+        content: Vec<u8>,
+        version: &'a FileVersion,
+    ) -> FaillibleFuture<'a, bool> {
+        Box::pin(async move { self.store_index_file_if_unmodified(path, content, version).await })
+    }
+
+    fn store_proxied_crate<'a>(
+        &'a self,
+        registry: &'a str,
+        name: &'a str,
+        version: &'a str,
+        content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()> {
+        Box::pin(async move { self.store_proxied_crate(registry, name, version, content).await })
+    }
+
+    fn download_proxied_crate<'a>(
+        &'a self,
+        registry: &'a str,
+        name: &'a str,
+        version: &'a str,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { self.download_proxied_crate(registry, name, version).await })
+    }
 }
 
 impl StorageImpl {
     /// Stores the data for a crate
-    async fn store_crate(&self, metadata: &CrateMetadata, content: Vec<u8>) -> Result<(), ApiError> {
-        let readme = extract_readme(&content)?;
+    async fn store_crate(&self, metadata: &CrateMetadata, content: Vec<u8>, readme: Vec<u8>) -> Result<(), ApiError> {
         let metadata_json = serde_json::to_vec(metadata)?;
         let name = &metadata.name;
         let version = &metadata.vers;
@@ -204,6 +315,98 @@ impl StorageImpl {
         self.read_from_file(&format!("docs/{path}")).await.map_err(ApiError::from)
     }
 
+    /// Stores a file of the index
+    async fn store_index_file(&self, path: &str, content: Vec<u8>) -> Result<(), ApiError> {
+        self.write_to_file(&format!("sparse-index/{path}"), content).await
+    }
+
+    /// Gets the content of a file of the index, if it exists
+    async fn download_index_file(&self, path: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        match self.read_from_file(&format!("sparse-index/{path}")).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Gets the content of a file of the index, if it exists, with its version for a later conditional write
+    async fn download_index_file_versioned(&self, path: &str) -> Result<(Option<Vec<u8>>, FileVersion), ApiError> {
+        let path = format!("sparse-index/{path}");
+        let capability = self.opendal_operator.info().capability();
+        loop {
+            let metadata = match self.opendal_operator.stat(&path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok((None, FileVersion::Missing)),
+                Err(e) => return Err(e.into()),
+            };
+            let Some(etag) = metadata
+                .etag()
+                .filter(|_| capability.read_with_if_match && capability.write_with_if_match)
+            else {
+                // conditional updates are not supported, e.g. on the file system
+                return Ok((Some(self.read_from_file(&path).await?), FileVersion::Untagged));
+            };
+            match self.opendal_operator.read_with(&path).if_match(etag).await {
+                Ok(buffer) => return Ok((Some(buffer.to_vec()), FileVersion::ETag(etag.to_string()))),
+                // modified or removed since the stat, try again
+                Err(e) if matches!(e.kind(), ErrorKind::ConditionNotMatch | ErrorKind::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Stores a file of the index only if it was not modified since its version was read
+    async fn store_index_file_if_unmodified(
+        &self,
+        path: &str,
+        content: Vec<u8>,
+        version: &FileVersion,
+    ) -> Result<bool, ApiError> {
+        let path = format!("sparse-index/{path}");
+        let capability = self.opendal_operator.info().capability();
+        let result = match version {
+            FileVersion::Missing if capability.write_with_if_not_exists => {
+                self.opendal_operator.write_with(&path, content).if_not_exists(true).await
+            }
+            FileVersion::ETag(etag) => self.opendal_operator.write_with(&path, content).if_match(etag).await,
+            FileVersion::Missing | FileVersion::Untagged => self.opendal_operator.write(&path, content).await,
+        };
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::ConditionNotMatch => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores a SBOM document for a crate
+    async fn store_crate_sbom(&self, name: &str, version: &str, format: SbomFormat, content: Vec<u8>) -> Result<(), ApiError> {
+        self.write_to_file(&Self::sbom_path(name, version, format), content).await
+    }
+
+    /// Downloads a SBOM document for a crate, if it exists
+    async fn download_crate_sbom(&self, name: &str, version: &str, format: SbomFormat) -> Result<Option<Vec<u8>>, ApiError> {
+        match self.read_from_file(&Self::sbom_path(name, version, format)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores the content of a crate proxied from an external registry
+    async fn store_proxied_crate(&self, registry: &str, name: &str, version: &str, content: Vec<u8>) -> Result<(), ApiError> {
+        self.write_to_file(&Self::proxied_path(registry, name, version), content)
+            .await
+    }
+
+    /// Downloads the content of a crate proxied from an external registry, if it is cached
+    async fn download_proxied_crate(&self, registry: &str, name: &str, version: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        match self.read_from_file(&Self::proxied_path(registry, name, version)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Write to a file
     async fn write_to_file(&self, path: &str, content: Vec<u8>) -> Result<(), ApiError> {
         self.opendal_operator.write(path, content).await?;
@@ -231,27 +434,67 @@ impl StorageImpl {
     fn readme_path(name: &str, version: &str) -> String {
         Self::crate_file_key(name, version, "readme")
     }
+
+    fn sbom_path(name: &str, version: &str, format: SbomFormat) -> String {
+        Self::crate_file_key(name, version, &format!("sbom-{format}"))
+    }
+
+    fn proxied_path(registry: &str, name: &str, version: &str) -> String {
+        // crate names are case-insensitive, like in the index
+        format!("proxy/{registry}/{}/{version}", name.to_ascii_lowercase())
+    }
+}
+
+/// The version of a stored file, as read before a conditional write
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum FileVersion {
+    /// The file does not exist
+    #[default]
+    Missing,
+    /// The file exists, with this entity tag
+    ETag(String),
+    /// The file exists, but the backend does not support conditional updates
+    Untagged,
 }
 
-/// Extract the content of the README from the
-pub fn extract_readme(crate_content: &[u8]) -> Result<Vec<u8>, ApiError> {
+/// The files extracted from a packaged crate when it is published
+#[derive(Debug, Default)]
+pub struct ExtractedFiles {
+    /// The content of the README, empty when there is none
+    pub readme: Vec<u8>,
+    /// The VCS information embedded by cargo in `.cargo_vcs_info.json`, if any
+    pub vcs_info: Option<CargoVcsInfo>,
+}
+
+/// Extracts the content of the README and the VCS information from a packaged crate
+/// Both are read in a single pass, so that the archive is only decompressed once.
+pub fn extract_crate_files(crate_content: &[u8]) -> Result<ExtractedFiles, ApiError> {
     let decoder = GzDecoder::new(crate_content);
     let mut archive = Archive::new(decoder);
-    let mut buffer = Vec::new();
-
-    archive
-        .entries()?
-        .find(|entry| {
-            entry.as_ref().is_ok_and(|entry| {
-                entry.header().path().is_ok_and(|path| {
-                    path.file_name()
-                        .is_some_and(|file_name| file_name.to_string_lossy().contains("README"))
-                })
-            })
-        })
-        .transpose()?
-        .map(|mut entry| entry.read_to_end(&mut buffer))
-        .transpose()?;
-
-    Ok(buffer)
+    let mut files = ExtractedFiles::default();
+    let mut has_readme = false;
+    let mut has_vcs_info = false;
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.into_owned();
+        let Some(file_name) = path.file_name().map(|file_name| file_name.to_string_lossy().to_string()) else {
+            continue;
+        };
+        if !has_readme && file_name.contains("README") {
+            entry.take(PACKAGED_FILE_MAX_SIZE).read_to_end(&mut files.readme)?;
+            has_readme = true;
+        } else if !has_vcs_info && file_name == ".cargo_vcs_info.json" && path.components().count() == 2 {
+            // at the root of the crate, within the `<name>-<version>` directory
+            let mut buffer = Vec::new();
+            entry.take(PACKAGED_FILE_MAX_SIZE).read_to_end(&mut buffer)?;
+            files.vcs_info = serde_json::from_slice(&buffer).ok();
+            has_vcs_info = true;
+        }
+        if has_readme && has_vcs_info {
+            break;
+        }
+    }
+
+    Ok(files)
 }
@@ -0,0 +1,901 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests for the security advisories
+
+use std::path::Path;
+
+use axum::http::StatusCode;
+use chrono::{Duration, Local, NaiveDateTime};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use semver::Version;
+
+use super::{ADMIN_NAME, async_test, setup_create_token, setup_create_user};
+use crate::model::advisories::{
+    AdvisorySeverityLevel, AdvisorySuppression, AdvisorySuppressionCreation, INTERNAL_ADVISORY_PREFIX, InternalAdvisoryData,
+    cvss_score, cvss_type,
+};
+use crate::model::cargo::CrateMetadata;
+use crate::model::config::{AdvisoryDbSource, Configuration};
+use crate::model::deps::{AdvisoryImpactedVersion, CRATES_IO_REGISTRY_URI, DepAdvisory, DepResolution, DepsAnalysis};
+use crate::model::osv::{Advisory, SimpleAdvisory, SimpleAdvisoryRange};
+use crate::services::deps::{DepsChecker, resolve_crate_versions};
+use crate::services::rustsec::{RustSecChecker, get_service};
+use crate::tests::publish::{crate_metadata, crate_payload};
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::ApiError;
+use crate::utils::axum::auth::{AuthData, Token};
+use crate::utils::db::RwSqlitePool;
+
+/// Builds a resolution of a crate on crates.io
+fn resolution(package: &str, version: &str, is_direct: bool) -> DepResolution {
+    DepResolution {
+        registry: Some(String::from(CRATES_IO_REGISTRY_URI)),
+        package: package.to_string(),
+        version: version.to_string(),
+        is_direct,
+    }
+}
+
+/// Builds an advisory against a range of versions
+fn advisory(package: &str, introduced: &str, fixed: &str) -> SimpleAdvisory {
+    SimpleAdvisory {
+        package: package.to_string(),
+        id: String::from("RUSTSEC-2024-0001"),
+        published: String::from("2024-01-01T00:00:00Z"),
+        modified: String::from("2024-01-01T00:00:00Z"),
+        summary: String::from("Test advisory"),
+        ranges: vec![SimpleAdvisoryRange {
+            introduced: introduced.parse().unwrap(),
+            fixed: Some(fixed.parse().unwrap()),
+            last_affected: None,
+        }],
+        versions: Vec::new(),
+        is_internal: false,
+        aliases: Vec::new(),
+        severity: None,
+        severity_level: None,
+        informational: None,
+    }
+}
+
+#[test]
+fn test_advisory_impact() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let crates: [(CrateMetadata, Vec<DepResolution>); 4] = [
+            (
+                crate_metadata("acme-core", "1.0.0"),
+                vec![resolution("serde", "1.0.5", true), resolution("itoa", "1.0.0", false)],
+            ),
+            (crate_metadata("acme", "1.0.0"), vec![resolution("serde", "1.0.0", true)]),
+            (
+                crate_metadata("acme", "1.1.0"),
+                // the same version may resolve to multiple versions of a crate
+                vec![
+                    resolution("other", "0.1.0", true),
+                    resolution("serde", "1.0.1", false),
+                    resolution("serde", "1.0.0", false),
+                ],
+            ),
+            (
+                crate_metadata("acme-local", "0.1.0"),
+                // a local crate with the same name as the affected one
+                vec![DepResolution {
+                    registry: None,
+                    ..resolution("serde", "1.0.0", true)
+                }],
+            ),
+        ];
+        for (metadata, resolutions) in &crates {
+            application
+                .publish_crate_version(&admin_auth, &crate_payload(metadata, &[])?)
+                .await?;
+            application
+                .db_transaction_write("test_advisory_impact", |app| async move {
+                    app.database
+                        .set_crate_deps_resolutions(&metadata.name, &metadata.vers, resolutions)
+                        .await
+                })
+                .await?;
+        }
+
+        let advisory = &advisory("serde", "1.0.0", "1.0.2");
+        let summary = |impacted: &[AdvisoryImpactedVersion]| {
+            impacted
+                .iter()
+                .map(|v| {
+                    (
+                        v.package.clone(),
+                        v.version.clone(),
+                        v.resolved.clone(),
+                        v.is_direct,
+                        v.is_latest,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let impacted = application
+            .db_transaction_read(
+                |app| async move { app.database.get_advisory_impact(advisory, Some(CRATES_IO_REGISTRY_URI)).await },
+            )
+            .await?;
+        assert_eq!(
+            summary(&impacted),
+            vec![
+                (
+                    String::from("acme"),
+                    String::from("1.1.0"),
+                    String::from("1.0.1"),
+                    false,
+                    true
+                ),
+                (
+                    String::from("acme"),
+                    String::from("1.0.0"),
+                    String::from("1.0.0"),
+                    true,
+                    false
+                ),
+            ]
+        );
+
+        // yanked versions are not reported
+        application.yank_crate_version(&admin_auth, "acme", "1.1.0").await?;
+        let impacted = application
+            .db_transaction_read(
+                |app| async move { app.database.get_advisory_impact(advisory, Some(CRATES_IO_REGISTRY_URI)).await },
+            )
+            .await?;
+        assert_eq!(
+            summary(&impacted),
+            vec![(String::from("acme"), String::from("1.0.0"), String::from("1.0.0"), true, true)]
+        );
+
+        // unknown advisories
+        let error = application
+            .get_advisory_impact(&admin_auth, "RUSTSEC-0000-0000")
+            .await
+            .expect_err("the advisory is unknown");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        Ok(())
+    })
+}
+
+/// Dependency checker resolving the older versions of crates to an affected version of serde
+struct ResolvingDepsChecker;
+
+impl DepsChecker for ResolvingDepsChecker {
+    fn precache_crate_io(&self) -> FaillibleFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn check_crate<'a>(
+        &'a self,
+        _package: &'a str,
+        version: &'a str,
+        _targets: &'a [String],
+    ) -> FaillibleFuture<'a, DepsAnalysis> {
+        let serde = if version == "1.0.0" { "1.0.0" } else { "1.0.5" };
+        Box::pin(async move {
+            Ok(DepsAnalysis {
+                resolutions: vec![resolution("serde", serde, true)],
+                ..Default::default()
+            })
+        })
+    }
+
+    fn analyze_crate<'a>(
+        &'a self,
+        package: &'a str,
+        version: &'a str,
+        targets: &'a [String],
+    ) -> FaillibleFuture<'a, DepsAnalysis> {
+        self.check_crate(package, version, targets)
+    }
+}
+
+#[test]
+fn test_advisory_impact_older_versions() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        for version in ["1.0.0", "1.1.0"] {
+            application
+                .publish_crate_version(&admin_auth, &crate_payload(&crate_metadata("acme", version), &[])?)
+                .await?;
+        }
+        // the versions that were never the latest one are resolved in the background
+        let pool = RwSqlitePool::new(&application.configuration.get_database_url())?;
+        resolve_crate_versions(&pool, &ResolvingDepsChecker).await?;
+        let unresolved = application
+            .db_transaction_read(|app| async move { app.database.get_unresolved_crate_versions().await })
+            .await?;
+        assert!(unresolved.is_empty());
+
+        let advisory = &advisory("serde", "1.0.0", "1.0.2");
+        let impacted = application
+            .db_transaction_read(
+                |app| async move { app.database.get_advisory_impact(advisory, Some(CRATES_IO_REGISTRY_URI)).await },
+            )
+            .await?;
+        assert_eq!(impacted.len(), 1);
+        assert_eq!(impacted[0].package, "acme");
+        assert_eq!(impacted[0].version, "1.0.0");
+        assert!(!impacted[0].is_latest);
+
+        // reading the analysis of a version does not change the saved resolutions
+        application.check_crate_version_deps(&admin_auth, "acme", "1.0.0").await?;
+        let impacted = application
+            .db_transaction_read(
+                |app| async move { app.database.get_advisory_impact(advisory, Some(CRATES_IO_REGISTRY_URI)).await },
+            )
+            .await?;
+        assert_eq!(impacted.len(), 1);
+        Ok(())
+    })
+}
+
+/// Builds the data for an internal advisory against a range of versions
+fn advisory_data(summary: &str, introduced: &str, fixed: Option<&str>) -> InternalAdvisoryData {
+    InternalAdvisoryData {
+        summary: summary.to_string(),
+        details: String::from("Some details"),
+        severity: Some(String::from("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H")),
+        ranges: vec![SimpleAdvisoryRange {
+            introduced: introduced.parse().unwrap(),
+            fixed: fixed.map(|v| v.parse().unwrap()),
+            last_affected: None,
+        }],
+    }
+}
+
+#[test]
+fn test_cvss_type() {
+    assert_eq!(cvss_type("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some("CVSS_V3"));
+    assert_eq!(
+        cvss_type("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N"),
+        Some("CVSS_V4")
+    );
+    assert_eq!(cvss_type("CVSS:2.0/AV:N/AC:L"), None);
+    assert_eq!(cvss_type("CVSS:3.1/AV:N/AC"), None);
+    assert_eq!(cvss_type("CVSS:3.1"), None);
+    assert_eq!(cvss_type("high"), None);
+}
+
+#[test]
+fn test_cvss_score() {
+    assert_eq!(cvss_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+    assert_eq!(cvss_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:H"), Some(7.5));
+    assert_eq!(cvss_score("CVSS:3.0/AV:L/AC:L/PR:L/UI:N/S:U/C:H/I:N/A:N"), Some(5.5));
+    assert_eq!(cvss_score("CVSS:3.1/AV:N/AC:L/PR:L/UI:R/S:C/C:L/I:L/A:N"), Some(5.4));
+    assert_eq!(cvss_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H"), Some(10.0));
+    assert_eq!(cvss_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), Some(0.0));
+    assert_eq!(
+        cvss_score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N"),
+        None
+    );
+    assert_eq!(cvss_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H"), None);
+    assert_eq!(AdvisorySeverityLevel::from_score(9.8), AdvisorySeverityLevel::Critical);
+    assert_eq!(AdvisorySeverityLevel::from_score(7.0), AdvisorySeverityLevel::High);
+    assert_eq!(AdvisorySeverityLevel::from_score(5.5), AdvisorySeverityLevel::Medium);
+    assert_eq!(AdvisorySeverityLevel::from_score(0.1), AdvisorySeverityLevel::Low);
+    assert_eq!(AdvisorySeverityLevel::from_score(0.0), AdvisorySeverityLevel::None);
+    assert_eq!(AdvisorySeverityLevel::parse("High"), Some(AdvisorySeverityLevel::High));
+    assert_eq!(AdvisorySeverityLevel::parse("severe"), None);
+}
+
+#[test]
+fn test_advisory_classification() -> Result<(), ApiError> {
+    let advisory = serde_json::from_value::<Advisory>(serde_json::json!({
+        "id": "RUSTSEC-2024-0001",
+        "modified": "2024-01-01T00:00:00Z",
+        "published": "2024-01-01T00:00:00Z",
+        "aliases": ["CVE-2024-0001", "GHSA-aaaa-bbbb-cccc"],
+        "summary": "Test advisory",
+        "severity": [{ "type": "CVSS_V3", "score": "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:H" }],
+        "affected": [{
+            "package": { "ecosystem": "crates.io", "name": "serde", "purl": "pkg:cargo/serde" },
+            "ranges": [{ "type": "SEMVER", "events": [{ "introduced": "1.0.0" }, { "fixed": "1.0.2" }] }]
+        }]
+    }))?;
+    let simple = SimpleAdvisory::try_from(advisory).unwrap();
+    assert_eq!(simple.aliases, ["CVE-2024-0001", "GHSA-aaaa-bbbb-cccc"]);
+    assert_eq!(
+        simple.severity.as_deref(),
+        Some("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:H")
+    );
+    assert_eq!(simple.severity_level, Some(AdvisorySeverityLevel::High));
+    assert_eq!(simple.informational, None);
+
+    let advisory = serde_json::from_value::<Advisory>(serde_json::json!({
+        "id": "RUSTSEC-2024-0002",
+        "modified": "2024-01-01T00:00:00Z",
+        "published": "2024-01-01T00:00:00Z",
+        "summary": "serde is unmaintained",
+        "affected": [{
+            "package": { "ecosystem": "crates.io", "name": "serde", "purl": "pkg:cargo/serde" },
+            "ranges": [{ "type": "SEMVER", "events": [{ "introduced": "0.0.0-0" }] }],
+            "database_specific": { "categories": [], "cvss": null, "informational": "unmaintained" }
+        }]
+    }))?;
+    let simple = SimpleAdvisory::try_from(advisory).unwrap();
+    assert!(simple.aliases.is_empty());
+    assert_eq!(simple.severity, None);
+    assert_eq!(simple.severity_level, None);
+    assert_eq!(simple.informational.as_deref(), Some("unmaintained"));
+    Ok(())
+}
+
+#[test]
+fn test_advisory_thresholds() {
+    let dep_advisory = |id: &str, severity: Option<&str>, informational: Option<&str>| DepAdvisory {
+        package: String::from("serde"),
+        version: Version::new(1, 0, 0),
+        content: SimpleAdvisory {
+            id: id.to_string(),
+            severity: severity.map(str::to_string),
+            severity_level: severity.and_then(cvss_score).map(AdvisorySeverityLevel::from_score),
+            informational: informational.map(str::to_string),
+            ..advisory("serde", "1.0.0", "1.0.2")
+        },
+        suppression: None,
+        is_flagged: false,
+    };
+    let mut analysis = DepsAnalysis {
+        advisories: vec![
+            // critical
+            dep_advisory(
+                "RUSTSEC-2024-0001",
+                Some("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"),
+                None,
+            ),
+            // medium
+            dep_advisory(
+                "RUSTSEC-2024-0002",
+                Some("CVSS:3.0/AV:L/AC:L/PR:L/UI:N/S:U/C:H/I:N/A:N"),
+                None,
+            ),
+            // unknown
+            dep_advisory("RUSTSEC-2024-0003", None, None),
+            // informational
+            dep_advisory("RUSTSEC-2024-0004", None, Some("unsound")),
+        ],
+        ..Default::default()
+    };
+    for (min_severity, informational, expected) in [
+        (AdvisorySeverityLevel::None, true, [true, true, true, true]),
+        (AdvisorySeverityLevel::None, false, [true, true, true, false]),
+        (AdvisorySeverityLevel::Medium, false, [true, true, true, false]),
+        (AdvisorySeverityLevel::High, false, [true, false, true, false]),
+        (AdvisorySeverityLevel::Critical, true, [true, false, true, true]),
+    ] {
+        analysis.flag_advisories(min_severity, informational);
+        let flagged = analysis
+            .advisories
+            .iter()
+            .map(|advisory| advisory.is_flagged)
+            .collect::<Vec<_>>();
+        assert_eq!(flagged, expected);
+    }
+
+    // suppressed advisories are never flagged
+    analysis.advisories.truncate(1);
+    analysis.apply_suppressions("1.0.0", &[suppression("RUSTSEC-2024-0001", None, None)]);
+    analysis.flag_advisories(AdvisorySeverityLevel::None, true);
+    assert!(!analysis.has_cves());
+}
+
+#[test]
+fn test_internal_advisory_lifecycle() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let metadata = crate_metadata("acme", "1.0.0");
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await?;
+
+        let advisory = application
+            .create_crate_advisory(
+                &admin_auth,
+                "acme",
+                &advisory_data("Remote code execution", "0.1.0", Some("1.0.1")),
+            )
+            .await?;
+        assert!(advisory.id.starts_with(INTERNAL_ADVISORY_PREFIX));
+        assert!(advisory.id.ends_with("-0001"));
+        assert_eq!(advisory.author.as_deref(), Some(ADMIN_NAME));
+        assert!(advisory.withdrawn.is_none());
+        let second = application
+            .create_crate_advisory(&admin_auth, "acme", &advisory_data("Denial of service", "1.0.0", None))
+            .await?;
+        assert!(second.id.ends_with("-0002"));
+        let advisories = application.get_crate_advisories(&admin_auth, "acme").await?;
+        assert_eq!(advisories.len(), 2);
+
+        // impact on the local crates that depend on the affected versions
+        let dependent = crate_metadata("acme-app", "0.1.0");
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&dependent, &[])?)
+            .await?;
+        let resolutions = &[
+            DepResolution {
+                registry: None,
+                ..resolution("acme", "1.0.0", true)
+            },
+            // the crate with the same name on crates.io is not affected
+            resolution("acme", "0.5.0", false),
+        ];
+        application
+            .db_transaction_write("test_internal_advisory_lifecycle", |app| async move {
+                app.database
+                    .set_crate_deps_resolutions("acme-app", "0.1.0", resolutions)
+                    .await
+            })
+            .await?;
+        let impact = application.get_advisory_impact(&admin_auth, &advisory.id).await?;
+        assert!(impact.advisory.is_internal);
+        assert_eq!(impact.impacted.len(), 1);
+        assert_eq!(impact.impacted[0].package, "acme-app");
+        assert_eq!(impact.impacted[0].resolved, "1.0.0");
+
+        // update
+        let updated = application
+            .update_crate_advisory(
+                &admin_auth,
+                "acme",
+                &advisory.id,
+                &advisory_data("Remote code execution", "0.1.0", Some("0.9.0")),
+            )
+            .await?;
+        assert_eq!(updated.id, advisory.id);
+        assert_eq!(updated.ranges[0].fixed, Some("0.9.0".parse().unwrap()));
+        let impact = application.get_advisory_impact(&admin_auth, &advisory.id).await?;
+        assert!(impact.impacted.is_empty());
+
+        // OSV feed
+        let osv = application.get_advisory_osv(&admin_auth, &advisory.id).await?;
+        assert_eq!(osv.affected[0].package.name, "acme");
+        assert!(osv.affected[0].package.purl.starts_with("pkg:cargo/acme?repository_url="));
+        assert_eq!(osv.affected[0].ranges[0].events[0].introduced.as_deref(), Some("0.1.0"));
+        assert_eq!(osv.affected[0].ranges[0].events[1].fixed.as_deref(), Some("0.9.0"));
+        assert_eq!(osv.severity[0].type_value, "CVSS_V3");
+        assert!(osv.withdrawn.is_empty());
+
+        // withdrawal
+        application.withdraw_crate_advisory(&admin_auth, "acme", &advisory.id).await?;
+        let error = application
+            .withdraw_crate_advisory(&admin_auth, "acme", &advisory.id)
+            .await
+            .expect_err("the advisory is already withdrawn");
+        assert_eq!(error.http, StatusCode::CONFLICT);
+        let error = application
+            .update_crate_advisory(&admin_auth, "acme", &advisory.id, &advisory_data("Withdrawn", "0.1.0", None))
+            .await
+            .expect_err("the advisory is withdrawn");
+        assert_eq!(error.http, StatusCode::CONFLICT);
+        let feed = application.get_advisories_osv(&admin_auth).await?;
+        assert_eq!(feed.len(), 2);
+        let withdrawn = feed.iter().find(|osv| osv.id == advisory.id).unwrap();
+        assert!(!withdrawn.withdrawn.is_empty());
+        let error = application
+            .get_advisory_osv(&admin_auth, "CRATERY-0000-0000")
+            .await
+            .expect_err("the advisory is unknown");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_internal_advisory_validation() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let metadata = crate_metadata("acme", "1.0.0");
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await?;
+
+        // invalid data
+        for data in [
+            advisory_data(" ", "0.1.0", None),
+            InternalAdvisoryData {
+                severity: Some(String::from("critical")),
+                ..advisory_data("Invalid severity", "0.1.0", None)
+            },
+            InternalAdvisoryData {
+                ranges: Vec::new(),
+                ..advisory_data("No range", "0.1.0", None)
+            },
+            advisory_data("Fixed before introduced", "1.0.0", Some("0.1.0")),
+        ] {
+            let error = application
+                .create_crate_advisory(&admin_auth, "acme", &data)
+                .await
+                .expect_err("the advisory is invalid");
+            assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        }
+
+        // only the owners may publish advisories
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, 2, true, false).await?,
+        });
+        let error = application
+            .create_crate_advisory(&user_auth, "acme", &advisory_data("Not an owner", "0.1.0", None))
+            .await
+            .expect_err("the user is not an owner");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+
+        let error = application
+            .create_crate_advisory(&admin_auth, "unknown", &advisory_data("Unknown crate", "0.1.0", None))
+            .await
+            .expect_err("the crate does not exist");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_internal_advisory_deleted_author() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, 2, true, false).await?,
+        });
+        let metadata = crate_metadata("acme", "1.0.0");
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await?;
+        application
+            .add_crate_owners(&admin_auth, "acme", &[String::from("user")])
+            .await?;
+        let advisory = application
+            .create_crate_advisory(&user_auth, "acme", &advisory_data("Remote code execution", "0.1.0", None))
+            .await?;
+        assert_eq!(advisory.author.as_deref(), Some("user"));
+
+        // the advisory is kept when its author is deleted
+        application.delete_user(&admin_auth, "user").await?;
+        let advisories = application.get_crate_advisories(&admin_auth, "acme").await?;
+        assert_eq!(advisories.len(), 1);
+        assert_eq!(advisories[0].id, advisory.id);
+        assert!(advisories[0].author.is_none());
+        Ok(())
+    })
+}
+
+/// Builds the content of an advisory in the OSV format against a crate on crates.io
+fn osv_content(id: &str, package: &str, introduced: &str, fixed: &str) -> String {
+    serde_json::json!({
+        "id": id,
+        "modified": "2024-01-01T00:00:00Z",
+        "published": "2024-01-01T00:00:00Z",
+        "summary": "Test advisory",
+        "affected": [{
+            "package": { "ecosystem": "crates.io", "name": package, "purl": format!("pkg:cargo/{package}") },
+            "ranges": [{ "type": "SEMVER", "events": [{ "introduced": introduced }, { "fixed": fixed }] }]
+        }]
+    })
+    .to_string()
+}
+
+/// Gets the real service for the advisories, with a specific source
+fn advisory_db_service(
+    configuration: &Configuration,
+    source: AdvisoryDbSource,
+) -> Result<std::sync::Arc<dyn RustSecChecker + Send + Sync>, ApiError> {
+    let configuration = Configuration {
+        deps_advisory_db: source,
+        // always update the data
+        deps_stale_registry: 0,
+        ..configuration.clone()
+    };
+    let pool = RwSqlitePool::new(&configuration.get_database_url())?;
+    Ok(get_service(&configuration, pool))
+}
+
+/// Gets the identifiers of the advisories against a version of a crate on crates.io
+async fn check_crate_ids(
+    service: &(dyn RustSecChecker + Send + Sync),
+    package: &str,
+    version: &str,
+) -> Result<Vec<String>, ApiError> {
+    let version = version.parse::<Version>()?;
+    let advisories = service.check_crate(Some(CRATES_IO_REGISTRY_URI), package, &version).await?;
+    Ok(advisories.into_iter().map(|advisory| advisory.id).collect())
+}
+
+#[test]
+fn test_advisory_db_local() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        let location = Path::new(&application.configuration.data_dir).join("osv-local");
+        let service = advisory_db_service(
+            &application.configuration,
+            AdvisoryDbSource::Local {
+                path: location.to_string_lossy().to_string(),
+            },
+        )?;
+
+        // the directory does not exist yet
+        assert!(check_crate_ids(service.as_ref(), "serde", "1.0.0").await?.is_empty());
+        let status = service.get_db_status().await?;
+        assert!(!status.is_upload);
+        assert!(status.last_success.is_none());
+        assert!(status.last_failure.is_some());
+        assert!(status.last_error.is_some());
+
+        tokio::fs::create_dir_all(location.join("crates").join("serde")).await?;
+        tokio::fs::create_dir_all(location.join(".git")).await?;
+        tokio::fs::write(
+            location.join("crates").join("serde").join("RUSTSEC-2024-0001.json"),
+            osv_content("RUSTSEC-2024-0001", "serde", "1.0.0", "1.0.2"),
+        )
+        .await?;
+        tokio::fs::write(location.join("crates").join("invalid.json"), "{").await?;
+        // hidden directories are ignored
+        tokio::fs::write(
+            location.join(".git").join("RUSTSEC-2024-0002.json"),
+            osv_content("RUSTSEC-2024-0002", "serde", "0.1.0", "2.0.0"),
+        )
+        .await?;
+        assert_eq!(
+            check_crate_ids(service.as_ref(), "serde", "1.0.0").await?,
+            vec![String::from("RUSTSEC-2024-0001")]
+        );
+        assert!(check_crate_ids(service.as_ref(), "serde", "1.0.2").await?.is_empty());
+        let status = service.get_db_status().await?;
+        assert_eq!(status.advisories, 1);
+        assert_eq!(status.invalid_files, 1);
+        assert!(status.last_success.is_some());
+
+        // only uploaded databases can be replaced
+        let error = service.upload_db(&[]).await.expect_err("the database is not uploaded");
+        assert_eq!(error.http, StatusCode::CONFLICT);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_advisory_db_upload() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let service = advisory_db_service(&application.configuration, AdvisoryDbSource::Upload)?;
+        assert!(service.get_db_status().await?.is_upload);
+        // nothing uploaded yet
+        assert!(check_crate_ids(service.as_ref(), "serde", "1.0.0").await?.is_empty());
+        assert!(service.get_db_status().await?.last_failure.is_some());
+
+        let archive = |files: &[(&str, String)]| -> Result<Vec<u8>, ApiError> {
+            let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+            for (path, content) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                archive.append_data(&mut header, path, content.as_bytes())?;
+            }
+            Ok(archive.into_inner()?.finish()?)
+        };
+        let error = service
+            .upload_db(b"not an archive")
+            .await
+            .expect_err("the archive is invalid");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let error = service
+            .upload_db(&archive(&[("README.md", String::from("empty"))])?)
+            .await
+            .expect_err("the archive contains no advisory");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+
+        let status = service
+            .upload_db(&archive(&[(
+                "advisory-db/crates/serde/RUSTSEC-2024-0001.json",
+                osv_content("RUSTSEC-2024-0001", "serde", "1.0.0", "1.0.2"),
+            )])?)
+            .await?;
+        assert_eq!(status.advisories, 1);
+        assert!(status.last_success.is_some());
+        assert_eq!(
+            check_crate_ids(service.as_ref(), "serde", "1.0.1").await?,
+            vec![String::from("RUSTSEC-2024-0001")]
+        );
+
+        // only administrators can see the status
+        application.get_advisory_db_status(&admin_auth).await?;
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, 2, true, false).await?,
+        });
+        let error = application
+            .get_advisory_db_status(&user_auth)
+            .await
+            .expect_err("the user is not an administrator");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        Ok(())
+    })
+}
+
+/// Builds a suppression of an advisory
+fn suppression(advisory: &str, version: Option<&str>, expires: Option<NaiveDateTime>) -> AdvisorySuppression {
+    AdvisorySuppression {
+        id: 1,
+        package: String::from("acme"),
+        version: version.map(str::to_string),
+        advisory: advisory.to_string(),
+        justification: String::from("The vulnerable feature is not used"),
+        author: Some(String::from(ADMIN_NAME)),
+        created: Local::now().naive_local(),
+        expires,
+    }
+}
+
+#[test]
+fn test_advisory_suppressions_analysis() {
+    let mut analysis = DepsAnalysis {
+        advisories: vec![DepAdvisory {
+            package: String::from("serde"),
+            version: Version::new(1, 0, 0),
+            content: advisory("serde", "1.0.0", "1.0.2"),
+            suppression: None,
+            is_flagged: false,
+        }],
+        ..Default::default()
+    };
+    let now = Local::now().naive_local();
+    for (suppressions, is_suppressed) in [
+        (vec![], false),
+        (vec![suppression("RUSTSEC-2024-0001", None, None)], true),
+        (vec![suppression("RUSTSEC-2024-0001", Some("1.0.0"), None)], true),
+        (vec![suppression("RUSTSEC-2024-0001", Some("0.9.0"), None)], false),
+        (vec![suppression("RUSTSEC-2024-0002", None, None)], false),
+        (
+            vec![suppression("RUSTSEC-2024-0001", None, Some(now + Duration::days(1)))],
+            true,
+        ),
+        (
+            vec![suppression("RUSTSEC-2024-0001", None, Some(now - Duration::days(1)))],
+            false,
+        ),
+    ] {
+        analysis.apply_suppressions("1.0.0", &suppressions);
+        analysis.flag_advisories(AdvisorySeverityLevel::None, true);
+        assert_eq!(analysis.advisories[0].suppression.is_some(), is_suppressed);
+        assert_eq!(analysis.has_cves(), !is_suppressed);
+    }
+}
+
+#[test]
+fn test_advisory_suppressions() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let metadata = crate_metadata("acme", "1.0.0");
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await?;
+        application
+            .db_transaction_write("test_advisory_suppressions", |app| async move {
+                app.database
+                    .set_crate_deps_analysis("acme", "1.0.0", false, true, false)
+                    .await
+            })
+            .await?;
+        let is_unanalyzed = || async {
+            let jobs = application
+                .db_transaction_read(|app| async move { app.database.get_unanalyzed_crates(60).await })
+                .await?;
+            Ok::<_, ApiError>(jobs.iter().any(|job| job.package == "acme"))
+        };
+        assert!(!is_unanalyzed().await?);
+
+        let creation =
+            |version: Option<&str>, justification: &str, expires: Option<NaiveDateTime>| AdvisorySuppressionCreation {
+                version: version.map(str::to_string),
+                advisory: String::from("RUSTSEC-2024-0001"),
+                justification: justification.to_string(),
+                expires,
+            };
+        let created = application
+            .create_advisory_suppression(&admin_auth, "acme", &creation(Some("1.0.0"), "Feature not used", None))
+            .await?;
+        assert_eq!(created.author.as_deref(), Some(ADMIN_NAME));
+        assert_eq!(created.version.as_deref(), Some("1.0.0"));
+        // the version is analysed again
+        assert!(is_unanalyzed().await?);
+
+        // invalid suppressions
+        let now = Local::now().naive_local();
+        for (creation, status) in [
+            (creation(None, "  ", None), StatusCode::BAD_REQUEST),
+            (
+                AdvisorySuppressionCreation {
+                    advisory: String::new(),
+                    ..creation(None, "Feature not used", None)
+                },
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                creation(None, "Feature not used", Some(now - Duration::days(1))),
+                StatusCode::BAD_REQUEST,
+            ),
+            (creation(Some("2.0.0"), "Feature not used", None), StatusCode::NOT_FOUND),
+        ] {
+            let error = application
+                .create_advisory_suppression(&admin_auth, "acme", &creation)
+                .await
+                .expect_err("the suppression is invalid");
+            assert_eq!(error.http, status);
+        }
+
+        // only the owners may suppress advisories
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, 2, true, false).await?,
+        });
+        let error = application
+            .create_advisory_suppression(&user_auth, "acme", &creation(None, "Feature not used", None))
+            .await
+            .expect_err("the user is not an owner");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+
+        application
+            .create_advisory_suppression(
+                &admin_auth,
+                "acme",
+                &creation(None, "Feature not used", Some(now + Duration::days(30))),
+            )
+            .await?;
+        let suppressions = application.get_advisory_suppressions(&admin_auth, "acme").await?;
+        assert_eq!(suppressions.len(), 2);
+        application
+            .delete_advisory_suppression(&admin_auth, "acme", created.id)
+            .await?;
+        let suppressions = application.get_advisory_suppressions(&admin_auth, "acme").await?;
+        assert_eq!(suppressions.len(), 1);
+        assert!(suppressions[0].version.is_none());
+        let error = application
+            .delete_advisory_suppression(&admin_auth, "acme", created.id)
+            .await
+            .expect_err("the suppression is already removed");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_advisory_suppression_deleted_author() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, 2, true, false).await?,
+        });
+        let metadata = crate_metadata("acme", "1.0.0");
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await?;
+        application
+            .add_crate_owners(&admin_auth, "acme", &[String::from("user")])
+            .await?;
+        let created = application
+            .create_advisory_suppression(
+                &user_auth,
+                "acme",
+                &AdvisorySuppressionCreation {
+                    version: None,
+                    advisory: String::from("RUSTSEC-2024-0001"),
+                    justification: String::from("Feature not used"),
+                    expires: None,
+                },
+            )
+            .await?;
+        assert_eq!(created.author.as_deref(), Some("user"));
+
+        // the suppression is kept when its author is deleted
+        application.delete_user(&admin_auth, "user").await?;
+        let suppressions = application.get_advisory_suppressions(&admin_auth, "acme").await?;
+        assert_eq!(suppressions.len(), 1);
+        assert_eq!(suppressions[0].id, created.id);
+        assert!(suppressions[0].author.is_none());
+        Ok(())
+    })
+}
@@ -0,0 +1,228 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests about the index
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use chrono::{TimeZone, Utc};
+
+use crate::model::cargo::{CrateMetadata, IndexCrateMetadata};
+use crate::model::config::{Configuration, IndexBackend, IndexConfig};
+use crate::model::sbom::SbomFormat;
+use crate::services::index::{self, IndexFile, build_package_index_path, parse_package_index_path};
+use crate::services::storage::{FileVersion, Storage};
+use crate::tests::async_test;
+use crate::tests::mocks::MockService;
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::ApiError;
+use crate::utils::hashes::sha256;
+
+#[test]
+fn test_package_index_path() {
+    assert_eq!(build_package_index_path("a"), "1/a");
+    assert_eq!(build_package_index_path("ab"), "2/ab");
+    assert_eq!(build_package_index_path("abc"), "3/a/abc");
+    assert_eq!(build_package_index_path("Serde"), "se/rd/serde");
+    assert_eq!(parse_package_index_path("se/rd/serde"), Some("serde"));
+    assert_eq!(parse_package_index_path("se/rd/Serde"), None);
+    assert_eq!(parse_package_index_path("xx/rd/serde"), None);
+    assert_eq!(parse_package_index_path("../serde"), None);
+}
+
+#[test]
+fn test_index_file_etag() {
+    let file = IndexFile::new(b"content".to_vec(), None);
+    assert!(!file.is_current(None, None));
+    assert!(file.is_current(Some(&file.etag), None));
+    assert!(file.is_current(Some(&format!("W/{}", file.etag)), None));
+    assert!(file.is_current(Some(&format!("\"other\", {}", file.etag)), None));
+    assert!(file.is_current(Some("*"), None));
+    assert!(!file.is_current(Some("\"other\""), None));
+    // a changed content yields another tag
+    let changed = IndexFile::new(b"changed".to_vec(), None);
+    assert!(!changed.is_current(Some(&file.etag), None));
+}
+
+#[test]
+fn test_index_file_last_modified() {
+    let last_modified = Utc.with_ymd_and_hms(2024, 11, 6, 8, 49, 37).unwrap();
+    let file = IndexFile::new(b"content".to_vec(), Some(last_modified));
+    let header = file.last_modified_header().unwrap();
+    assert_eq!(header, "Wed, 06 Nov 2024 08:49:37 GMT");
+    assert!(file.is_current(None, Some(&header)));
+    assert!(file.is_current(None, Some("Thu, 07 Nov 2024 08:49:37 GMT")));
+    assert!(!file.is_current(None, Some("Tue, 05 Nov 2024 08:49:37 GMT")));
+    assert!(!file.is_current(None, Some("not a date")));
+    // If-None-Match takes precedence
+    assert!(!file.is_current(Some("\"other\""), Some(&header)));
+    // without a known modification date, the client's copy cannot be validated by date
+    let file = IndexFile::new(b"content".to_vec(), None);
+    assert!(!file.is_current(None, Some(&header)));
+}
+
+/// A storage for the index files that emulates entity tags,
+/// where another instance of the registry adds a version of `acme` right before the first conditional write
+#[derive(Default)]
+struct RacingStorage {
+    /// The index files
+    files: Mutex<HashMap<String, Vec<u8>>>,
+    /// Whether the other instance already wrote
+    raced: AtomicBool,
+    /// The number of conditional writes that failed
+    conflicts: AtomicUsize,
+}
+
+impl Storage for RacingStorage {
+    fn store_crate<'a>(&'a self, metadata: &'a CrateMetadata, content: Vec<u8>, readme: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        MockService.store_crate(metadata, content, readme)
+    }
+
+    fn download_crate<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        MockService.download_crate(name, version)
+    }
+
+    fn download_crate_metadata<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Option<CrateMetadata>> {
+        MockService.download_crate_metadata(name, version)
+    }
+
+    fn download_crate_readme<'a>(&'a self, name: &'a str, version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        MockService.download_crate_readme(name, version)
+    }
+
+    fn store_crate_sbom<'a>(
+        &'a self,
+        name: &'a str,
+        version: &'a str,
+        format: SbomFormat,
+        content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()> {
+        MockService.store_crate_sbom(name, version, format, content)
+    }
+
+    fn download_crate_sbom<'a>(
+        &'a self,
+        name: &'a str,
+        version: &'a str,
+        format: SbomFormat,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        MockService.download_crate_sbom(name, version, format)
+    }
+
+    fn store_doc_file<'a>(&'a self, path: &'a str, file: &'a Path) -> FaillibleFuture<'a, ()> {
+        MockService.store_doc_file(path, file)
+    }
+
+    fn store_doc_data<'a>(&'a self, path: &'a str, content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        MockService.store_doc_data(path, content)
+    }
+
+    fn download_doc_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        MockService.download_doc_file(path)
+    }
+
+    fn store_index_file<'a>(&'a self, path: &'a str, content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        self.files.lock().unwrap().insert(path.to_string(), content);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn download_index_file<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        let content = self.files.lock().unwrap().get(path).cloned();
+        Box::pin(async move { Ok(content) })
+    }
+
+    fn download_index_file_versioned<'a>(&'a self, path: &'a str) -> FaillibleFuture<'a, (Option<Vec<u8>>, FileVersion)> {
+        let content = self.files.lock().unwrap().get(path).cloned();
+        let version = content
+            .as_ref()
+            .map_or(FileVersion::Missing, |content| FileVersion::ETag(sha256(content)));
+        Box::pin(async move { Ok((content, version)) })
+    }
+
+    fn store_index_file_if_unmodified<'a>(
+        &'a self,
+        path: &'a str,
+        content: Vec<u8>,
+        version: &'a FileVersion,
+    ) -> FaillibleFuture<'a, bool> {
+        let mut files = self.files.lock().unwrap();
+        if !self.raced.swap(true, Ordering::SeqCst) {
+            let mut other = files.get(path).cloned().unwrap_or_default();
+            other.extend_from_slice(&serde_json::to_vec(&index_metadata("0.9.0")).unwrap());
+            other.push(b'\n');
+            files.insert(path.to_string(), other);
+        }
+        let current = files.get(path).map(|content| sha256(content));
+        let is_unmodified = match version {
+            FileVersion::Missing => current.is_none(),
+            FileVersion::ETag(etag) => current.as_ref() == Some(etag),
+            FileVersion::Untagged => true,
+        };
+        if is_unmodified {
+            files.insert(path.to_string(), content);
+        } else {
+            self.conflicts.fetch_add(1, Ordering::SeqCst);
+        }
+        drop(files);
+        Box::pin(async move { Ok(is_unmodified) })
+    }
+
+    fn store_proxied_crate<'a>(
+        &'a self,
+        registry: &'a str,
+        name: &'a str,
+        version: &'a str,
+        content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()> {
+        MockService.store_proxied_crate(registry, name, version, content)
+    }
+
+    fn download_proxied_crate<'a>(
+        &'a self,
+        registry: &'a str,
+        name: &'a str,
+        version: &'a str,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        MockService.download_proxied_crate(registry, name, version)
+    }
+}
+
+/// Gets the metadata for a version of `acme` in the index
+fn index_metadata(version: &str) -> IndexCrateMetadata {
+    IndexCrateMetadata {
+        name: String::from("acme"),
+        vers: version.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_sparse_index_concurrent_update() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        let storage = std::sync::Arc::new(RacingStorage::default());
+        let configuration = Configuration {
+            index: IndexConfig {
+                backend: IndexBackend::Storage,
+                ..application.configuration.index.clone()
+            },
+            ..application.configuration.as_ref().clone()
+        };
+        let index = index::get_service(&configuration, storage.clone(), true).await?;
+        // the version published by the other instance is kept
+        index.publish_crate_version(&index_metadata("1.0.0")).await?;
+        assert_eq!(storage.conflicts.load(Ordering::SeqCst), 1);
+        let versions = index.get_crate_data("acme").await?;
+        assert_eq!(
+            versions.iter().map(|v| v.vers.as_str()).collect::<Vec<_>>(),
+            vec!["0.9.0", "1.0.0"]
+        );
+        index.remove_crate_version("acme", "0.9.0").await?;
+        let versions = index.get_crate_data("acme").await?;
+        assert_eq!(versions.iter().map(|v| v.vers.as_str()).collect::<Vec<_>>(), vec!["1.0.0"]);
+        Ok(())
+    })
+}
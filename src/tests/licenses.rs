@@ -0,0 +1,154 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests for the license policy on dependencies
+
+use axum::http::StatusCode;
+
+use super::async_test;
+use crate::model::deps::{DepLicense, DepsAnalysis};
+use crate::model::policy::{LicensePolicy, LicenseRule, LicenseRuleCreation};
+use crate::tests::{ADMIN_UID, setup_create_token, setup_create_user};
+use crate::utils::apierror::ApiError;
+use crate::utils::axum::auth::{AuthData, Token};
+
+/// Builds a rule of the license policy
+fn rule(license: &str, is_allowed: bool) -> LicenseRule {
+    LicenseRule {
+        id: 0,
+        license: license.to_string(),
+        is_allowed,
+    }
+}
+
+/// Builds the license of a dependency
+fn dep_license(package: &str, license: Option<&str>) -> DepLicense {
+    DepLicense {
+        registry: None,
+        package: package.to_string(),
+        version: String::from("1.0.0"),
+        license: license.map(str::to_string),
+        violation: None,
+    }
+}
+
+#[test]
+fn test_license_policy_evaluation() {
+    let policy = LicensePolicy::new(&[]);
+    assert!(policy.evaluate("GPL-3.0-only").is_none());
+
+    // deny list only
+    let policy = LicensePolicy::new(&[rule("GPL-3.0-only", false)]);
+    assert!(policy.evaluate("MIT").is_none());
+    assert!(policy.evaluate("MIT OR GPL-3.0-only").is_none());
+    assert!(policy.evaluate("GPL-3.0-only").is_some());
+    assert!(policy.evaluate("MIT AND GPL-3.0-only").is_some());
+
+    // allow list, with a complete expression
+    let policy = LicensePolicy::new(&[
+        rule("MIT", true),
+        rule("Apache-2.0", true),
+        rule("Unicode-3.0 AND BSD-3-Clause", true),
+        rule("apache-2.0", false),
+    ]);
+    assert!(policy.evaluate("MIT").is_none());
+    assert!(policy.evaluate("MIT/Apache-2.0").is_none());
+    assert!(policy.evaluate("(MIT OR Apache-2.0) AND Unicode-3.0").is_some());
+    assert!(policy.evaluate("Unicode-3.0  AND BSD-3-Clause").is_none());
+    assert!(policy.evaluate("Zlib").is_some());
+    // denied licenses win over allowed ones
+    assert!(policy.evaluate("Apache-2.0").is_some());
+    assert!(policy.evaluate("MIT OR Apache-2.0").is_none());
+
+    let mut analysis = DepsAnalysis {
+        licenses: vec![
+            dep_license("serde", Some("MIT OR Apache-2.0")),
+            dep_license("ring", Some("ISC")),
+            dep_license("unknown", None),
+        ],
+        ..Default::default()
+    };
+    analysis.check_licenses(&policy);
+    assert!(analysis.has_license_violations());
+    assert!(analysis.licenses[0].violation.is_none());
+    assert!(analysis.licenses[1].violation.is_some());
+    // unknown licenses are violations when some licenses are allowed
+    assert!(analysis.licenses[2].violation.is_some());
+    analysis.check_licenses(&LicensePolicy::new(&[rule("ISC", false)]));
+    assert!(analysis.licenses[1].violation.is_some());
+    assert!(analysis.licenses[2].violation.is_none());
+    analysis.check_licenses(&LicensePolicy::default());
+    assert!(!analysis.has_license_violations());
+}
+
+#[test]
+fn test_license_policy_admin() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, ADMIN_UID + 1, true, true).await?,
+        });
+        let creation = LicenseRuleCreation {
+            license: String::from("GPL-3.0-only"),
+            is_allowed: false,
+        };
+        // only admins manage the policy
+        let error = application
+            .create_license_rule(&user_auth, &creation)
+            .await
+            .expect_err("only admins can change the policy");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .get_license_rules(&user_auth)
+            .await
+            .expect_err("only admins can read the policy");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+
+        let denied = application.create_license_rule(&admin_auth, &creation).await?;
+        let error = application
+            .create_license_rule(&admin_auth, &creation)
+            .await
+            .expect_err("the license already has a rule");
+        assert_eq!(error.http, StatusCode::CONFLICT);
+        let error = application
+            .create_license_rule(
+                &admin_auth,
+                &LicenseRuleCreation {
+                    license: String::from("  "),
+                    is_allowed: true,
+                },
+            )
+            .await
+            .expect_err("the license is empty");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let allowed = application
+            .create_license_rule(
+                &admin_auth,
+                &LicenseRuleCreation {
+                    license: String::from(" MIT  OR Apache-2.0 "),
+                    is_allowed: true,
+                },
+            )
+            .await?;
+        assert_eq!(allowed.license, "MIT OR Apache-2.0");
+        let rules = application.get_license_rules(&admin_auth).await?;
+        assert_eq!(rules.len(), 2);
+        assert!(!rules[0].is_allowed && rules[1].is_allowed);
+
+        let error = application
+            .delete_license_rule(&user_auth, denied.id)
+            .await
+            .expect_err("only admins can change the policy");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        application.delete_license_rule(&admin_auth, denied.id).await?;
+        let error = application
+            .delete_license_rule(&admin_auth, denied.id)
+            .await
+            .expect_err("the rule is already removed");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        assert_eq!(application.get_license_rules(&admin_auth).await?.len(), 1);
+        Ok(())
+    })
+}
@@ -5,7 +5,6 @@
 //! Types and utilities for mocking services
 
 use std::env::temp_dir;
-use std::path::PathBuf;
 use std::sync::Arc;
 
 use chrono::NaiveDateTime;
@@ -13,24 +12,33 @@ use futures::future::BoxFuture;
 use semver::Version;
 use tokio::sync::mpsc::Sender;
 
+use crate::model::advisories::AdvisoryDbStatus;
+use crate::model::auth::OAuthLoginRequest;
 use crate::model::cargo::{CrateMetadata, IndexCrateMetadata};
 use crate::model::config::Configuration;
 use crate::model::deps::DepsAnalysis;
 use crate::model::docs::{DocGenEvent, DocGenJob, DocGenJobSpec, DocGenJobState, DocGenTrigger};
 use crate::model::osv::SimpleAdvisory;
+use crate::model::sbom::SbomFormat;
 use crate::model::worker::WorkersManager;
 use crate::services::database::{DbReadError, DbWriteError};
 use crate::services::deps::DepsChecker;
 use crate::services::docs::DocsGenerator;
 use crate::services::emails::EmailSender;
-use crate::services::index::{GitIndexError, Index, IndexError};
+use crate::services::index::{Index, IndexError, IndexFile};
+use crate::services::oauth::OAuthProvider;
+use crate::services::proxy::CratesIoProxy;
 use crate::services::rustsec::RustSecChecker;
-use crate::services::storage::Storage;
+use crate::services::storage::{FileVersion, Storage};
 use crate::services::{ConfigurationError, ServiceProvider};
 use crate::utils::FaillibleFuture;
+use crate::utils::apierror::error_backend_failure;
 use crate::utils::db::RwSqlitePool;
 use crate::utils::token::generate_token;
 
+/// The marker for the email addresses to which sending emails fails
+pub const UNDELIVERABLE_EMAIL: &str = "undeliverable";
+
 /// A mocking service
 pub struct MockService;
 
@@ -49,6 +57,8 @@ impl ServiceProvider for MockService {
             .map_err(|source| ConfigurationError::CreateTempDir { source, path: temp_dir })?;
         Ok(Configuration {
             data_dir,
+            // no background analysis of the crates, the tests run them explicitly
+            deps_stale_analysis: 0,
             ..Default::default()
         })
     }
@@ -57,11 +67,15 @@ impl ServiceProvider for MockService {
         Arc::new(Self)
     }
 
-    async fn get_index(_config: &Configuration, _expect_empty: bool) -> Result<Arc<dyn Index + Send + Sync>, GitIndexError> {
+    async fn get_index(
+        _config: &Configuration,
+        _service_storage: Arc<dyn Storage + Send + Sync>,
+        _expect_empty: bool,
+    ) -> Result<Arc<dyn Index + Send + Sync>, IndexError> {
         Ok(Arc::new(Self))
     }
 
-    fn get_rustsec(_config: &Configuration) -> Arc<dyn RustSecChecker + Send + Sync> {
+    fn get_rustsec(_config: &Configuration, _service_db_pool: RwSqlitePool) -> Arc<dyn RustSecChecker + Send + Sync> {
         Arc::new(Self)
     }
 
@@ -69,6 +83,9 @@ impl ServiceProvider for MockService {
         _configuration: Arc<Configuration>,
         _service_index: Arc<dyn Index + Send + Sync>,
         _service_rustsec: Arc<dyn RustSecChecker + Send + Sync>,
+        _service_storage: Arc<dyn Storage + Send + Sync>,
+        _service_crates_io_proxy: Arc<dyn CratesIoProxy + Send + Sync>,
+        _http_client: reqwest::Client,
     ) -> Arc<dyn DepsChecker + Send + Sync> {
         Arc::new(Self)
     }
@@ -85,10 +102,22 @@ impl ServiceProvider for MockService {
     ) -> Arc<dyn DocsGenerator + Send + Sync> {
         Arc::new(Self)
     }
+
+    fn get_crates_io_proxy(
+        _configuration: Arc<Configuration>,
+        _service_storage: Arc<dyn Storage + Send + Sync>,
+        _http_client: reqwest::Client,
+    ) -> Arc<dyn CratesIoProxy + Send + Sync> {
+        Arc::new(Self)
+    }
+
+    fn get_oauth_provider(_configuration: Arc<Configuration>) -> Arc<dyn OAuthProvider + Send + Sync> {
+        Arc::new(Self)
+    }
 }
 
 impl Index for MockService {
-    fn get_index_file<'a>(&'a self, _file_path: &'a std::path::Path) -> FaillibleFuture<'a, Option<PathBuf>> {
+    fn get_index_file<'a>(&'a self, _file_path: &'a std::path::Path) -> FaillibleFuture<'a, Option<IndexFile>> {
         resolved_default()
     }
 
@@ -126,6 +155,15 @@ impl DepsChecker for MockService {
     ) -> FaillibleFuture<'a, DepsAnalysis> {
         resolved_default()
     }
+
+    fn analyze_crate<'a>(
+        &'a self,
+        _package: &'a str,
+        _version: &'a str,
+        _targets: &'a [String],
+    ) -> FaillibleFuture<'a, DepsAnalysis> {
+        resolved_default()
+    }
 }
 
 impl DocsGenerator for MockService {
@@ -166,19 +204,69 @@ impl DocsGenerator for MockService {
 }
 
 impl EmailSender for MockService {
-    fn send_email<'a>(&'a self, _to: &'a [String], _subject: &'a str, _body: String) -> FaillibleFuture<'a, ()> {
+    fn send_email<'a>(&'a self, to: &'a [String], _subject: &'a str, _body: String) -> FaillibleFuture<'a, ()> {
+        // recipients with an undeliverable address make the sending fail
+        if to.iter().any(|email| email.contains(UNDELIVERABLE_EMAIL)) {
+            return Box::pin(async { Err(error_backend_failure()) });
+        }
         resolved_default()
     }
 }
 
 impl RustSecChecker for MockService {
-    fn check_crate<'a>(&'a self, _package: &'a str, _version: &'a Version) -> FaillibleFuture<'a, Vec<SimpleAdvisory>> {
+    fn check_crate<'a>(
+        &'a self,
+        _registry: Option<&'a str>,
+        _package: &'a str,
+        _version: &'a Version,
+    ) -> FaillibleFuture<'a, Vec<SimpleAdvisory>> {
+        resolved_default()
+    }
+
+    fn get_advisory<'a>(&'a self, _id: &'a str) -> FaillibleFuture<'a, Option<SimpleAdvisory>> {
+        resolved_default()
+    }
+
+    fn get_db_status(&self) -> FaillibleFuture<'_, AdvisoryDbStatus> {
+        resolved_default()
+    }
+
+    fn upload_db<'a>(&'a self, _content: &'a [u8]) -> FaillibleFuture<'a, AdvisoryDbStatus> {
+        resolved_default()
+    }
+}
+
+impl CratesIoProxy for MockService {
+    fn get_index_file<'a>(&'a self, _path: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        resolved_default()
+    }
+
+    fn download_crate<'a>(&'a self, _name: &'a str, _version: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
+        resolved_default()
+    }
+
+    fn get_popular_crates(&self) -> FaillibleFuture<'_, Vec<String>> {
+        resolved_default()
+    }
+
+    fn refresh_popular_crates(&self, _count: usize) -> FaillibleFuture<'_, Vec<String>> {
         resolved_default()
     }
 }
 
+/// Mock identity provider, where the code is the email of the user logging in
+impl OAuthProvider for MockService {
+    fn get_login_uri<'a>(&'a self, request: &'a OAuthLoginRequest) -> FaillibleFuture<'a, String> {
+        Box::pin(async move { Ok(format!("http://localhost/oauth/login?state={}", request.state)) })
+    }
+
+    fn get_user_claims<'a>(&'a self, code: &'a str, _request: &'a OAuthLoginRequest) -> FaillibleFuture<'a, serde_json::Value> {
+        Box::pin(async move { Ok(serde_json::json!({ "sub": code, "email": code })) })
+    }
+}
+
 impl Storage for MockService {
-    fn store_crate<'a>(&'a self, _metadata: &'a CrateMetadata, _content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+    fn store_crate<'a>(&'a self, _metadata: &'a CrateMetadata, _content: Vec<u8>, _readme: Vec<u8>) -> FaillibleFuture<'a, ()> {
         resolved_default()
     }
 
@@ -194,6 +282,25 @@ impl Storage for MockService {
         resolved_default()
     }
 
+    fn store_crate_sbom<'a>(
+        &'a self,
+        _name: &'a str,
+        _version: &'a str,
+        _format: SbomFormat,
+        _content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()> {
+        resolved_default()
+    }
+
+    fn download_crate_sbom<'a>(
+        &'a self,
+        _name: &'a str,
+        _version: &'a str,
+        _format: SbomFormat,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        resolved_default()
+    }
+
     fn store_doc_file<'a>(&'a self, _path: &'a str, _file: &'a std::path::Path) -> FaillibleFuture<'a, ()> {
         resolved_default()
     }
@@ -205,4 +312,44 @@ impl Storage for MockService {
     fn download_doc_file<'a>(&'a self, _path: &'a str) -> FaillibleFuture<'a, Vec<u8>> {
         resolved_default()
     }
+
+    fn store_index_file<'a>(&'a self, _path: &'a str, _content: Vec<u8>) -> FaillibleFuture<'a, ()> {
+        resolved_default()
+    }
+
+    fn download_index_file<'a>(&'a self, _path: &'a str) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        resolved_default()
+    }
+
+    fn download_index_file_versioned<'a>(&'a self, _path: &'a str) -> FaillibleFuture<'a, (Option<Vec<u8>>, FileVersion)> {
+        resolved_default()
+    }
+
+    fn store_index_file_if_unmodified<'a>(
+        &'a self,
+        _path: &'a str,
+        _content: Vec<u8>,
+        _version: &'a FileVersion,
+    ) -> FaillibleFuture<'a, bool> {
+        Box::pin(async { Ok(true) })
+    }
+
+    fn store_proxied_crate<'a>(
+        &'a self,
+        _registry: &'a str,
+        _name: &'a str,
+        _version: &'a str,
+        _content: Vec<u8>,
+    ) -> FaillibleFuture<'a, ()> {
+        resolved_default()
+    }
+
+    fn download_proxied_crate<'a>(
+        &'a self,
+        _registry: &'a str,
+        _name: &'a str,
+        _version: &'a str,
+    ) -> FaillibleFuture<'a, Option<Vec<u8>>> {
+        resolved_default()
+    }
 }
@@ -17,7 +17,13 @@ use crate::utils::apierror::{ApiError, AsStatusCode};
 use crate::utils::axum::auth::{AuthData, Token};
 use crate::utils::token::{generate_token, hash_token};
 
+pub mod advisories;
+pub mod index;
+pub mod licenses;
 pub mod mocks;
+pub mod proxy;
+pub mod publish;
+pub mod sbom;
 pub mod security;
 
 pub const ADMIN_UID: i64 = 1;
@@ -104,3 +110,21 @@ pub async fn setup_create_token(
     }).await?;
     Ok(token_secret)
 }
+
+pub async fn setup_create_package(application: &Application, name: &str, owner: i64) -> Result<(), ApiError> {
+    application
+        .db_transaction_write("setup_create_package", |app| async move {
+            sqlx::query("INSERT INTO Package (name, lowercase, targets, nativeTargets, capabilities, isDeprecated, canRemove) VALUES ($1, $2, '', '', '', FALSE, FALSE)")
+                .bind(name)
+                .bind(name.to_ascii_lowercase())
+                .execute(&mut *app.database.transaction.borrow().await)
+                .await?;
+            sqlx::query("INSERT INTO PackageOwner (package, owner) VALUES ($1, $2)")
+                .bind(name)
+                .bind(owner)
+                .execute(&mut *app.database.transaction.borrow().await)
+                .await
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,212 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests for the pull-through caching proxy
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use crate::model::cargo::IndexCrateMetadata;
+use crate::model::config::Configuration;
+use crate::services::proxy::{CratesIoProxy, ProxyUpstream, get_service_for_upstream};
+use crate::services::storage;
+use crate::tests::async_test;
+use crate::utils::apierror::ApiError;
+use crate::utils::hashes::sha256;
+
+/// The upstream answers with server errors
+const UPSTREAM_FAILING: u8 = 1;
+/// The upstream accepts connections but never answers
+const UPSTREAM_HANGING: u8 = 2;
+
+/// The content of the crate served by the upstream
+const CRATE_CONTENT: &[u8] = b"crate content";
+
+/// The state of the fake upstream registry
+#[derive(Default)]
+struct Upstream {
+    /// The current behaviour, answering normally by default
+    mode: AtomicU8,
+    /// The number of requests for index files
+    index_hits: AtomicUsize,
+    /// The number of requests for crates
+    crate_hits: AtomicUsize,
+    /// The number of requests to the web API
+    api_hits: AtomicUsize,
+}
+
+/// Serves the index file of `acme` with the versions 1.0.0 and 2.0.0, the content of 2.0.0 not matching its checksum,
+/// and the pages of the most downloaded crates
+async fn upstream_handler(State(upstream): State<Arc<Upstream>>, request: Request) -> Response {
+    match upstream.mode.load(Ordering::SeqCst) {
+        UPSTREAM_FAILING => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        UPSTREAM_HANGING => {
+            tokio::time::sleep(Duration::from_mins(1)).await;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        _ => {}
+    }
+    match request.uri().path() {
+        "/index/ac/me/acme" => {
+            upstream.index_hits.fetch_add(1, Ordering::SeqCst);
+            let lines = ["1.0.0", "2.0.0"]
+                .into_iter()
+                .map(|vers| {
+                    serde_json::to_string(&IndexCrateMetadata {
+                        name: String::from("acme"),
+                        vers: vers.to_string(),
+                        cksum: sha256(CRATE_CONTENT),
+                        ..Default::default()
+                    })
+                    .unwrap()
+                })
+                .collect::<Vec<_>>();
+            lines.join("\n").into_response()
+        }
+        "/crates/acme/acme-1.0.0.crate" => {
+            upstream.crate_hits.fetch_add(1, Ordering::SeqCst);
+            CRATE_CONTENT.into_response()
+        }
+        "/crates/acme/acme-2.0.0.crate" => {
+            upstream.crate_hits.fetch_add(1, Ordering::SeqCst);
+            b"tampered content".into_response()
+        }
+        "/api/crates" => {
+            upstream.api_hits.fetch_add(1, Ordering::SeqCst);
+            // two crates per page
+            let page = if request.uri().query().is_some_and(|query| query.contains("page=2")) {
+                serde_json::json!({"crates": [{"name": "rand"}, {"name": "syn"}], "meta": {"next_page": "?page=3&sort=downloads"}})
+            } else {
+                serde_json::json!({"crates": [{"name": "serde"}, {"name": "tokio"}], "meta": {"next_page": "?page=2&sort=downloads"}})
+            };
+            page.to_string().into_response()
+        }
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Starts the fake upstream registry and gets the proxy to it
+async fn setup_proxy(
+    configuration: &Configuration,
+    stale_index: u64,
+) -> Result<(Arc<Upstream>, Arc<dyn CratesIoProxy + Send + Sync>), ApiError> {
+    let upstream = Arc::new(Upstream::default());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    let app = Router::new().fallback(upstream_handler).with_state(upstream.clone());
+    let _handle = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let configuration = Configuration {
+        proxy_crates_io: true,
+        proxy_stale_index: stale_index,
+        ..configuration.clone()
+    };
+    let service_storage = storage::get_service(&configuration);
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_millis(500))
+        .read_timeout(Duration::from_millis(500))
+        .build()?;
+    let proxy = get_service_for_upstream(
+        Arc::new(configuration),
+        service_storage,
+        client,
+        ProxyUpstream {
+            index_uri: format!("http://{address}/index/"),
+            download_uri: format!("http://{address}/crates"),
+            api_uri: format!("http://{address}/api"),
+        },
+    );
+    Ok((upstream, proxy))
+}
+
+#[test]
+fn test_proxy_caching() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        let (upstream, proxy) = setup_proxy(&application.configuration, 60 * 1000).await?;
+        // the index file is cached until it is stale
+        let index = proxy.get_index_file("ac/me/acme").await?;
+        assert_eq!(proxy.get_index_file("ac/me/acme").await?, index);
+        assert_eq!(upstream.index_hits.load(Ordering::SeqCst), 1);
+        // the crates are downloaded once
+        assert_eq!(proxy.download_crate("acme", "1.0.0").await?, CRATE_CONTENT);
+        assert_eq!(proxy.download_crate("acme", "1.0.0").await?, CRATE_CONTENT);
+        // crate names are case-insensitive
+        assert_eq!(proxy.download_crate("Acme", "1.0.0").await?, CRATE_CONTENT);
+        assert_eq!(upstream.crate_hits.load(Ordering::SeqCst), 1);
+        // unknown crates and versions
+        assert_eq!(
+            proxy.download_crate("acme", "3.0.0").await.expect_err("unknown version").http,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            proxy.get_index_file("ot/he/other").await.expect_err("unknown crate").http,
+            StatusCode::NOT_FOUND
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_proxy_checksum_mismatch() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        let (upstream, proxy) = setup_proxy(&application.configuration, 60 * 1000).await?;
+        let error = proxy
+            .download_crate("acme", "2.0.0")
+            .await
+            .expect_err("the content does not match the checksum");
+        assert!(error.details.as_ref().is_some_and(|details| details.contains("checksum")));
+        // the content is not cached
+        proxy
+            .download_crate("acme", "2.0.0")
+            .await
+            .expect_err("the content does not match the checksum");
+        assert_eq!(upstream.crate_hits.load(Ordering::SeqCst), 2);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_proxy_upstream_failure() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        // the index files are always stale
+        let (upstream, proxy) = setup_proxy(&application.configuration, 0).await?;
+        let index = proxy.get_index_file("ac/me/acme").await?;
+        proxy.download_crate("acme", "1.0.0").await?;
+        for mode in [UPSTREAM_FAILING, UPSTREAM_HANGING] {
+            upstream.mode.store(mode, Ordering::SeqCst);
+            // the cached copies are served
+            assert_eq!(proxy.get_index_file("ac/me/acme").await?, index);
+            assert_eq!(proxy.download_crate("acme", "1.0.0").await?, CRATE_CONTENT);
+            // the crates that were never cached are unavailable
+            proxy
+                .download_crate("acme", "2.0.0")
+                .await
+                .expect_err("the upstream is unavailable");
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_proxy_popular_crates() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        let (upstream, proxy) = setup_proxy(&application.configuration, 60 * 1000).await?;
+        assert!(proxy.get_popular_crates().await?.is_empty());
+        // the pages are fetched until the expected number of crates is reached
+        let names = proxy.refresh_popular_crates(3).await?;
+        assert_eq!(names, vec!["serde", "tokio", "rand"]);
+        assert_eq!(upstream.api_hits.load(Ordering::SeqCst), 2);
+        assert_eq!(proxy.get_popular_crates().await?, names);
+        // the names are kept across restarts
+        let (_upstream, proxy) = setup_proxy(&application.configuration, 60 * 1000).await?;
+        assert_eq!(proxy.get_popular_crates().await?, names);
+        Ok(())
+    })
+}
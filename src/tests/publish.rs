@@ -0,0 +1,616 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests for publishing crates
+
+use std::fmt::Write;
+
+use axum::http::StatusCode;
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use super::async_test;
+use crate::model::cargo::{
+    CrateContentError, CrateMetadata, CrateMetadataDependency, CrateUploadData, DependencyKind, PACKAGED_FILE_MAX_SIZE,
+    check_crate_archive, check_crate_archive_with_limits, read_crate_license,
+};
+use crate::model::policy::{
+    PublishRuleCreation, PublishRuleKind, find_local_dependencies, license_expression_allowed, repository_has_prefix,
+};
+use crate::services::storage::extract_crate_files;
+use crate::tests::{ADMIN_UID, setup_create_token, setup_create_user};
+use crate::utils::apierror::{ApiError, AsStatusCode};
+use crate::utils::axum::auth::{AuthData, Token};
+
+/// A crate packaged by `cargo package`, with a renamed, a target-specific and a build dependency
+const CARGO_PACKAGE_FIXTURE: &[u8] = include_bytes!("fixtures/acme-fixture-0.1.0.crate");
+
+/// Builds the metadata for a crate to publish
+pub fn crate_metadata(name: &str, version: &str) -> CrateMetadata {
+    CrateMetadata {
+        name: name.to_string(),
+        vers: version.to_string(),
+        description: Some(String::from("A test crate")),
+        license: Some(String::from("MIT")),
+        ..Default::default()
+    }
+}
+
+/// Builds the normalized manifest for a crate to publish, as produced by cargo
+fn crate_manifest(metadata: &CrateMetadata) -> String {
+    let mut manifest = format!("[package]\nname = \"{}\"\nversion = \"{}\"\n", metadata.name, metadata.vers);
+    for dep in &metadata.deps {
+        let section = match dep.kind {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Dev => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        };
+        let section = dep
+            .target
+            .as_ref()
+            .map_or_else(|| section.to_string(), |target| format!("target.'{target}'.{section}"));
+        let toml_name = dep.explicit_name_in_toml.as_ref().unwrap_or(&dep.name);
+        let _ = write!(
+            manifest,
+            "\n[{section}.{toml_name}]\nversion = \"{}\"\npackage = \"{}\"\n",
+            dep.version_req, dep.name
+        );
+    }
+    manifest
+}
+
+/// Builds the payload sent by cargo to publish a crate, with the packaged crate
+fn crate_payload_with(metadata: &CrateMetadata, content: &[u8]) -> Result<Vec<u8>, ApiError> {
+    let metadata = serde_json::to_vec(metadata)?;
+    let mut payload = Vec::new();
+    payload.write_u32::<LittleEndian>(u32::try_from(metadata.len()).unwrap())?;
+    payload.extend_from_slice(&metadata);
+    payload.write_u32::<LittleEndian>(u32::try_from(content.len()).unwrap())?;
+    payload.extend_from_slice(content);
+    Ok(payload)
+}
+
+/// Builds the payload sent by cargo to publish a crate, with the files of the packaged crate
+/// The normalized `Cargo.toml` manifest is generated from the metadata.
+pub fn crate_payload(metadata: &CrateMetadata, files: &[(&str, &str)]) -> Result<Vec<u8>, ApiError> {
+    let manifest = crate_manifest(metadata);
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, content) in std::iter::once(&("Cargo.toml", manifest.as_str())).chain(files) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(
+            &mut header,
+            format!("{}-{}/{path}", metadata.name, metadata.vers),
+            content.as_bytes(),
+        )?;
+    }
+    let content = archive.into_inner()?.finish()?;
+    crate_payload_with(metadata, &content)
+}
+
+#[test]
+fn test_publish_policy_checks() {
+    let allowed = vec![String::from("MIT"), String::from("Apache-2.0")];
+    assert!(license_expression_allowed("MIT", &allowed));
+    assert!(license_expression_allowed("MIT OR GPL-3.0", &allowed));
+    assert!(license_expression_allowed("MIT/Apache-2.0", &allowed));
+    assert!(license_expression_allowed("(MIT AND apache-2.0) OR GPL-3.0", &allowed));
+    assert!(license_expression_allowed("Apache-2.0 WITH LLVM-exception", &allowed));
+    assert!(!license_expression_allowed("MIT AND GPL-3.0", &allowed));
+    assert!(!license_expression_allowed("MIT OR", &allowed));
+    let manifest = r#"
+[package]
+name = "acme"
+
+[dependencies]
+serde = "1"
+acme-core = { path = "../acme-core", version = "1" }
+acme-macros.path = "../acme-macros"
+acme-net = {
+    version = "1",
+    git = "https://git.acme.com/acme-net",
+}
+
+[target.'cfg(unix)'.dependencies]
+acme-unix = { git = "https://git.acme.com/acme-unix", version = "1" } # comment with path = "x"
+
+[target."cfg(windows)".dependencies.acme-windows]
+version = "1"
+
+[target."cfg(windows)".dependencies.acme-registry]
+workspace = true
+
+[dev-dependencies.acme-test]
+path = "../acme-test"
+
+[build-dependencies]
+acme-build = { workspace = true }
+acme-shared = { workspace = true, features = ["std"] }
+"#;
+    // the dependencies inherited from the workspace, as normalized by cargo
+    let normalized = r#"
+[package]
+name = "acme"
+
+[target."cfg(windows)".dependencies.acme-registry]
+version = "1"
+
+[build-dependencies]
+acme-shared = { version = "1", features = ["std"] }
+"#;
+    let manifest = toml::from_str::<toml::Table>(manifest).expect("the manifest is valid");
+    let normalized = toml::from_str::<toml::Table>(normalized).expect("the manifest is valid");
+    // dependencies with a version are published with it, dev-dependencies without a version are removed
+    assert_eq!(
+        find_local_dependencies(&manifest, &normalized),
+        vec![
+            (String::from("acme-macros"), "path"),
+            (String::from("acme-build"), "workspace")
+        ]
+    );
+    assert!(repository_has_prefix(
+        "https://git.acme.com/team/acme",
+        "https://git.acme.com/team"
+    ));
+    assert!(repository_has_prefix(
+        "https://git.acme.com/team/acme",
+        "https://git.acme.com/team/"
+    ));
+    assert!(repository_has_prefix(
+        "https://git.acme.com/team",
+        "https://git.acme.com/team"
+    ));
+    assert!(!repository_has_prefix(
+        "https://git.acme.com/team-evil/acme",
+        "https://git.acme.com/team"
+    ));
+    assert!(!repository_has_prefix(
+        "https://git.acme.com.evil.org/team",
+        "https://git.acme.com"
+    ));
+}
+
+#[test]
+fn test_publish_policy() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, "user", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: String::from("user"),
+            secret: setup_create_token(&application, ADMIN_UID + 1, true, true).await?,
+        });
+        // only admins manage the policy
+        let creation = PublishRuleCreation {
+            kind: PublishRuleKind::LicenseAllowlist,
+            parameter: String::from("MIT, Apache-2.0"),
+            is_blocking: true,
+        };
+        let error = application
+            .create_publish_rule(&user_auth, &creation)
+            .await
+            .expect_err("only admins can change the policy");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .create_publish_rule(
+                &admin_auth,
+                &PublishRuleCreation {
+                    kind: PublishRuleKind::MaxTarballSize,
+                    parameter: String::from("large"),
+                    is_blocking: true,
+                },
+            )
+            .await
+            .expect_err("the parameter is invalid");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let license_rule = application.create_publish_rule(&admin_auth, &creation).await?;
+        application
+            .create_publish_rule(
+                &admin_auth,
+                &PublishRuleCreation {
+                    kind: PublishRuleKind::NoPathOrGitDependencies,
+                    parameter: String::new(),
+                    is_blocking: true,
+                },
+            )
+            .await?;
+        application
+            .create_publish_rule(
+                &admin_auth,
+                &PublishRuleCreation {
+                    kind: PublishRuleKind::RustVersionRequired,
+                    parameter: String::new(),
+                    is_blocking: false,
+                },
+            )
+            .await?;
+        let error = application
+            .get_publish_rules(&user_auth)
+            .await
+            .expect_err("only admins can read the policy");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        assert_eq!(application.get_publish_rules(&admin_auth).await?.len(), 3);
+
+        // blocking rules reject the crate
+        let mut metadata = crate_metadata("acme", "1.0.0");
+        metadata.license = Some(String::from("GPL-3.0"));
+        let error = application
+            .publish_crate_version(&user_auth, &crate_payload(&metadata, &[])?)
+            .await
+            .expect_err("the license is not allowed");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        assert!(error.details.as_ref().is_some_and(|details| details.contains("GPL-3.0")));
+        let metadata = crate_metadata("acme", "1.0.0");
+        let manifest = "[dependencies]\nacme-core = { path = \"../acme-core\" }\n";
+        let error = application
+            .publish_crate_version(&user_auth, &crate_payload(&metadata, &[("Cargo.toml.orig", manifest)])?)
+            .await
+            .expect_err("path dependencies without a version are not allowed");
+        assert!(error.details.as_ref().is_some_and(|details| details.contains("acme-core")));
+        // other rules are reported as warnings
+        let result = application
+            .publish_crate_version(&user_auth, &crate_payload(&metadata, &[("Cargo.toml.orig", "")])?)
+            .await?;
+        assert!(result.warnings.other.iter().any(|warning| warning.contains("rust-version")));
+
+        // removing a rule lifts it
+        application.delete_publish_rule(&admin_auth, license_rule.id).await?;
+        let mut metadata = crate_metadata("acme", "1.0.1");
+        metadata.license = Some(String::from("GPL-3.0"));
+        metadata.rust_version = Some(String::from("1.85"));
+        let result = application
+            .publish_crate_version(&user_auth, &crate_payload(&metadata, &[("Cargo.toml.orig", "")])?)
+            .await?;
+        assert!(result.warnings.other.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_publish_policy_original_manifest() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        application
+            .create_publish_rule(
+                &admin_auth,
+                &PublishRuleCreation {
+                    kind: PublishRuleKind::NoPathOrGitDependencies,
+                    parameter: String::new(),
+                    is_blocking: true,
+                },
+            )
+            .await?;
+        let metadata = crate_metadata("acme", "1.0.0");
+        // the original manifest must be present and valid to be checked
+        let error = application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await
+            .expect_err("the original manifest is missing");
+        assert!(
+            error
+                .details
+                .as_ref()
+                .is_some_and(|details| details.contains("Cargo.toml.orig"))
+        );
+        let error = application
+            .publish_crate_version(
+                &admin_auth,
+                &crate_payload(&metadata, &[("Cargo.toml.orig", "[dependencies")])?,
+            )
+            .await
+            .expect_err("the original manifest is invalid");
+        assert!(
+            error
+                .details
+                .as_ref()
+                .is_some_and(|details| details.contains("Cargo.toml.orig"))
+        );
+        // path dependencies with a version are published with the version
+        let manifest = "[dependencies]\nacme-core = { path = \"../acme-core\", version = \"1\" }\n";
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[("Cargo.toml.orig", manifest)])?)
+            .await?;
+        // dev-dependencies without a version are removed from the published crate
+        let metadata = CrateMetadata {
+            vers: String::from("1.0.1"),
+            ..metadata
+        };
+        let manifest = "[dev-dependencies]\nacme-test = { path = \"../acme-test\" }\n";
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[("Cargo.toml.orig", manifest)])?)
+            .await?;
+        Ok(())
+    })
+}
+
+/// Expects the payload for a crate to be rejected
+fn expect_rejected(payload: &[u8]) -> CrateContentError {
+    let Err(error) = CrateUploadData::new(payload) else {
+        panic!("the payload should be rejected");
+    };
+    error
+}
+
+#[test]
+fn test_publish_cargo_package() -> Result<(), ApiError> {
+    let dependency = |name: &str, version_req: &str, kind: DependencyKind| CrateMetadataDependency {
+        name: name.to_string(),
+        version_req: version_req.to_string(),
+        kind,
+        default_features: true,
+        ..Default::default()
+    };
+    let mut metadata = crate_metadata("acme-fixture", "0.1.0");
+    metadata.license = Some(String::from("MIT OR Apache-2.0"));
+    metadata.deps = vec![
+        CrateMetadataDependency {
+            default_features: false,
+            ..dependency("serde", "^1.0", DependencyKind::Normal)
+        },
+        CrateMetadataDependency {
+            explicit_name_in_toml: Some(String::from("logging")),
+            ..dependency("log", "^0.4", DependencyKind::Normal)
+        },
+        CrateMetadataDependency {
+            target: Some(String::from("cfg(unix)")),
+            ..dependency("libc", "^0.2", DependencyKind::Normal)
+        },
+        dependency("cc", "^1.0", DependencyKind::Build),
+    ];
+    let package = CrateUploadData::new(&crate_payload_with(&metadata, CARGO_PACKAGE_FIXTURE)?)?;
+    assert!(package.files.manifest_orig.is_some());
+    assert_eq!(
+        read_crate_license("acme-fixture", "0.1.0", CARGO_PACKAGE_FIXTURE).as_deref(),
+        Some("MIT OR Apache-2.0")
+    );
+
+    // the dependencies declared by cargo must all be in the metadata
+    metadata.deps.pop();
+    let error = expect_rejected(&crate_payload_with(&metadata, CARGO_PACKAGE_FIXTURE)?);
+    assert!(error.to_string().contains("`cc`"));
+    Ok(())
+}
+
+#[test]
+fn test_publish_archive_checks() -> Result<(), ApiError> {
+    let mut metadata = crate_metadata("acme", "1.0.0");
+    metadata.deps.push(CrateMetadataDependency {
+        name: String::from("serde"),
+        version_req: String::from("1.0"),
+        kind: DependencyKind::Normal,
+        ..Default::default()
+    });
+    metadata.deps.push(CrateMetadataDependency {
+        name: String::from("libc"),
+        version_req: String::from("^0.2"),
+        target: Some(String::from("cfg(unix)")),
+        explicit_name_in_toml: Some(String::from("c")),
+        kind: DependencyKind::Build,
+        ..Default::default()
+    });
+    let payload = crate_payload(&metadata, &[("src/lib.rs", "")])?;
+    assert!(CrateUploadData::new(&payload).is_ok());
+
+    // the manifest does not match the metadata
+    let mut other = metadata.clone();
+    other.vers = String::from("1.0.1");
+    let content = CrateUploadData::new(&crate_payload(&other, &[])?)?.content;
+    let error = expect_rejected(&crate_payload_with(&metadata, &content)?);
+    assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+    let mut other = metadata.clone();
+    other.deps.pop();
+    let content = CrateUploadData::new(&crate_payload(&other, &[])?)?.content;
+    let error = expect_rejected(&crate_payload_with(&metadata, &content)?);
+    assert!(error.to_string().contains("`c`"));
+
+    // the files read by the registry are limited in size
+    let large = "#".repeat(usize::try_from(PACKAGED_FILE_MAX_SIZE).unwrap() + 1);
+    let error = expect_rejected(&crate_payload(&metadata, &[("Cargo.toml.orig", &large)])?);
+    assert!(matches!(error, CrateContentError::EntryTooLarge { .. }));
+
+    // the payload is truncated
+    assert!(CrateUploadData::new(&payload[..payload.len() - 1]).is_err());
+    assert!(CrateUploadData::new(&payload[..6]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_publish_archive_malicious() -> Result<(), ApiError> {
+    let metadata = crate_metadata("acme", "1.0.0");
+    let build = |entries: &[(&[u8], tar::EntryType)]| -> Result<Vec<u8>, ApiError> {
+        let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let manifest = crate_manifest(&metadata);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_path("acme-1.0.0/Cargo.toml")?;
+        header.set_cksum();
+        archive.append(&header, manifest.as_bytes())?;
+        for (path, entry_type) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_entry_type(*entry_type);
+            // write the raw path, bypassing the checks of the builder
+            header.as_old_mut().name[..path.len()].copy_from_slice(path);
+            if entry_type.is_symlink() {
+                header.set_link_name("/etc/passwd")?;
+            }
+            header.set_cksum();
+            archive.append(&header, std::io::empty())?;
+        }
+        let content = archive.into_inner()?.finish()?;
+        crate_payload_with(&metadata, &content)
+    };
+    for entries in [
+        &[(&b"acme-1.0.0/../../evil"[..], tar::EntryType::Regular)][..],
+        &[(&b"/tmp/evil"[..], tar::EntryType::Regular)][..],
+        &[(&b"other-1.0.0/evil"[..], tar::EntryType::Regular)][..],
+        &[(&b"acme-1.0.0/link"[..], tar::EntryType::Symlink)][..],
+        &[(&b"acme-1.0.0/link"[..], tar::EntryType::Link)][..],
+    ] {
+        let error = expect_rejected(&build(entries)?);
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+    }
+    let content = CrateUploadData::new(&build(&[])?)?.content;
+    assert!(check_crate_archive("acme", "1.0.0", &content).is_ok());
+    assert!(check_crate_archive("other", "1.0.0", &content).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_publish_archive_limits() -> Result<(), ApiError> {
+    let metadata = crate_metadata("acme", "1.0.0");
+    let data = "0".repeat(64 * 1024);
+    let files = [
+        ("src/a.rs", data.as_str()),
+        ("src/b.rs", data.as_str()),
+        ("src/c.rs", data.as_str()),
+    ];
+    let content = CrateUploadData::new(&crate_payload(&metadata, &files)?)?.content;
+    assert!(check_crate_archive("acme", "1.0.0", &content).is_ok());
+    assert!(check_crate_archive_with_limits("acme", "1.0.0", &content, 1024 * 1024, 4).is_ok());
+    // the content expands beyond the limit, even when the files are not read by the registry
+    let error =
+        check_crate_archive_with_limits("acme", "1.0.0", &content, 128 * 1024, 4).expect_err("the archive is too large");
+    assert!(matches!(error, CrateContentError::ArchiveTooLarge { .. }));
+    assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+    // too many entries
+    let error = check_crate_archive_with_limits("acme", "1.0.0", &content, 1024 * 1024, 3).expect_err("too many entries");
+    assert!(matches!(error, CrateContentError::TooManyEntries { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_publish_vcs_info() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        application
+            .create_publish_rule(
+                &admin_auth,
+                &PublishRuleCreation {
+                    kind: PublishRuleKind::CleanVcsRequired,
+                    parameter: String::new(),
+                    is_blocking: true,
+                },
+            )
+            .await?;
+        let metadata = crate_metadata("acme", "1.0.0");
+        let error = application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[])?)
+            .await
+            .expect_err("the crate has no VCS information");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let dirty = r#"{"git":{"sha1":"0123456789abcdef0123456789abcdef01234567","dirty":true},"path_in_vcs":"crates/acme"}"#;
+        let error = application
+            .publish_crate_version(&admin_auth, &crate_payload(&metadata, &[(".cargo_vcs_info.json", dirty)])?)
+            .await
+            .expect_err("the crate was packaged with uncommitted changes");
+        assert!(error.details.as_ref().is_some_and(|details| details.contains("uncommitted")));
+        let clean = dirty.replace("true", "false");
+        let payload = crate_payload(&metadata, &[("README.md", "# acme"), (".cargo_vcs_info.json", &clean)])?;
+        application.publish_crate_version(&admin_auth, &payload).await?;
+
+        // the README and the VCS information are read in the same pass
+        let files = extract_crate_files(&CrateUploadData::new(&payload)?.content)?;
+        assert_eq!(files.readme, b"# acme");
+        assert_eq!(files.vcs_info.and_then(|info| info.git).map(|git| git.dirty), Some(false));
+
+        // the VCS information is recorded for the version
+        let index_data = CrateUploadData::new(&payload)?.build_index_data();
+        let info = application
+            .db_transaction_read(|app| async move { app.database.get_crate_info("acme", vec![index_data]).await })
+            .await?;
+        let vcs = info.versions[0].vcs.as_ref().expect("the VCS information is recorded");
+        assert_eq!(vcs.path_in_vcs, "crates/acme");
+        assert_eq!(
+            vcs.git.as_ref().map(|git| (git.sha1.as_str(), git.dirty)),
+            Some(("0123456789abcdef0123456789abcdef01234567", false))
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn test_crate_dependents() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let with_dependency = |name: &str, version: &str, dependency: &str, version_req: &str, kind: DependencyKind| {
+            let mut metadata = crate_metadata(name, version);
+            metadata.deps.push(CrateMetadataDependency {
+                name: dependency.to_string(),
+                version_req: version_req.to_string(),
+                kind,
+                ..Default::default()
+            });
+            crate_payload(&metadata, &[])
+        };
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&crate_metadata("acme-core", "1.0.0"), &[])?)
+            .await?;
+        application
+            .publish_crate_version(
+                &admin_auth,
+                &with_dependency("acme", "1.0.0", "acme-core", "^1.0", DependencyKind::Normal)?,
+            )
+            .await?;
+        application
+            .publish_crate_version(
+                &admin_auth,
+                &with_dependency("acme", "1.1.0", "acme-core", "^1.0", DependencyKind::Normal)?,
+            )
+            .await?;
+        application
+            .publish_crate_version(
+                &admin_auth,
+                &with_dependency("acme-tests", "0.1.0", "acme-core", "^2", DependencyKind::Dev)?,
+            )
+            .await?;
+
+        let dependents = application.get_crate_dependents(&admin_auth, "acme-core").await?;
+        assert_eq!(dependents.head.as_deref(), Some("1.0.0"));
+        let summary = dependents
+            .dependents
+            .iter()
+            .map(|d| (d.package.as_str(), d.version.as_str(), d.kind, d.is_latest, d.is_compatible))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("acme", "1.1.0", DependencyKind::Normal, true, true),
+                ("acme", "1.0.0", DependencyKind::Normal, false, true),
+                ("acme-tests", "0.1.0", DependencyKind::Dev, true, false),
+            ]
+        );
+        assert!(
+            application
+                .get_crate_dependents(&admin_auth, "acme")
+                .await?
+                .dependents
+                .is_empty()
+        );
+
+        // a breaking release of the dependency
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&crate_metadata("acme-core", "2.0.0"), &[])?)
+            .await?;
+        let dependents = application.get_crate_dependents(&admin_auth, "acme-core").await?;
+        assert_eq!(dependents.head.as_deref(), Some("2.0.0"));
+        assert_eq!(
+            dependents.dependents.iter().map(|d| d.is_compatible).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+
+        // yanked versions are no longer dependents
+        application.yank_crate_version(&admin_auth, "acme", "1.1.0").await?;
+        let dependents = application.get_crate_dependents(&admin_auth, "acme-core").await?;
+        assert_eq!(
+            dependents
+                .dependents
+                .iter()
+                .map(|d| (d.package.as_str(), d.version.as_str(), d.is_latest))
+                .collect::<Vec<_>>(),
+            vec![("acme", "1.0.0", true), ("acme-tests", "0.1.0", true)]
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,269 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+ ******************************************************************************/
+
+//! Tests for the software bills of materials
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+use tokio::runtime::Builder;
+
+use super::async_test;
+use super::mocks::MockService;
+use crate::model::cargo::{DependencyKind, IndexCrateDependency, IndexCrateMetadata};
+use crate::model::deps::{CRATES_IO_REGISTRY_URI, DepsAnalysis, DepsGraph, DepsGraphCrateOrigin};
+use crate::model::packages::CrateInfoTarget;
+use crate::model::sbom::{Sbom, SbomComponent, SbomFormat};
+use crate::services::deps;
+use crate::services::index::{Index, IndexError, IndexFile};
+use crate::services::storage;
+use crate::tests::publish::{crate_metadata, crate_payload};
+use crate::utils::FaillibleFuture;
+use crate::utils::apierror::ApiError;
+
+const LOCAL_REGISTRY: &str = "https://cratery.acme.com/";
+
+/// Builds a dependency on crates.io
+fn dependency(name: &str, req: &str, kind: DependencyKind) -> IndexCrateDependency {
+    IndexCrateDependency {
+        name: name.to_string(),
+        req: req.to_string(),
+        kind,
+        default_features: true,
+        registry: Some(String::from(CRATES_IO_REGISTRY_URI)),
+        ..Default::default()
+    }
+}
+
+/// Builds the index metadata for a crate version
+fn version(name: &str, vers: &str, deps: Vec<IndexCrateDependency>) -> IndexCrateMetadata {
+    IndexCrateMetadata {
+        name: name.to_string(),
+        vers: vers.to_string(),
+        deps,
+        cksum: format!("{name}-{vers}-cksum"),
+        ..Default::default()
+    }
+}
+
+/// Gets the known versions for a crate on crates.io
+fn get_versions(name: &str) -> Vec<IndexCrateMetadata> {
+    match name {
+        "serde" => vec![
+            version("serde", "1.0.0", Vec::new()),
+            version("serde", "1.0.1", vec![dependency("itoa", "1", DependencyKind::Normal)]),
+        ],
+        "itoa" => vec![version("itoa", "1.0.5", Vec::new())],
+        "cc" => vec![version("cc", "1.2.0", Vec::new())],
+        "proptest" => vec![version("proptest", "1.4.0", Vec::new())],
+        _ => Vec::new(),
+    }
+}
+
+#[test]
+fn test_sbom_generation() -> Result<(), ApiError> {
+    let root = version(
+        "acme",
+        "1.0.0",
+        vec![
+            dependency("serde", "1.0", DependencyKind::Normal),
+            dependency("cc", "1", DependencyKind::Build),
+            dependency("proptest", "1", DependencyKind::Dev),
+        ],
+    );
+    let mut graph = DepsGraph::new(&[String::from("x86_64-unknown-linux-gnu")]);
+    let get_versions = |_registry: Option<String>, name: String| async move { Ok(get_versions(&name)) };
+    Builder::new_current_thread().enable_all().build()?.block_on(async {
+        for dep in &root.deps {
+            graph
+                .resolve(dep, &[], &[DepsGraphCrateOrigin::Direct(dep.kind)], &get_versions)
+                .await?;
+        }
+        graph.close(&get_versions).await
+    })?;
+
+    // all the resolved versions are recorded, including dev-dependencies
+    let analysis = DepsAnalysis::new(&graph, &root.deps, Vec::new(), Vec::new());
+    let mut resolutions = analysis
+        .resolutions
+        .iter()
+        .map(|r| (r.package.as_str(), r.version.as_str(), r.is_direct))
+        .collect::<Vec<_>>();
+    resolutions.sort_unstable();
+    assert_eq!(
+        resolutions,
+        vec![
+            ("cc", "1.2.0", true),
+            ("itoa", "1.0.5", false),
+            ("proptest", "1.4.0", true),
+            ("serde", "1.0.1", true)
+        ]
+    );
+
+    let mut component = SbomComponent::new(None, &root, LOCAL_REGISTRY);
+    component.license = Some(String::from("MIT"));
+    let sbom = Sbom::new(component, &root.deps, &graph, LOCAL_REGISTRY);
+    assert_eq!(
+        sbom.root.purl,
+        "pkg:cargo/acme@1.0.0?repository_url=https%3A%2F%2Fcratery.acme.com%2F"
+    );
+    // dev-dependencies are excluded
+    let names = sbom
+        .components
+        .iter()
+        .map(|c| (c.name.as_str(), c.version.as_str(), c.is_build_only))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![("serde", "1.0.1", false), ("cc", "1.2.0", true), ("itoa", "1.0.5", false)]
+    );
+    assert_eq!(sbom.components[0].purl, "pkg:cargo/serde@1.0.1");
+    assert_eq!(sbom.root.dependencies.len(), 2);
+    assert_eq!(sbom.components[0].dependencies[0].component, 2);
+
+    let cyclonedx = sbom.render(
+        SbomFormat::CycloneDx,
+        "https://cratery.acme.com/api/v1/crates/acme/1.0.0/sbom",
+    );
+    assert_eq!(cyclonedx["bomFormat"], "CycloneDX");
+    assert_eq!(cyclonedx["metadata"]["component"]["licenses"][0]["expression"], "MIT");
+    assert_eq!(cyclonedx["components"][1]["scope"], "excluded");
+    assert_eq!(cyclonedx["components"][0]["hashes"][0]["content"], "serde-1.0.1-cksum");
+    assert_eq!(cyclonedx["dependencies"][1]["dependsOn"][0], "pkg:cargo/itoa@1.0.5");
+    let spdx = sbom.render(SbomFormat::Spdx, "https://cratery.acme.com/api/v1/crates/acme/1.0.0/sbom");
+    assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+    assert_eq!(spdx["packages"].as_array().map(Vec::len), Some(4));
+    assert_eq!(spdx["packages"][1]["licenseDeclared"], "NOASSERTION");
+    let relationships = spdx["relationships"].as_array().cloned().unwrap_or_default();
+    assert!(relationships.iter().any(|r| r["relationshipType"] == "BUILD_DEPENDENCY_OF"
+        && r["spdxElementId"] == "SPDXRef-Component-1"
+        && r["relatedSpdxElement"] == "SPDXRef-Root"));
+    Ok(())
+}
+
+/// An index of local crates, in memory
+#[derive(Default)]
+struct MemoryIndex {
+    /// The versions of each crate
+    crates: Mutex<HashMap<String, Vec<IndexCrateMetadata>>>,
+}
+
+impl Index for MemoryIndex {
+    fn get_index_file<'a>(&'a self, _file_path: &'a Path) -> FaillibleFuture<'a, Option<IndexFile>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn get_upload_pack_info_refs(&self) -> FaillibleFuture<'_, Vec<u8>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn get_upload_pack_for<'a>(&'a self, _input: &'a [u8]) -> FaillibleFuture<'a, Vec<u8>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn publish_crate_version<'a>(&'a self, metadata: &'a IndexCrateMetadata) -> FaillibleFuture<'a, ()> {
+        self.crates
+            .lock()
+            .unwrap()
+            .entry(metadata.name.clone())
+            .or_default()
+            .push(metadata.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove_crate_version<'a>(&'a self, _package: &'a str, _version: &'a str) -> BoxFuture<'a, Result<(), IndexError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get_crate_data<'a>(&'a self, package: &'a str) -> BoxFuture<'a, Result<Vec<IndexCrateMetadata>, IndexError>> {
+        let versions = self.crates.lock().unwrap().get(package).cloned().unwrap_or_default();
+        Box::pin(async { Ok(versions) })
+    }
+}
+
+#[test]
+fn test_sbom_refresh() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let index = Arc::new(MemoryIndex::default());
+        let local = |name: &str, vers: &str, deps: Vec<IndexCrateDependency>| IndexCrateMetadata {
+            deps: deps
+                .into_iter()
+                .map(|dep| IndexCrateDependency { registry: None, ..dep })
+                .collect(),
+            ..version(name, vers, Vec::new())
+        };
+        index.publish_crate_version(&local("base", "1.0.0", Vec::new())).await?;
+        index
+            .publish_crate_version(&local("acme", "1.0.0", vec![dependency("base", "1", DependencyKind::Normal)]))
+            .await?;
+        let service_storage = storage::get_service(&application.configuration);
+        let service_deps_checker = deps::get_service(
+            application.configuration.clone(),
+            index.clone(),
+            Arc::new(MockService),
+            service_storage.clone(),
+            Arc::new(MockService),
+            reqwest::Client::new(),
+        );
+        let stored_base_version = || async {
+            let content = service_storage
+                .download_crate_sbom("acme", "1.0.0", SbomFormat::CycloneDx)
+                .await?
+                .expect("the SBOM is stored");
+            let document = serde_json::from_slice::<serde_json::Value>(&content)?;
+            Ok::<_, ApiError>(document["components"][0]["version"].as_str().map(str::to_string))
+        };
+
+        // the SBOM documents are stored on analysis, in all formats
+        service_deps_checker.analyze_crate("acme", "1.0.0", &[]).await?;
+        assert_eq!(stored_base_version().await?.as_deref(), Some("1.0.0"));
+        assert!(
+            service_storage
+                .download_crate_sbom("acme", "1.0.0", SbomFormat::Spdx)
+                .await?
+                .is_some()
+        );
+        // the stored SBOM is replaced when the resolution changes
+        index.publish_crate_version(&local("base", "1.1.0", Vec::new())).await?;
+        service_deps_checker.analyze_crate("acme", "1.0.0", &[]).await?;
+        assert_eq!(stored_base_version().await?.as_deref(), Some("1.1.0"));
+
+        // the SBOM is not served before the dependencies of the version are resolved
+        application
+            .publish_crate_version(&admin_auth, &crate_payload(&crate_metadata("acme", "1.0.0"), &[])?)
+            .await?;
+        let error = application
+            .get_crate_version_sbom(&admin_auth, "acme", "1.0.0", SbomFormat::CycloneDx)
+            .await
+            .expect_err("the SBOM is not generated yet");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        let is_resolved = || async {
+            application
+                .db_transaction_read(|app| async move { app.database.get_crate_deps_resolved("acme", "1.0.0").await })
+                .await
+        };
+        application
+            .db_transaction_write("test_sbom_refresh", |app| async move {
+                app.database.set_crate_deps_resolutions("acme", "1.0.0", &[]).await
+            })
+            .await?;
+        assert!(is_resolved().await?);
+        // changing the targets of the crate requires a new resolution
+        let targets = [CrateInfoTarget {
+            target: String::from("x86_64-unknown-linux-gnu"),
+            docs_use_native: false,
+        }];
+        let targets = &targets;
+        application
+            .db_transaction_write("test_sbom_refresh", |app| async move {
+                app.database.set_crate_targets("acme", targets).await
+            })
+            .await?;
+        assert!(!is_resolved().await?);
+        Ok(())
+    })
+}
@@ -4,12 +4,34 @@
 
 //! Tests about security checks
 
+use std::error::Error;
+
+use axum::http::StatusCode;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Local, TimeDelta};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P384_SHA384_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+
 use super::{async_test, setup_create_user_inactive};
 use crate::application::Application;
-use crate::model::auth::ROLE_ADMIN;
-use crate::tests::{ADMIN_NAME, ADMIN_UID, setup_create_token, setup_create_user};
+use crate::model::auth::{
+    DEVICE_CODE_GRANT_TYPE, DeviceAuthorizationApproval, DeviceTokenPoll, DeviceTokenRequest, EndpointScope, OAuthLoginRequest,
+    ROLE_ADMIN, RegistryUserTokenCreation, TokenExpiryOptions, TokenScopes,
+};
+use crate::model::cargo::{CrateUploadData, RegistryUser};
+use crate::model::config::{Configuration, OAuthClaimMapping};
+use crate::model::reservations::{NameReservationCreation, name_pattern_matches};
+use crate::model::teams::TeamMembership;
+use crate::model::typosquat::{NameSimilarity, TyposquatPolicy, name_similarity};
+use crate::services::database::users::OAuthLoginError;
+use crate::tests::publish::{crate_metadata, crate_payload};
+use crate::tests::{ADMIN_NAME, ADMIN_UID, setup_create_package, setup_create_token, setup_create_user};
 use crate::utils::apierror::ApiError;
 use crate::utils::axum::auth::{AuthData, Token};
+use crate::utils::jwt::{JwkSet, Jwt, JwtError, pkce_challenge};
+use crate::utils::paseto::public_key_id;
+use crate::utils::token::{generate_token_id, generate_token_with_id, parse_token_id};
 
 /// Creates authentication data for the admin in read-only
 async fn create_auth_admin_ro(application: &Application) -> Result<AuthData, ApiError> {
@@ -131,3 +153,1310 @@ fn test_get_users_admin_only() -> Result<(), ApiError> {
         Ok(())
     })
 }
+
+#[test]
+fn test_crates_io_proxy_disabled_by_default() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let error = application
+            .get_crates_io_index_file(&admin_auth, "config.json")
+            .await
+            .expect_err("the proxy must be disabled");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        let error = application
+            .get_crates_io_crate_content(&admin_auth, "serde", "1.0.0")
+            .await
+            .expect_err("the proxy must be disabled");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        Ok(())
+    })
+}
+
+/// A key pair to sign asymmetric tokens, as cargo would
+struct AsymmetricKey {
+    key_pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl AsymmetricKey {
+    fn generate() -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P384_SHA384_FIXED_SIGNING, &rng).unwrap();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P384_SHA384_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        Self { key_pair, rng }
+    }
+
+    /// Gets the compressed public key
+    fn compressed(&self) -> Vec<u8> {
+        let uncompressed = self.key_pair.public_key().as_ref();
+        let mut compressed = vec![0x02 | (uncompressed[96] & 1)];
+        compressed.extend_from_slice(&uncompressed[1..49]);
+        compressed
+    }
+
+    /// Gets the public key in the PASERK format
+    fn paserk(&self) -> String {
+        format!("k3.public.{}", URL_SAFE_NO_PAD.encode(self.compressed()))
+    }
+
+    /// Produces a signed PASETO v3.public token
+    fn sign(&self, url: &str, claims: &serde_json::Value) -> String {
+        let message = serde_json::to_vec(claims).unwrap();
+        let footer = serde_json::to_vec(&serde_json::json!({
+            "url": url,
+            "kip": public_key_id(&self.paserk()),
+        }))
+        .unwrap();
+        let mut pae = Vec::new();
+        let pieces: [&[u8]; 5] = [&self.compressed(), b"v3.public.", &message, &footer, b""];
+        pae.extend_from_slice(&(pieces.len() as u64).to_le_bytes());
+        for piece in pieces {
+            pae.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+            pae.extend_from_slice(piece);
+        }
+        let signature = self.key_pair.sign(&self.rng, &pae).unwrap();
+        let mut payload = message;
+        payload.extend_from_slice(signature.as_ref());
+        format!(
+            "v3.public.{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(footer)
+        )
+    }
+
+    /// Gets the authentication data for a signed token
+    fn auth(&self, url: &str, claims: &serde_json::Value) -> AuthData {
+        let mut auth_data = AuthData::default();
+        auth_data.asymmetric_token = Some(self.sign(url, claims));
+        auth_data
+    }
+}
+
+#[test]
+fn test_asymmetric_token_read_only() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let key = AsymmetricKey::generate();
+        // invalid keys are rejected
+        let error = application
+            .add_key(&admin_auth, "invalid", "k3.public.invalid", true)
+            .await
+            .expect_err("invalid key must be rejected");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let registered = application.add_key(&admin_auth, "laptop", &key.paserk(), true).await?;
+        assert_eq!(registered.key_id, public_key_id(&key.paserk()));
+
+        let claims = serde_json::json!({ "iat": Local::now().to_rfc3339() });
+        let authentication = application
+            .authenticate(&key.auth("sparse+http://localhost/", &claims))
+            .await?;
+        assert_eq!(authentication.uid()?, ADMIN_UID);
+        assert!(!authentication.can_write);
+        assert!(!authentication.can_admin);
+
+        // the token is bound to the registry
+        let error = application
+            .authenticate(&key.auth("sparse+https://other.registry/", &claims))
+            .await
+            .expect_err("the url must match");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        // old tokens are rejected
+        let claims = serde_json::json!({ "iat": (Local::now() - TimeDelta::hours(1)).to_rfc3339() });
+        let error = application
+            .authenticate(&key.auth("sparse+http://localhost/", &claims))
+            .await
+            .expect_err("old tokens must be rejected");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        // unknown keys are rejected
+        let claims = serde_json::json!({ "iat": Local::now().to_rfc3339() });
+        let error = application
+            .authenticate(&AsymmetricKey::generate().auth("sparse+http://localhost/", &claims))
+            .await
+            .expect_err("unknown keys must be rejected");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        // removed keys are rejected
+        application.remove_key(&admin_auth, registered.id).await?;
+        let error = application
+            .authenticate(&key.auth("sparse+http://localhost/", &claims))
+            .await
+            .expect_err("removed keys must be rejected");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_asymmetric_token_mutation_claims() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let key = AsymmetricKey::generate();
+        application.add_key(&admin_auth, "laptop", &key.paserk(), true).await?;
+        let claims = |mutation: &str, name: &str, vers: &str| {
+            serde_json::json!({
+                "iat": Local::now().to_rfc3339(),
+                "mutation": mutation,
+                "name": name,
+                "vers": vers,
+                "v": 1,
+            })
+        };
+        // read-only token
+        let auth = key.auth("http://localhost", &serde_json::json!({ "iat": Local::now().to_rfc3339() }));
+        let error = application
+            .yank_crate_version(&auth, "pkg", "1.0.0")
+            .await
+            .expect_err("a read-only token cannot yank");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        // mismatching claims
+        for (mutation, name, vers) in [
+            ("unyank", "pkg", "1.0.0"),
+            ("yank", "other", "1.0.0"),
+            ("yank", "pkg", "2.0.0"),
+        ] {
+            let auth = key.auth("http://localhost", &claims(mutation, name, vers));
+            let error = application
+                .yank_crate_version(&auth, "pkg", "1.0.0")
+                .await
+                .expect_err("mismatching claims must be rejected");
+            assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        }
+        // matching claims pass authentication, the crate does not exist
+        let auth = key.auth("http://localhost", &claims("yank", "pkg", "1.0.0"));
+        let error = application
+            .yank_crate_version(&auth, "pkg", "1.0.0")
+            .await
+            .expect_err("the crate does not exist");
+        assert_ne!(error.http, StatusCode::UNAUTHORIZED);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_scopes_matching() {
+    let scopes = TokenScopes::parse("acme-*, serde", "yank,change-owners").unwrap();
+    assert_eq!(scopes.crates, vec![String::from("acme-*"), String::from("serde")]);
+    assert_eq!(scopes.endpoints, vec![EndpointScope::Yank, EndpointScope::ChangeOwners]);
+    assert!(scopes.is_crate_allowed("acme-net"));
+    assert!(scopes.is_crate_allowed("Acme_Net"));
+    assert!(scopes.is_crate_allowed("serde"));
+    assert!(!scopes.is_crate_allowed("serde-json"));
+    assert!(!scopes.is_crate_allowed("acme"));
+    assert!(scopes.check(Some(EndpointScope::Yank), "acme-net").is_ok());
+    assert!(scopes.check(Some(EndpointScope::PublishNew), "acme-net").is_err());
+    assert!(scopes.check(None, "acme-net").is_err());
+    assert!(TokenScopes::default().check(None, "anything").is_ok());
+    assert!(TokenScopes::parse("acme*net", "").is_err());
+    assert!(TokenScopes::parse("", "delete").is_err());
+}
+
+#[test]
+fn test_scoped_token_enforcement() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let options = |crate_scopes: &str, endpoint_scopes: &str| RegistryUserTokenCreation {
+            can_write: true,
+            crate_scopes: crate_scopes.to_string(),
+            endpoint_scopes: endpoint_scopes.to_string(),
+            ..Default::default()
+        };
+        let error = application
+            .create_token(&admin_auth, "invalid", &options("", "delete"))
+            .await
+            .expect_err("unknown endpoint scopes are rejected");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let token = application
+            .create_token(&admin_auth, "scoped", &options("acme-*", "yank"))
+            .await?;
+        assert_eq!(token.scopes.endpoints, vec![EndpointScope::Yank]);
+        let auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: token.secret,
+        });
+        // out of the crate scopes
+        let error = application
+            .yank_crate_version(&auth, "other", "1.0.0")
+            .await
+            .expect_err("the crate is out of scope");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // out of the endpoint scopes
+        let error = application
+            .add_crate_owners(&auth, "acme-net", &[String::from("someone")])
+            .await
+            .expect_err("changing owners is out of scope");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .set_crate_deprecation(&auth, "acme-net", true)
+            .await
+            .expect_err("operations without an endpoint scope are out of scope");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // in scope, the crate does not exist
+        let error = application
+            .yank_crate_version(&auth, "acme-net", "1.0.0")
+            .await
+            .expect_err("the crate does not exist");
+        assert_ne!(error.http, StatusCode::FORBIDDEN);
+        // the scopes are listed with the tokens
+        let tokens = application.get_tokens(&admin_auth).await?;
+        let scoped = tokens.iter().find(|t| t.name == "scoped").unwrap();
+        assert_eq!(scoped.scopes.crates, vec![String::from("acme-*")]);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_scoped_token_cannot_escalate() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let options = |crate_scopes: &str| RegistryUserTokenCreation {
+            can_write: true,
+            can_admin: true,
+            crate_scopes: crate_scopes.to_string(),
+            ..Default::default()
+        };
+        let initial = application.get_tokens(&admin_auth).await?.len();
+        let scoped = application.create_token(&admin_auth, "scoped", &options("acme-*")).await?;
+        let auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: scoped.secret,
+        });
+        // a scoped token cannot mint an unscoped token, nor another scoped one
+        let error = application
+            .create_token(&auth, "unscoped", &options(""))
+            .await
+            .expect_err("a scoped token cannot create tokens");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .create_token(&auth, "narrower", &options("acme-net"))
+            .await
+            .expect_err("a scoped token cannot create tokens");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // nor obtain the secret of another token
+        let unscoped = application.create_token(&admin_auth, "unscoped", &options("")).await?;
+        let error = application
+            .rotate_token(&auth, unscoped.id, TokenExpiryOptions::default())
+            .await
+            .expect_err("a scoped token cannot rotate tokens");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // nor administrate the registry
+        let error = application
+            .create_global_token(&auth, "ci", TokenExpiryOptions::default())
+            .await
+            .expect_err("a scoped token cannot create global tokens");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .get_users(&auth)
+            .await
+            .expect_err("a scoped token cannot list users");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // nor change the members of the teams owning crates
+        application.create_team(&admin_auth, "core").await?;
+        let membership = TeamMembership {
+            email: ADMIN_NAME.to_string(),
+            is_maintainer: true,
+        };
+        let error = application
+            .set_team_member(&auth, "core", &membership)
+            .await
+            .expect_err("a scoped token cannot add team members");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        application.set_team_member(&admin_auth, "core", &membership).await?;
+        let error = application
+            .remove_team_member(&auth, "core", ADMIN_NAME)
+            .await
+            .expect_err("a scoped token cannot remove team members");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        assert_eq!(application.get_team(&admin_auth, "core").await?.members.len(), 1);
+        assert_eq!(application.get_tokens(&admin_auth).await?.len(), initial + 2);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_expiry() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let error = application
+            .create_token(
+                &admin_auth,
+                "invalid",
+                &RegistryUserTokenCreation {
+                    expires_in_days: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect_err("the validity must be positive");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let token = application
+            .create_token(
+                &admin_auth,
+                "expiring",
+                &RegistryUserTokenCreation {
+                    expires_in_days: Some(30),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert!(token.expires_at.is_some());
+        let auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: token.secret.clone(),
+        });
+        application.get_current_user(&auth).await?;
+        // move the expiration date in the past
+        application
+            .db_transaction_write("test_token_expiry", |app| async move {
+                sqlx::query("UPDATE RegistryUserToken SET expiresAt = $2 WHERE id = $1")
+                    .bind(token.id)
+                    .bind(Local::now().naive_local() - TimeDelta::minutes(1))
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await
+            })
+            .await?;
+        let error = application.get_current_user(&auth).await.expect_err("the token is expired");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_rotation() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let token = application
+            .create_token(&admin_auth, "rotated", &RegistryUserTokenCreation::default())
+            .await?;
+        let old_auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: token.secret.clone(),
+        });
+        let rotated = application
+            .rotate_token(
+                &admin_auth,
+                token.id,
+                TokenExpiryOptions {
+                    expires_in_days: Some(10),
+                },
+            )
+            .await?;
+        assert_eq!(rotated.id, token.id);
+        assert_ne!(rotated.secret, token.secret);
+        assert!(rotated.expires_at.is_some());
+        let new_auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: rotated.secret.clone(),
+        });
+        // both secrets are valid during the grace period
+        application.get_current_user(&new_auth).await?;
+        application.get_current_user(&old_auth).await?;
+        let tokens = application.get_tokens(&admin_auth).await?;
+        let listed = tokens.iter().find(|t| t.id == token.id).unwrap();
+        assert!(listed.previous_expires_at.is_some());
+        // rotating again drops the first secret
+        let again = application
+            .rotate_token(&admin_auth, token.id, TokenExpiryOptions::default())
+            .await?;
+        // the expiration date is recomputed from now with the same validity
+        assert!(again.expires_at >= rotated.expires_at);
+        let error = application
+            .get_current_user(&old_auth)
+            .await
+            .expect_err("the first secret is no longer valid");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        application.get_current_user(&new_auth).await?;
+        // unknown token
+        let error = application
+            .rotate_token(&admin_auth, 1000, TokenExpiryOptions::default())
+            .await
+            .expect_err("the token does not exist");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_rotation_expired() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let token = application
+            .create_token(
+                &admin_auth,
+                "expired",
+                &RegistryUserTokenCreation {
+                    expires_in_days: Some(30),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        // move the expiration date in the past
+        application
+            .db_transaction_write("test_token_rotation_expired", |app| async move {
+                sqlx::query("UPDATE RegistryUserToken SET expiresAt = $2 WHERE id = $1")
+                    .bind(token.id)
+                    .bind(Local::now().naive_local() - TimeDelta::days(1))
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await
+            })
+            .await?;
+        // the rotated token is valid for the same duration, from now
+        let rotated = application
+            .rotate_token(&admin_auth, token.id, TokenExpiryOptions::default())
+            .await?;
+        let expires_at = rotated.expires_at.unwrap();
+        assert!(expires_at > Local::now().naive_local() + TimeDelta::days(29));
+        let auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: rotated.secret,
+        });
+        application.get_current_user(&auth).await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_expiry_notify_failures() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        setup_create_user(&application, "undeliverable@example.com", "").await?;
+        setup_create_token(&application, ADMIN_UID, true, true).await?;
+        setup_create_token(&application, 2, true, true).await?;
+        // all the tokens expire tomorrow
+        application
+            .db_transaction_write("test_token_expiry_notify_failures", |app| async move {
+                sqlx::query("UPDATE RegistryUserToken SET expiresAt = $1")
+                    .bind(Local::now().naive_local() + TimeDelta::days(1))
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await
+            })
+            .await?;
+        application.token_expiry_notify().await?;
+        // the failure for one user does not prevent the notification of the others
+        let notified = application
+            .db_transaction_read(|app| async move {
+                sqlx::query_as::<_, (i64, bool)>("SELECT user, expiryNotified FROM RegistryUserToken ORDER BY id")
+                    .fetch_all(&mut *app.database.transaction.borrow().await)
+                    .await
+            })
+            .await?;
+        assert!(notified.iter().any(|(user, _)| *user == 2));
+        for (user, is_notified) in notified {
+            assert_eq!(is_notified, user == ADMIN_UID);
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_global_token_rotation() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let token = application
+            .create_global_token(
+                &admin_auth,
+                "ci",
+                TokenExpiryOptions {
+                    expires_in_days: Some(5),
+                },
+            )
+            .await?;
+        let rotated = application
+            .rotate_global_token(&admin_auth, token.id, TokenExpiryOptions::default())
+            .await?;
+        assert!(rotated.expires_at >= token.expires_at);
+        for secret in [token.secret, rotated.secret] {
+            let auth = AuthData::from(Token {
+                id: String::from("ci"),
+                secret,
+            });
+            application.get_registry_information(&auth).await?;
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_id_format() {
+    let token_id = generate_token_id();
+    let secret = generate_token_with_id(&token_id, 64);
+    assert!(secret.starts_with("crt_"));
+    assert_eq!(parse_token_id(&secret), Some(token_id.as_str()));
+    assert_eq!(parse_token_id("crt_0123456789abcdef"), None);
+    assert_eq!(parse_token_id("crt_xyz_secret"), None);
+    assert_eq!(parse_token_id("QUJDREVGR0hJSktMTU5PUA=="), None);
+}
+
+#[test]
+fn test_token_lookup_by_id() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let token = application
+            .create_token(&admin_auth, "with-id", &RegistryUserTokenCreation::default())
+            .await?;
+        assert!(parse_token_id(&token.secret).is_some());
+        application
+            .get_current_user(&AuthData::from(Token {
+                id: ADMIN_NAME.to_string(),
+                secret: token.secret.clone(),
+            }))
+            .await?;
+        // the token is bound to its user
+        setup_create_user(&application, USER_NAME, "").await?;
+        let error = application
+            .get_current_user(&AuthData::from(Token {
+                id: USER_NAME.to_string(),
+                secret: token.secret.clone(),
+            }))
+            .await
+            .expect_err("the token belongs to another user");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_token_legacy_format_migration() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        // tokens in the legacy format remain valid
+        let legacy_secret = setup_create_token(&application, ADMIN_UID, false, false).await?;
+        assert!(parse_token_id(&legacy_secret).is_none());
+        let legacy_auth = AuthData::from(Token {
+            id: ADMIN_NAME.to_string(),
+            secret: legacy_secret,
+        });
+        application.get_current_user(&legacy_auth).await?;
+        // rotating a legacy token yields a secret with an identifier
+        let token_id = application
+            .get_tokens(&admin_auth)
+            .await?
+            .into_iter()
+            .map(|token| token.id)
+            .max()
+            .unwrap();
+        let rotated = application
+            .rotate_token(&admin_auth, token_id, TokenExpiryOptions::default())
+            .await?;
+        assert!(parse_token_id(&rotated.secret).is_some());
+        // the legacy secret is still valid during the grace period
+        application.get_current_user(&legacy_auth).await?;
+        application
+            .get_current_user(&AuthData::from(Token {
+                id: ADMIN_NAME.to_string(),
+                secret: rotated.secret,
+            }))
+            .await?;
+        Ok(())
+    })
+}
+
+/// Gets the RFC 8628 error code for a device token request
+async fn poll_device_error(application: &Application, device_code: &str) -> Result<&'static str, ApiError> {
+    let poll = application
+        .poll_device_authorization(&DeviceTokenRequest {
+            grant_type: DEVICE_CODE_GRANT_TYPE.to_string(),
+            device_code: device_code.to_string(),
+        })
+        .await?;
+    Ok(poll.error_code().expect("the request shall not be granted"))
+}
+
+#[test]
+fn test_device_authorization_flow() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let request = application.request_device_authorization().await?;
+        assert!(request.verification_uri_complete.ends_with(&request.user_code));
+        assert_eq!(
+            poll_device_error(&application, &request.device_code).await?,
+            "authorization_pending"
+        );
+        // polling too fast
+        assert_eq!(poll_device_error(&application, &request.device_code).await?, "slow_down");
+        // approve with a lower case code, as typed by a user
+        application
+            .decide_device_authorization(
+                &admin_auth,
+                &DeviceAuthorizationApproval {
+                    user_code: request.user_code.to_lowercase().replace('-', ""),
+                    approve: true,
+                    name: String::from("laptop"),
+                    options: RegistryUserTokenCreation {
+                        can_write: true,
+                        ..Default::default()
+                    },
+                },
+            )
+            .await?;
+        application
+            .db_transaction_write("test_device_authorization_flow", |app| async move {
+                sqlx::query("UPDATE DeviceAuthorization SET lastPolled = NULL")
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await
+            })
+            .await?;
+        let poll = application
+            .poll_device_authorization(&DeviceTokenRequest {
+                grant_type: DEVICE_CODE_GRANT_TYPE.to_string(),
+                device_code: request.device_code.clone(),
+            })
+            .await?;
+        let DeviceTokenPoll::Granted { login, token } = poll else {
+            panic!("the request shall be granted, got {poll:?}");
+        };
+        assert_eq!(login, ADMIN_NAME);
+        assert_eq!(token.name, "laptop");
+        assert!(token.can_write && !token.can_admin);
+        application
+            .get_current_user(&AuthData::from(Token {
+                id: login,
+                secret: token.secret,
+            }))
+            .await?;
+        // the device code is consumed
+        assert_eq!(poll_device_error(&application, &request.device_code).await?, "invalid_grant");
+        Ok(())
+    })
+}
+
+#[test]
+fn test_device_authorization_denied() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        let request = application.request_device_authorization().await?;
+        let approval = DeviceAuthorizationApproval {
+            user_code: request.user_code.clone(),
+            approve: false,
+            name: String::new(),
+            options: RegistryUserTokenCreation::default(),
+        };
+        application.decide_device_authorization(&admin_auth, &approval).await?;
+        assert_eq!(poll_device_error(&application, &request.device_code).await?, "access_denied");
+        // the request can no longer be decided upon
+        let error = application
+            .decide_device_authorization(&admin_auth, &approval)
+            .await
+            .expect_err("the request is no longer pending");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        // unsupported grant type
+        let poll = application
+            .poll_device_authorization(&DeviceTokenRequest {
+                grant_type: String::from("authorization_code"),
+                device_code: request.device_code,
+            })
+            .await?;
+        assert_eq!(poll.error_code(), Some("unsupported_grant_type"));
+        Ok(())
+    })
+}
+
+#[test]
+fn test_device_authorization_deleted_user() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, true).await?,
+        });
+        let request = application.request_device_authorization().await?;
+        application
+            .decide_device_authorization(
+                &user_auth,
+                &DeviceAuthorizationApproval {
+                    user_code: request.user_code.clone(),
+                    approve: true,
+                    name: String::from("laptop"),
+                    options: RegistryUserTokenCreation::default(),
+                },
+            )
+            .await?;
+        // the user is deleted before the device polls
+        application.delete_user(&admin_auth, USER_NAME).await?;
+        assert_eq!(poll_device_error(&application, &request.device_code).await?, "invalid_grant");
+        Ok(())
+    })
+}
+
+#[test]
+fn test_device_authorization_limit() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        for _ in 0..100 {
+            application.request_device_authorization().await?;
+        }
+        let error = application
+            .request_device_authorization()
+            .await
+            .expect_err("too many pending requests");
+        assert_eq!(error.http, StatusCode::TOO_MANY_REQUESTS);
+        // expired requests are purged and no longer count
+        application
+            .db_transaction_write("test_device_authorization_limit", |app| async move {
+                sqlx::query("UPDATE DeviceAuthorization SET expiresAt = $1")
+                    .bind(Local::now().naive_local() - TimeDelta::minutes(1))
+                    .execute(&mut *app.database.transaction.borrow().await)
+                    .await
+            })
+            .await?;
+        application.request_device_authorization().await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_oauth_login_state() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        let request = OAuthLoginRequest::new();
+        let login_uri = application.get_oauth_login_uri(&request).await?;
+        assert!(login_uri.contains(&request.state));
+        // the state returned by the identity provider must match
+        let error = application
+            .login_with_oauth_code("newcomer@example.com", "forged", &request)
+            .await
+            .expect_err("the state does not match");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        // the first login creates the user
+        let user = application
+            .login_with_oauth_code("newcomer@example.com", &request.state, &request)
+            .await?;
+        assert_eq!(user.login, "newcomer");
+        assert!(!user.roles.contains(ROLE_ADMIN));
+        let user = application
+            .login_with_oauth_code(ADMIN_NAME, &request.state, &request)
+            .await?;
+        assert_eq!(user.id, ADMIN_UID);
+        // inactive users cannot login
+        setup_create_user_inactive(&application, USER_NAME, "").await?;
+        let error = application
+            .login_with_oauth_code(USER_NAME, &request.state, &request)
+            .await
+            .expect_err("the user is inactive");
+        assert_eq!(error.http, StatusCode::UNAUTHORIZED);
+        Ok(())
+    })
+}
+
+/// Logs a user in with the specified claims and mapping rules
+async fn login_with_claims(
+    application: &Application,
+    configuration: &Configuration,
+    claims: serde_json::Value,
+) -> Result<(RegistryUser, Vec<String>), ApiError> {
+    let result = application
+        .db_transaction_write("login_with_claims", |app| async move {
+            let user = app.database.login_with_oauth_claims(configuration, &claims).await?;
+            let teams = sqlx::query_scalar::<_, String>(
+                "SELECT Team.name FROM TeamMember INNER JOIN Team ON TeamMember.team = Team.id WHERE TeamMember.user = $1 ORDER BY Team.name",
+            )
+            .bind(user.id)
+            .fetch_all(&mut *app.database.transaction.borrow().await)
+            .await
+            .map_err(OAuthLoginError::UserDatabaseRequest)?;
+            Ok::<_, OAuthLoginError>((user, teams))
+        })
+        .await?;
+    Ok(result)
+}
+
+#[test]
+fn test_oauth_claim_mappings() -> Result<(), ApiError> {
+    async_test(|application, _admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "reviewer").await?;
+        let configuration = Configuration {
+            oauth_claim_mappings: vec![
+                OAuthClaimMapping {
+                    claim: String::from("groups"),
+                    value: String::from("rust-admins"),
+                    roles: vec![String::from(ROLE_ADMIN)],
+                    teams: vec![String::from("core")],
+                },
+                OAuthClaimMapping {
+                    claim: String::from("org.department"),
+                    value: String::from("platform"),
+                    roles: Vec::new(),
+                    teams: vec![String::from("core"), String::from("platform")],
+                },
+            ],
+            ..Default::default()
+        };
+        // joining the group grants the role and the team
+        let (user, teams) = login_with_claims(
+            &application,
+            &configuration,
+            serde_json::json!({"email": USER_NAME, "groups": ["developers", "rust-admins"]}),
+        )
+        .await?;
+        assert_eq!(user.roles, "reviewer,admin");
+        assert_eq!(teams, vec![String::from("core")]);
+        // another rule keeps the shared team
+        let (user, teams) = login_with_claims(
+            &application,
+            &configuration,
+            serde_json::json!({"email": USER_NAME, "groups": "developers", "org": {"department": "platform"}}),
+        )
+        .await?;
+        assert_eq!(user.roles, "reviewer");
+        assert_eq!(teams, vec![String::from("core"), String::from("platform")]);
+        // leaving all groups revokes the managed roles and teams, but keeps the others
+        let (user, teams) = login_with_claims(&application, &configuration, serde_json::json!({"email": USER_NAME})).await?;
+        assert_eq!(user.roles, "reviewer");
+        assert!(teams.is_empty());
+        // the roles are persisted
+        let user = application
+            .db_transaction_read(|app| async move { app.database.get_user_profile(USER_UID).await })
+            .await?;
+        assert_eq!(user.roles, "reviewer");
+        Ok(())
+    })
+}
+
+/// Signs a JSON Web Token with an ECDSA P-256 key and gets the matching key set
+fn sign_jwt(alg: &str, claims: &serde_json::Value) -> (String, JwkSet) {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+    let header = serde_json::json!({"alg": alg, "kid": "key-1"});
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap()),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap())
+    );
+    let signature = key_pair.sign(&rng, signed.as_bytes()).unwrap();
+    let point = key_pair.public_key().as_ref();
+    let keys = serde_json::from_value(serde_json::json!({
+        "keys": [{
+            "kty": "EC",
+            "kid": "key-1",
+            "use": "sig",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        }]
+    }))
+    .unwrap();
+    (format!("{signed}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref())), keys)
+}
+
+#[test]
+fn test_jwt_verification() {
+    let claims = serde_json::json!({"iss": "https://idp.example.com", "sub": "1234", "nonce": "abc"});
+    let (token, keys) = sign_jwt("ES256", &claims);
+    let jwt = Jwt::parse(&token).unwrap();
+    assert!(jwt.verify(&keys).is_ok());
+    assert_eq!(jwt.claims, claims);
+
+    // tampered claims
+    let (header, rest) = token.split_once('.').unwrap();
+    let (_, signature) = rest.split_once('.').unwrap();
+    let forged_claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&serde_json::json!({"sub": "admin"})).unwrap());
+    let forged = Jwt::parse(&format!("{header}.{forged_claims}.{signature}")).unwrap();
+    assert!(matches!(forged.verify(&keys), Err(JwtError::InvalidSignature)));
+
+    // unsigned tokens are rejected
+    let (token, keys) = sign_jwt("none", &claims);
+    assert!(matches!(
+        Jwt::parse(&token).unwrap().verify(&keys),
+        Err(JwtError::UnsupportedAlgorithm(_))
+    ));
+
+    // unknown key
+    let (token, _) = sign_jwt("ES256", &claims);
+    assert!(matches!(
+        Jwt::parse(&token).unwrap().verify(&JwkSet::default()),
+        Err(JwtError::NoMatchingKey)
+    ));
+}
+
+#[test]
+fn test_pkce_challenge() {
+    // example from RFC 7636, appendix B
+    assert_eq!(
+        pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+}
+
+#[test]
+fn test_team_crate_owners() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_package(&application, "acme-net", ADMIN_UID).await?;
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, true).await?,
+        });
+        let membership = TeamMembership {
+            email: USER_NAME.to_string(),
+            is_maintainer: false,
+        };
+        // only admins create teams
+        let error = application
+            .create_team(&user_auth, "core")
+            .await
+            .expect_err("only admins can create teams");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .create_team(&admin_auth, "core team")
+            .await
+            .expect_err("the name is invalid");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        application.create_team(&admin_auth, "core").await?;
+        // teams are added as owners with their login
+        let error = application
+            .add_crate_owners(&admin_auth, "acme-net", &[String::from("team:unknown")])
+            .await
+            .expect_err("the team does not exist");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        application
+            .add_crate_owners(&admin_auth, "acme-net", &[String::from("team:core")])
+            .await?;
+        let error = application
+            .set_crate_deprecation(&user_auth, "acme-net", true)
+            .await
+            .expect_err("the user is not a member of the team");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // members of an owning team can manage the crate
+        application.set_team_member(&admin_auth, "core", &membership).await?;
+        application.set_crate_deprecation(&user_auth, "acme-net", true).await?;
+        let owners = application.get_crate_owners(&admin_auth, "acme-net").await?;
+        assert_eq!(owners.users.len(), 1);
+        assert_eq!(owners.teams.len(), 1);
+        assert_eq!(owners.teams[0].login(), "team:core");
+        assert_eq!(owners.teams[0].members[0].uid, USER_UID);
+        // only maintainers manage the members
+        let error = application
+            .set_team_member(&user_auth, "core", &membership)
+            .await
+            .expect_err("the user is not a maintainer");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        // the team can be the last owner, but cannot be removed then
+        application
+            .remove_crate_owners(&admin_auth, "acme-net", &[ADMIN_NAME.to_string()])
+            .await?;
+        let error = application
+            .remove_crate_owners(&admin_auth, "acme-net", &[String::from("team:core")])
+            .await
+            .expect_err("the team is the last owner");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let error = application
+            .delete_team(&admin_auth, "core")
+            .await
+            .expect_err("the team is the last owner");
+        assert_eq!(error.http, StatusCode::CONFLICT);
+        // leaving the team revokes the access
+        application.remove_team_member(&admin_auth, "core", USER_NAME).await?;
+        let error = application
+            .set_crate_deprecation(&user_auth, "acme-net", false)
+            .await
+            .expect_err("the user left the team");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_team_members_visibility() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_package(&application, "acme-net", ADMIN_UID).await?;
+        setup_create_user(&application, USER_NAME, "").await?;
+        setup_create_user(&application, "outsider", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, false, false).await?,
+        });
+        let outsider_auth = AuthData::from(Token {
+            id: String::from("outsider"),
+            secret: setup_create_token(&application, USER_UID + 1, false, false).await?,
+        });
+        application.create_team(&admin_auth, "core").await?;
+        let membership = TeamMembership {
+            email: USER_NAME.to_string(),
+            is_maintainer: false,
+        };
+        application.set_team_member(&admin_auth, "core", &membership).await?;
+        // admins and members see the members
+        assert_eq!(application.get_team(&admin_auth, "core").await?.members.len(), 1);
+        assert_eq!(application.get_team(&user_auth, "core").await?.members.len(), 1);
+        assert_eq!(application.get_teams(&user_auth).await?[0].members.len(), 1);
+        // other users only see the team
+        assert!(application.get_team(&outsider_auth, "core").await?.members.is_empty());
+        assert!(application.get_teams(&outsider_auth).await?[0].members.is_empty());
+        // the members of owning teams are notified along with the owners
+        application
+            .add_crate_owners(&admin_auth, "acme-net", &[String::from("team:core")])
+            .await?;
+        let owners = application.get_crate_owners(&admin_auth, "acme-net").await?;
+        assert_eq!(owners.emails(), vec![ADMIN_NAME.to_string(), USER_NAME.to_string()]);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_crate_readers() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_package(&application, "acme-secret", ADMIN_UID).await?;
+        setup_create_user(&application, USER_NAME, "").await?;
+        setup_create_user(&application, "auditor", "auditor").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, false).await?,
+        });
+        let auditor_auth = AuthData::from(Token {
+            id: String::from("auditor"),
+            secret: setup_create_token(&application, 3, false, false).await?,
+        });
+        // without readers, all users can read the crate
+        application.get_crate_owners(&user_auth, "acme-secret").await?;
+        assert_eq!(application.get_crates_stats(&user_auth).await?.total_crates, 1);
+        // only the owners set the readers, that must exist
+        let error = application
+            .set_crate_readers(&user_auth, "acme-secret", &[USER_NAME.to_string()])
+            .await
+            .expect_err("the user is not an owner");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .set_crate_readers(&admin_auth, "acme-secret", &[String::from("team:unknown")])
+            .await
+            .expect_err("the team does not exist");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        application
+            .set_crate_readers(&admin_auth, "acme-secret", &[String::from("role:auditor")])
+            .await?;
+        // the crate is hidden from other users, as if it did not exist
+        let error = application
+            .get_crate_owners(&user_auth, "acme-secret")
+            .await
+            .expect_err("the crate is hidden");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        let error = application
+            .check_crate_readable(&user_auth, "ACME-secret")
+            .await
+            .expect_err("the index file is hidden");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        assert_eq!(application.get_crates_stats(&user_auth).await?.total_crates, 0);
+        // readers with the role and owners can read
+        application.get_crate_owners(&auditor_auth, "acme-secret").await?;
+        application.check_crate_readable(&admin_auth, "acme-secret").await?;
+        assert_eq!(application.get_crates_stats(&admin_auth).await?.total_crates, 1);
+        // the git index cannot be filtered and is refused when some crates are hidden
+        let error = application
+            .check_git_index_readable(&user_auth)
+            .await
+            .expect_err("some crates are hidden");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        application.check_git_index_readable(&admin_auth).await?;
+        // readers through a team
+        application.create_team(&admin_auth, "readers").await?;
+        application
+            .set_team_member(
+                &admin_auth,
+                "readers",
+                &TeamMembership {
+                    email: USER_NAME.to_string(),
+                    is_maintainer: false,
+                },
+            )
+            .await?;
+        application
+            .set_crate_readers(&admin_auth, "acme-secret", &[String::from("team:readers")])
+            .await?;
+        application.get_crate_owners(&user_auth, "acme-secret").await?;
+        let error = application
+            .get_crate_owners(&auditor_auth, "acme-secret")
+            .await
+            .expect_err("the role is no longer a reader");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        // readers designated by their email
+        application.remove_team_member(&admin_auth, "readers", USER_NAME).await?;
+        application
+            .set_crate_readers(&admin_auth, "acme-secret", &[USER_NAME.to_string()])
+            .await?;
+        assert_eq!(
+            application.get_crate_readers(&user_auth, "acme-secret").await?,
+            vec![USER_NAME]
+        );
+        application.check_git_index_readable(&user_auth).await?;
+        // owners can always read their restricted crates
+        setup_create_package(&application, "acme-private", USER_UID).await?;
+        application
+            .set_crate_readers(&admin_auth, "acme-private", &[String::from("role:auditor")])
+            .await?;
+        application.check_crate_readable(&user_auth, "acme-private").await?;
+        assert_eq!(application.get_crates_stats(&user_auth).await?.total_crates, 2);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_name_reservations() -> Result<(), ApiError> {
+    assert!(name_pattern_matches("acme-payments-*", "acme-payments-core"));
+    assert!(name_pattern_matches("acme-payments-*", "Acme_Payments_Core"));
+    assert!(name_pattern_matches("acme-*-sys", "acme-net-sys"));
+    assert!(name_pattern_matches("acme-???", "acme-net"));
+    assert!(!name_pattern_matches("acme-payments-*", "acme-net"));
+    assert!(!name_pattern_matches("acme-*-sys", "acme-net"));
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, true).await?,
+        });
+        application.create_team(&admin_auth, "payments").await?;
+        let creation = NameReservationCreation {
+            pattern: String::from("acme-payments-*"),
+            owner: String::from("team:payments"),
+        };
+        // only admins manage the reservations
+        let error = application
+            .create_name_reservation(&user_auth, &creation)
+            .await
+            .expect_err("only admins can reserve names");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        let error = application
+            .create_name_reservation(
+                &admin_auth,
+                &NameReservationCreation {
+                    pattern: String::from("acme payments"),
+                    owner: ADMIN_NAME.to_string(),
+                },
+            )
+            .await
+            .expect_err("the pattern is invalid");
+        assert_eq!(error.http, StatusCode::BAD_REQUEST);
+        let reservation = application.create_name_reservation(&admin_auth, &creation).await?;
+        let error = application
+            .create_name_reservation(&admin_auth, &creation)
+            .await
+            .expect_err("the pattern is already reserved");
+        assert_eq!(error.http, StatusCode::CONFLICT);
+        assert_eq!(
+            application.get_name_reservations(&admin_auth).await?[0].owner,
+            "team:payments"
+        );
+        // only members of the team can publish new crates with a reserved name
+        let check = |uid: i64, package: &'static str| {
+            let application = &application;
+            async move {
+                application
+                    .db_transaction_read(|app| async move { app.database.check_name_reservation(uid, package).await })
+                    .await
+            }
+        };
+        check(USER_UID, "acme-net").await?;
+        let error = check(USER_UID, "acme-payments-core").await.expect_err("the name is reserved");
+        assert!(
+            error
+                .source()
+                .is_some_and(|source| source.to_string().contains("team:payments"))
+        );
+        assert_eq!(ApiError::from(error).http, StatusCode::FORBIDDEN);
+        check(ADMIN_UID, "acme-payments-core")
+            .await
+            .expect_err("the name is reserved");
+        application
+            .set_team_member(
+                &admin_auth,
+                "payments",
+                &TeamMembership {
+                    email: USER_NAME.to_string(),
+                    is_maintainer: false,
+                },
+            )
+            .await?;
+        check(USER_UID, "acme-payments-core").await?;
+        // removing the reservation frees the names
+        application.delete_name_reservation(&admin_auth, reservation.id).await?;
+        check(ADMIN_UID, "acme-payments-core").await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_typosquat_detection() -> Result<(), ApiError> {
+    assert_eq!(name_similarity("Serde", "serde"), Some(NameSimilarity::Identical));
+    assert_eq!(name_similarity("serde-json", "serde_json"), Some(NameSimilarity::Separators));
+    assert_eq!(name_similarity("serdejson", "serde_json"), Some(NameSimilarity::Separators));
+    assert_eq!(name_similarity("t0kio", "tokio"), Some(NameSimilarity::Confusable));
+    assert_eq!(name_similarity("wasrn", "wasm"), Some(NameSimilarity::Confusable));
+    assert_eq!(name_similarity("tokoi", "tokio"), Some(NameSimilarity::Typo));
+    assert_eq!(name_similarity("reqwests", "reqwest"), Some(NameSimilarity::Typo));
+    assert_eq!(name_similarity("rank", "rand"), Some(NameSimilarity::Typo));
+    assert_eq!(name_similarity("syn", "sync"), None);
+    assert_eq!(name_similarity("acme-net", "acme-core"), None);
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        setup_create_package(&application, "acme_json_ext", ADMIN_UID).await?;
+        let popular = vec![String::from("serde_json")];
+        let find = |uid: i64, package: &'static str| {
+            let application = &application;
+            let popular = &popular;
+            async move {
+                application
+                    .db_transaction_read(|app| async move { app.database.find_confusable_crate(uid, package, popular).await })
+                    .await
+            }
+        };
+        let collision = find(USER_UID, "serde-json").await?.expect("close to a popular crate");
+        assert!(collision.popular);
+        assert_eq!(collision.similar, "serde_json");
+        assert_eq!(collision.similarity, NameSimilarity::Separators);
+        let collision = find(USER_UID, "acme-json-ext").await?.expect("close to a local crate");
+        assert!(!collision.popular);
+        assert_eq!(collision.similar, "acme_json_ext");
+        // the crates of the publisher are not considered
+        assert!(find(ADMIN_UID, "acme-json-ext").await?.is_none());
+        assert!(find(USER_UID, "acme-yaml").await?.is_none());
+        // the crates hidden from the publisher are not considered
+        application
+            .set_crate_readers(&admin_auth, "acme_json_ext", &[ADMIN_NAME.to_string()])
+            .await?;
+        assert!(find(USER_UID, "acme-json-ext").await?.is_none());
+        assert!(find(USER_UID, "serde-json").await?.is_some());
+        Ok(())
+    })
+}
+
+#[test]
+fn test_typosquat_review() -> Result<(), ApiError> {
+    async_test(|application, admin_auth| async move {
+        setup_create_user(&application, USER_NAME, "").await?;
+        setup_create_user(&application, "other", "").await?;
+        let user_auth = AuthData::from(Token {
+            id: USER_NAME.to_string(),
+            secret: setup_create_token(&application, USER_UID, true, false).await?,
+        });
+        let other_auth = AuthData::from(Token {
+            id: String::from("other"),
+            secret: setup_create_token(&application, 3, false, false).await?,
+        });
+        let package = CrateUploadData::new(&crate_payload(&crate_metadata("serde-json", "1.0.0"), &[])?)?;
+        let result = application
+            .db_transaction_write("test_typosquat_review", |app| async move {
+                app.database
+                    .publish_crate_version(
+                        USER_UID,
+                        &package,
+                        None,
+                        TyposquatPolicy::Review,
+                        &[String::from("serde_json")],
+                    )
+                    .await
+            })
+            .await?;
+        assert!(result.warnings.other[0].contains("until an administrator approves it"));
+        // the crate is only visible to its owners and the administrators
+        application.check_crate_readable(&user_auth, "serde-json").await?;
+        application.check_crate_readable(&admin_auth, "serde-json").await?;
+        let error = application
+            .check_crate_readable(&other_auth, "serde-json")
+            .await
+            .expect_err("the crate is held for review");
+        assert_eq!(error.http, StatusCode::NOT_FOUND);
+        // the readers set by the owners do not apply until the crate is approved
+        application
+            .set_crate_readers(&user_auth, "serde-json", &[String::from("other")])
+            .await?;
+        application
+            .check_crate_readable(&other_auth, "serde-json")
+            .await
+            .expect_err("the crate is held for review");
+        // only admins manage the reviews
+        let reviews = application.get_crate_reviews(&admin_auth).await?;
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(reviews[0].package, "serde-json");
+        assert!(reviews[0].reason.contains("serde_json"));
+        let error = application
+            .approve_crate_review(&user_auth, "serde-json")
+            .await
+            .expect_err("only admins can approve crates");
+        assert_eq!(error.http, StatusCode::FORBIDDEN);
+        application.approve_crate_review(&admin_auth, "serde-json").await?;
+        application.check_crate_readable(&other_auth, "serde-json").await?;
+        assert!(application.get_crate_reviews(&admin_auth).await?.is_empty());
+        Ok(())
+    })
+}
@@ -17,6 +17,7 @@ use cookie::{Cookie, CookieJar, Expiration, Key, SameSite};
 
 use super::extractors::Cookies;
 use crate::model::auth::Authentication;
+use crate::utils::paseto::TOKEN_HEADER;
 
 /// An authentication token
 #[derive(Debug, Clone)]
@@ -79,6 +80,8 @@ pub struct AuthData {
     pub cookie_jar: CookieJar,
     /// The authentication token, if any
     pub token: Option<Token>,
+    /// The asymmetric token (PASETO v3), if any
+    pub asymmetric_token: Option<String>,
 }
 
 impl Default for AuthData {
@@ -89,6 +92,7 @@ impl Default for AuthData {
             cookie_key: Key::from(&[0; 64]),
             cookie_jar: CookieJar::default(),
             token: None,
+            asymmetric_token: None,
         }
     }
 }
@@ -101,6 +105,7 @@ impl From<Token> for AuthData {
             cookie_key: Key::from(&[0; 64]),
             cookie_jar: CookieJar::default(),
             token: Some(token),
+            asymmetric_token: None,
         }
     }
 }
@@ -114,16 +119,22 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &Arc<S>) -> Result<Self, Self::Rejection> {
         let cookie_key = state.get_cookie_key().clone();
         let cookie_jar = parts.extract::<Cookies>().await?.0;
-        let token = parts
+        let authorization = parts
             .headers
             .get("authorization")
-            .and_then(|header| header.to_str().ok().and_then(Token::try_parse));
+            .and_then(|header| header.to_str().ok())
+            .map(str::trim);
+        let token = authorization.and_then(Token::try_parse);
+        let asymmetric_token = authorization
+            .filter(|value| value.starts_with(TOKEN_HEADER))
+            .map(str::to_string);
         Ok(Self {
             cookie_domain: state.get_domain(),
             cookie_id_name: state.get_id_cookie_name(),
             cookie_key,
             cookie_jar,
             token,
+            asymmetric_token,
         })
     }
 }
@@ -176,6 +187,15 @@ impl AuthData {
         }
     }
 
+    /// Gets the value of a private cookie sent with the request, if any
+    #[must_use]
+    pub fn get_private_cookie(&self, name: &str) -> Option<String> {
+        self.cookie_jar
+            .private(&self.cookie_key)
+            .get(name)
+            .map(|cookie| cookie.value().to_string())
+    }
+
     /// Creates an identification cookie to be returned on the HTTP response
     ///
     /// # Panics
@@ -0,0 +1,192 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+******************************************************************************/
+
+//! Utility APIs for signed JSON Web Tokens (JWS compact serialization), as used for OIDC ID tokens
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::digest::{SHA256, digest};
+use ring::signature::{
+    ECDSA_P256_SHA256_FIXED, ECDSA_P384_SHA384_FIXED, RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_2048_8192_SHA384,
+    RSA_PKCS1_2048_8192_SHA512, RSA_PSS_2048_8192_SHA256, RSA_PSS_2048_8192_SHA384, RSA_PSS_2048_8192_SHA512, RsaParameters,
+    RsaPublicKeyComponents, UnparsedPublicKey, VerificationAlgorithm,
+};
+use serde_derive::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::apierror::AsStatusCode;
+
+#[derive(Debug, Error)]
+pub enum JwtError {
+    #[error("the token is not a valid JSON Web Token")]
+    InvalidToken,
+
+    #[error("unsupported signature algorithm `{0}`")]
+    UnsupportedAlgorithm(String),
+
+    #[error("no matching key to verify the token")]
+    NoMatchingKey,
+
+    #[error("the signature of the token is invalid")]
+    InvalidSignature,
+}
+
+impl AsStatusCode for JwtError {}
+
+/// The header of a JSON Web Token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtHeader {
+    /// The signature algorithm
+    pub alg: String,
+    /// The identifier of the signing key, if any
+    pub kid: Option<String>,
+}
+
+/// A public key in the JSON Web Key format (RFC 7517)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Jwk {
+    /// The key type, `RSA` or `EC`
+    pub kty: String,
+    /// The key identifier
+    pub kid: Option<String>,
+    /// The intended use of the key
+    #[serde(rename = "use")]
+    pub key_use: Option<String>,
+    /// The algorithm intended for the key
+    pub alg: Option<String>,
+    /// The RSA modulus
+    pub n: Option<String>,
+    /// The RSA exponent
+    pub e: Option<String>,
+    /// The elliptic curve
+    pub crv: Option<String>,
+    /// The x coordinate of an elliptic curve point
+    pub x: Option<String>,
+    /// The y coordinate of an elliptic curve point
+    pub y: Option<String>,
+}
+
+/// A set of public keys (RFC 7517, section 5)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JwkSet {
+    /// The keys
+    pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    /// Finds the key to use for verifying a token
+    /// When the token does not specify a key, the only usable key is selected.
+    #[must_use]
+    pub fn find(&self, header: &JwtHeader) -> Option<&Jwk> {
+        let key_type = key_type_for(&header.alg)?;
+        let mut candidates = self.keys.iter().filter(|key| {
+            key.kty == key_type
+                && key.key_use.as_deref().is_none_or(|key_use| key_use == "sig")
+                && key.alg.as_deref().is_none_or(|alg| alg == header.alg)
+        });
+        if let Some(kid) = &header.kid {
+            candidates.find(|key| key.kid.as_ref() == Some(kid))
+        } else {
+            let first = candidates.next()?;
+            candidates.next().is_none().then_some(first)
+        }
+    }
+}
+
+/// A parsed JSON Web Token, not yet verified
+#[derive(Debug, Clone)]
+pub struct Jwt {
+    /// The header
+    pub header: JwtHeader,
+    /// The claims
+    pub claims: serde_json::Value,
+    /// The signed input, the encoded header and payload
+    signed: String,
+    /// The signature
+    signature: Vec<u8>,
+}
+
+impl Jwt {
+    /// Parses a token
+    pub fn parse(input: &str) -> Result<Self, JwtError> {
+        let mut parts = input.trim().split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(JwtError::InvalidToken);
+        };
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| JwtError::InvalidToken);
+        let header_data = decode(header)?;
+        let payload_data = decode(payload)?;
+        Ok(Self {
+            header: serde_json::from_slice(&header_data).map_err(|_| JwtError::InvalidToken)?,
+            claims: serde_json::from_slice(&payload_data).map_err(|_| JwtError::InvalidToken)?,
+            signed: format!("{header}.{payload}"),
+            signature: decode(signature)?,
+        })
+    }
+
+    /// Verifies the signature of this token against a set of keys
+    pub fn verify(&self, keys: &JwkSet) -> Result<(), JwtError> {
+        // fail early on unsupported algorithms, including `none`
+        key_type_for(&self.header.alg).ok_or_else(|| JwtError::UnsupportedAlgorithm(self.header.alg.clone()))?;
+        let key = keys.find(&self.header).ok_or(JwtError::NoMatchingKey)?;
+        let decode = |part: Option<&String>| {
+            part.and_then(|part| URL_SAFE_NO_PAD.decode(part).ok())
+                .ok_or(JwtError::NoMatchingKey)
+        };
+        let message = self.signed.as_bytes();
+        if let Some(parameters) = rsa_parameters_for(&self.header.alg) {
+            let components = RsaPublicKeyComponents {
+                n: decode(key.n.as_ref())?,
+                e: decode(key.e.as_ref())?,
+            };
+            return components
+                .verify(parameters, message, &self.signature)
+                .map_err(|_| JwtError::InvalidSignature);
+        }
+        let (algorithm, curve): (&'static dyn VerificationAlgorithm, &str) = match self.header.alg.as_str() {
+            "ES256" => (&ECDSA_P256_SHA256_FIXED, "P-256"),
+            "ES384" => (&ECDSA_P384_SHA384_FIXED, "P-384"),
+            alg => return Err(JwtError::UnsupportedAlgorithm(alg.to_string())),
+        };
+        if key.crv.as_deref() != Some(curve) {
+            return Err(JwtError::NoMatchingKey);
+        }
+        // uncompressed point
+        let mut point = vec![0x04];
+        point.extend_from_slice(&decode(key.x.as_ref())?);
+        point.extend_from_slice(&decode(key.y.as_ref())?);
+        UnparsedPublicKey::new(algorithm, point)
+            .verify(message, &self.signature)
+            .map_err(|_| JwtError::InvalidSignature)
+    }
+}
+
+/// Gets the type of key for a signature algorithm, if supported
+fn key_type_for(alg: &str) -> Option<&'static str> {
+    match alg {
+        "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512" => Some("RSA"),
+        "ES256" | "ES384" => Some("EC"),
+        _ => None,
+    }
+}
+
+/// Gets the RSA parameters for a signature algorithm, if this is an RSA algorithm
+fn rsa_parameters_for(alg: &str) -> Option<&'static RsaParameters> {
+    match alg {
+        "RS256" => Some(&RSA_PKCS1_2048_8192_SHA256),
+        "RS384" => Some(&RSA_PKCS1_2048_8192_SHA384),
+        "RS512" => Some(&RSA_PKCS1_2048_8192_SHA512),
+        "PS256" => Some(&RSA_PSS_2048_8192_SHA256),
+        "PS384" => Some(&RSA_PSS_2048_8192_SHA384),
+        "PS512" => Some(&RSA_PSS_2048_8192_SHA512),
+        _ => None,
+    }
+}
+
+/// Computes the PKCE code challenge for a code verifier, using the `S256` method (RFC 7636, section 4.2)
+#[must_use]
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()))
+}
@@ -22,6 +22,8 @@ pub mod axum;
 pub mod concurrent;
 pub mod db;
 pub mod hashes;
+pub mod jwt;
+pub mod paseto;
 pub mod shared;
 pub mod sigterm;
 pub mod token;
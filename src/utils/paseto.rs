@@ -0,0 +1,134 @@
+/*******************************************************************************
+ * Copyright (c) 2024 Cénotélie Opérations SAS (cenotelie.fr)
+******************************************************************************/
+
+//! Utility APIs for PASETO v3 public tokens and PASERK keys, as used by cargo for asymmetric tokens
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use p384::PublicKey;
+use p384::elliptic_curve::sec1::ToEncodedPoint;
+use ring::digest::{SHA384, digest};
+use ring::signature::{ECDSA_P384_SHA384_FIXED, UnparsedPublicKey};
+use thiserror::Error;
+
+use super::apierror::AsStatusCode;
+
+/// The header for PASETO v3 public tokens
+pub const TOKEN_HEADER: &str = "v3.public.";
+/// The prefix for PASERK v3 public keys
+pub const PASERK_PUBLIC_PREFIX: &str = "k3.public.";
+/// The prefix for PASERK v3 key identifiers
+pub const PASERK_PID_PREFIX: &str = "k3.pid.";
+
+/// The length of a compressed P-384 point
+const COMPRESSED_POINT_LENGTH: usize = 49;
+/// The length of an ECDSA P-384 signature
+const SIGNATURE_LENGTH: usize = 96;
+/// The length of the hash in a PASERK key identifier
+const PID_HASH_LENGTH: usize = 33;
+
+#[derive(Debug, Error)]
+pub enum PasetoError {
+    #[error("the public key is not a valid PASERK k3.public key")]
+    InvalidPublicKey,
+
+    #[error("the token is not a valid PASETO v3.public token")]
+    InvalidToken,
+
+    #[error("the signature of the token is invalid")]
+    InvalidSignature,
+}
+
+impl AsStatusCode for PasetoError {}
+
+/// A parsed PASETO v3 public token, not yet verified
+#[derive(Debug, Clone)]
+pub struct PasetoToken {
+    /// The message
+    pub message: Vec<u8>,
+    /// The signature
+    signature: Vec<u8>,
+    /// The footer
+    pub footer: Vec<u8>,
+}
+
+impl PasetoToken {
+    /// Parses a token
+    pub fn parse(input: &str) -> Result<Self, PasetoError> {
+        let rest = input.strip_prefix(TOKEN_HEADER).ok_or(PasetoError::InvalidToken)?;
+        let (payload, footer) = rest.split_once('.').unwrap_or((rest, ""));
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| PasetoError::InvalidToken)?;
+        let footer = URL_SAFE_NO_PAD.decode(footer).map_err(|_| PasetoError::InvalidToken)?;
+        if payload.len() < SIGNATURE_LENGTH {
+            return Err(PasetoError::InvalidToken);
+        }
+        let (message, signature) = payload.split_at(payload.len() - SIGNATURE_LENGTH);
+        Ok(Self {
+            message: message.to_vec(),
+            signature: signature.to_vec(),
+            footer,
+        })
+    }
+
+    /// Verifies the signature of this token against a public key in the PASERK format
+    pub fn verify(&self, public_key: &str) -> Result<(), PasetoError> {
+        let compressed = parse_public_key(public_key)?;
+        let uncompressed = decompress_point(&compressed).ok_or(PasetoError::InvalidPublicKey)?;
+        // no implicit assertion
+        let signed = pre_auth_encode(&[&compressed, TOKEN_HEADER.as_bytes(), &self.message, &self.footer, b""]);
+        UnparsedPublicKey::new(&ECDSA_P384_SHA384_FIXED, &uncompressed)
+            .verify(&signed, &self.signature)
+            .map_err(|_| PasetoError::InvalidSignature)
+    }
+}
+
+/// Parses a public key in the PASERK format and gets the compressed point
+pub fn parse_public_key(public_key: &str) -> Result<Vec<u8>, PasetoError> {
+    let data = public_key
+        .trim()
+        .strip_prefix(PASERK_PUBLIC_PREFIX)
+        .ok_or(PasetoError::InvalidPublicKey)?;
+    let compressed = URL_SAFE_NO_PAD.decode(data).map_err(|_| PasetoError::InvalidPublicKey)?;
+    if compressed.len() != COMPRESSED_POINT_LENGTH || decompress_point(&compressed).is_none() {
+        return Err(PasetoError::InvalidPublicKey);
+    }
+    Ok(compressed)
+}
+
+/// Gets the PASERK identifier (`k3.pid.`) for a public key in the PASERK format
+#[must_use]
+pub fn public_key_id(public_key: &str) -> String {
+    let mut input = String::from(PASERK_PID_PREFIX);
+    input.push_str(public_key.trim());
+    let hash = digest(&SHA384, input.as_bytes());
+    format!(
+        "{PASERK_PID_PREFIX}{}",
+        URL_SAFE_NO_PAD.encode(&hash.as_ref()[..PID_HASH_LENGTH])
+    )
+}
+
+/// Pre-authentication encoding of pieces, as specified by PASETO
+fn pre_auth_encode(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut result = Vec::new();
+    result.extend_from_slice(&le64(pieces.len()));
+    for piece in pieces {
+        result.extend_from_slice(&le64(piece.len()));
+        result.extend_from_slice(piece);
+    }
+    result
+}
+
+/// Encodes a length as a 64-bit little-endian integer, with the most significant bit cleared
+const fn le64(value: usize) -> [u8; 8] {
+    ((value as u64) & (u64::MAX >> 1)).to_le_bytes()
+}
+
+/// Decompresses a point on the P-384 curve into its uncompressed SEC1 encoding
+fn decompress_point(compressed: &[u8]) -> Option<Vec<u8>> {
+    if compressed.len() != COMPRESSED_POINT_LENGTH {
+        return None;
+    }
+    let public_key = PublicKey::from_sec1_bytes(compressed).ok()?;
+    Some(public_key.to_encoded_point(false).as_bytes().to_vec())
+}
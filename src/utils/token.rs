@@ -13,6 +13,19 @@ use ring::digest::{Context, SHA256};
 
 use super::apierror::{ApiError, error_unauthorized};
 
+/// The prefix for token secrets that embed a public identifier
+pub const TOKEN_ID_PREFIX: &str = "crt_";
+/// The separator between the public identifier and the secret part
+const TOKEN_ID_SEPARATOR: char = '_';
+/// The number of random bytes in a public token identifier
+const TOKEN_ID_BYTES: usize = 8;
+/// The number of random bytes in a device code and other secrets
+const DEVICE_CODE_BYTES: usize = 32;
+/// The characters for user codes, without vowels nor ambiguous characters
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+/// The number of characters in a user code
+const USER_CODE_LENGTH: usize = 8;
+
 /// Generates a token
 #[must_use]
 pub fn generate_token(length: usize) -> String {
@@ -25,6 +38,78 @@ pub fn generate_token(length: usize) -> String {
     STANDARD.encode(&bytes)
 }
 
+/// Generates a random hexadecimal string from a number of random bytes
+fn generate_hex(bytes_count: usize) -> String {
+    let rng = rand::rng();
+    let bytes = rng
+        .sample_iter::<u8, _>(StandardUniform)
+        .take(bytes_count)
+        .collect::<Vec<_>>();
+    HEXLOWER.encode(&bytes)
+}
+
+/// Generates a public token identifier
+#[must_use]
+pub fn generate_token_id() -> String {
+    generate_hex(TOKEN_ID_BYTES)
+}
+
+/// Generates a device code for the device authorization flow
+#[must_use]
+pub fn generate_device_code() -> String {
+    generate_hex(DEVICE_CODE_BYTES)
+}
+
+/// Generates a random secret made of hexadecimal characters, so that it can be used as is in URIs
+#[must_use]
+pub fn generate_hex_secret() -> String {
+    generate_hex(DEVICE_CODE_BYTES)
+}
+
+/// Generates a user code for the device authorization flow, in the `XXXX-XXXX` format
+#[must_use]
+pub fn generate_user_code() -> String {
+    let mut rng = rand::rng();
+    let code = (0..USER_CODE_LENGTH)
+        .map(|_| char::from(USER_CODE_ALPHABET[rng.random_range(0..USER_CODE_ALPHABET.len())]))
+        .collect::<String>();
+    normalize_user_code(&code)
+}
+
+/// Normalizes a user code entered by a user
+/// Letters are put in upper case, other characters are ignored and the separator is put back in place.
+#[must_use]
+pub fn normalize_user_code(input: &str) -> String {
+    let mut result = String::with_capacity(USER_CODE_LENGTH + 1);
+    for (index, c) in input
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .enumerate()
+    {
+        if index == USER_CODE_LENGTH / 2 {
+            result.push('-');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Generates a token secret that embeds a public identifier, so that the token can be looked up directly
+/// The format is `crt_<identifier>_<secret>`
+#[must_use]
+pub fn generate_token_with_id(token_id: &str, length: usize) -> String {
+    format!("{TOKEN_ID_PREFIX}{token_id}{TOKEN_ID_SEPARATOR}{}", generate_token(length))
+}
+
+/// Gets the public identifier embedded in a token secret, if any
+/// Secrets in the legacy format, without identifier, yield `None`
+#[must_use]
+pub fn parse_token_id(secret: &str) -> Option<&str> {
+    let (token_id, _) = secret.strip_prefix(TOKEN_ID_PREFIX)?.split_once(TOKEN_ID_SEPARATOR)?;
+    (token_id.len() == TOKEN_ID_BYTES * 2 && token_id.bytes().all(|c| c.is_ascii_hexdigit())).then_some(token_id)
+}
+
 /// Computes the SHA256 digest of bytes
 #[must_use]
 pub fn sha256(buffer: &[u8]) -> String {
@@ -21,8 +21,8 @@ async function apiMe() {
   return await onResponseJson(response);
 }
 
-async function apiOAuthLoginWithCode(code) {
-  const response = await fetch("/api/v1/oauth/code", {
+async function apiOAuthLoginWithCode(code, state) {
+  const response = await fetch(`/api/v1/oauth/code?state=${encodeURIComponent(state || "")}`, {
     method: "POST",
     body: code,
   });
@@ -41,9 +41,10 @@ async function apiGetUserTokens() {
   return await onResponseJson(response);
 }
 
-async function apiCreateUserToken(name, canWrite, canAdmin) {
+async function apiCreateUserToken(name, canWrite, canAdmin, crateScopes, endpointScopes, expiresInDays) {
+  const expiry = expiresInDays ? `&expiresInDays=${expiresInDays}` : "";
   const response = await fetch(
-    `/api/v1/me/tokens?canWrite=${canWrite}&canAdmin=${canAdmin}`,
+    `/api/v1/me/tokens?canWrite=${canWrite}&canAdmin=${canAdmin}&crateScopes=${encodeURIComponent(crateScopes || "")}&endpointScopes=${encodeURIComponent(endpointScopes || "")}${expiry}`,
     {
       method: "PUT",
       body: name,
@@ -52,6 +53,14 @@ async function apiCreateUserToken(name, canWrite, canAdmin) {
   return await onResponseJson(response);
 }
 
+async function apiRotateUserToken(token_id, expiresInDays) {
+  const expiry = expiresInDays ? `?expiresInDays=${expiresInDays}` : "";
+  const response = await fetch(`/api/v1/me/tokens/${token_id}/rotate${expiry}`, {
+    method: "POST",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiRevokeUserToken(token_id) {
   const response = await fetch(`/api/v1/me/tokens/${token_id}`, {
     method: "DELETE",
@@ -59,19 +68,58 @@ async function apiRevokeUserToken(token_id) {
   return await onResponseJson(response);
 }
 
+async function apiDecideDeviceAuthorization(userCode, approve, name, canWrite, canAdmin, expiresInDays) {
+  const response = await fetch("/api/v1/me/device", {
+    method: "POST",
+    body: JSON.stringify({ userCode, approve, name, canWrite, canAdmin, expiresInDays }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetUserKeys() {
+  const response = await fetch("/api/v1/me/keys");
+  return await onResponseJson(response);
+}
+
+async function apiAddUserKey(name, publicKey, canWrite) {
+  const response = await fetch("/api/v1/me/keys", {
+    method: "PUT",
+    body: JSON.stringify({ name, publicKey, canWrite }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiRemoveUserKey(key_id) {
+  const response = await fetch(`/api/v1/me/keys/${key_id}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiGetGlobalTokens() {
   const response = await fetch("/api/v1/admin/tokens");
   return await onResponseJson(response);
 }
 
-async function apiCreateGlobalToken(name) {
-  const response = await fetch("/api/v1/admin/tokens", {
+async function apiCreateGlobalToken(name, expiresInDays) {
+  const expiry = expiresInDays ? `?expiresInDays=${expiresInDays}` : "";
+  const response = await fetch(`/api/v1/admin/tokens${expiry}`, {
     method: "PUT",
     body: name,
   });
   return await onResponseJson(response);
 }
 
+async function apiRotateGlobalToken(token_id, expiresInDays) {
+  const expiry = expiresInDays ? `?expiresInDays=${expiresInDays}` : "";
+  const response = await fetch(`/api/v1/admin/tokens/${token_id}/rotate${expiry}`, {
+    method: "POST",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiRevokeGlobalToken(token_id) {
   const response = await fetch(`/api/v1/admin/tokens/${token_id}`, {
     method: "DELETE",
@@ -79,6 +127,100 @@ async function apiRevokeGlobalToken(token_id) {
   return await onResponseJson(response);
 }
 
+async function apiGetNameReservations() {
+  const response = await fetch("/api/v1/admin/reservations");
+  return await onResponseJson(response);
+}
+
+async function apiCreateNameReservation(pattern, owner) {
+  const response = await fetch("/api/v1/admin/reservations", {
+    method: "PUT",
+    body: JSON.stringify({ pattern, owner }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiDeleteNameReservation(reservation_id) {
+  const response = await fetch(`/api/v1/admin/reservations/${reservation_id}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetCrateReviews() {
+  const response = await fetch("/api/v1/admin/reviews");
+  return await onResponseJson(response);
+}
+
+async function apiApproveCrateReview(crate) {
+  const response = await fetch(`/api/v1/admin/reviews/${crate}/approve`, {
+    method: "POST",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetPublishRules() {
+  const response = await fetch("/api/v1/admin/policy");
+  return await onResponseJson(response);
+}
+
+async function apiCreatePublishRule(kind, parameter, isBlocking) {
+  const response = await fetch("/api/v1/admin/policy", {
+    method: "PUT",
+    body: JSON.stringify({ kind, parameter, isBlocking }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiDeletePublishRule(rule_id) {
+  const response = await fetch(`/api/v1/admin/policy/${rule_id}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetLicenseRules() {
+  const response = await fetch("/api/v1/admin/licenses");
+  return await onResponseJson(response);
+}
+
+async function apiCreateLicenseRule(license, isAllowed) {
+  const response = await fetch("/api/v1/admin/licenses", {
+    method: "PUT",
+    body: JSON.stringify({ license, isAllowed }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiDeleteLicenseRule(rule_id) {
+  const response = await fetch(`/api/v1/admin/licenses/${rule_id}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetAdvisoryImpact(id) {
+  const response = await fetch(`/api/v1/advisories/${id}/impact`);
+  return await onResponseJson(response);
+}
+
+async function apiGetAdvisoryDbStatus() {
+  const response = await fetch("/api/v1/admin/advisories/db");
+  return await onResponseJson(response);
+}
+
+async function apiUploadAdvisoryDb(file) {
+  const response = await fetch("/api/v1/admin/advisories/db", {
+    method: "PUT",
+    body: file,
+    headers: [["content-type", "application/gzip"]],
+  });
+  return await onResponseJson(response);
+}
+
 async function apiGetDocGenJobs() {
   const response = await fetch("/api/v1/admin/jobs/docgen");
   return await onResponseJson(response);
@@ -135,6 +277,43 @@ async function apiReactivateUser(email) {
   return await onResponseJson(response);
 }
 
+async function apiGetTeams() {
+  const response = await fetch("/api/v1/teams");
+  return await onResponseJson(response);
+}
+
+async function apiCreateTeam(name) {
+  const response = await fetch("/api/v1/teams", {
+    method: "PUT",
+    body: JSON.stringify({ name }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiDeleteTeam(name) {
+  const response = await fetch(`/api/v1/teams/${encodeURIComponent(name)}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiSetTeamMember(name, email, isMaintainer) {
+  const response = await fetch(`/api/v1/teams/${encodeURIComponent(name)}/members`, {
+    method: "PUT",
+    body: JSON.stringify({ email, isMaintainer }),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiRemoveTeamMember(name, email) {
+  const response = await fetch(`/api/v1/teams/${encodeURIComponent(name)}/members/${btoa(email)}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiGetCratesStats() {
   const response = await fetch("/api/v1/crates/stats");
   return await onResponseJson(response);
@@ -225,6 +404,71 @@ async function apiSetCrateCapabilities(crate, capabilities) {
   return await onResponseJson(response);
 }
 
+async function apiGetCrateReaders(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/readers`);
+  return await onResponseJson(response);
+}
+
+async function apiSetCrateReaders(crate, readers) {
+  const response = await fetch(`/api/v1/crates/${crate}/readers`, {
+    method: "PATCH",
+    body: JSON.stringify(readers),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetCrateAdvisories(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/advisories`);
+  return await onResponseJson(response);
+}
+
+async function apiCreateCrateAdvisory(crate, data) {
+  const response = await fetch(`/api/v1/crates/${crate}/advisories`, {
+    method: "PUT",
+    body: JSON.stringify(data),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiUpdateCrateAdvisory(crate, id, data) {
+  const response = await fetch(`/api/v1/crates/${crate}/advisories/${id}`, {
+    method: "PATCH",
+    body: JSON.stringify(data),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiWithdrawCrateAdvisory(crate, id) {
+  const response = await fetch(`/api/v1/crates/${crate}/advisories/${id}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
+async function apiGetAdvisorySuppressions(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/suppressions`);
+  return await onResponseJson(response);
+}
+
+async function apiCreateAdvisorySuppression(crate, data) {
+  const response = await fetch(`/api/v1/crates/${crate}/suppressions`, {
+    method: "PUT",
+    body: JSON.stringify(data),
+    headers: [["content-type", "application/json"]],
+  });
+  return await onResponseJson(response);
+}
+
+async function apiDeleteAdvisorySuppression(crate, suppressionId) {
+  const response = await fetch(`/api/v1/crates/${crate}/suppressions/${suppressionId}`, {
+    method: "DELETE",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiSetCrateDeprecation(crate, isDeprecated) {
   const response = await fetch(`/api/v1/crates/${crate}/deprecated`, {
     method: "PATCH",
@@ -264,6 +508,13 @@ async function apiCheckCrateDeps(crate, version) {
   return await onResponseJson(response);
 }
 
+async function apiGetCrateDependents(crate) {
+  const response = await fetch(`/api/v1/crates/${crate}/dependents`, {
+    method: "GET",
+  });
+  return await onResponseJson(response);
+}
+
 async function apiGetCrateDlStats(crate) {
   const response = await fetch(`/api/v1/crates/${crate}/dlstats`, {
     method: "GET",
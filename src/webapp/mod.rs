@@ -30,10 +30,16 @@ pub fn get_resources() -> EmbeddedResources {
     add!(resources, "admin.html");
     add!(resources, "admin-users.html");
     add!(resources, "admin-tokens.html");
+    add!(resources, "admin-reservations.html");
+    add!(resources, "admin-policy.html");
+    add!(resources, "admin-licenses.html");
+    add!(resources, "admin-advisories.html");
     add!(resources, "admin-jobs-docgen.html");
     add!(resources, "admin-workers.html");
     add!(resources, "crate.html");
     add!(resources, "oauthcallback.html");
+    add!(resources, "device.html");
+    add!(resources, "teams.html");
     // CSS
     add!(resources, "index.css");
     // JS